@@ -0,0 +1,112 @@
+//! Neighborhood of triangles in a mesh.
+
+use std::collections::HashMap;
+
+/// The triangles sharing an edge with each triangle of a mesh.
+///
+/// Vertices are identified by position, so triangles are neighbors even if
+/// their vertices were split in the mesh because of different normals or
+/// texture coordinates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaceAdjacency {
+    neighbors: Vec<[Option<u32>; 3]>,
+    boundary_edges: usize,
+    non_manifold_edges: usize,
+}
+
+impl FaceAdjacency {
+    /// Finds the neighbors of the triangles in the given indexed mesh, with
+    /// three position components per vertex and three indices per triangle.
+    pub fn build(positions: &[f32], indices: &[u32]) -> FaceAdjacency {
+        let vertex_ids = position_ids(positions);
+
+        let mut edges: HashMap<(u32, u32), Vec<(usize, usize)>> = HashMap::new();
+        for (face, triangle) in indices.chunks(3).enumerate() {
+            for edge in 0..3 {
+                let from = vertex_ids[triangle[edge] as usize];
+                let to = vertex_ids[triangle[(edge + 1) % 3] as usize];
+                let key = if from < to { (from, to) } else { (to, from) };
+                edges.entry(key).or_default().push((face, edge));
+            }
+        }
+
+        let mut adjacency = FaceAdjacency {
+            neighbors: vec![[None; 3]; indices.len() / 3],
+            boundary_edges: 0,
+            non_manifold_edges: 0,
+        };
+        for sharing in edges.values() {
+            match sharing.len() {
+                1 => adjacency.boundary_edges += 1,
+                2 => {
+                    let (a, a_edge) = sharing[0];
+                    let (b, b_edge) = sharing[1];
+                    adjacency.neighbors[a][a_edge] = Some(b as u32);
+                    adjacency.neighbors[b][b_edge] = Some(a as u32);
+                }
+                _ => adjacency.non_manifold_edges += 1,
+            }
+        }
+        adjacency
+    }
+
+    /// Number of triangles in the mesh.
+    pub fn face_count(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    /// Gets the triangles adjacent to the triangle with the given index. The
+    /// neighbor at index `i` shares the edge from corner `i` to the next
+    /// corner of the triangle, or is `None` if the edge is on the boundary or
+    /// shared by more than two triangles.
+    pub fn neighbors(&self, face: usize) -> [Option<u32>; 3] {
+        self.neighbors[face]
+    }
+
+    /// Number of edges without a neighbor on one side.
+    pub fn boundary_edges(&self) -> usize {
+        self.boundary_edges
+    }
+
+    /// Number of edges that are shared by more than two triangles.
+    pub fn non_manifold_edges(&self) -> usize {
+        self.non_manifold_edges
+    }
+}
+
+/// Numbers the distinct positions of the vertices in order of first use and
+/// gets the number of the position of each vertex, so that vertices at the
+/// same position get the same id.
+pub(crate) fn position_ids(positions: &[f32]) -> Vec<u32> {
+    let mut welded = HashMap::new();
+    positions
+        .chunks(3)
+        .map(|p| {
+            let key = (p[0].to_bits(), p[1].to_bits(), p[2].to_bits());
+            let next_id = welded.len() as u32;
+            *welded.entry(key).or_insert(next_id)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quad_with_split_vertices() {
+        // Two triangles of a quad, with the shared diagonal split
+        let positions = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, // first
+            0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, // second
+        ];
+        let indices = [0, 1, 2, 3, 4, 5];
+        let adjacency = FaceAdjacency::build(&positions, &indices);
+
+        assert_eq!(2, adjacency.face_count());
+        assert_eq!([None, None, Some(1)], adjacency.neighbors(0));
+        assert_eq!([Some(0), None, None], adjacency.neighbors(1));
+        assert_eq!(4, adjacency.boundary_edges());
+        assert_eq!(0, adjacency.non_manifold_edges());
+    }
+}
@@ -0,0 +1,126 @@
+//! Loading scenes that are distributed as ZIP archives.
+//!
+//! ```no_run
+//! # extern crate aitios_asset;
+//! use aitios_asset::archive;
+//!
+//! # fn main() {
+//! let scene = archive::load("scene.zip", "interior.obj").unwrap();
+//! # }
+//! ```
+
+use err::{AssetError, Result};
+use loaded::LoadedScene;
+use obj::{self, LoadOptions};
+use std::fs::{create_dir_all, File};
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// Loads the OBJ with the given name inside the ZIP archive at the given
+/// path, along with its MTL files and textures from the same archive.
+///
+/// The archive is extracted into a new, uniquely named directory in the
+/// temporary directory first, which is where the texture maps of the loaded
+/// materials point to. The directory is not removed, since the textures are
+/// usually needed after loading.
+pub fn load<P: AsRef<Path>>(archive_path: P, obj_name: &str) -> Result<LoadedScene> {
+    load_with(archive_path, obj_name, &LoadOptions::default())
+}
+
+/// Loads an OBJ from a ZIP archive like `load`, but with the given options.
+pub fn load_with<P: AsRef<Path>>(
+    archive_path: P,
+    obj_name: &str,
+    options: &LoadOptions,
+) -> Result<LoadedScene> {
+    let archive_path = archive_path.as_ref();
+    let dir = TempDir::new()?;
+    extract(archive_path, dir.path())?;
+
+    if !dir.path().join(obj_name).is_file() {
+        return Err(archive_error(
+            archive_path,
+            format!("No OBJ named \"{}\" in archive", obj_name),
+        ));
+    }
+
+    obj::load_with(dir.into_path().join(obj_name), options)
+}
+
+/// Extracts all files in the ZIP archive at the given path into the given
+/// directory, which is created if it does not exist yet. Existing files are
+/// overwritten.
+///
+/// Entries with paths that would end up outside of the directory fail the
+/// extraction.
+pub fn extract<P: AsRef<Path>, Q: Into<PathBuf>>(archive_path: P, dir: Q) -> Result<()> {
+    let archive_path = archive_path.as_ref();
+    let dir = dir.into();
+
+    let file = File::open(archive_path).map_err(AssetError::Read)?;
+    let mut archive = ZipArchive::new(file).map_err(|err| zip_error(archive_path, err))?;
+
+    create_dir_all(&dir).map_err(AssetError::Read)?;
+    archive
+        .extract(&dir)
+        .map_err(|err| zip_error(archive_path, err))
+}
+
+fn zip_error(archive_path: &Path, err: ZipError) -> AssetError {
+    match err {
+        ZipError::Io(err) => AssetError::Read(err),
+        err => archive_error(archive_path, err.to_string()),
+    }
+}
+
+fn archive_error<S: Into<String>>(archive_path: &Path, reason: S) -> AssetError {
+    AssetError::Archive {
+        path: archive_path.to_path_buf(),
+        reason: reason.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use tempdir::tempdir_scope;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn test_load_from_archive() {
+        let (scene, missing) = tempdir_scope(|dir| {
+            let archive_path = dir.join("scene.zip");
+            let mut zip = ZipWriter::new(File::create(&archive_path)?);
+            for &(name, path) in &[
+                ("models/cube.obj", "tests/cube.obj"),
+                ("models/cube.mtl", "tests/cube.mtl"),
+            ] {
+                zip.start_file(name, FileOptions::default()).unwrap();
+                zip.write_all(&fs::read(path)?)?;
+            }
+            zip.finish().unwrap();
+
+            Ok((
+                load(&archive_path, "models/cube.obj"),
+                load(&archive_path, "models/missing.obj"),
+            ))
+        })
+        .unwrap();
+
+        let scene = scene.unwrap();
+        assert_eq!(
+            obj::load("tests/cube.obj").unwrap().stats.without_timings(),
+            scene.stats.without_timings()
+        );
+        assert!(scene.material("Material").is_some());
+        match missing {
+            Err(AssetError::Archive { .. }) => (),
+            _ => panic!("Expected archive error"),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Conversion of many files at once on multiple threads.
+
+use bundle;
+use err::{AssetError, Result};
+use loaded::LoadedScene;
+use obj::{self, LoadOptions, SaveOptions};
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+
+/// The kind of files written by `convert_batch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// An OBJ and an MTL with the same stem.
+    Obj,
+    /// A bundle with the extension `aitb`, see the `bundle` module.
+    Bundle,
+}
+
+/// Controls where and how `convert_batch` converts files.
+///
+/// Load and save options may hold functions that cannot be sent to other
+/// threads, so each worker thread creates its own from the given functions.
+#[derive(Clone)]
+pub struct BatchOptions {
+    pub(crate) output_dir: Option<PathBuf>,
+    pub(crate) threads: usize,
+    pub(crate) load_options: Arc<dyn Fn() -> LoadOptions + Send + Sync>,
+    pub(crate) save_options: Arc<dyn Fn() -> SaveOptions + Send + Sync>,
+}
+
+impl BatchOptions {
+    pub fn new() -> BatchOptions {
+        BatchOptions::default()
+    }
+
+    /// Sets the directory to write converted files to, instead of the
+    /// directory of each input.
+    pub fn output_dir<P: Into<PathBuf>>(mut self, dir: P) -> BatchOptions {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets the number of files converted at the same time, by default the
+    /// number of available processors.
+    pub fn threads(mut self, threads: usize) -> BatchOptions {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Sets the function creating the options to load each input with.
+    pub fn load_options<F>(mut self, load_options: F) -> BatchOptions
+    where
+        F: Fn() -> LoadOptions + Send + Sync + 'static,
+    {
+        self.load_options = Arc::new(load_options);
+        self
+    }
+
+    /// Sets the function creating the options to save OBJ outputs with.
+    pub fn save_options<F>(mut self, save_options: F) -> BatchOptions
+    where
+        F: Fn() -> SaveOptions + Send + Sync + 'static,
+    {
+        self.save_options = Arc::new(save_options);
+        self
+    }
+}
+
+impl Default for BatchOptions {
+    fn default() -> BatchOptions {
+        BatchOptions {
+            output_dir: None,
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            load_options: Arc::new(LoadOptions::default),
+            save_options: Arc::new(SaveOptions::default),
+        }
+    }
+}
+
+impl fmt::Debug for BatchOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BatchOptions")
+            .field("output_dir", &self.output_dir)
+            .field("threads", &self.threads)
+            .finish()
+    }
+}
+
+/// The outcome of converting one input of a batch.
+#[derive(Debug)]
+pub struct Conversion {
+    pub input: PathBuf,
+    /// The OBJ or bundle that was written, or would have been written if
+    /// conversion had succeeded.
+    pub output: PathBuf,
+    pub result: Result<()>,
+}
+
+/// Converts each of the given OBJ files or bundles into the given format,
+/// on multiple threads.
+///
+/// A file that fails to convert does not stop the others, the returned
+/// conversions hold the result for each input in the order of the inputs.
+/// Inputs with the extension `aitb` are loaded as bundles, all others as
+/// OBJ. Outputs that would overwrite their input or the output of an earlier
+/// input are not written.
+pub fn convert_batch<I, P>(
+    inputs: I,
+    output_format: OutputFormat,
+    options: &BatchOptions,
+) -> Vec<Conversion>
+where
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
+{
+    let jobs: Vec<(PathBuf, PathBuf)> = inputs
+        .into_iter()
+        .map(|input| {
+            let input = input.into();
+            let output = output_path(&input, output_format, options);
+            (input, output)
+        })
+        .collect();
+
+    // Reject conflicting outputs before writing anything
+    let mut results: Vec<Option<Result<()>>> = Vec::with_capacity(jobs.len());
+    let mut writers: HashMap<&Path, &Path> = HashMap::new();
+    for (input, output) in jobs.iter() {
+        let conflict = if output == input {
+            Some("Output would overwrite the input".to_string())
+        } else if let Some(earlier) = writers.get(output.as_path()) {
+            Some(format!("Output is also written for {:?}", earlier))
+        } else {
+            writers.insert(output, input);
+            None
+        };
+        results.push(conflict.map(|reason| {
+            Err(AssetError::Preflight {
+                path: output.clone(),
+                reason,
+            })
+        }));
+    }
+
+    let pending: Arc<Vec<(PathBuf, PathBuf)>> = Arc::new(
+        jobs.iter()
+            .zip(results.iter())
+            .filter(|&(_, result)| result.is_none())
+            .map(|(job, _)| job.clone())
+            .collect(),
+    );
+    let next = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = channel();
+    let workers: Vec<_> = (0..options.threads.min(pending.len()))
+        .map(|_| {
+            let pending = Arc::clone(&pending);
+            let next = Arc::clone(&next);
+            let sender = sender.clone();
+            let load_options = Arc::clone(&options.load_options);
+            let save_options = Arc::clone(&options.save_options);
+            thread::spawn(move || {
+                let load_options = load_options();
+                let save_options = save_options();
+                loop {
+                    let idx = next.fetch_add(1, Ordering::SeqCst);
+                    let (input, output) = match pending.get(idx) {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    // A panic must not take the rest of the batch with it
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        convert(input, output, output_format, &load_options, &save_options)
+                    }))
+                    .unwrap_or_else(|_| {
+                        Err(AssetError::InvalidData(format!(
+                            "Converting {:?} panicked",
+                            input
+                        )))
+                    });
+                    if sender.send((idx, result)).is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut finished: HashMap<usize, Result<()>> = receiver.iter().collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    let mut pending_idx = 0;
+    jobs.into_iter()
+        .zip(results)
+        .map(|((input, output), result)| {
+            let result = result.unwrap_or_else(|| {
+                let result = finished.remove(&pending_idx).unwrap_or_else(|| {
+                    Err(AssetError::InvalidData(format!(
+                        "Converting {:?} did not finish",
+                        input
+                    )))
+                });
+                pending_idx += 1;
+                result
+            });
+            Conversion {
+                input,
+                output,
+                result,
+            }
+        })
+        .collect()
+}
+
+fn convert(
+    input: &Path,
+    output: &Path,
+    output_format: OutputFormat,
+    load_options: &LoadOptions,
+    save_options: &SaveOptions,
+) -> Result<()> {
+    let scene: LoadedScene = if is_bundle(input) {
+        bundle::load_with(input, load_options)?
+    } else {
+        obj::load_with(input, load_options)?
+    };
+
+    match output_format {
+        OutputFormat::Obj => obj::save_scene_with(
+            &scene,
+            Some(output.to_path_buf()),
+            Some(output.with_extension("mtl")),
+            save_options,
+        ),
+        OutputFormat::Bundle => bundle::save(scene.entities.iter(), output),
+    }
+}
+
+/// Path of the OBJ or bundle for the given input, with the same stem as the
+/// input, without any compression extension.
+fn output_path(input: &Path, output_format: OutputFormat, options: &BatchOptions) -> PathBuf {
+    let mut stem = PathBuf::from(input.file_stem().unwrap_or_default());
+    let compressed_obj = stem
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("obj"))
+        .unwrap_or(false);
+    if compressed_obj {
+        stem.set_extension("");
+    }
+
+    let dir = match options.output_dir {
+        Some(ref dir) => dir.clone(),
+        None => input.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    let extension = match output_format {
+        OutputFormat::Obj => "obj",
+        OutputFormat::Bundle => "aitb",
+    };
+    dir.join(stem).with_extension(extension)
+}
+
+fn is_bundle(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.eq_ignore_ascii_case("aitb"))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::tempdir_scope;
+
+    #[test]
+    fn test_failures_isolated() {
+        let (dir, conversions, converted) = tempdir_scope(|dir| {
+            let options = BatchOptions::new().output_dir(dir).threads(2);
+            let inputs = vec!["tests/cube.obj", "tests/missing.obj", "tests/cube.obj"];
+            let conversions = convert_batch(inputs, OutputFormat::Obj, &options);
+            let converted = obj::load(dir.join("cube.obj"));
+            Ok((dir.to_path_buf(), conversions, converted))
+        })
+        .unwrap();
+
+        assert_eq!(3, conversions.len());
+        assert!(conversions[0].result.is_ok());
+        assert!(conversions[1].result.is_err());
+        assert_eq!(dir.join("missing.obj"), conversions[1].output);
+        match conversions[2].result {
+            Err(AssetError::Preflight { .. }) => (),
+            _ => panic!("Expected conflicting output"),
+        }
+        assert_eq!(1, converted.unwrap().entities.len());
+
+        let in_place = convert_batch(
+            vec!["tests/cube.obj"],
+            OutputFormat::Obj,
+            &BatchOptions::new(),
+        );
+        assert!(in_place[0].result.is_err());
+    }
+}
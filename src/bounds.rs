@@ -0,0 +1,108 @@
+//! Bounding volumes of meshes.
+
+/// An axis-aligned bounding box along with a bounding sphere around its
+/// center.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Center of the box and of the sphere.
+    pub center: [f32; 3],
+    /// Radius of the sphere, the largest distance of a position from the
+    /// center.
+    pub radius: f32,
+}
+
+impl Bounds {
+    /// Bounds of the given positions with three components each, or `None`
+    /// if there are no positions.
+    pub fn of(positions: &[f32]) -> Option<Bounds> {
+        if positions.len() < 3 {
+            return None;
+        }
+
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for position in positions.chunks(3).filter(|p| p.len() == 3) {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+        let center = [
+            (min[0] + max[0]) * 0.5,
+            (min[1] + max[1]) * 0.5,
+            (min[2] + max[2]) * 0.5,
+        ];
+
+        let radius_squared = positions
+            .chunks(3)
+            .filter(|p| p.len() == 3)
+            .map(|p| {
+                let offset = [p[0] - center[0], p[1] - center[1], p[2] - center[2]];
+                offset[0] * offset[0] + offset[1] * offset[1] + offset[2] * offset[2]
+            })
+            .fold(0.0f32, f32::max);
+
+        Some(Bounds {
+            min,
+            max,
+            center,
+            radius: radius_squared.sqrt(),
+        })
+    }
+
+    /// Bounds enclosing both these and the other bounds.
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        let mut min = self.min;
+        let mut max = self.max;
+        for axis in 0..3 {
+            min[axis] = min[axis].min(other.min[axis]);
+            max[axis] = max[axis].max(other.max[axis]);
+        }
+        let center = [
+            (min[0] + max[0]) * 0.5,
+            (min[1] + max[1]) * 0.5,
+            (min[2] + max[2]) * 0.5,
+        ];
+
+        // The spheres of both are within the new sphere
+        let reach = |bounds: &Bounds| {
+            let offset = [
+                bounds.center[0] - center[0],
+                bounds.center[1] - center[1],
+                bounds.center[2] - center[2],
+            ];
+            (offset[0] * offset[0] + offset[1] * offset[1] + offset[2] * offset[2]).sqrt()
+                + bounds.radius
+        };
+        Bounds {
+            min,
+            max,
+            center,
+            radius: reach(self).max(reach(other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bounds() {
+        assert_eq!(None, Bounds::of(&[]));
+
+        let bounds = Bounds::of(&[0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 1.0, 4.0, -2.0]).unwrap();
+        assert_eq!([0.0, 0.0, -2.0], bounds.min);
+        assert_eq!([2.0, 4.0, 0.0], bounds.max);
+        assert_eq!([1.0, 2.0, -1.0], bounds.center);
+        assert_eq!(6.0f32.sqrt(), bounds.radius);
+
+        let other = Bounds::of(&[10.0, 2.0, -1.0]).unwrap();
+        let union = bounds.union(&other);
+        assert_eq!([10.0, 4.0, 0.0], union.max);
+        assert_eq!([5.0, 2.0, -1.0], union.center);
+        assert_eq!(4.0 + 6.0f32.sqrt(), union.radius);
+    }
+}
@@ -0,0 +1,630 @@
+//! Single-file bundles of a scene with its materials and textures.
+//!
+//! A bundle is a ZIP archive containing an OBJ, its MTL, a copy of every
+//! texture referenced by the materials and a `manifest.json` describing the
+//! contents.
+//!
+//! For sequences of similar scenes, delta bundles only contain the files
+//! that changed relative to a base bundle.
+//!
+//! ```no_run
+//! # extern crate aitios_asset;
+//! use aitios_asset::{bundle, obj};
+//!
+//! # fn main() {
+//! let scene = obj::load("weathered.obj").unwrap();
+//! bundle::save(scene.entities.iter(), "weathered.aitb").unwrap();
+//!
+//! let scene = bundle::load("weathered.aitb").unwrap();
+//! # }
+//! ```
+
+use archive;
+use err::{AssetError, Result};
+use loaded::LoadedScene;
+use maps::resolve_maps;
+use obj::{self, LoadOptions};
+use scene::Entity;
+use serde_json;
+use std::borrow::Borrow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, create_dir_all, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use tempdir::TempDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Version of the bundle layout written by `save`.
+const BUNDLE_VERSION: u32 = 1;
+
+const MANIFEST_NAME: &str = "manifest.json";
+
+const DELTA_MANIFEST_NAME: &str = "delta.json";
+
+/// Describes the contents of a bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Name of the OBJ within the bundle.
+    pub obj: String,
+    /// Name of the MTL within the bundle.
+    pub mtl: String,
+    /// Names of the textures within the bundle.
+    pub textures: Vec<String>,
+    /// Names of the entities in the OBJ, in order.
+    pub entities: Vec<String>,
+}
+
+/// Saves the given entities as a bundle at the given path, with their
+/// materials and copies of all textures the materials reference.
+///
+/// Textures are stored in a `textures` directory in the bundle. If textures
+/// in different directories have the same file name but different contents,
+/// all but the first get a hash of their contents appended to the file stem,
+/// e.g. `rust-0123456789abcdef.png`. Textures with the same name and
+/// contents are stored once.
+pub fn save<I, E, P>(entities: I, path: P) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Borrow<Entity>,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    // The staged files are in the bundle or useless after an error
+    let staging = TempDir::new()?;
+    let manifest = stage(entities, staging.path())?;
+    write(staging.path(), &manifest, path)
+}
+
+/// Loads the scene in the bundle at the given path.
+///
+/// Like `archive::load`, the bundle is extracted into the temporary directory,
+/// which is where the texture maps of the loaded materials point to.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<LoadedScene> {
+    load_with(path, &LoadOptions::default())
+}
+
+/// Loads the scene in a bundle like `load`, but with the given options.
+pub fn load_with<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<LoadedScene> {
+    let manifest = manifest(&path)?;
+    archive::load_with(path, &manifest.obj, options)
+}
+
+/// Reads only the manifest of the bundle at the given path.
+pub fn manifest<P: AsRef<Path>>(path: P) -> Result<Manifest> {
+    let path = path.as_ref();
+    let json = read_entry(&mut open(path)?, MANIFEST_NAME, path)?;
+    let manifest: Manifest =
+        serde_json::from_slice(&json).map_err(|err| bundle_error(path, err.to_string()))?;
+    if manifest.version > BUNDLE_VERSION {
+        return Err(bundle_error(
+            path,
+            format!("Bundle version {} is not supported", manifest.version),
+        ));
+    }
+    check_names(&manifest, path)?;
+    Ok(manifest)
+}
+
+/// Saves the given entities like `save`, but only stores the files that
+/// differ from the bundle at `base`, along with a manifest describing how to
+/// combine them with the base.
+///
+/// Files are compared by content, so textures that were renamed but not
+/// changed are still taken from the base. The base must be a complete bundle,
+/// e.g. the first iteration of a weathering sequence.
+pub fn save_delta<I, E, P, Q>(entities: I, base: P, path: Q) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Borrow<Entity>,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let staging = TempDir::new()?;
+    let manifest = stage(entities, staging.path())?;
+    write_delta(staging.path(), manifest, base.as_ref(), path.as_ref())
+}
+
+/// Loads the scene that results from applying the delta bundle at `delta`
+/// to the bundle at `base`.
+pub fn apply_delta<P: AsRef<Path>, Q: AsRef<Path>>(base: P, delta: Q) -> Result<LoadedScene> {
+    apply_delta_with(base, delta, &LoadOptions::default())
+}
+
+/// Loads the scene of a delta bundle like `apply_delta`, but with the given
+/// options.
+pub fn apply_delta_with<P: AsRef<Path>, Q: AsRef<Path>>(
+    base: P,
+    delta: Q,
+    options: &LoadOptions,
+) -> Result<LoadedScene> {
+    // Kept after loading, since the texture maps point into it
+    let dir = TempDir::new()?;
+    let manifest = unpack_delta(base.as_ref(), delta.as_ref(), dir.path())?;
+    obj::load_with(dir.into_path().join(&manifest.obj), options)
+}
+
+/// Applies the delta bundle at `delta` to the bundle at `base` and saves the
+/// result as a complete bundle at `path`.
+pub fn merge_delta<P, Q, R>(base: P, delta: Q, path: R) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    R: AsRef<Path>,
+{
+    let staging = TempDir::new()?;
+    let manifest = unpack_delta(base.as_ref(), delta.as_ref(), staging.path())?;
+    write(staging.path(), &manifest, path.as_ref())
+}
+
+/// Describes the contents of a delta bundle written by `save_delta`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeltaManifest {
+    pub version: u32,
+    /// Manifest of the bundle the delta applies to.
+    pub base: Manifest,
+    /// Manifest of the bundle that results from applying the delta.
+    pub result: Manifest,
+    /// Files of the result that are taken from the base, by their name in
+    /// the result, with their name in the base. All other files of the
+    /// result are stored in the delta.
+    pub unchanged: BTreeMap<String, String>,
+}
+
+/// Copies the files of the given bundle into the staging directory, with
+/// texture maps pointing to the copies, and returns its manifest.
+fn stage<I, E>(entities: I, staging: &Path) -> Result<Manifest>
+where
+    I: IntoIterator<Item = E>,
+    E: Borrow<Entity>,
+{
+    let mut entities: Vec<Entity> = entities.into_iter().map(|e| e.borrow().clone()).collect();
+    let texture_dir = staging.join("textures");
+    create_dir_all(&texture_dir).map_err(AssetError::Save)?;
+
+    // Copy each texture once and point the maps to the copies
+    let mut copied: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut copy_error = None;
+    resolve_maps(entities.iter_mut().map(|e| &mut e.material), |texture| {
+        if let Some(existing) = copied.iter().find(|c| c.0 == texture) {
+            return existing.1.clone();
+        }
+
+        let copy = match copy_name(texture, &texture_dir, &copied) {
+            Ok((copy, true)) => fs::copy(texture, &copy).map(|_| copy),
+            Ok((copy, false)) => Ok(copy),
+            Err(err) => Err(err),
+        };
+        let copy = copy.unwrap_or_else(|err| {
+            copy_error.get_or_insert(err);
+            texture_dir.join(texture.file_name().unwrap_or_default())
+        });
+        copied.push((texture.to_path_buf(), copy.clone()));
+        copy
+    });
+    if let Some(err) = copy_error {
+        return Err(AssetError::Read(err));
+    }
+
+    let manifest = Manifest {
+        version: BUNDLE_VERSION,
+        obj: "scene.obj".to_string(),
+        mtl: "scene.mtl".to_string(),
+        textures: copied.iter().fold(Vec::new(), |mut textures, c| {
+            let name = format!("textures/{}", c.1.file_name().unwrap().to_string_lossy());
+            if !textures.contains(&name) {
+                textures.push(name);
+            }
+            textures
+        }),
+        entities: entities.iter().map(|e| e.name.clone()).collect(),
+    };
+    obj::save(
+        entities.iter(),
+        Some(staging.join(&manifest.obj)),
+        Some(staging.join(&manifest.mtl)),
+    )?;
+
+    Ok(manifest)
+}
+
+/// Chooses the path of the copy of the given texture in the texture
+/// directory, given the textures copied so far, and whether it still has to
+/// be copied there.
+///
+/// The file name is kept, unless a texture with different contents has
+/// already been copied with the same name. Then the hash of the contents is
+/// appended to the stem.
+fn copy_name(
+    texture: &Path,
+    texture_dir: &Path,
+    copied: &[(PathBuf, PathBuf)],
+) -> io::Result<(PathBuf, bool)> {
+    let file_name = Path::new(texture.file_name().unwrap_or_default());
+    let copy = texture_dir.join(file_name);
+    if !copied.iter().any(|c| c.1 == copy) {
+        return Ok((copy, true));
+    }
+
+    let contents = fs::read(texture)?;
+    if fs::read(&copy)? == contents {
+        return Ok((copy, false));
+    }
+
+    let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+    let hashed = match file_name.extension() {
+        Some(extension) => format!(
+            "{}-{:016x}.{}",
+            stem,
+            hash(&contents),
+            extension.to_string_lossy()
+        ),
+        None => format!("{}-{:016x}", stem, hash(&contents)),
+    };
+    let copy = texture_dir.join(hashed);
+    let exists = copied.iter().any(|c| c.1 == copy);
+    Ok((copy, !exists))
+}
+
+/// Writes the staged files of a bundle with the given manifest to a ZIP
+/// archive at the given path.
+fn write(staging: &Path, manifest: &Manifest, path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(manifest)
+        .map_err(|err| bundle_error(path, err.to_string()))?;
+    write_archive(staging, MANIFEST_NAME, &json, &files(manifest), path)
+}
+
+/// Writes the staged files that differ from the base bundle to a delta
+/// bundle at the given path.
+fn write_delta(staging: &Path, result: Manifest, base_path: &Path, path: &Path) -> Result<()> {
+    let base = manifest(base_path)?;
+    let mut base_archive = open(base_path)?;
+
+    // Hash the base files first, to only compare contents on a match
+    let mut base_hashes: HashMap<u64, Vec<&String>> = HashMap::new();
+    for name in files(&base) {
+        let contents = read_entry(&mut base_archive, name, base_path)?;
+        base_hashes.entry(hash(&contents)).or_default().push(name);
+    }
+
+    let mut unchanged = BTreeMap::new();
+    let mut changed = Vec::new();
+    for name in files(&result) {
+        let contents = fs::read(staging.join(name)).map_err(AssetError::Read)?;
+        let mut same = None;
+        for &candidate in base_hashes.get(&hash(&contents)).into_iter().flatten() {
+            if read_entry(&mut base_archive, candidate, base_path)? == contents {
+                same = Some(candidate.clone());
+                break;
+            }
+        }
+        match same {
+            Some(base_name) => {
+                unchanged.insert(name.clone(), base_name);
+            }
+            None => changed.push(name),
+        }
+    }
+
+    let delta = DeltaManifest {
+        version: BUNDLE_VERSION,
+        base: base.clone(),
+        result: result.clone(),
+        unchanged,
+    };
+    let json =
+        serde_json::to_string_pretty(&delta).map_err(|err| bundle_error(path, err.to_string()))?;
+    write_archive(staging, DELTA_MANIFEST_NAME, &json, &changed, path)
+}
+
+/// Extracts the files that result from applying the delta bundle at `delta`
+/// to the bundle at `base` into the given directory.
+fn unpack_delta(base_path: &Path, delta_path: &Path, dir: &Path) -> Result<Manifest> {
+    let mut delta_archive = open(delta_path)?;
+    let json = read_entry(&mut delta_archive, DELTA_MANIFEST_NAME, delta_path)?;
+    let delta: DeltaManifest =
+        serde_json::from_slice(&json).map_err(|err| bundle_error(delta_path, err.to_string()))?;
+    if delta.version > BUNDLE_VERSION {
+        return Err(bundle_error(
+            delta_path,
+            format!("Bundle version {} is not supported", delta.version),
+        ));
+    }
+    // Result files are written into the directory under these names
+    check_names(&delta.result, delta_path)?;
+    if manifest(base_path)? != delta.base {
+        return Err(bundle_error(
+            delta_path,
+            format!("Delta does not apply to base bundle {:?}", base_path),
+        ));
+    }
+
+    let mut base_archive = open(base_path)?;
+    for name in files(&delta.result) {
+        let contents = match delta.unchanged.get(name) {
+            Some(base_name) => read_entry(&mut base_archive, base_name, base_path)?,
+            None => read_entry(&mut delta_archive, name, delta_path)?,
+        };
+        let file = dir.join(name);
+        if let Some(parent) = file.parent() {
+            create_dir_all(parent).map_err(AssetError::Read)?;
+        }
+        fs::write(file, contents).map_err(AssetError::Read)?;
+    }
+
+    Ok(delta.result)
+}
+
+/// Names of the OBJ, MTL and textures of a bundle.
+fn files(manifest: &Manifest) -> Vec<&String> {
+    let mut files = vec![&manifest.obj, &manifest.mtl];
+    files.extend(manifest.textures.iter());
+    files
+}
+
+/// Fails unless the names of all files in the given manifest are relative
+/// paths that stay within the bundle, like `textures/rust.png`, so that
+/// extracting them cannot write anywhere else.
+fn check_names(manifest: &Manifest, path: &Path) -> Result<()> {
+    for name in files(manifest) {
+        let enclosed = !name.is_empty()
+            && Path::new(name)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !enclosed {
+            return Err(bundle_error(
+                path,
+                format!("File name \"{}\" points outside of the bundle", name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Writes a ZIP archive with the given manifest and the given staged files.
+fn write_archive(
+    staging: &Path,
+    manifest_name: &str,
+    manifest_json: &str,
+    names: &[&String],
+    path: &Path,
+) -> Result<()> {
+    let mut zip = ZipWriter::new(File::create(path).map_err(AssetError::Save)?);
+    let text = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    // Images are usually compressed already
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    zip.start_file(manifest_name, text)
+        .map_err(|err| bundle_error(path, err.to_string()))?;
+    zip.write_all(manifest_json.as_bytes())
+        .map_err(AssetError::Save)?;
+
+    for name in names {
+        let options = if name.starts_with("textures/") {
+            stored
+        } else {
+            text
+        };
+        let contents = fs::read(staging.join(name)).map_err(AssetError::Read)?;
+        zip.start_file(name.as_str(), options)
+            .map_err(|err| bundle_error(path, err.to_string()))?;
+        zip.write_all(&contents).map_err(AssetError::Save)?;
+    }
+
+    zip.finish()
+        .map_err(|err| bundle_error(path, err.to_string()))?;
+    Ok(())
+}
+
+fn open(path: &Path) -> Result<ZipArchive<File>> {
+    let file = File::open(path).map_err(AssetError::Read)?;
+    ZipArchive::new(file).map_err(|err| bundle_error(path, err.to_string()))
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str, path: &Path) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    archive
+        .by_name(name)
+        .map_err(|_| bundle_error(path, format!("No {} in bundle", name)))?
+        .read_to_end(&mut contents)
+        .map_err(AssetError::Read)?;
+    Ok(contents)
+}
+
+fn hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn bundle_error<S: Into<String>>(path: &Path, reason: S) -> AssetError {
+    AssetError::Archive {
+        path: path.to_path_buf(),
+        reason: reason.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use scene::MaterialBuilder;
+    use std::rc::Rc;
+    use tempdir::tempdir_scope;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut cube = obj::load("tests/cube.obj").unwrap().entities.remove(0);
+        cube.material = Rc::new(
+            MaterialBuilder::from(&*cube.material)
+                .diffuse_color_map("tests/cube.mtl")
+                .build(),
+        );
+
+        let (manifest, loaded) = tempdir_scope(|dir| {
+            let path = dir.join("cube_roundtrip.aitb");
+            save(vec![&cube], &path)?;
+            Ok((manifest(&path), load(&path)))
+        })
+        .unwrap();
+
+        let manifest = manifest.unwrap();
+        assert_eq!(vec!["textures/cube.mtl".to_string()], manifest.textures);
+        assert_eq!(vec![cube.name.clone()], manifest.entities);
+
+        let loaded = loaded.unwrap();
+        assert_eq!(
+            cube.mesh.positions.len(),
+            loaded.entities[0].mesh.positions.len()
+        );
+        let maps = loaded.entities[0].material.maps();
+        assert_eq!(1, maps.len());
+        assert_eq!(
+            fs::read("tests/cube.mtl").unwrap(),
+            fs::read(&maps[0].1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_texture_name_collision() {
+        let base_cube = obj::load("tests/cube.obj").unwrap().entities.remove(0);
+        let (manifest, loaded) = tempdir_scope(|dir| {
+            let mut cubes = Vec::new();
+            for (subdir, contents) in ["a", "b", "c"]
+                .iter()
+                .zip(["first", "second", "first"].iter())
+            {
+                let subdir = dir.join(subdir);
+                create_dir_all(&subdir)?;
+                let map = subdir.join("diffuse.png");
+                fs::write(&map, contents)?;
+                let mut cube = base_cube.clone();
+                cube.material = Rc::new(
+                    MaterialBuilder::from(&*cube.material)
+                        .diffuse_color_map(map)
+                        .build(),
+                );
+                cubes.push(cube);
+            }
+
+            let path = dir.join("collision.aitb");
+            save(cubes.iter(), &path)?;
+            Ok((manifest(&path), load(&path)))
+        })
+        .unwrap();
+
+        let renamed = format!("textures/diffuse-{:016x}.png", hash(b"second"));
+        assert_eq!(
+            vec!["textures/diffuse.png".to_string(), renamed],
+            manifest.unwrap().textures
+        );
+        let loaded = loaded.unwrap();
+        let contents: Vec<Vec<u8>> = loaded
+            .entities
+            .iter()
+            .map(|e| fs::read(&e.material.maps()[0].1).unwrap())
+            .collect();
+        assert_eq!(
+            vec![b"first".to_vec(), b"second".to_vec(), b"first".to_vec()],
+            contents
+        );
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let base_cube = obj::load("tests/cube.obj").unwrap().entities.remove(0);
+        let with_map = |map: &str| {
+            let mut cube = base_cube.clone();
+            cube.material = Rc::new(
+                MaterialBuilder::from(&*cube.material)
+                    .diffuse_color_map(map)
+                    .build(),
+            );
+            cube
+        };
+        let (first, second) = (with_map("tests/cube.mtl"), with_map("tests/cube.obj"));
+
+        let (applied, merged_manifest, delta_manifest, wrong_base) = tempdir_scope(|dir| {
+            let base = dir.join("cube_delta_base.aitb");
+            let delta = dir.join("cube_delta.aitb");
+            let merged = dir.join("cube_delta_merged.aitb");
+            save(vec![&first], &base)?;
+            save_delta(vec![&second], &base, &delta)?;
+            let applied = apply_delta(&base, &delta);
+            let merged_manifest =
+                merge_delta(&base, &delta, &merged).and_then(|_| manifest(&merged));
+            let delta_manifest = open(&delta)
+                .and_then(|mut archive| read_entry(&mut archive, DELTA_MANIFEST_NAME, &delta));
+            let wrong_base = apply_delta(&delta, &delta);
+            Ok((applied, merged_manifest, delta_manifest, wrong_base))
+        })
+        .unwrap();
+
+        // Only the texture and the MTL referencing it changed
+        let delta_manifest: DeltaManifest =
+            serde_json::from_slice(&delta_manifest.unwrap()).unwrap();
+        let unchanged: Vec<&String> = delta_manifest.unchanged.keys().collect();
+        assert_eq!(vec!["scene.obj"], unchanged);
+        assert_eq!(delta_manifest.result, merged_manifest.unwrap());
+
+        let applied = applied.unwrap();
+        let maps = applied.entities[0].material.maps();
+        assert_eq!(
+            fs::read("tests/cube.obj").unwrap(),
+            fs::read(&maps[0].1).unwrap()
+        );
+        assert!(wrong_base.is_err());
+    }
+
+    #[test]
+    fn test_names_outside_bundle_rejected() {
+        let cube = obj::load("tests/cube.obj").unwrap().entities.remove(0);
+        tempdir_scope(|dir| {
+            let base = dir.join("base.aitb");
+            save(vec![&cube], &base)?;
+            let base_manifest = manifest(&base)?;
+
+            let mut result = base_manifest.clone();
+            result.obj = "../../escaped.obj".to_string();
+            let delta = DeltaManifest {
+                version: BUNDLE_VERSION,
+                base: base_manifest,
+                result,
+                unchanged: BTreeMap::new(),
+            };
+            let delta_path = dir.join("delta.aitb");
+            let mut zip = ZipWriter::new(File::create(&delta_path)?);
+            zip.start_file(DELTA_MANIFEST_NAME, FileOptions::default())
+                .unwrap();
+            zip.write_all(serde_json::to_string(&delta).unwrap().as_bytes())?;
+            zip.start_file("../../escaped.obj", FileOptions::default())
+                .unwrap();
+            zip.write_all(b"o Escaped\n")?;
+            zip.finish().unwrap();
+
+            match apply_delta(&base, &delta_path) {
+                Err(AssetError::Archive { .. }) => (),
+                _ => panic!("Expected archive error"),
+            }
+            let merged = dir.join("merged.aitb");
+            assert!(merge_delta(&base, &delta_path, &merged).is_err());
+
+            let mut absolute = delta.result.clone();
+            absolute.obj = "/etc/escaped.obj".to_string();
+            let absolute_path = dir.join("absolute.aitb");
+            let mut zip = ZipWriter::new(File::create(&absolute_path)?);
+            zip.start_file(MANIFEST_NAME, FileOptions::default())
+                .unwrap();
+            zip.write_all(serde_json::to_string(&absolute).unwrap().as_bytes())?;
+            zip.finish().unwrap();
+            match load(&absolute_path) {
+                Err(AssetError::Archive { .. }) => (),
+                _ => panic!("Expected archive error"),
+            }
+            Ok(())
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+//! Collision geometry, as declared by entity names like `UCX_chair_01` or
+//! `chair_collision`.
+
+/// Prefixes of collision meshes in the naming convention of Unreal, for
+/// convex hulls, boxes, capsules and spheres.
+const UNREAL_PREFIXES: [&str; 4] = ["UCX_", "UBX_", "UCP_", "USP_"];
+
+const SUFFIX: &str = "_collision";
+
+/// Gets the name of the entity that the named entity is collision geometry
+/// for, if the name is `UCX_` or another Unreal prefix followed by the render
+/// entity name and an optional number, or the render entity name followed
+/// by `_collision`.
+pub fn render_name(name: &str) -> Option<String> {
+    if let Some(rest) = UNREAL_PREFIXES
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+    {
+        let digits = rest.len() - rest.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        let render = if digits > 0 {
+            rest[..rest.len() - digits]
+                .strip_suffix('_')
+                .unwrap_or(rest)
+        } else {
+            rest
+        };
+        return Some(render.to_string()).filter(|render| !render.is_empty());
+    }
+
+    let split = name.len().checked_sub(SUFFIX.len())?;
+    if !name.is_char_boundary(split) || !name[split..].eq_ignore_ascii_case(SUFFIX) {
+        return None;
+    }
+    Some(name[..split].to_string()).filter(|render| !render.is_empty())
+}
+
+/// Name of the given collision entity in the naming convention of Unreal,
+/// keeping the Unreal prefix if it already had one, or else naming it a
+/// convex hull.
+pub fn unreal_name(name: &str, render: &str, number: usize) -> String {
+    let prefix = UNREAL_PREFIXES
+        .iter()
+        .find(|prefix| name.starts_with(*prefix))
+        .unwrap_or(&UNREAL_PREFIXES[0]);
+    format!("{}{}_{:02}", prefix, render, number)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_name() {
+        let chair = Some("chair".to_string());
+        assert_eq!(chair, render_name("UCX_chair_01"));
+        assert_eq!(chair, render_name("UBX_chair"));
+        assert_eq!(chair, render_name("chair_Collision"));
+        assert_eq!(Some("chair_2".to_string()), render_name("USP_chair_2_00"));
+        assert_eq!(None, render_name("UCX_"));
+        assert_eq!(None, render_name("chair"));
+        assert_eq!(None, render_name("_collision"));
+
+        assert_eq!(
+            "UCX_chair_LOD0_01",
+            unreal_name("chair_collision", "chair_LOD0", 1)
+        );
+        assert_eq!("UBX_chair_02", unreal_name("UBX_chair_00", "chair", 2));
+    }
+}
@@ -0,0 +1,367 @@
+//! Conversion of meshes between coordinate systems and units, and other
+//! transformations of whole meshes.
+
+use obj::CoordinateSystem;
+use scene::DeinterleavedIndexedMeshBuf;
+
+/// Converts a mesh authored in the given coordinate system into the Y-up,
+/// right-handed coordinate system of loaded entities.
+///
+/// Face sizes of preserved polygons keep matching the triangles, if given.
+pub fn to_scene(
+    mesh: &mut DeinterleavedIndexedMeshBuf,
+    face_sizes: Option<&Vec<u32>>,
+    system: CoordinateSystem,
+) {
+    transform(mesh, face_sizes, basis(system));
+}
+
+/// Converts a mesh of a loaded entity into the given coordinate system, like
+/// `to_scene` in reverse.
+pub fn from_scene(
+    mesh: &mut DeinterleavedIndexedMeshBuf,
+    face_sizes: Option<&Vec<u32>>,
+    system: CoordinateSystem,
+) {
+    let basis = basis(system);
+    let mut inverse = [[0.0; 3]; 3];
+    for (row, inverse_row) in inverse.iter_mut().enumerate() {
+        for (col, value) in inverse_row.iter_mut().enumerate() {
+            // The basis only swaps and negates axes, so it is orthogonal
+            *value = basis[col][row];
+        }
+    }
+    transform(mesh, face_sizes, inverse);
+}
+
+/// Converts a single vector from the given coordinate system into the
+/// system of loaded entities in double precision, like `to_scene`.
+pub fn vector_to_scene(vector: [f64; 3], system: CoordinateSystem) -> [f64; 3] {
+    let basis = basis(system);
+    let mut converted = [0.0; 3];
+    for (component, row) in converted.iter_mut().zip(basis.iter()) {
+        *component = (0..3).map(|col| f64::from(row[col]) * vector[col]).sum();
+    }
+    converted
+}
+
+/// Converts a single vector from the system of loaded entities into the
+/// given coordinate system in double precision, like `from_scene`.
+pub fn vector_from_scene(vector: [f64; 3], system: CoordinateSystem) -> [f64; 3] {
+    let basis = basis(system);
+    let mut converted = [0.0; 3];
+    for (row, component) in converted.iter_mut().enumerate() {
+        *component = (0..3)
+            .map(|col| f64::from(basis[col][row]) * vector[col])
+            .sum();
+    }
+    converted
+}
+
+/// Converts positions with three components each in double precision from
+/// the given coordinate system into the system of loaded entities.
+pub fn precise_to_scene(positions: &mut [f64], system: CoordinateSystem) {
+    for position in positions.chunks_mut(3).filter(|p| p.len() == 3) {
+        let converted = vector_to_scene([position[0], position[1], position[2]], system);
+        position.copy_from_slice(&converted);
+    }
+}
+
+/// Converts positions with three components each in double precision from
+/// the system of loaded entities into the given coordinate system.
+pub fn precise_from_scene(positions: &mut [f64], system: CoordinateSystem) {
+    for position in positions.chunks_mut(3).filter(|p| p.len() == 3) {
+        let converted = vector_from_scene([position[0], position[1], position[2]], system);
+        position.copy_from_slice(&converted);
+    }
+}
+
+/// Transforms positions with three components each in double precision
+/// with the given column-major matrix, like `apply_matrix`.
+pub fn precise_apply_matrix(positions: &mut [f64], matrix: [[f32; 4]; 4]) {
+    for position in positions.chunks_mut(3).filter(|p| p.len() == 3) {
+        let original = [position[0], position[1], position[2]];
+        for (row, component) in position.iter_mut().enumerate() {
+            *component = (0..3)
+                .map(|col| f64::from(matrix[col][row]) * original[col])
+                .sum::<f64>()
+                + f64::from(matrix[3][row]);
+        }
+    }
+}
+
+/// Multiplies all positions of the mesh with the given factor.
+pub fn scale(mesh: &mut DeinterleavedIndexedMeshBuf, factor: f32) {
+    for coordinate in mesh.positions.iter_mut() {
+        *coordinate *= factor;
+    }
+}
+
+/// Replaces the V texture coordinate of each vertex with one minus V, for
+/// tools with the texture origin in the top left rather than bottom left.
+pub fn flip_v(mesh: &mut DeinterleavedIndexedMeshBuf) {
+    for texcoord in mesh.texcoords.chunks_mut(2) {
+        if let Some(v) = texcoord.get_mut(1) {
+            *v = 1.0 - *v;
+        }
+    }
+}
+
+/// Transforms positions of the mesh with the given column-major matrix and
+/// normals with its inverse transpose, keeping them of unit length.
+///
+/// Faces are reversed for matrices that mirror, like in `to_scene`.
+pub fn apply_matrix(
+    mesh: &mut DeinterleavedIndexedMeshBuf,
+    face_sizes: Option<&Vec<u32>>,
+    matrix: [[f32; 4]; 4],
+) {
+    let mut linear = [[0.0; 3]; 3];
+    for (row, linear_row) in linear.iter_mut().enumerate() {
+        for (col, value) in linear_row.iter_mut().enumerate() {
+            *value = matrix[col][row];
+        }
+    }
+    let det = determinant(linear);
+
+    for position in mesh.positions.chunks_mut(3) {
+        if position.len() < 3 {
+            continue;
+        }
+        let original = [position[0], position[1], position[2]];
+        for (row, component) in position.iter_mut().enumerate() {
+            *component = matrix[0][row] * original[0]
+                + matrix[1][row] * original[1]
+                + matrix[2][row] * original[2]
+                + matrix[3][row];
+        }
+    }
+
+    // The cofactor matrix is the inverse transpose scaled by the determinant,
+    // but also exists for singular matrices
+    let cofactor = |row: usize, col: usize| {
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        let (c0, c1) = ((col + 1) % 3, (col + 2) % 3);
+        linear[r0][c0] * linear[r1][c1] - linear[r0][c1] * linear[r1][c0]
+    };
+    let sign = if det < 0.0 { -1.0 } else { 1.0 };
+    for normal in mesh.normals.chunks_mut(3) {
+        if normal.len() < 3 {
+            continue;
+        }
+        let original = [normal[0], normal[1], normal[2]];
+        let mut transformed = [0.0; 3];
+        for (row, component) in transformed.iter_mut().enumerate() {
+            *component = sign
+                * (cofactor(row, 0) * original[0]
+                    + cofactor(row, 1) * original[1]
+                    + cofactor(row, 2) * original[2]);
+        }
+        let length = transformed.iter().map(|c| c * c).sum::<f32>().sqrt();
+        if length > 0.0 {
+            for (component, transformed) in normal.iter_mut().zip(transformed.iter()) {
+                *component = transformed / length;
+            }
+        }
+    }
+
+    if det < 0.0 {
+        reverse_winding(&mut mesh.indices, face_sizes);
+    }
+}
+
+/// Column-major matrix that scales, then rotates by the given quaternion in
+/// `[x, y, z, w]` order, then translates.
+pub fn trs_matrix(translation: [f32; 3], rotation: [f32; 4], scale: [f32; 3]) -> [[f32; 4]; 4] {
+    let length = rotation.iter().map(|c| c * c).sum::<f32>().sqrt();
+    let [x, y, z, w] = if length > 0.0 {
+        [
+            rotation[0] / length,
+            rotation[1] / length,
+            rotation[2] / length,
+            rotation[3] / length,
+        ]
+    } else {
+        [0.0, 0.0, 0.0, 1.0]
+    };
+    let rotation = [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y + z * w),
+            2.0 * (x * z - y * w),
+        ],
+        [
+            2.0 * (x * y - z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z + x * w),
+        ],
+        [
+            2.0 * (x * z + y * w),
+            2.0 * (y * z - x * w),
+            1.0 - 2.0 * (x * x + y * y),
+        ],
+    ];
+
+    let mut matrix = [[0.0; 4]; 4];
+    for col in 0..3 {
+        for row in 0..3 {
+            matrix[col][row] = rotation[col][row] * scale[col];
+        }
+        matrix[3][col] = translation[col];
+    }
+    matrix[3][3] = 1.0;
+    matrix
+}
+
+/// Matrix taking coordinates in the given system to Y-up, right-handed
+/// coordinates, by row.
+fn basis(system: CoordinateSystem) -> [[f32; 3]; 3] {
+    match system {
+        CoordinateSystem::YUpRightHanded => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        CoordinateSystem::YUpLeftHanded => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]],
+        CoordinateSystem::ZUpRightHanded => [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]],
+        CoordinateSystem::ZUpLeftHanded => [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]],
+    }
+}
+
+fn transform(
+    mesh: &mut DeinterleavedIndexedMeshBuf,
+    face_sizes: Option<&Vec<u32>>,
+    matrix: [[f32; 3]; 3],
+) {
+    for vector in mesh
+        .positions
+        .chunks_mut(3)
+        .chain(mesh.normals.chunks_mut(3))
+    {
+        if vector.len() < 3 {
+            continue;
+        }
+        let original = [vector[0], vector[1], vector[2]];
+        for (component, row) in vector.iter_mut().zip(matrix.iter()) {
+            *component = row[0] * original[0] + row[1] * original[1] + row[2] * original[2];
+        }
+    }
+
+    if determinant(matrix) < 0.0 {
+        reverse_winding(&mut mesh.indices, face_sizes);
+    }
+}
+
+fn determinant(m: [[f32; 3]; 3]) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// Reverses the order of the vertices of each face, so faces still point
+/// outwards after mirroring. Polygons stay fans around their first vertex.
+pub fn reverse_winding(indices: &mut [u32], face_sizes: Option<&Vec<u32>>) {
+    let triangle_count = indices.len() / 3;
+    let face_sizes = face_sizes.filter(|sizes| {
+        sizes.iter().all(|&s| s >= 3)
+            && sizes.iter().map(|&s| s as usize - 2).sum::<usize>() == triangle_count
+    });
+
+    let mut start = 0;
+    let mut reverse_fan = |fan_triangles: usize| {
+        let fan = &mut indices[start * 3..(start + fan_triangles) * 3];
+        let mut triangles: Vec<[u32; 3]> = fan.chunks(3).map(|t| [t[0], t[2], t[1]]).collect();
+        triangles.reverse();
+        for (slot, triangle) in fan.chunks_mut(3).zip(triangles) {
+            slot.copy_from_slice(&triangle);
+        }
+        start += fan_triangles;
+    };
+
+    match face_sizes {
+        Some(face_sizes) => {
+            for &face_size in face_sizes {
+                reverse_fan(face_size as usize - 2);
+            }
+        }
+        None => {
+            for _ in 0..triangle_count {
+                reverse_fan(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_and_winding() {
+        // A quad as a fan of two triangles
+        let original = DeinterleavedIndexedMeshBuf {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+            normals: [0.0, 0.0, 1.0].repeat(4),
+            texcoords: vec![0.0; 8],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        };
+        let quad = Some(vec![4]);
+
+        let mut blender = original.clone();
+        to_scene(
+            &mut blender,
+            quad.as_ref(),
+            CoordinateSystem::ZUpRightHanded,
+        );
+        assert_eq!(&[0.0, 1.0, 0.0], &blender.normals[0..3]);
+        assert_eq!(original.indices, blender.indices);
+
+        let mut mirrored = original.clone();
+        to_scene(
+            &mut mirrored,
+            quad.as_ref(),
+            CoordinateSystem::ZUpLeftHanded,
+        );
+        assert_eq!(vec![0, 3, 2, 0, 2, 1], mirrored.indices);
+
+        for &system in [
+            CoordinateSystem::YUpLeftHanded,
+            CoordinateSystem::ZUpRightHanded,
+            CoordinateSystem::ZUpLeftHanded,
+        ]
+        .iter()
+        {
+            let mut mesh = original.clone();
+            to_scene(&mut mesh, None, system);
+            from_scene(&mut mesh, None, system);
+            assert_eq!(original, mesh);
+        }
+    }
+
+    #[test]
+    fn test_apply_matrix() {
+        // A triangle in the plane z = x, facing away from the x axis
+        let mut mesh = DeinterleavedIndexedMeshBuf {
+            positions: vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0],
+            normals: [-0.5f32.sqrt(), 0.0, 0.5f32.sqrt()].repeat(3),
+            texcoords: vec![0.0; 6],
+            indices: vec![0, 1, 2],
+        };
+
+        // Stretching along x makes the plane steeper, the normal must stay
+        // perpendicular to it
+        let matrix = trs_matrix([1.0, 2.0, 3.0], [0.0, 0.0, 0.0, 1.0], [2.0, 1.0, 1.0]);
+        apply_matrix(&mut mesh, None, matrix);
+        assert_eq!(&[1.0, 2.0, 3.0], &mesh.positions[0..3]);
+        assert_eq!(&[3.0, 2.0, 4.0], &mesh.positions[6..9]);
+        let expected = [-1.0 / 5.0f32.sqrt(), 0.0, 2.0 / 5.0f32.sqrt()];
+        for (normal, expected) in mesh.normals[0..3].iter().zip(expected.iter()) {
+            assert!((normal - expected).abs() < 1e-6);
+        }
+        assert_eq!(vec![0, 1, 2], mesh.indices);
+
+        // Half a turn around y, then mirrored along x
+        let turned = trs_matrix([0.0; 3], [0.0, 1.0, 0.0, 0.0], [1.0; 3]);
+        assert!((turned[0][0] + 1.0).abs() < 1e-6 && (turned[2][2] + 1.0).abs() < 1e-6);
+        let mirror = trs_matrix([0.0; 3], [0.0, 0.0, 0.0, 1.0], [-1.0, 1.0, 1.0]);
+        apply_matrix(&mut mesh, None, mirror);
+        assert_eq!(vec![0, 2, 1], mesh.indices);
+        assert!(mesh.normals[0] > 0.0 && mesh.normals[2] > 0.0);
+    }
+}
@@ -1,25 +1,39 @@
 use std::io;
+use std::path::PathBuf;
 use std::result;
-use tobj;
 
 pub type Result<T> = result::Result<T, AssetError>;
 
 #[derive(Debug, Fail)]
 pub enum AssetError {
-    #[fail(display = "Asset import encountered error")]
-    Load(#[cause] tobj::LoadError),
+    #[fail(display = "{:?}:{}:{}: {}: \"{}\"", path, line, column, message, token)]
+    Parse {
+        path: PathBuf,
+        /// Line number, starting at one.
+        line: usize,
+        /// Column of the first character of `token` in the line, starting at one.
+        column: usize,
+        /// The offending part of the line, empty if something was missing
+        /// at the end of the line.
+        token: String,
+        message: String,
+    },
+    #[fail(display = "Asset import encountered IO error")]
+    Read(#[cause] io::Error),
     #[fail(display = "Asset export encountered IO error")]
     Save(#[cause] io::Error),
+    #[fail(display = "Export to {:?} not possible: {}", path, reason)]
+    Preflight { path: PathBuf, reason: String },
+    #[fail(display = "Profile {:?} could not be used: {}", path, reason)]
+    Profile { path: PathBuf, reason: String },
+    #[fail(display = "Archive {:?} could not be read: {}", path, reason)]
+    Archive { path: PathBuf, reason: String },
+    #[fail(display = "{} could not be downloaded: {}", url, reason)]
+    Download { url: String, reason: String },
     #[fail(display = "Invalid data during asset import/export: ")]
     InvalidData(String),
 }
 
-impl From<tobj::LoadError> for AssetError {
-    fn from(err: tobj::LoadError) -> AssetError {
-        AssetError::Load(err)
-    }
-}
-
 impl From<io::Error> for AssetError {
     fn from(err: io::Error) -> AssetError {
         AssetError::Save(err)
@@ -0,0 +1,208 @@
+//! Conveniences for collections of entities.
+
+use err::Result;
+use obj;
+use scene::{DeinterleavedIndexedMeshBuf, Entity, Material};
+use std::path::Path;
+use std::rc::Rc;
+
+/// Adds common operations of asset pipelines to slices of entities, e.g.:
+///
+/// ```
+/// # extern crate aitios_asset;
+/// use aitios_asset::{obj, EntitiesExt};
+///
+/// # fn main() {
+/// let entities = obj::load_entities("tests/cube.obj").unwrap();
+/// let merged = entities.dedup_materials().merge_by_material();
+///
+/// let (min, max) = merged.bounds().unwrap();
+/// assert_eq!([-1.0, -1.0, -1.0], min);
+/// assert!(max.iter().all(|&c| c > 0.99));
+/// # }
+/// ```
+pub trait EntitiesExt {
+    /// Saves the entities to the OBJ at the given path, along with an MTL
+    /// with the same stem next to it.
+    fn save_obj<P: AsRef<Path>>(&self, obj_path: P) -> Result<()>;
+
+    /// Gets the minimum and maximum of all positions of all entities, or
+    /// `None` if there are no positions.
+    fn bounds(&self) -> Option<([f32; 3], [f32; 3])>;
+
+    /// Combines entities with equal materials into a single entity per
+    /// material, named after the material, in the order of first use.
+    ///
+    /// If only some of the entities with a material have normals or texture
+    /// coordinates, they are set to zero for the others.
+    fn merge_by_material(&self) -> Vec<Entity>;
+
+    /// Clones the entities, letting entities with equal materials share the
+    /// same material `Rc`.
+    fn dedup_materials(&self) -> Vec<Entity>;
+
+    /// Clones the entities, giving each its own mesh and material, so that
+    /// they can be changed with `Rc::get_mut` without affecting any other
+    /// entity.
+    fn deep_clone(&self) -> Vec<Entity>;
+}
+
+impl EntitiesExt for [Entity] {
+    fn save_obj<P: AsRef<Path>>(&self, obj_path: P) -> Result<()> {
+        let obj_path = obj_path.as_ref();
+        obj::save(
+            self.iter(),
+            Some(obj_path.to_path_buf()),
+            Some(obj_path.with_extension("mtl")),
+        )
+    }
+
+    fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        let mut positions = self
+            .iter()
+            .flat_map(|e| e.mesh.positions.chunks(3))
+            .peekable();
+        positions.peek()?;
+
+        let mut min = [::std::f32::INFINITY; 3];
+        let mut max = [::std::f32::NEG_INFINITY; 3];
+        for position in positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+
+        Some((min, max))
+    }
+
+    fn merge_by_material(&self) -> Vec<Entity> {
+        let mut groups: Vec<(Rc<Material>, Vec<&Entity>)> = Vec::new();
+        for entity in self {
+            match groups.iter_mut().find(|g| *g.0 == *entity.material) {
+                Some(group) => group.1.push(entity),
+                None => groups.push((Rc::clone(&entity.material), vec![entity])),
+            }
+        }
+
+        groups
+            .into_iter()
+            .map(|(material, entities)| Entity {
+                name: material.name().to_string(),
+                mesh: Rc::new(merge_meshes(&entities)),
+                material,
+            })
+            .collect()
+    }
+
+    fn dedup_materials(&self) -> Vec<Entity> {
+        let mut materials: Vec<Rc<Material>> = Vec::new();
+        self.iter()
+            .map(|entity| {
+                let material = match materials.iter().find(|m| ***m == *entity.material) {
+                    Some(material) => Rc::clone(material),
+                    None => {
+                        materials.push(Rc::clone(&entity.material));
+                        Rc::clone(&entity.material)
+                    }
+                };
+
+                Entity {
+                    material,
+                    ..entity.clone()
+                }
+            })
+            .collect()
+    }
+
+    fn deep_clone(&self) -> Vec<Entity> {
+        self.iter()
+            .map(|entity| Entity {
+                name: entity.name.clone(),
+                mesh: Rc::new((*entity.mesh).clone()),
+                material: Rc::new((*entity.material).clone()),
+            })
+            .collect()
+    }
+}
+
+/// Concatenates the meshes of the given entities.
+fn merge_meshes(entities: &[&Entity]) -> DeinterleavedIndexedMeshBuf {
+    let has_normals = entities.iter().any(|e| !e.mesh.normals.is_empty());
+    let has_texcoords = entities.iter().any(|e| !e.mesh.texcoords.is_empty());
+    let mut merged = DeinterleavedIndexedMeshBuf {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        texcoords: Vec::new(),
+        indices: Vec::new(),
+    };
+
+    for entity in entities {
+        let mesh = &entity.mesh;
+        let vertex_count = mesh.positions.len() / 3;
+        let index_base = (merged.positions.len() / 3) as u32;
+
+        merged.positions.extend_from_slice(&mesh.positions);
+        if has_normals {
+            if mesh.normals.is_empty() {
+                merged.normals.extend(vec![0.0; vertex_count * 3]);
+            } else {
+                merged.normals.extend_from_slice(&mesh.normals);
+            }
+        }
+        if has_texcoords {
+            if mesh.texcoords.is_empty() {
+                merged.texcoords.extend(vec![0.0; vertex_count * 2]);
+            } else {
+                merged.texcoords.extend_from_slice(&mesh.texcoords);
+            }
+        }
+        merged
+            .indices
+            .extend(mesh.indices.iter().map(|&i| i + index_base));
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use obj::load_entities;
+    use scene::MaterialBuilder;
+
+    #[test]
+    fn test_merge_and_dedup_by_material() {
+        let cube = load_entities("tests/cube.obj").unwrap().remove(0);
+        let copy = Entity {
+            name: "Copy".to_string(),
+            material: Rc::new((*cube.material).clone()),
+            ..cube.clone()
+        };
+        let other = Entity {
+            name: "Other".to_string(),
+            material: Rc::new(MaterialBuilder::new().name("Other").build()),
+            ..cube.clone()
+        };
+        let entities = vec![cube.clone(), other, copy];
+
+        let deduped = entities.dedup_materials();
+        assert!(Rc::ptr_eq(&deduped[0].material, &deduped[2].material));
+        assert!(!Rc::ptr_eq(&entities[0].material, &entities[2].material));
+
+        let merged = entities.merge_by_material();
+        assert_eq!(2, merged.len());
+        assert_eq!("Material", merged[0].name);
+        assert_eq!(cube.mesh.indices.len() * 2, merged[0].mesh.indices.len());
+        assert_eq!(
+            cube.mesh.positions.len() * 2,
+            merged[0].mesh.positions.len()
+        );
+        assert_eq!(entities.bounds(), merged.bounds());
+
+        let mut cloned = deduped.deep_clone();
+        assert!(!Rc::ptr_eq(&cloned[0].material, &cloned[2].material));
+        Rc::get_mut(&mut cloned[0].mesh).unwrap().indices.clear();
+        assert_eq!(cube.mesh.indices.len(), cloned[1].mesh.indices.len());
+    }
+}
@@ -0,0 +1,153 @@
+//! Parsing and formatting of the decimal numbers in OBJ and MTL files.
+//!
+//! Numbers are read in the plain or scientific notation, e.g. `0.001` or
+//! `1e-3`, and also with a decimal comma as written by exporters that format
+//! numbers for the current locale. They are always written with a decimal
+//! point, in the shortest notation that reads back as the same value and
+//! without an exponent, which some parsers do not understand.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Parses a single precision number, see the module documentation.
+pub fn parse_f32(word: &str) -> Option<f32> {
+    parse(word)
+}
+
+/// Parses a double precision number, see the module documentation.
+pub fn parse_f64(word: &str) -> Option<f64> {
+    parse(word)
+}
+
+fn parse<T: FromStr>(word: &str) -> Option<T> {
+    match word.parse() {
+        Ok(number) => Some(number),
+        // Only a lone comma is a decimal comma, not a thousands separator
+        Err(_) if !word.contains('.') && word.matches(',').count() == 1 => {
+            word.replace(',', ".").parse().ok()
+        }
+        Err(_) => None,
+    }
+}
+
+/// Formats a number for writing into an OBJ or MTL file.
+///
+/// With a precision, e.g. `{:.3}`, the number is rounded to at most that many
+/// decimal places, without trailing zeros. Negative zero is written as `0`.
+/// Since there is no way to express them in OBJ or MTL files, infinities are
+/// written as the largest finite number with the same sign and NaN is written
+/// as `0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decimal<T>(pub T);
+
+impl fmt::Display for Decimal<f32> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = if self.0.is_nan() {
+            0.0
+        } else if self.0.is_infinite() {
+            f32::MAX.copysign(self.0)
+        } else {
+            self.0
+        };
+        write_finite(f, value)
+    }
+}
+
+impl fmt::Display for Decimal<f64> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = if self.0.is_nan() {
+            0.0
+        } else if self.0.is_infinite() {
+            f64::MAX.copysign(self.0)
+        } else {
+            self.0
+        };
+        write_finite(f, value)
+    }
+}
+
+fn write_finite<T: fmt::Display + Default + PartialEq>(
+    f: &mut fmt::Formatter,
+    value: T,
+) -> fmt::Result {
+    if let Some(decimal_places) = f.precision() {
+        let rounded = format!("{:.*}", decimal_places, value);
+        let rounded = if rounded.contains('.') {
+            rounded.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            &rounded
+        };
+        // Small negative numbers may round to negative zero
+        return match rounded {
+            "-0" => f.write_str("0"),
+            rounded => f.write_str(rounded),
+        };
+    }
+
+    // Negative zero compares equal to zero
+    if value == T::default() {
+        f.write_str("0")
+    } else {
+        // Display writes the shortest digits that read back as the same value
+        write!(f, "{}", value)
+    }
+}
+
+/// Formats a number like `Decimal`, rounded to the given number of decimal
+/// places, if any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rounded<T>(pub T, pub Option<usize>);
+
+impl<T: Copy> fmt::Display for Rounded<T>
+where
+    Decimal<T>: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.1 {
+            Some(decimal_places) => write!(f, "{:.*}", decimal_places, Decimal(self.0)),
+            None => write!(f, "{}", Decimal(self.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Some(0.001), parse_f32("1e-3"));
+        assert_eq!(Some(1000.0), parse_f32("1E+3"));
+        assert_eq!(Some(-0.25), parse_f32("-2.5e-1"));
+        assert_eq!(Some(0.5), parse_f32("+.5"));
+        assert_eq!(Some(0.5), parse_f32("0,5"));
+        assert_eq!(Some(-1.5), parse_f64("-1,5"));
+        assert_eq!(None, parse_f32("1,000,000"));
+        assert_eq!(None, parse_f32("1.5,0"));
+        assert_eq!(None, parse_f32("1e"));
+        assert_eq!(None, parse_f32(""));
+    }
+
+    #[test]
+    fn test_format() {
+        let format = |value: f32| Decimal(value).to_string();
+        assert_eq!("0", format(-0.0));
+        assert_eq!("1", format(1.0));
+        assert_eq!("0.1", format(0.1));
+        assert_eq!("0.001", format(1e-3));
+        assert_eq!("-2.5", format(-2.5));
+        assert_eq!("0", format(f32::NAN));
+        assert_eq!(f32::MIN, parse_f32(&format(f32::NEG_INFINITY)).unwrap());
+        assert_eq!("0.1", Decimal(0.1f64).to_string());
+        assert_eq!("0.333", Rounded(1.0f32 / 3.0, Some(3)).to_string());
+        assert_eq!("1.5", Rounded(1.5f64, Some(3)).to_string());
+        assert_eq!("2", Rounded(1.9996f32, Some(3)).to_string());
+        assert_eq!("0", Rounded(-0.0001f32, Some(3)).to_string());
+        assert_eq!("-3", Rounded(-3.0f32, Some(0)).to_string());
+        assert_eq!("0.1", Rounded(0.1f32, None).to_string());
+
+        for &value in [1.0e-7f32, 3.4028e38, 0.3, 123_456.79].iter() {
+            assert_eq!(Some(value), parse_f32(&format(value)));
+        }
+    }
+}
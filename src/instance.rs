@@ -0,0 +1,340 @@
+//! Detection of entities that are copies of the same mesh in other places.
+
+use scene::{DeinterleavedIndexedMeshBuf, Entity};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use vector::{cross, length, sub};
+
+/// Column-major matrix that does not transform anything.
+pub const IDENTITY: [[f32; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Maximum distance of transformed positions, relative to the size of the
+/// mesh, and of transformed normals to be considered equal.
+const POSITION_TOLERANCE: f32 = 1e-4;
+const NORMAL_TOLERANCE: f32 = 1e-3;
+
+/// An entity whose mesh other entities may share.
+struct Prototype {
+    entity: usize,
+    mesh: Rc<DeinterleavedIndexedMeshBuf>,
+    frame: Frame,
+}
+
+/// Orthonormal axes spanned by three vertices of a mesh, used to find the
+/// rotation between two meshes with the same vertex order.
+struct Frame {
+    anchors: [usize; 3],
+    origin: [f32; 3],
+    axes: [[f32; 3]; 3],
+    size: f32,
+}
+
+/// Lets each entity whose mesh equals the mesh of an earlier entity after a
+/// rotation and translation share the earlier mesh.
+///
+/// Mirrored copies share the mesh too if their triangles are reversed, so
+/// they face outwards like the original. Their transform then mirrors and
+/// the triangles of the shared mesh have to be reversed again when placing
+/// it, like `coords::apply_matrix` does.
+///
+/// Returns for each entity the column-major matrix transforming its mesh
+/// into its original place, if the mesh is shared. Entities that others
+/// share their mesh with get the identity.
+pub fn share_instances(entities: &mut [Entity]) -> Vec<Option<[[f32; 4]; 4]>> {
+    let mut transforms = vec![None; entities.len()];
+    let mut prototypes: HashMap<u64, Vec<Prototype>> = HashMap::new();
+
+    for idx in 0..entities.len() {
+        let mut shared = None;
+        for &mirrored in [false, true].iter() {
+            let key = topology_hash(&entities[idx].mesh, mirrored);
+            for prototype in prototypes.get(&key).into_iter().flatten() {
+                let mesh = &entities[idx].mesh;
+                if let Some(transform) = rigid_transform(prototype, mesh, mirrored) {
+                    shared = Some((prototype.entity, Rc::clone(&prototype.mesh), transform));
+                    break;
+                }
+            }
+            if shared.is_some() {
+                break;
+            }
+        }
+
+        match shared {
+            Some((prototype, mesh, transform)) => {
+                entities[idx].mesh = mesh;
+                transforms[idx] = Some(transform);
+                transforms[prototype].get_or_insert(IDENTITY);
+            }
+            None => {
+                let mesh = &entities[idx].mesh;
+                if let Some(frame) = Frame::of(&mesh.positions) {
+                    let key = topology_hash(mesh, false);
+                    prototypes.entry(key).or_default().push(Prototype {
+                        entity: idx,
+                        mesh: Rc::clone(mesh),
+                        frame,
+                    });
+                }
+            }
+        }
+    }
+
+    transforms
+}
+
+/// Hash of everything but positions and normals, which must be equal for
+/// two meshes to be instances of each other, with the triangles reversed
+/// for mirrored meshes.
+fn topology_hash(mesh: &DeinterleavedIndexedMeshBuf, mirrored: bool) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    mesh.positions.len().hash(&mut hasher);
+    mesh.normals.len().hash(&mut hasher);
+    if mirrored {
+        reversed_triangles(&mesh.indices).hash(&mut hasher);
+    } else {
+        mesh.indices.hash(&mut hasher);
+    }
+    for texcoord in mesh.texcoords.iter() {
+        texcoord.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Indices with the second and third corner of each triangle swapped.
+fn reversed_triangles(indices: &[u32]) -> Vec<u32> {
+    indices
+        .chunks(3)
+        .flat_map(|t| match *t {
+            [a, b, c] => vec![a, c, b],
+            _ => t.to_vec(),
+        })
+        .collect()
+}
+
+/// Finds the transform of the prototype mesh into the given mesh, if the
+/// given mesh is only a rotated and translated copy, or also mirrored.
+fn rigid_transform(
+    prototype: &Prototype,
+    mesh: &DeinterleavedIndexedMeshBuf,
+    mirrored: bool,
+) -> Option<[[f32; 4]; 4]> {
+    let original = &*prototype.mesh;
+    let same_indices = if mirrored {
+        original.indices == reversed_triangles(&mesh.indices)
+    } else {
+        original.indices == mesh.indices
+    };
+    let same_topology = original.positions.len() == mesh.positions.len()
+        && original.normals.len() == mesh.normals.len()
+        && same_indices
+        && original.texcoords == mesh.texcoords;
+    if !same_topology {
+        return None;
+    }
+
+    let from = &prototype.frame;
+    let mut to = Frame::with_anchors(&mesh.positions, from.anchors)?;
+    if mirrored {
+        // The normal of the anchor plane flips along with the mesh
+        to.axes[2] = scale(to.axes[2], -1.0);
+    }
+
+    // Rotation taking the axes of the prototype to the axes of the copy
+    let mut rotation = [[0.0; 3]; 3];
+    for (row, rotation_row) in rotation.iter_mut().enumerate() {
+        for (col, value) in rotation_row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| to.axes[k][row] * from.axes[k][col]).sum();
+        }
+    }
+    let rotate = |v: &[f32]| {
+        let mut rotated = [0.0; 3];
+        for (row, value) in rotated.iter_mut().enumerate() {
+            *value = (0..3).map(|col| rotation[row][col] * v[col]).sum();
+        }
+        rotated
+    };
+    let rotated_origin = rotate(&from.origin);
+    let translation = [
+        to.origin[0] - rotated_origin[0],
+        to.origin[1] - rotated_origin[1],
+        to.origin[2] - rotated_origin[2],
+    ];
+
+    let position_tolerance = POSITION_TOLERANCE * from.size;
+    let positions = original.positions.chunks(3).zip(mesh.positions.chunks(3));
+    for (original, copy) in positions {
+        let rotated = rotate(original);
+        let moved = [
+            rotated[0] + translation[0],
+            rotated[1] + translation[1],
+            rotated[2] + translation[2],
+        ];
+        if distance(moved, copy) > position_tolerance {
+            return None;
+        }
+    }
+    for (original, copy) in original.normals.chunks(3).zip(mesh.normals.chunks(3)) {
+        if distance(rotate(original), copy) > NORMAL_TOLERANCE {
+            return None;
+        }
+    }
+
+    let mut transform = IDENTITY;
+    for col in 0..3 {
+        for row in 0..3 {
+            transform[col][row] = rotation[row][col];
+        }
+        transform[3][col] = translation[col];
+    }
+    Some(transform)
+}
+
+impl Frame {
+    /// Spans a frame with the first vertex, the vertex farthest from it and
+    /// the vertex farthest from the line through both, or `None` if all
+    /// vertices lie on a line.
+    fn of(positions: &[f32]) -> Option<Frame> {
+        if positions.len() < 9 {
+            return None;
+        }
+        let vertex = |i: usize| [positions[i * 3], positions[i * 3 + 1], positions[i * 3 + 2]];
+        let vertex_count = positions.len() / 3;
+        let first = vertex(0);
+
+        let farthest = (1..vertex_count).max_by(|&a, &b| {
+            let a = length(sub(vertex(a), first));
+            let b = length(sub(vertex(b), first));
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        })?;
+        let axis = sub(vertex(farthest), first);
+        let off_axis = (1..vertex_count).max_by(|&a, &b| {
+            let a = length(cross(axis, sub(vertex(a), first)));
+            let b = length(cross(axis, sub(vertex(b), first)));
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        })?;
+
+        Frame::with_anchors(positions, [0, farthest, off_axis])
+    }
+
+    fn with_anchors(positions: &[f32], anchors: [usize; 3]) -> Option<Frame> {
+        let vertex = |i: usize| [positions[i * 3], positions[i * 3 + 1], positions[i * 3 + 2]];
+        let origin = vertex(anchors[0]);
+        let x = sub(vertex(anchors[1]), origin);
+        let z = cross(x, sub(vertex(anchors[2]), origin));
+        let size = length(x);
+        // Also rejects NaN
+        let spans_plane = size > 0.0 && length(z) > size * size * POSITION_TOLERANCE;
+        if !spans_plane {
+            return None;
+        }
+
+        let x = scale(x, 1.0 / size);
+        let z = scale(z, 1.0 / length(z));
+        Some(Frame {
+            anchors,
+            origin,
+            axes: [x, cross(z, x), z],
+            size,
+        })
+    }
+}
+
+fn scale(v: [f32; 3], factor: f32) -> [f32; 3] {
+    [v[0] * factor, v[1] * factor, v[2] * factor]
+}
+
+fn distance(a: [f32; 3], b: &[f32]) -> f32 {
+    length([a[0] - b[0], a[1] - b[1], a[2] - b[2]])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use coords;
+    use obj;
+
+    #[test]
+    fn test_share_instances() {
+        let cube = obj::load("tests/cube.obj").unwrap().entities.remove(0);
+
+        // Rotated by 90 degrees around z and moved along x
+        let turn = |v: &[f32], offset: f32| vec![offset - v[1], v[0], v[2]];
+        let mut turned = (*cube.mesh).clone();
+        turned.positions = cube
+            .mesh
+            .positions
+            .chunks(3)
+            .flat_map(|p| turn(p, 10.0))
+            .collect();
+        turned.normals = cube
+            .mesh
+            .normals
+            .chunks(3)
+            .flat_map(|n| turn(n, 0.0))
+            .collect();
+        let mut squashed = turned.clone();
+        squashed.positions[2] += 0.5;
+
+        let copy = |mesh: DeinterleavedIndexedMeshBuf| Entity {
+            mesh: Rc::new(mesh),
+            ..cube.clone()
+        };
+        // Mirrored along x, with triangles reversed to still face outwards
+        let mut mirrored = (*cube.mesh).clone();
+        for (position, normal) in mirrored
+            .positions
+            .chunks_mut(3)
+            .zip(mirrored.normals.chunks_mut(3))
+        {
+            position[0] = 5.0 - position[0];
+            normal[0] = -normal[0];
+        }
+        mirrored.indices = reversed_triangles(&mirrored.indices);
+
+        let mut entities = vec![
+            cube.clone(),
+            copy(turned),
+            copy(squashed),
+            copy(mirrored.clone()),
+        ];
+        let transforms = share_instances(&mut entities);
+
+        assert!(Rc::ptr_eq(&entities[0].mesh, &entities[1].mesh));
+        assert!(!Rc::ptr_eq(&entities[0].mesh, &entities[2].mesh));
+        assert!(Rc::ptr_eq(&entities[0].mesh, &entities[3].mesh));
+        assert_eq!(Some(IDENTITY), transforms[0]);
+        assert_eq!(None, transforms[2]);
+
+        // Placing the shared mesh restores the mirrored copy
+        let mut placed = (*entities[3].mesh).clone();
+        coords::apply_matrix(&mut placed, None, transforms[3].unwrap());
+        assert_eq!(mirrored.indices, placed.indices);
+        let placed_vertices = placed.positions.iter().chain(placed.normals.iter());
+        let mirrored_vertices = mirrored.positions.iter().chain(mirrored.normals.iter());
+        for (placed, expected) in placed_vertices.zip(mirrored_vertices) {
+            assert!((placed - expected).abs() < 1e-5);
+        }
+
+        let transform = transforms[1].unwrap();
+        let expected = [
+            [0.0, 1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [10.0, 0.0, 0.0, 1.0],
+        ];
+        for (col, expected_col) in transform.iter().zip(expected.iter()) {
+            for (value, expected) in col.iter().zip(expected_col.iter()) {
+                assert!((value - expected).abs() < 1e-5);
+            }
+        }
+    }
+}
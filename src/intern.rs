@@ -0,0 +1,86 @@
+//! Deduplicated storage of names.
+//!
+//! Scenes with many similarly named objects store the same entity and
+//! material names over and over. Interning them yields a `Symbol` for each
+//! distinct name, which is cheap to copy and compare.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Handle of a string in a `StringTable`.
+///
+/// Symbols are only meaningful for the table that created them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+/// Stores each distinct string once and hands out symbols for them.
+#[derive(Debug, Clone, Default)]
+pub struct StringTable {
+    strings: Vec<Rc<str>>,
+    symbols: HashMap<Rc<str>, Symbol>,
+}
+
+impl StringTable {
+    pub fn new() -> StringTable {
+        StringTable::default()
+    }
+
+    /// Gets the symbol of the given string, adding the string first if it is
+    /// not in the table yet.
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(string) {
+            return symbol;
+        }
+
+        let symbol = Symbol(self.strings.len() as u32);
+        let string: Rc<str> = Rc::from(string);
+        self.strings.push(Rc::clone(&string));
+        self.symbols.insert(string, symbol);
+        symbol
+    }
+
+    /// Gets the symbol of the given string, if it has been interned.
+    pub fn get(&self, string: &str) -> Option<Symbol> {
+        self.symbols.get(string).cloned()
+    }
+
+    /// Gets the string of a symbol created by this table.
+    ///
+    /// Panics if the symbol was created by another table with more strings.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+
+    /// Iterates over the strings in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.strings.iter().map(|s| &**s)
+    }
+
+    /// Number of distinct strings in the table.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_equal_strings_share_symbol() {
+        let mut table = StringTable::new();
+        let chair = table.intern("Chair");
+        let table_symbol = table.intern("Table");
+
+        assert_eq!(chair, table.intern("Chair"));
+        assert_ne!(chair, table_symbol);
+        assert_eq!(Some(chair), table.get("Chair"));
+        assert_eq!(None, table.get("Lamp"));
+        assert_eq!("Table", table.resolve(table_symbol));
+        assert_eq!(2, table.len());
+    }
+}
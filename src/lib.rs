@@ -9,12 +9,12 @@
 //!
 //! # fn main() {
 //! // Load entities from an OBJ as aitios_scene entities
-//! let entities = obj::load("tests/cube.obj")
+//! let scene = obj::load("tests/cube.obj")
 //!     .unwrap();
 //!
-//! // Save them back to OBJ/MTL
-//! obj::save(
-//!     entities.iter(),
+//! // Save them back to OBJ/MTL, along with the material colors
+//! obj::save_scene(
+//!     &scene,
 //!     Some("tests/cube_with_mtl.obj"),
 //!     Some("tests/cube_with_mtl.mtl")
 //! ).unwrap();
@@ -25,10 +25,62 @@
 extern crate aitios_geom as geom;
 extern crate aitios_scene as scene;
 extern crate failure;
+extern crate flate2;
+extern crate fs2;
+#[macro_use]
+extern crate log;
 extern crate pathdiff;
-extern crate tobj;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+extern crate unicode_normalization;
+#[cfg(feature = "http")]
+extern crate ureq;
+#[cfg(feature = "http")]
+extern crate url;
+extern crate zip;
 #[macro_use]
 extern crate failure_derive;
 
+mod adjacency;
+pub mod archive;
+mod batch;
+mod bounds;
+pub mod bundle;
+mod collision;
+mod coords;
 pub mod err;
+mod ext;
+mod float;
+mod instance;
+mod intern;
+mod loaded;
+mod lod;
+mod maps;
+mod normalize;
 pub mod obj;
+mod preflight;
+pub mod report;
+pub mod surfel;
+mod tempdir;
+mod texel;
+pub mod validate;
+mod vector;
+pub mod vfs;
+mod weld;
+mod winding;
+
+pub use adjacency::FaceAdjacency;
+pub use batch::{convert_batch, BatchOptions, Conversion, OutputFormat};
+pub use bounds::Bounds;
+pub use ext::EntitiesExt;
+pub use intern::{StringTable, Symbol};
+pub use loaded::{
+    ColorSpace, EntityExtras, EntityStats, LoadStats, LoadTimings, LoadedScene, MaterialProperties,
+    MaterialUsage, ReflectionMap, Source, TextureOptions, TextureUsage, Warning,
+};
+pub use lod::LodLevel;
+pub use maps::{rebase_maps, resolve_maps};
+pub use tempdir::{tempdir_scope, TempDir};
@@ -0,0 +1,1329 @@
+use adjacency::FaceAdjacency;
+use bounds::Bounds;
+use float::Decimal;
+use intern::{StringTable, Symbol};
+use lod::LodLevel;
+use maps;
+use scene::{DeinterleavedIndexedMeshBuf, Entity, Material, MaterialBuilder};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+use surfel::Surfel;
+use validate::Finding;
+
+/// Entities loaded from an asset, along with data that has no
+/// representation in `aitios_scene::Entity`.
+#[derive(Clone, Default)]
+pub struct LoadedScene {
+    pub entities: Vec<Entity>,
+    /// Extra data for each entity, with the same order and length as `entities`.
+    pub extras: Vec<EntityExtras>,
+    /// Every material defined in the asset by name, plus the default material
+    /// if it was assigned to some entity. Entities share the same `Rc`.
+    ///
+    /// Use `replace_material` to change a material for all entities at once.
+    pub materials: BTreeMap<String, Rc<Material>>,
+    /// Colors and factors of the materials defined in MTL files, by the name
+    /// of the material in `materials`.
+    pub material_properties: BTreeMap<String, MaterialProperties>,
+    /// Resolved paths of all texture maps referenced by the materials,
+    /// without duplicates.
+    pub textures: Vec<PathBuf>,
+    /// Problems that did not prevent loading, but may be of interest.
+    pub warnings: Vec<Warning>,
+    pub stats: LoadStats,
+    /// Names of entities and materials and keys of texture maps, only filled
+    /// if requested with `LoadOptions::intern_names`.
+    pub names: StringTable,
+    /// Offset that was subtracted from all positions on load to keep them
+    /// precise, see `LoadOptions::shift_origin`. Adding it to a position in
+    /// double precision gives the original position.
+    pub origin: Option<[f64; 3]>,
+}
+
+/// Data about a loaded entity that is kept beside the entity itself.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntityExtras {
+    /// Number of vertices of each face as authored in the source file, if
+    /// polygons were preserved on load. The triangles in the entity mesh
+    /// are fans around the first vertex of each face, in the same order.
+    pub face_sizes: Option<Vec<u32>>,
+    /// RGB color of each vertex in the entity mesh, if the source file
+    /// specified vertex colors.
+    pub colors: Option<Vec<f32>>,
+    /// Third texture coordinate component of each vertex in the entity mesh,
+    /// if requested with `LoadOptions::texcoord_w` and present in the source file.
+    pub texcoord_ws: Option<Vec<f32>>,
+    /// Polylines from OBJ `l` statements, as indices of vertices in the
+    /// entity mesh. Vertices only used by lines are not part of any triangle.
+    pub lines: Vec<Vec<u32>>,
+    /// Points from OBJ `p` statements, as indices of vertices in the entity mesh.
+    pub points: Vec<u32>,
+    /// Neighbors of each triangle in the entity mesh, if requested with
+    /// `LoadOptions::face_adjacency`.
+    pub adjacency: Option<FaceAdjacency>,
+    /// Samples on the surface of the entity mesh, if requested with
+    /// `LoadOptions::surfel_density`.
+    pub surfels: Option<Vec<Surfel>>,
+    /// Position of each vertex in the entity mesh in double precision, if
+    /// requested with `LoadOptions::double_precision`. Relative to the scene
+    /// origin, like the mesh positions.
+    pub precise_positions: Option<Vec<f64>>,
+    /// Bounding box and sphere of the entity mesh in the scene, if requested
+    /// with `LoadOptions::bounds`.
+    pub bounds: Option<Bounds>,
+    /// Column-major matrix transforming the entity mesh into the scene, if
+    /// the mesh is shared with other entities, see
+    /// `LoadOptions::detect_instances`. Surfels and other extras are in
+    /// scene space regardless.
+    ///
+    /// The matrix mirrors for mirrored instances, the triangles of the mesh
+    /// then have to be reversed after transforming. Saving takes care of
+    /// both.
+    pub instance_transform: Option<[[f32; 4]; 4]>,
+    /// The level of detail group of the entity, if its name follows the
+    /// `chair_LOD0` convention, see `LodLevel::parse`.
+    pub lod: Option<LodLevel>,
+    /// Name of the entity this entity is collision geometry for, if its name
+    /// follows a convention like `UCX_chair_01` or `chair_collision`. Set it
+    /// to have the entity saved as collision geometry, see
+    /// `SaveOptions::collision_export`.
+    pub collision_for: Option<String>,
+    /// The file and object the entity was loaded from.
+    pub source: Option<Source>,
+    /// Symbol of the entity name in the `names` of the scene, if interned.
+    pub name_symbol: Option<Symbol>,
+    /// Symbol of the name of the entity material in the `names` of the
+    /// scene, if interned.
+    pub material_symbol: Option<Symbol>,
+}
+
+/// Colors and factors of a material as specified with MTL statements, which
+/// have no representation in `aitios_scene::Material`. Properties that were
+/// not specified, or in an unsupported form like spectral colors, are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialProperties {
+    /// Ambient RGB color from `Ka`.
+    pub ambient: Option<[f32; 3]>,
+    /// Diffuse RGB color from `Kd`.
+    pub diffuse: Option<[f32; 3]>,
+    /// Specular RGB color from `Ks`.
+    pub specular: Option<[f32; 3]>,
+    /// Emissive RGB color from `Ke`.
+    pub emissive: Option<[f32; 3]>,
+    /// RGB color of light passing through the material, from `Tf`.
+    pub transmission_filter: Option<[f32; 3]>,
+    /// Specular exponent from `Ns`.
+    pub shininess: Option<f32>,
+    /// Index of refraction from `Ni`.
+    pub optical_density: Option<f32>,
+    /// Opacity from `d`, or else one minus the transparency from `Tr`.
+    pub dissolve: Option<f32>,
+    /// Illumination model from `illum`.
+    pub illumination: Option<u8>,
+    /// Thickness of the clearcoat layer from `Pc`, as in car paint.
+    pub clearcoat: Option<f32>,
+    /// Roughness of the clearcoat layer from `Pcr`.
+    pub clearcoat_roughness: Option<f32>,
+    /// Anisotropy of specular highlights from `aniso`, as in brushed metal.
+    pub anisotropy: Option<f32>,
+    /// Rotation of the direction of anisotropy from `anisor`, from zero to
+    /// one for a full turn.
+    pub anisotropy_rotation: Option<f32>,
+    /// Resolved path of the dissolve map from `map_d`, a mask of the opacity
+    /// such as the cut-out of a leaf.
+    pub dissolve_map: Option<PathBuf>,
+    /// Resolved path of the specular exponent map from `map_Ns`, which
+    /// varies the glossiness across the surface.
+    pub shininess_map: Option<PathBuf>,
+    /// Resolved path of the ambient occlusion map, from `map_ao` or one of
+    /// its other spellings like `occlusion`. It is saved as `map_ao`.
+    pub occlusion_map: Option<PathBuf>,
+    /// Resolved path of the clearcoat thickness map from `map_Pc`.
+    pub clearcoat_map: Option<PathBuf>,
+    /// Resolved path of the clearcoat roughness map from `map_Pcr`.
+    pub clearcoat_roughness_map: Option<PathBuf>,
+    /// Environment maps from `refl` statements in the order of the MTL, one
+    /// for a sphere map or up to six for the faces of a cube map.
+    pub reflection_maps: Vec<ReflectionMap>,
+    /// Options of the texture maps that have any, by the MTL keyword of the
+    /// map in the material, e.g. `bump` for `map_bump -bm 0.3 bump.png`.
+    pub map_options: BTreeMap<String, TextureOptions>,
+    /// Color space of each map by MTL keyword, except for reflection maps.
+    /// Use `color_space` to get the color space of maps without a tag.
+    pub color_spaces: BTreeMap<String, ColorSpace>,
+    /// Rest of the line after the keyword of statements that are not kept in
+    /// the material or the other properties, by keyword, e.g. vendor-specific
+    /// extensions or `Pr` of the PBR extension. They are
+    /// written back unchanged when saving, except for statements that were
+    /// loaded as maps with `LoadOptions::unknown_params`.
+    pub unknown_params: BTreeMap<String, String>,
+}
+
+impl MaterialProperties {
+    /// Gets the maps among the properties that are set, along with their
+    /// MTL keywords, e.g. `map_d` for the dissolve map. Reflection maps are
+    /// not included, since there may be more than one with the same keyword.
+    pub fn maps(&self) -> Vec<(&'static str, &PathBuf)> {
+        let maps = [
+            ("map_d", &self.dissolve_map),
+            ("map_Ns", &self.shininess_map),
+            ("map_ao", &self.occlusion_map),
+            ("map_Pc", &self.clearcoat_map),
+            ("map_Pcr", &self.clearcoat_roughness_map),
+        ];
+        maps.iter()
+            .filter_map(|&(key, path)| path.as_ref().map(|path| (key, path)))
+            .collect()
+    }
+
+    /// Gets the color space of the map with the given MTL keyword, which is
+    /// the conventional one for the map if it has no tag.
+    pub fn color_space(&self, key: &str) -> ColorSpace {
+        self.color_spaces
+            .get(key)
+            .cloned()
+            .unwrap_or_else(|| ColorSpace::default_for(key))
+    }
+
+    /// Checks if the colors and factors of both properties differ by at most
+    /// the given tolerance in each component, and everything else is equal.
+    pub fn similar(&self, other: &MaterialProperties, tolerance: f32) -> bool {
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() <= tolerance);
+        let colors = [
+            (self.ambient, other.ambient),
+            (self.diffuse, other.diffuse),
+            (self.specular, other.specular),
+            (self.emissive, other.emissive),
+            (self.transmission_filter, other.transmission_filter),
+        ];
+        let factors = [
+            (self.shininess, other.shininess),
+            (self.optical_density, other.optical_density),
+            (self.dissolve, other.dissolve),
+            (self.clearcoat, other.clearcoat),
+            (self.clearcoat_roughness, other.clearcoat_roughness),
+            (self.anisotropy, other.anisotropy),
+            (self.anisotropy_rotation, other.anisotropy_rotation),
+        ];
+        let colors_close = colors.iter().all(|&pair| match pair {
+            (Some(a), Some(b)) => close(&a, &b),
+            (a, b) => a.is_none() && b.is_none(),
+        });
+        let factors_close = factors.iter().all(|&pair| match pair {
+            (Some(a), Some(b)) => close(&[a], &[b]),
+            (a, b) => a.is_none() && b.is_none(),
+        });
+        let without_numbers = |p: &MaterialProperties| MaterialProperties {
+            ambient: None,
+            diffuse: None,
+            specular: None,
+            emissive: None,
+            transmission_filter: None,
+            shininess: None,
+            optical_density: None,
+            dissolve: None,
+            clearcoat: None,
+            clearcoat_roughness: None,
+            anisotropy: None,
+            anisotropy_rotation: None,
+            ..p.clone()
+        };
+        colors_close && factors_close && without_numbers(self) == without_numbers(other)
+    }
+
+    /// Gets the slot of the map with the given MTL keyword, e.g. `map_d`,
+    /// or `None` if there is no such map among the properties.
+    pub(crate) fn map_slot(&mut self, key: &str) -> Option<&mut Option<PathBuf>> {
+        match key {
+            "map_d" => Some(&mut self.dissolve_map),
+            "map_Ns" => Some(&mut self.shininess_map),
+            "map_ao" => Some(&mut self.occlusion_map),
+            "map_Pc" => Some(&mut self.clearcoat_map),
+            "map_Pcr" => Some(&mut self.clearcoat_roughness_map),
+            _ => None,
+        }
+    }
+
+    fn maps_mut(&mut self) -> Vec<&mut PathBuf> {
+        let maps = vec![
+            &mut self.dissolve_map,
+            &mut self.shininess_map,
+            &mut self.occlusion_map,
+            &mut self.clearcoat_map,
+            &mut self.clearcoat_roughness_map,
+        ];
+        let reflections = self.reflection_maps.iter_mut().map(|map| &mut map.path);
+        maps.into_iter()
+            .filter_map(|path| path.as_mut())
+            .chain(reflections)
+            .collect()
+    }
+}
+
+/// Encoding of the values of a texture map, either colors in sRGB or linear
+/// data, such as roughness or normals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    /// Gets the color space of maps with the given MTL keyword by convention,
+    /// sRGB for colors, e.g. `map_Kd`, and linear for anything else, e.g.
+    /// `map_Pr` or `norm`.
+    pub fn default_for(key: &str) -> ColorSpace {
+        match key {
+            "map_Kd" | "map_Ka" | "map_Ks" | "map_Ke" | "map_Ps" | "refl" => ColorSpace::Srgb,
+            _ => ColorSpace::Linear,
+        }
+    }
+
+    /// Gets the color space indicated by a suffix of the file stem of the
+    /// given path, such as `_srgb` in `wood_sRGB.png` or `_linear` in
+    /// `wood_rough_linear.png`.
+    pub fn from_file_name(path: &Path) -> Option<ColorSpace> {
+        let stem = path.file_stem()?.to_str()?.to_lowercase();
+        let suffix = stem.rsplit(['_', '-', '.']).next()?;
+        if suffix.len() == stem.len() {
+            return None;
+        }
+        ColorSpace::parse(suffix)
+    }
+
+    /// Parses the name of a color space as written in the `-colorspace`
+    /// option, ignoring case, e.g. `sRGB`, `linear` or `raw`.
+    pub fn parse(name: &str) -> Option<ColorSpace> {
+        match name.to_lowercase().as_str() {
+            "srgb" => Some(ColorSpace::Srgb),
+            "linear" | "lin" | "raw" => Some(ColorSpace::Linear),
+            _ => None,
+        }
+    }
+}
+
+/// Formats the color space as it is written in the `-colorspace` option.
+impl fmt::Display for ColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ColorSpace::Srgb => write!(f, "srgb"),
+            ColorSpace::Linear => write!(f, "linear"),
+        }
+    }
+}
+
+/// An environment map from a `refl` statement, such as
+/// `refl -type cube_top sky_top.png`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectionMap {
+    /// Resolved path of the map.
+    pub path: PathBuf,
+    /// Options of the statement, with the kind of the map, e.g. `sphere` or
+    /// `cube_left`, in `map_type`.
+    pub options: TextureOptions,
+}
+
+/// Options of a texture map statement in an MTL file, such as `-bm 0.3` in
+/// `bump -bm 0.3 bump.png`. Options that were not specified are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextureOptions {
+    /// Horizontal blending from `-blendu`.
+    pub blend_u: Option<bool>,
+    /// Vertical blending from `-blendv`.
+    pub blend_v: Option<bool>,
+    /// Multiplier of bump map values from `-bm`.
+    pub bump_multiplier: Option<f32>,
+    /// Sharpness boost of mip maps from `-boost`.
+    pub boost: Option<f32>,
+    /// Color correction from `-cc`.
+    pub color_correction: Option<bool>,
+    /// Clamping of texture coordinates from `-clamp`.
+    pub clamp: Option<bool>,
+    /// Channel of a scalar or bump texture from `-imfchan`, e.g. `r` or `l`.
+    pub channel: Option<String>,
+    /// Base value and gain of texture values from `-mm`.
+    pub range: Option<[f32; 2]>,
+    /// Offset of texture coordinates from `-o`, with zero for components
+    /// that were left out.
+    pub offset: Option<[f32; 3]>,
+    /// Scale of texture coordinates from `-s`, with one for components that
+    /// were left out.
+    pub scale: Option<[f32; 3]>,
+    /// Turbulence of texture coordinates from `-t`, with zero for
+    /// components that were left out.
+    pub turbulence: Option<[f32; 3]>,
+    /// Resolution of the texture from `-texres`.
+    pub resolution: Option<u32>,
+    /// Kind of reflection map from `-type`, e.g. `sphere`.
+    pub map_type: Option<String>,
+    /// Color space of the map from the non-standard `-colorspace`, e.g.
+    /// `-colorspace linear`.
+    pub color_space: Option<ColorSpace>,
+}
+
+impl TextureOptions {
+    /// Checks if no option was specified.
+    pub fn is_empty(&self) -> bool {
+        *self == TextureOptions::default()
+    }
+}
+
+/// Formats the options as they are written before the file name of a map
+/// statement, separated by spaces.
+impl fmt::Display for TextureOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        let mut options = Vec::new();
+        if let Some(blend) = self.blend_u {
+            options.push(format!("-blendu {}", on_off(blend)));
+        }
+        if let Some(blend) = self.blend_v {
+            options.push(format!("-blendv {}", on_off(blend)));
+        }
+        if let Some(multiplier) = self.bump_multiplier {
+            options.push(format!("-bm {}", Decimal(multiplier)));
+        }
+        if let Some(boost) = self.boost {
+            options.push(format!("-boost {}", Decimal(boost)));
+        }
+        if let Some(correction) = self.color_correction {
+            options.push(format!("-cc {}", on_off(correction)));
+        }
+        if let Some(clamp) = self.clamp {
+            options.push(format!("-clamp {}", on_off(clamp)));
+        }
+        if let Some(ref channel) = self.channel {
+            options.push(format!("-imfchan {}", channel));
+        }
+        if let Some([base, gain]) = self.range {
+            options.push(format!("-mm {} {}", Decimal(base), Decimal(gain)));
+        }
+        let vectors = [
+            ("-o", self.offset),
+            ("-s", self.scale),
+            ("-t", self.turbulence),
+        ];
+        for &(option, vector) in vectors.iter() {
+            if let Some([u, v, w]) = vector {
+                options.push(format!(
+                    "{} {} {} {}",
+                    option,
+                    Decimal(u),
+                    Decimal(v),
+                    Decimal(w)
+                ));
+            }
+        }
+        if let Some(resolution) = self.resolution {
+            options.push(format!("-texres {}", resolution));
+        }
+        if let Some(ref map_type) = self.map_type {
+            options.push(format!("-type {}", map_type));
+        }
+        if let Some(color_space) = self.color_space {
+            options.push(format!("-colorspace {}", color_space));
+        }
+        write!(f, "{}", options.join(" "))
+    }
+}
+
+/// Provenance of a loaded entity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    /// Path of the file the entity was loaded from, as passed to the loader.
+    pub path: PathBuf,
+    /// Name of the object within the file.
+    pub object: String,
+}
+
+/// A problem encountered during loading that did not cause loading to fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// An object uses a material that is not defined in the material library,
+    /// the default material was assigned instead.
+    UnknownMaterial { entity: String, material: String },
+    /// The material library was not declared or not found, and another one
+    /// was discovered and loaded instead.
+    MtlDiscovered {
+        declared: Option<PathBuf>,
+        discovered: PathBuf,
+    },
+    /// A material was defined again in the given material library, either
+    /// twice in the same library or in a later library of the same OBJ. The
+    /// first definition was used.
+    MaterialRedefined { material: String, library: PathBuf },
+    /// A material has the same statements as another material with a
+    /// different name and was merged into it, see
+    /// `LoadOptions::merge_duplicate_materials`.
+    MaterialMerged { material: String, into: String },
+    /// A texture map of a material refers to a file that does not exist, the
+    /// map was left out.
+    MissingTexture { material: String, path: PathBuf },
+    /// A face has no area.
+    DegenerateFace { path: PathBuf, line: usize },
+    /// A statement is not supported and was ignored. Only the first occurrence
+    /// of each kind of statement in a file is reported.
+    UnknownStatement {
+        path: PathBuf,
+        line: usize,
+        statement: String,
+    },
+    /// A malformed line was skipped, either in lenient mode or within the
+    /// budget set with `LoadOptions::max_skipped_lines`.
+    SkippedLine {
+        path: PathBuf,
+        line: usize,
+        content: String,
+        reason: String,
+    },
+    /// An index of the given corner of a face, line or point was out of range
+    /// and was clamped into range in lenient mode.
+    IndexClamped {
+        path: PathBuf,
+        line: usize,
+        corner: String,
+    },
+    /// The mesh of an entity has a problem that was found when validating
+    /// geometry after loading, see `LoadOptions::validate_geometry`.
+    InvalidGeometry { entity: String, finding: Finding },
+    /// Triangles of an entity are wound against their neighbors, or make a
+    /// closed part of the mesh face inwards, see `LoadOptions::winding`.
+    MisorientedFaces { entity: String, triangles: usize },
+    /// An object reached the vertex limit or the memory budget at the given
+    /// line and was continued in another entity of the same name, see
+    /// `LoadOptions::max_entity_vertices` and `LoadOptions::memory_budget`.
+    EntitySplit {
+        path: PathBuf,
+        line: usize,
+        entity: String,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::UnknownMaterial {
+                ref entity,
+                ref material,
+            } => write!(
+                f,
+                "Entity {} uses undefined material {}, using default material instead",
+                entity, material
+            ),
+            Warning::MtlDiscovered {
+                declared: Some(ref declared),
+                ref discovered,
+            } => write!(
+                f,
+                "MTL {:?} not found, using {:?} instead",
+                declared, discovered
+            ),
+            Warning::MtlDiscovered {
+                declared: None,
+                ref discovered,
+            } => write!(f, "No MTL declared, using {:?}", discovered),
+            Warning::MaterialRedefined {
+                ref material,
+                ref library,
+            } => write!(
+                f,
+                "Material {} redefined in {:?}, using earlier definition",
+                material, library
+            ),
+            Warning::MaterialMerged {
+                ref material,
+                ref into,
+            } => write!(
+                f,
+                "Material {} is identical to {} and was merged into it",
+                material, into
+            ),
+            Warning::MissingTexture {
+                ref material,
+                ref path,
+            } => write!(
+                f,
+                "Texture {:?} of material {} not found, ignoring it",
+                path, material
+            ),
+            Warning::DegenerateFace { ref path, line } => {
+                write!(f, "{:?}:{}: Face has no area", path, line)
+            }
+            Warning::UnknownStatement {
+                ref path,
+                line,
+                ref statement,
+            } => write!(
+                f,
+                "{:?}:{}: Ignored unknown statement {}",
+                path, line, statement
+            ),
+            Warning::SkippedLine {
+                ref path,
+                line,
+                ref content,
+                ref reason,
+            } => write!(
+                f,
+                "{:?}:{}: Skipped \"{}\": {}",
+                path, line, content, reason
+            ),
+            Warning::IndexClamped {
+                ref path,
+                line,
+                ref corner,
+            } => write!(
+                f,
+                "{:?}:{}: Clamped out-of-range index in {}",
+                path, line, corner
+            ),
+            Warning::InvalidGeometry {
+                ref entity,
+                ref finding,
+            } => write!(
+                f,
+                "Entity {} has {} {}, the first at index {}",
+                entity, finding.count, finding.problem, finding.first
+            ),
+            Warning::MisorientedFaces {
+                ref entity,
+                triangles,
+            } => write!(
+                f,
+                "Entity {} has {} triangles wound the wrong way",
+                entity, triangles
+            ),
+            Warning::EntitySplit {
+                ref path,
+                line,
+                ref entity,
+            } => write!(
+                f,
+                "{:?}:{}: Continued entity {} in another entity at the vertex limit or memory budget",
+                path, line, entity
+            ),
+        }
+    }
+}
+
+/// Counts of the elements in a loaded scene, of the statements in the
+/// source files and of the fix-ups applied while loading.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadStats {
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    pub material_count: usize,
+    /// Number of statements of each kind in the OBJ files, by keyword, e.g.
+    /// `v` or `usemtl`. Unknown statements are only counted in `unknown_directives`.
+    pub directives: BTreeMap<String, usize>,
+    /// Number of statements with a keyword that is not part of the OBJ
+    /// specification and not supported as an extension.
+    pub unknown_directives: usize,
+    /// Number of faces with more than three vertices that were split into triangles.
+    pub triangulated_faces: usize,
+    /// Number of vertices that got generated normals because their entity
+    /// had none in the source file.
+    pub generated_normals: usize,
+    /// Number of face corners that were merged into the vertex of an earlier
+    /// corner with the same position, texture coordinate and normal indices,
+    /// plus the vertices merged by `LoadOptions::weld_vertices`.
+    pub welded_vertices: usize,
+    /// Number of bytes in the lines of the OBJ and MTL files, after
+    /// decompression and without line endings.
+    pub bytes_parsed: u64,
+    /// Time spent in each phase of loading.
+    pub timings: LoadTimings,
+    /// Sizes of the loaded entities, in the same order as the entities.
+    pub entities: Vec<EntityStats>,
+}
+
+impl LoadStats {
+    /// Gets the number of statements with the given keyword.
+    pub fn directive_count(&self, keyword: &str) -> usize {
+        self.directives.get(keyword).cloned().unwrap_or(0)
+    }
+
+    /// Gets the entity with the most triangles, if there are any entities.
+    pub fn largest_entity(&self) -> Option<&EntityStats> {
+        self.entities.iter().max_by_key(|e| e.triangle_count)
+    }
+
+    /// Adds the counts of the other statistics to these, except for the
+    /// material count, which cannot be summed up if materials are shared.
+    fn add(&mut self, other: &LoadStats) {
+        self.vertex_count += other.vertex_count;
+        self.triangle_count += other.triangle_count;
+        for (keyword, count) in other.directives.iter() {
+            *self.directives.entry(keyword.clone()).or_insert(0) += count;
+        }
+        self.unknown_directives += other.unknown_directives;
+        self.triangulated_faces += other.triangulated_faces;
+        self.generated_normals += other.generated_normals;
+        self.welded_vertices += other.welded_vertices;
+        self.bytes_parsed += other.bytes_parsed;
+        self.timings.parse += other.timings.parse;
+        self.timings.materials += other.timings.materials;
+        self.timings.convert += other.timings.convert;
+        self.entities.extend(other.entities.iter().cloned());
+    }
+
+    /// Copy of the statistics with all timings set to zero, so that the
+    /// statistics of two loads of the same file are equal.
+    #[cfg(test)]
+    pub(crate) fn without_timings(&self) -> LoadStats {
+        LoadStats {
+            timings: LoadTimings::default(),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for LoadStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} entities with {} vertices, {} triangles and {} materials from {} bytes in {:?}",
+            self.entities.len(),
+            self.vertex_count,
+            self.triangle_count,
+            self.material_count,
+            self.bytes_parsed,
+            self.timings.total()
+        )
+    }
+}
+
+/// Wall-clock time spent in the phases of loading.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadTimings {
+    /// Reading and parsing the OBJ files.
+    pub parse: Duration,
+    /// Reading and parsing MTL files and resolving texture maps.
+    pub materials: Duration,
+    /// Building entity meshes, including generated normals and texture
+    /// coordinates, and face adjacency if requested.
+    pub convert: Duration,
+}
+
+impl LoadTimings {
+    pub fn total(&self) -> Duration {
+        self.parse + self.materials + self.convert
+    }
+}
+
+/// Size of a single loaded entity.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntityStats {
+    pub name: String,
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+}
+
+/// Materials and entities referencing a texture file, see
+/// `LoadedScene::texture_usage`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextureUsage {
+    pub path: PathBuf,
+    /// Names of the materials that have a map with the texture, in
+    /// alphabetical order.
+    pub materials: Vec<String>,
+    /// Names of the entities with a material that has a map with the
+    /// texture, in the order of the entities, without duplicates.
+    pub entities: Vec<String>,
+}
+
+impl TextureUsage {
+    /// Checks if the texture is referenced by some material, but no entity
+    /// uses any of these materials.
+    pub fn is_unused(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+/// Entities using a material and the maps they consume through it, see
+/// `LoadedScene::material_usage`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialUsage {
+    pub material: String,
+    /// Keys of the maps of the material, e.g. `map_Kd`, in the order of the
+    /// material.
+    pub maps: Vec<String>,
+    /// Names of the entities with the material, in the order of the
+    /// entities, without duplicates.
+    pub entities: Vec<String>,
+}
+
+impl MaterialUsage {
+    /// Checks if no entity uses the material, so neither it nor its maps
+    /// would be saved.
+    pub fn is_unused(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+/// Moves the given entities and their extras by the given offset.
+///
+/// Meshes of instances stay shared, their transforms are moved instead.
+fn move_entities(entities: &mut [Entity], extras: &mut [EntityExtras], precise_offset: [f64; 3]) {
+    let offset = [
+        precise_offset[0] as f32,
+        precise_offset[1] as f32,
+        precise_offset[2] as f32,
+    ];
+    for (entity, extras) in entities.iter_mut().zip(extras.iter_mut()) {
+        match extras.instance_transform {
+            Some(ref mut transform) => {
+                for axis in 0..3 {
+                    transform[3][axis] += offset[axis];
+                }
+            }
+            None => {
+                let mut mesh = (*entity.mesh).clone();
+                // Precise positions are moved first, so they still round to
+                // the mesh positions
+                match extras.precise_positions {
+                    Some(ref mut precise) => {
+                        let positions = mesh.positions.chunks_mut(3);
+                        for (position, precise) in positions.zip(precise.chunks_mut(3)) {
+                            for axis in 0..3 {
+                                precise[axis] += precise_offset[axis];
+                                position[axis] = precise[axis] as f32;
+                            }
+                        }
+                    }
+                    None => {
+                        for position in mesh.positions.chunks_mut(3) {
+                            for (coordinate, offset) in position.iter_mut().zip(offset.iter()) {
+                                *coordinate += offset;
+                            }
+                        }
+                    }
+                }
+                entity.mesh = Rc::new(mesh);
+            }
+        }
+
+        if let Some(ref mut bounds) = extras.bounds {
+            for (axis, &offset) in offset.iter().enumerate() {
+                bounds.min[axis] += offset;
+                bounds.max[axis] += offset;
+                bounds.center[axis] += offset;
+            }
+        }
+        for surfel in extras.surfels.iter_mut().flatten() {
+            for (coordinate, offset) in surfel.position.iter_mut().zip(offset.iter()) {
+                *coordinate += offset;
+            }
+        }
+    }
+}
+
+impl LoadedScene {
+    /// Iterates over the entities along with their extra data.
+    pub fn iter(&self) -> impl Iterator<Item = (&Entity, &EntityExtras)> {
+        self.entities.iter().zip(self.extras.iter())
+    }
+
+    /// Iterates over the entities that were loaded from the file at the given
+    /// path, along with their extra data.
+    pub fn from_source<'a, P: AsRef<Path>>(
+        &'a self,
+        path: P,
+    ) -> impl Iterator<Item = (&'a Entity, &'a EntityExtras)> + 'a {
+        let path = path.as_ref().to_path_buf();
+        self.iter().filter(move |&(_, extras)| match extras.source {
+            Some(ref source) => source.path == path,
+            None => false,
+        })
+    }
+
+    /// Indices of the entities in each level of detail group, by group name
+    /// and sorted by level.
+    pub fn lod_groups(&self) -> BTreeMap<String, Vec<usize>> {
+        let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (idx, extras) in self.extras.iter().enumerate() {
+            if let Some(ref lod) = extras.lod {
+                groups.entry(lod.group.clone()).or_default().push(idx);
+            }
+        }
+        for entities in groups.values_mut() {
+            entities.sort_by_key(|&idx| self.extras[idx].lod.as_ref().map(|lod| lod.level));
+        }
+        groups
+    }
+
+    /// Bounds of all entities with bounds computed on load, see
+    /// `LoadOptions::bounds`.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.extras.iter().filter_map(|extras| extras.bounds).fold(
+            None,
+            |union, bounds| match union {
+                Some(union) => Some(bounds.union(&union)),
+                None => Some(bounds),
+            },
+        )
+    }
+
+    /// Moves the entities and everything else in the other scene into this one.
+    ///
+    /// If both scenes have a different material with the same name, the
+    /// material of the other scene is kept under its name prefixed with the
+    /// source path of the other scene, e.g. `b.obj:Material`.
+    ///
+    /// If the other scene has a different `origin`, its entities are moved
+    /// so that they are relative to the origin of this scene. An empty scene
+    /// takes the origin of the other scene.
+    pub fn append(&mut self, other: LoadedScene) {
+        let prefix = other
+            .extras
+            .iter()
+            .filter_map(|e| e.source.as_ref())
+            .map(|source| source.path.display().to_string())
+            .next()
+            .unwrap_or_default();
+
+        let mut other_properties = other.material_properties;
+        for (name, material) in other.materials {
+            let properties = other_properties.remove(&name);
+            let name = match self.materials.get(&name) {
+                Some(existing) if !Rc::ptr_eq(existing, &material) => {
+                    format!("{}:{}", prefix, name)
+                }
+                _ => name,
+            };
+            if let Some(properties) = properties {
+                self.material_properties.insert(name.clone(), properties);
+            }
+            self.materials.insert(name, material);
+        }
+
+        for texture in other.textures {
+            if !self.textures.contains(&texture) {
+                self.textures.push(texture);
+            }
+        }
+
+        // Symbols of the other scene refer to its own table
+        for name in other.names.iter() {
+            self.names.intern(name);
+        }
+        let names = &mut self.names;
+        let other_names = &other.names;
+        let mut reintern =
+            |symbol: Option<Symbol>| symbol.map(|symbol| names.intern(other_names.resolve(symbol)));
+        let other_extras = other.extras.into_iter().map(|extras| EntityExtras {
+            name_symbol: reintern(extras.name_symbol),
+            material_symbol: reintern(extras.material_symbol),
+            ..extras
+        });
+
+        // Entities of the other scene are moved to the origin of this one
+        let mut other_entities = other.entities;
+        let mut other_extras: Vec<EntityExtras> = other_extras.collect();
+        if self.entities.is_empty() && self.origin.is_none() {
+            self.origin = other.origin;
+        } else if other.origin != self.origin {
+            let this = self.origin.unwrap_or_default();
+            let that = other.origin.unwrap_or_default();
+            let offset = [that[0] - this[0], that[1] - this[1], that[2] - this[2]];
+            move_entities(&mut other_entities, &mut other_extras, offset);
+        }
+
+        self.entities.extend(other_entities);
+        self.extras.extend(other_extras);
+        self.warnings.extend(other.warnings);
+        self.stats.add(&other.stats);
+        self.stats.material_count = self.materials.len();
+    }
+
+    /// Gets the material with the given name.
+    pub fn material(&self, name: &str) -> Option<&Rc<Material>> {
+        self.materials.get(name)
+    }
+
+    /// Replaces the material with the given name in the material table and in
+    /// every entity that shares it, returning the old material.
+    ///
+    /// The new material is stored under its own name, which may differ from
+    /// the old one, and keeps the properties of the old one. If no material
+    /// with the given name exists, nothing is changed and `None` is returned.
+    pub fn replace_material(&mut self, name: &str, material: Material) -> Option<Rc<Material>> {
+        let old = self.materials.remove(name)?;
+        let new = Rc::new(material);
+        if let Some(properties) = self.material_properties.remove(name) {
+            self.material_properties
+                .insert(new.name().to_string(), properties);
+        }
+
+        for entity in self.entities.iter_mut() {
+            if Rc::ptr_eq(&entity.material, &old) {
+                entity.material = Rc::clone(&new);
+            }
+        }
+
+        self.materials.insert(new.name().to_string(), new);
+        Some(old)
+    }
+
+    /// Changes the material with the given name in the material table and in
+    /// every entity that shares it, using a builder initialized from the
+    /// material, and returns the old material like `replace_material`.
+    ///
+    /// The old material itself is left unchanged, so other scenes that share
+    /// it, such as the original of a `deep_clone`, are not affected.
+    pub fn update_material<F>(&mut self, name: &str, update: F) -> Option<Rc<Material>>
+    where
+        F: FnOnce(MaterialBuilder) -> MaterialBuilder,
+    {
+        let material = update(MaterialBuilder::from(&**self.materials.get(name)?)).build();
+        self.replace_material(name, material)
+    }
+
+    /// Gets the mesh of the entity at the given index for changing it,
+    /// cloning the mesh first if it is shared, e.g. with instances or with
+    /// another scene, so that only this entity is changed.
+    ///
+    /// Extra data computed from the mesh on load, such as bounds or face
+    /// adjacency, is not updated.
+    pub fn mesh_mut(&mut self, entity: usize) -> &mut DeinterleavedIndexedMeshBuf {
+        Rc::make_mut(&mut self.entities[entity].mesh)
+    }
+
+    /// Clones the scene without sharing any mesh or material with this one,
+    /// so that either can be changed in place without affecting the other.
+    ///
+    /// Meshes and materials that are shared within this scene, e.g. between
+    /// instances or between entities and the material table, are shared in
+    /// the same way in the clone.
+    pub fn deep_clone(&self) -> LoadedScene {
+        let mut meshes = Vec::new();
+        let mut materials = Vec::new();
+        let mut clone = self.clone();
+        for material in clone.materials.values_mut() {
+            *material = unshare(material, &mut materials);
+        }
+        for entity in clone.entities.iter_mut() {
+            entity.mesh = unshare(&entity.mesh, &mut meshes);
+            entity.material = unshare(&entity.material, &mut materials);
+        }
+        clone
+    }
+
+    /// Replaces the `old_base` prefix of all texture map paths with `new_base`,
+    /// updating both the materials and the entities, see `rebase_maps`.
+    pub fn rebase_maps<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, old_base: P, new_base: Q) {
+        let (old_base, new_base) = (old_base.as_ref(), new_base.as_ref());
+        self.resolve_maps(|path| match path.strip_prefix(old_base) {
+            Ok(relative) => new_base.join(relative),
+            Err(_) => path.to_path_buf(),
+        });
+    }
+
+    /// Replaces all texture map paths with the path returned by the resolver,
+    /// updating the materials, the entities and the maps in the material
+    /// properties, see `resolve_maps`.
+    pub fn resolve_maps<F: FnMut(&Path) -> PathBuf>(&mut self, mut resolver: F) {
+        let entity_materials = self.entities.iter_mut().map(|e| &mut e.material);
+        maps::resolve_maps(
+            self.materials.values_mut().chain(entity_materials),
+            &mut resolver,
+        );
+        for properties in self.material_properties.values_mut() {
+            for path in properties.maps_mut() {
+                *path = resolver(path);
+            }
+        }
+        self.collect_textures();
+    }
+
+    /// Maps each texture in `textures` to the materials and entities that
+    /// reference it, in the order of `textures`.
+    pub fn texture_usage(&self) -> Vec<TextureUsage> {
+        self.textures
+            .iter()
+            .map(|texture| {
+                let materials = self
+                    .materials
+                    .iter()
+                    .filter(|&(name, material)| self.has_texture(name, material, texture))
+                    .map(|(name, _)| name.clone())
+                    .collect();
+
+                let mut entities: Vec<String> = Vec::new();
+                for entity in self.entities.iter() {
+                    let material = &entity.material;
+                    if self.has_texture(material.name(), material, texture)
+                        && !entities.contains(&entity.name)
+                    {
+                        entities.push(entity.name.clone());
+                    }
+                }
+
+                TextureUsage {
+                    path: texture.clone(),
+                    materials,
+                    entities,
+                }
+            })
+            .collect()
+    }
+
+    /// Maps each material in `materials` to its maps and the entities that
+    /// use it, in the order of `materials`.
+    ///
+    /// After removing entities, e.g. to export only some of them, this
+    /// shows which materials and maps the remaining entities still need.
+    pub fn material_usage(&self) -> Vec<MaterialUsage> {
+        self.materials
+            .iter()
+            .map(|(name, material)| {
+                let mut entities: Vec<String> = Vec::new();
+                for entity in self.entities.iter() {
+                    if uses_material(entity, material) && !entities.contains(&entity.name) {
+                        entities.push(entity.name.clone());
+                    }
+                }
+
+                let mut maps: Vec<String> = material
+                    .maps()
+                    .iter()
+                    .map(|(key, _)| key.to_string())
+                    .collect();
+                maps.extend(self.property_maps(name).map(|(key, _)| key.to_string()));
+
+                MaterialUsage {
+                    material: name.clone(),
+                    maps,
+                    entities,
+                }
+            })
+            .collect()
+    }
+
+    /// Removes the materials that no entity uses from `materials`, along with
+    /// the textures only they reference from `textures`, and returns the names
+    /// of the removed materials.
+    pub fn remove_unused_materials(&mut self) -> Vec<String> {
+        let unused: Vec<String> = self
+            .material_usage()
+            .into_iter()
+            .filter(|usage| usage.is_unused())
+            .map(|usage| usage.material)
+            .collect();
+        if unused.is_empty() {
+            return unused;
+        }
+
+        for name in unused.iter() {
+            self.materials.remove(name);
+            self.material_properties.remove(name);
+        }
+        self.stats.material_count = self.materials.len();
+        self.collect_textures();
+        unused
+    }
+
+    /// Interns the names of all entities and materials and the keys of all
+    /// texture maps, setting the symbols in the entity extras.
+    pub(crate) fn intern_names(&mut self) {
+        for (name, material) in self.materials.iter() {
+            self.names.intern(name);
+            for (key, _) in material.maps().iter() {
+                self.names.intern(key);
+            }
+        }
+
+        for (entity, extras) in self.entities.iter().zip(self.extras.iter_mut()) {
+            extras.name_symbol = Some(self.names.intern(&entity.name));
+            extras.material_symbol = Some(self.names.intern(entity.material.name()));
+        }
+    }
+
+    pub(crate) fn collect_textures(&mut self) {
+        self.textures.clear();
+        for (name, material) in self.materials.iter() {
+            let maps = material.maps().iter().map(|(_, path)| PathBuf::from(path));
+            let property_maps: Vec<PathBuf> = self
+                .property_maps(name)
+                .map(|(_, path)| path.clone())
+                .collect();
+            for path in maps.chain(property_maps) {
+                if !self.textures.contains(&path) {
+                    self.textures.push(path);
+                }
+            }
+        }
+    }
+
+    /// Gets the maps in the properties of the material with the given name,
+    /// including the reflection maps.
+    fn property_maps<'a>(
+        &'a self,
+        name: &str,
+    ) -> impl Iterator<Item = (&'static str, &'a PathBuf)> {
+        self.material_properties
+            .get(name)
+            .into_iter()
+            .flat_map(|properties| {
+                let reflections = properties
+                    .reflection_maps
+                    .iter()
+                    .map(|map| ("refl", &map.path));
+                properties.maps().into_iter().chain(reflections)
+            })
+    }
+
+    /// Checks if the material with the given name has a map with the given
+    /// texture path, either in the material or in its properties.
+    fn has_texture(&self, name: &str, material: &Material, texture: &Path) -> bool {
+        has_map(material, texture) || self.property_maps(name).any(|(_, path)| path == texture)
+    }
+}
+
+/// Gets the clone of the given shared value, cloning it on first use and
+/// remembering the clone along with the original in `clones`.
+fn unshare<T: Clone>(shared: &Rc<T>, clones: &mut Vec<(Rc<T>, Rc<T>)>) -> Rc<T> {
+    if let Some((_, clone)) = clones
+        .iter()
+        .find(|(original, _)| Rc::ptr_eq(original, shared))
+    {
+        return Rc::clone(clone);
+    }
+    let clone = Rc::new((**shared).clone());
+    clones.push((Rc::clone(shared), Rc::clone(&clone)));
+    clone
+}
+
+/// Checks if the entity has the given material or an equal one.
+fn uses_material(entity: &Entity, material: &Rc<Material>) -> bool {
+    Rc::ptr_eq(&entity.material, material) || *entity.material == **material
+}
+
+/// Checks if the material has a map with the given texture path.
+fn has_map(material: &Material, texture: &Path) -> bool {
+    material
+        .maps()
+        .iter()
+        .any(|(_, path)| Path::new(path) == texture)
+}
+
+#[cfg(test)]
+mod test {
+    use obj::load;
+    use scene::MaterialBuilder;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_replace_material_updates_entities() {
+        let mut scene = load("tests/cube.obj").unwrap();
+        let replacement = MaterialBuilder::from(&**scene.material("Material").unwrap())
+            .name("Calibrated")
+            .build();
+
+        let old = scene.replace_material("Material", replacement).unwrap();
+
+        assert_eq!("Material", old.name());
+        assert!(scene.material("Material").is_none());
+        let new = scene.material("Calibrated").unwrap();
+        assert!(Rc::ptr_eq(new, &scene.entities[0].material));
+    }
+
+    #[test]
+    fn test_deep_clone_and_copy_on_write() {
+        let mut scene = load("tests/cube.obj").unwrap();
+        let instance = scene.entities[0].clone();
+        scene.entities.push(instance);
+        scene.extras.push(scene.extras[0].clone());
+
+        let mut clone = scene.deep_clone();
+        let (original, cloned) = (&scene.entities[0], &clone.entities[0]);
+        assert!(!Rc::ptr_eq(&original.mesh, &cloned.mesh));
+        assert!(!Rc::ptr_eq(&original.material, &cloned.material));
+        assert!(Rc::ptr_eq(&cloned.mesh, &clone.entities[1].mesh));
+        assert!(Rc::ptr_eq(
+            &cloned.material,
+            clone.material("Material").unwrap()
+        ));
+
+        clone.mesh_mut(1).positions[0] = 100.0;
+        assert_eq!(100.0, clone.entities[1].mesh.positions[0]);
+        assert_ne!(100.0, clone.entities[0].mesh.positions[0]);
+        assert_ne!(100.0, scene.entities[1].mesh.positions[0]);
+
+        let old = clone
+            .update_material("Material", |builder| builder.bump_map("bumps.png"))
+            .unwrap();
+        assert!(old.maps().is_empty());
+        assert!(scene.entities[0].material.maps().is_empty());
+        for entity in clone.entities.iter() {
+            assert_eq!(1, entity.material.maps().len());
+        }
+    }
+
+    #[test]
+    fn test_texture_usage() {
+        let mut scene = load("tests/cube.obj").unwrap();
+        let textured = MaterialBuilder::new()
+            .name("Textured")
+            .diffuse_color_map("wood.png")
+            .build();
+        let unused = MaterialBuilder::new()
+            .name("Unused")
+            .diffuse_color_map("wood.png")
+            .bump_map("bumps.png")
+            .build();
+        scene.replace_material("Material", textured);
+        scene
+            .materials
+            .insert("Unused".to_string(), Rc::new(unused));
+        scene.resolve_maps(|path| path.to_path_buf());
+
+        let usage = scene.texture_usage();
+        let wood = usage
+            .iter()
+            .find(|u| u.path == Path::new("wood.png"))
+            .unwrap();
+        let bumps = usage
+            .iter()
+            .find(|u| u.path == Path::new("bumps.png"))
+            .unwrap();
+
+        assert_eq!(2, usage.len());
+        assert_eq!(vec!["Textured", "Unused"], wood.materials);
+        assert_eq!(vec![scene.entities[0].name.clone()], wood.entities);
+        assert!(!wood.is_unused());
+        assert_eq!(vec!["Unused"], bumps.materials);
+        assert!(bumps.is_unused());
+    }
+
+    #[test]
+    fn test_material_usage() {
+        let mut scene = load("tests/cube.obj").unwrap();
+        let unused = MaterialBuilder::new()
+            .name("Unused")
+            .diffuse_color_map("wood.png")
+            .bump_map("bumps.png")
+            .build();
+        scene
+            .materials
+            .insert("Unused".to_string(), Rc::new(unused));
+        scene.resolve_maps(|path| path.to_path_buf());
+
+        let usage = scene.material_usage();
+        assert_eq!(2, usage.len());
+        assert_eq!("Material", usage[0].material);
+        assert_eq!(vec![scene.entities[0].name.clone()], usage[0].entities);
+        assert_eq!("Unused", usage[1].material);
+        assert_eq!(2, usage[1].maps.len());
+        assert!(usage[1].is_unused());
+
+        assert_eq!(vec!["Unused"], scene.remove_unused_materials());
+        assert_eq!(1, scene.materials.len());
+        assert!(scene.textures.is_empty());
+        assert!(scene.remove_unused_materials().is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+//! Level of detail groups, as declared by entity names like `chair_LOD0`.
+
+/// Position of an entity in a group of entities that show the same asset in
+/// decreasing detail, with level zero being the most detailed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LodLevel {
+    pub group: String,
+    pub level: u32,
+}
+
+impl LodLevel {
+    /// Recognizes names ending in `LOD` and a level, separated from the group
+    /// name with `_`, `-`, `.` or a space, e.g. `chair_LOD0` or `chair-lod2`.
+    /// Case is ignored.
+    pub fn parse(name: &str) -> Option<LodLevel> {
+        let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return None;
+        }
+        let (rest, level) = name.split_at(name.len() - digits);
+        let level = level.parse().ok()?;
+
+        if rest.len() < 3 || !rest.is_char_boundary(rest.len() - 3) {
+            return None;
+        }
+        let (rest, keyword) = rest.split_at(rest.len() - 3);
+        if !keyword.eq_ignore_ascii_case("lod") {
+            return None;
+        }
+        let group = rest.strip_suffix(&['_', '-', '.', ' '][..])?;
+        if group.is_empty() {
+            return None;
+        }
+
+        Some(LodLevel {
+            group: group.to_string(),
+            level,
+        })
+    }
+
+    /// The name of the entity in the conventional form, e.g. `chair_LOD1`.
+    pub fn name(&self) -> String {
+        format!("{}_LOD{}", self.group, self.level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let chair = |level| {
+            Some(LodLevel {
+                group: "chair".to_string(),
+                level,
+            })
+        };
+        assert_eq!(chair(0), LodLevel::parse("chair_LOD0"));
+        assert_eq!(chair(12), LodLevel::parse("chair-lod12"));
+        assert_eq!(chair(1), LodLevel::parse("chair Lod1"));
+        assert_eq!("chair_LOD1", LodLevel::parse("chair.LOD01").unwrap().name());
+        assert_eq!(None, LodLevel::parse("chair_LOD"));
+        assert_eq!(None, LodLevel::parse("Clod3"));
+        assert_eq!(None, LodLevel::parse("_LOD0"));
+        assert_eq!(None, LodLevel::parse("chair_2"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Changing the texture map paths of materials after loading.
+
+use scene::{Material, MaterialBuilder};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Replaces the `old_base` prefix of the map paths of the given materials with
+/// `new_base`. Maps outside of `old_base` are left unchanged.
+///
+/// Loaded map paths are usually canonical, so `old_base` should be as well.
+///
+/// Materials that were shared between multiple of the given `Rc`s are still
+/// shared after rebasing, e.g. when passing the materials of all entities.
+pub fn rebase_maps<'a, I, P, Q>(materials: I, old_base: P, new_base: Q)
+where
+    I: IntoIterator<Item = &'a mut Rc<Material>>,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let (old_base, new_base) = (old_base.as_ref(), new_base.as_ref());
+    resolve_maps(materials, |path| match path.strip_prefix(old_base) {
+        Ok(relative) => new_base.join(relative),
+        Err(_) => path.to_path_buf(),
+    })
+}
+
+/// Replaces the map paths of the given materials with the path returned by
+/// the resolver for each of them.
+///
+/// Materials that were shared between multiple of the given `Rc`s are still
+/// shared afterwards, and the resolver is only called once for each map of
+/// a shared material.
+pub fn resolve_maps<'a, I, F>(materials: I, mut resolver: F)
+where
+    I: IntoIterator<Item = &'a mut Rc<Material>>,
+    F: FnMut(&Path) -> PathBuf,
+{
+    let mut resolved: Vec<(Rc<Material>, Rc<Material>)> = Vec::new();
+
+    for material in materials {
+        let existing = resolved
+            .iter()
+            .find(|resolved| Rc::ptr_eq(&resolved.0, material))
+            .map(|resolved| Rc::clone(&resolved.1));
+
+        let new = match existing {
+            Some(new) => new,
+            None => {
+                let mut builder = MaterialBuilder::new().name(material.name().to_string());
+                for (key, path) in material.maps().iter() {
+                    builder = with_map(builder, key, resolver(path.as_ref()));
+                }
+
+                let new = Rc::new(builder.build());
+                resolved.push((Rc::clone(material), Rc::clone(&new)));
+                new
+            }
+        };
+
+        *material = new;
+    }
+}
+
+/// Checks if the given MTL keyword is one of a map that materials can have,
+/// see `with_map`.
+pub(crate) fn is_material_map(key: &str) -> bool {
+    matches!(
+        key,
+        "map_Kd"
+            | "map_Ka"
+            | "map_Ks"
+            | "bump"
+            | "disp"
+            | "norm"
+            | "map_Pr"
+            | "map_Pm"
+            | "map_Ps"
+            | "map_Ke"
+    )
+}
+
+/// Sets the map with the given MTL keyword on the builder.
+pub(crate) fn with_map(builder: MaterialBuilder, key: &str, path: PathBuf) -> MaterialBuilder {
+    match key {
+        "map_Kd" => builder.diffuse_color_map(path),
+        "map_Ka" => builder.ambient_color_map(path),
+        "map_Ks" => builder.specular_color_map(path),
+        "bump" => builder.bump_map(path),
+        "disp" => builder.displacement_map(path),
+        "norm" => builder.normal_map(path),
+        "map_Pr" => builder.roughness_map(path),
+        "map_Pm" => builder.metallic_map(path),
+        "map_Ps" => builder.sheen_map(path),
+        "map_Ke" => builder.emissive_map(path),
+        _ => {
+            warn!("Dropping map {:?} with unsupported key {}", path, key);
+            builder
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rebase_keeps_sharing() {
+        let shared = Rc::new(
+            MaterialBuilder::new()
+                .name("Shared")
+                .diffuse_color_map("/old/textures/wood.png")
+                .normal_map("/elsewhere/wood_normal.png")
+                .build(),
+        );
+        let mut materials = [Rc::clone(&shared), Rc::clone(&shared)];
+
+        rebase_maps(materials.iter_mut(), "/old", "/new/assets");
+
+        assert!(Rc::ptr_eq(&materials[0], &materials[1]));
+        let mut maps: Vec<_> = materials[0]
+            .maps()
+            .iter()
+            .map(|(key, path)| (key.to_string(), PathBuf::from(path)))
+            .collect();
+        maps.sort();
+        assert_eq!(
+            vec![
+                (
+                    "map_Kd".to_string(),
+                    PathBuf::from("/new/assets/textures/wood.png")
+                ),
+                (
+                    "norm".to_string(),
+                    PathBuf::from("/elsewhere/wood_normal.png")
+                ),
+            ],
+            maps
+        );
+    }
+}
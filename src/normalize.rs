@@ -0,0 +1,140 @@
+//! Unicode normalization of names and paths.
+//!
+//! The same name can be encoded in composed (NFC) or decomposed (NFD) form,
+//! e.g. macOS tends to produce decomposed file names, while most other
+//! software produces composed names. Both look the same, but do not compare
+//! equal unless normalized.
+//!
+//! Similarly, file names that only differ in case refer to the same file on
+//! Windows, but not on most other systems.
+//!
+//! Paths written on Windows also tend to use backslashes as separators, which
+//! only Windows understands.
+
+use std::path::{Component, Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+use vfs::FileSystem;
+
+/// Converts the given text to NFC.
+pub fn nfc(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Converts the given text to lowercase, for case-insensitive comparisons.
+pub fn lowercase(text: &str) -> String {
+    text.to_lowercase()
+}
+
+/// Converts backslashes in a path from an OBJ or MTL to forward slashes and
+/// collapses repeated separators, e.g. `textures\wood.png` or
+/// `textures\/wood.png` to `textures/wood.png`.
+///
+/// A leading double separator is kept, since it starts a UNC path.
+pub fn separators(path: &str) -> String {
+    let mut normalized = String::with_capacity(path.len());
+    for (idx, c) in path.chars().enumerate() {
+        let c = if c == '\\' { '/' } else { c };
+        if c == '/' && idx > 1 && normalized.ends_with('/') {
+            continue;
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+/// Looks for an existing file that has the given path when both are folded
+/// with the given function, e.g. `nfc`, comparing each component of the path
+/// with the directory entries in the given file system.
+///
+/// Components that exist as they are are preferred over folded matches.
+pub fn find_file<F>(path: &Path, fold: F, file_system: &dyn FileSystem) -> Option<PathBuf>
+where
+    F: Fn(&str) -> String,
+{
+    let mut found = PathBuf::new();
+
+    for component in path.components() {
+        let name = match component {
+            Component::Normal(name) => name,
+            other => {
+                found.push(other.as_os_str());
+                continue;
+            }
+        };
+
+        let candidate = found.join(name);
+        if file_system.exists(&candidate) {
+            found = candidate;
+            continue;
+        }
+
+        let name = fold(name.to_str()?);
+        let dir = if found.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            found.as_path()
+        };
+        let entry = file_system.read_dir(dir).ok()?.into_iter().find(|e| {
+            e.file_name()
+                .and_then(|entry_name| entry_name.to_str())
+                .map(|entry_name| fold(entry_name) == name)
+                .unwrap_or(false)
+        })?;
+
+        found.push(entry.file_name()?);
+    }
+
+    if file_system.is_file(&found) {
+        Some(found)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{remove_file, File};
+    use vfs::DiskFileSystem;
+
+    #[test]
+    fn test_composed_and_decomposed_equal_after_nfc() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+
+        assert_ne!(composed, decomposed);
+        assert_eq!(nfc(composed), nfc(decomposed));
+    }
+
+    #[test]
+    fn test_separators() {
+        assert_eq!("textures/wood.png", separators("textures\\wood.png"));
+        assert_eq!("C:/assets/wood.png", separators("C:\\assets\\/wood.png"));
+        assert_eq!(
+            "//server/share/a.png",
+            separators("\\\\server\\share//a.png")
+        );
+        assert_eq!("/abs/path.png", separators("/abs//path.png"));
+    }
+
+    #[test]
+    fn test_decomposed_file_found_by_composed_path() {
+        let decomposed = Path::new("tests/cafe\u{301}.png");
+        File::create(decomposed).unwrap();
+
+        let found = find_file(Path::new("tests/caf\u{e9}.png"), nfc, &DiskFileSystem);
+        remove_file(decomposed).unwrap();
+
+        assert_eq!(Some(decomposed.to_path_buf()), found);
+    }
+
+    #[test]
+    fn test_find_file_ignoring_case() {
+        let disk = &DiskFileSystem;
+        assert_eq!(None, find_file(Path::new("TESTS/Cube.OBJ"), nfc, disk));
+        assert_eq!(
+            Some(PathBuf::from("tests/cube.obj")),
+            find_file(Path::new("TESTS/Cube.OBJ"), lowercase, disk)
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Loading of OBJ files that returns before the textures are checked, for
+//! viewers that show geometry as early as possible.
+
+use err::Result;
+use loaded::LoadedScene;
+use obj::load::load_with;
+use obj::options::{LoadOptions, TextureCheck};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use vfs::{DiskFileSystem, FileSystem};
+
+/// Outcome of checking a single texture in the background.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureStatus {
+    /// The path of the texture as it is stored in the materials of the
+    /// loaded scene.
+    pub path: PathBuf,
+    /// The path the texture was found at, or `None` if it does not exist.
+    pub resolved: Option<PathBuf>,
+}
+
+impl TextureStatus {
+    pub fn is_missing(&self) -> bool {
+        self.resolved.is_none()
+    }
+}
+
+/// Texture checks that are still running after `load_staged` returned.
+///
+/// Iterating blocks until the next texture has been checked and ends when
+/// all of them have been.
+pub struct PendingTextures {
+    receiver: Receiver<TextureStatus>,
+    remaining: usize,
+}
+
+impl PendingTextures {
+    /// Number of textures that have not been received yet.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Returns the next checked texture if one is available, without
+    /// blocking. Useful for polling from an event loop.
+    pub fn try_next(&mut self) -> Option<TextureStatus> {
+        let status = self.receiver.try_recv().ok();
+        if status.is_some() {
+            self.remaining -= 1;
+        }
+        status
+    }
+
+    /// Blocks until all textures have been checked and returns the remaining
+    /// results.
+    pub fn wait(self) -> Vec<TextureStatus> {
+        self.collect()
+    }
+}
+
+impl Iterator for PendingTextures {
+    type Item = TextureStatus;
+
+    fn next(&mut self) -> Option<TextureStatus> {
+        let status = self.receiver.recv().ok();
+        if status.is_some() {
+            self.remaining -= 1;
+        }
+        status
+    }
+}
+
+/// Loads the OBJ file at the given path with the given options, but returns
+/// as soon as geometry and materials are loaded, checking textures on a
+/// background thread afterwards.
+///
+/// Materials reference texture paths relative to the OBJ or MTL, as with
+/// `TextureCheck::Skip`, and the scene does not report missing textures.
+/// Instead, the status of each texture in `textures` is sent through the
+/// returned `PendingTextures` as soon as it has been checked with the
+/// configured texture check. Textures that cannot be found are also looked
+/// for by file name in the search directories. Missing texture policies do
+/// not apply, since the materials are already in use when the checks finish.
+///
+/// A file system set with `LoadOptions::file_system` cannot be shared with
+/// another thread, so textures are then checked through it before returning
+/// and all of them are available right away.
+pub fn load_staged<P: Into<PathBuf>>(
+    from: P,
+    options: &LoadOptions,
+) -> Result<(LoadedScene, PendingTextures)> {
+    let geometry_options = options.clone().texture_check(TextureCheck::Skip);
+    let scene = load_with(from, &geometry_options)?;
+
+    let check = match (options.authored_texture_paths, options.texture_check) {
+        (true, TextureCheck::Canonicalize) => TextureCheck::Metadata,
+        (_, check) => check,
+    };
+    let textures = scene.textures.clone();
+    let search_dirs = options.search_dirs.clone();
+    let remaining = textures.len();
+
+    let (sender, receiver) = channel();
+    match options.file_system {
+        Some(ref file_system) => {
+            for path in textures {
+                let resolved = check_texture(&**file_system, &path, check, &search_dirs);
+                // Cannot fail while the receiver is still here
+                let _ = sender.send(TextureStatus { path, resolved });
+            }
+        }
+        None => {
+            thread::spawn(move || {
+                for path in textures {
+                    let resolved = check_texture(&DiskFileSystem, &path, check, &search_dirs);
+                    if sender.send(TextureStatus { path, resolved }).is_err() {
+                        // Nobody is interested in the results anymore
+                        break;
+                    }
+                }
+            });
+        }
+    }
+
+    Ok((
+        scene,
+        PendingTextures {
+            receiver,
+            remaining,
+        },
+    ))
+}
+
+fn check_texture(
+    file_system: &dyn FileSystem,
+    path: &Path,
+    check: TextureCheck,
+    search_dirs: &[PathBuf],
+) -> Option<PathBuf> {
+    let probe = |path: &Path| match check {
+        TextureCheck::Canonicalize => file_system.canonicalize(path).ok(),
+        TextureCheck::Metadata => Some(path.to_path_buf()).filter(|p| file_system.exists(p)),
+        TextureCheck::Skip => Some(path.to_path_buf()),
+    };
+
+    probe(path).or_else(|| {
+        let file_name = path.file_name()?;
+        search_dirs
+            .iter()
+            .filter_map(|dir| probe(&dir.join(file_name)))
+            .next()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempdir::tempdir_scope;
+    use vfs::MemoryFileSystem;
+
+    #[test]
+    fn test_textures_checked_in_background() {
+        tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_staged.obj");
+            let obj = fs::read_to_string("tests/cube.obj")?
+                .replace("mtllib cube.mtl", "mtllib cube_staged.mtl");
+            let mtl = fs::read_to_string("tests/cube.mtl")?
+                + "map_Kd cube.obj\nmap_Ks missing.png\nmap_Ka textures/cube.mtl\n";
+            fs::write(&obj_path, &obj)?;
+            fs::write(dir.join("cube.obj"), &obj)?;
+            fs::write(dir.join("cube_staged.mtl"), mtl)?;
+
+            let options = LoadOptions::new()
+                .texture_check(TextureCheck::Metadata)
+                .search_dir("tests");
+            let (scene, pending) = load_staged(&obj_path, &options)?;
+            assert_eq!(3, scene.textures.len());
+            assert_eq!(3, pending.remaining());
+
+            let statuses = pending.wait();
+            assert_eq!(
+                scene.textures,
+                statuses.iter().map(|s| s.path.clone()).collect::<Vec<_>>()
+            );
+            let missing: Vec<_> = statuses.iter().filter(|s| s.is_missing()).collect();
+            assert_eq!(1, missing.len());
+            assert_eq!(dir.join("missing.png"), missing[0].path);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_textures_checked_through_file_system() {
+        let files = MemoryFileSystem::new();
+        files.insert("assets/cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert(
+            "assets/cube.mtl",
+            "newmtl Material\nmap_Kd wood.png\nmap_Ks missing.png\n",
+        );
+        files.insert("assets/wood.png", "");
+
+        let options = LoadOptions::new().file_system(files.clone());
+        let (_, mut pending) = load_staged("assets/cube.obj", &options).unwrap();
+        let wood = pending.try_next().unwrap();
+        let missing = pending.try_next().unwrap();
+
+        assert!(wood.resolved.unwrap().ends_with("assets/wood.png"));
+        assert!(missing.is_missing());
+        assert_eq!(0, pending.remaining());
+    }
+}
@@ -0,0 +1,358 @@
+//! Parsing of OBJ files into a stream of statements, for tools that inspect
+//! or convert OBJ files without building meshes, such as linters or
+//! statistics gatherers.
+//!
+//! Statements are tokenized in the same way as by `load`, but nothing is
+//! triangulated, welded or converted.
+
+use err::Result;
+use loaded::Warning;
+use obj::options::{LoadOptions, ParseMode};
+use obj::parse::{
+    check_corner_count, decode_line, expect_end, open_file, parse_corner, parse_floats,
+    parse_smoothing_group, parse_texcoord, parse_vertex_color, raw_rest_of_line, read_lines,
+    rest_of_line, strip_comment, ParseResult, SyntaxError, Words, IGNORED_STATEMENTS,
+};
+use std::io::BufRead;
+use std::path::Path;
+
+/// A vertex of a face, line or point, with zero-based indices into the
+/// positions, texcoords and normals defined before it. Negative indices in
+/// the file are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjCorner {
+    pub position: usize,
+    pub texcoord: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+/// A statement of an OBJ file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjEvent {
+    /// A position from `v`, with the non-standard vertex color if present.
+    Vertex {
+        position: [f32; 3],
+        color: Option<[f32; 3]>,
+    },
+    /// Texture coordinates from `vt`, with zeroes for missing components.
+    Texcoord([f32; 3]),
+    /// A normal from `vn`.
+    Normal([f32; 3]),
+    Face(Vec<ObjCorner>),
+    Line(Vec<ObjCorner>),
+    Point(Vec<ObjCorner>),
+    /// Smoothing group from `s`, zero if smoothing is off.
+    Smoothing(u32),
+    /// Name of an object from `o`, which may be empty.
+    Object(String),
+    /// Name of a group from `g`, which may be empty.
+    Group(String),
+    UseMtl(String),
+    /// File name from `mtllib`, as written.
+    MtlLib(String),
+    /// Any other statement, with the rest of the line after the keyword.
+    /// These are statements of the specification that `load` ignores, or
+    /// unknown statements, which are an error in strict mode.
+    Other {
+        keyword: String,
+        params: String,
+    },
+}
+
+/// Parses the OBJ file at the given path and hands each statement to the
+/// given function, along with its line number, starting at one. Parse mode
+/// and encoding are taken from the given options.
+///
+/// Returns the warnings about lines that were skipped and indices that were
+/// clamped in lenient mode.
+pub fn read_events<P, F>(path: P, options: &LoadOptions, event: F) -> Result<Vec<Warning>>
+where
+    P: AsRef<Path>,
+    F: FnMut(usize, ObjEvent) -> Result<()>,
+{
+    let path = path.as_ref();
+    parse_events(open_file(path, options.vfs())?, path, options, event)
+}
+
+/// Parses OBJ statements from the given reader like `read_events`. The path
+/// is only used to report the location of errors.
+pub fn parse_events<R, F>(
+    reader: R,
+    path: &Path,
+    options: &LoadOptions,
+    mut event: F,
+) -> Result<Vec<Warning>>
+where
+    R: BufRead,
+    F: FnMut(usize, ObjEvent) -> Result<()>,
+{
+    let mut warnings = Vec::new();
+    let mut skip_budget = options.skip_budget();
+    // Numbers of positions, texcoords and normals, to resolve indices
+    let mut lens = [0; 3];
+
+    for (line_idx, bytes) in read_lines(reader).enumerate() {
+        let bytes = bytes?;
+        let line_number = line_idx + 1;
+
+        let (line, result) = match decode_line(&bytes, options.encoding) {
+            Ok(line) => {
+                let result = statement(strip_comment(&line), &mut lens, options.parse_mode);
+                (line, result)
+            }
+            Err(err) => (String::from_utf8_lossy(&bytes).into_owned(), Err(err)),
+        };
+        let line = strip_comment(&line);
+
+        match result {
+            Ok((parsed, clamped)) => {
+                for corner in clamped {
+                    warnings.push(Warning::IndexClamped {
+                        path: path.to_path_buf(),
+                        line: line_number,
+                        corner,
+                    });
+                }
+                if let Some(parsed) = parsed {
+                    event(line_number, parsed)?;
+                }
+            }
+            Err(err) if skip_budget > 0 => {
+                skip_budget -= 1;
+                warnings.push(err.skip(path, line_number, line));
+            }
+            Err(err) => return Err(err.locate(path, line_number, line)),
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Parses a single statement, if the line is not empty, along with the
+/// corners that had their indices clamped.
+fn statement(
+    line: &str,
+    lens: &mut [usize; 3],
+    mode: ParseMode,
+) -> ParseResult<(Option<ObjEvent>, Vec<String>)> {
+    let strict = mode == ParseMode::Strict;
+    let mut words = Words::new(line);
+    let first = match words.next() {
+        Some(first) => first,
+        None => return Ok((None, Vec::new())),
+    };
+    let keyword = first.text;
+
+    let mut clamped = Vec::new();
+    let event = match keyword {
+        "v" => {
+            let position = parse_floats(&mut words)?;
+            let color = parse_vertex_color(words, strict)?;
+            lens[0] += 1;
+            ObjEvent::Vertex { position, color }
+        }
+        "vt" => {
+            let texcoord = parse_texcoord(&mut words)?;
+            if strict {
+                expect_end(&mut words)?;
+            }
+            lens[1] += 1;
+            ObjEvent::Texcoord(texcoord)
+        }
+        "vn" => {
+            let normal = parse_floats(&mut words)?;
+            if strict {
+                expect_end(&mut words)?;
+            }
+            lens[2] += 1;
+            ObjEvent::Normal(normal)
+        }
+        "f" | "l" | "p" => {
+            let clamp = mode == ParseMode::Lenient;
+            let mut corners = Vec::new();
+            for word in words {
+                let ((position, texcoord, normal), was_clamped) = parse_corner(word, *lens, clamp)?;
+                if was_clamped {
+                    clamped.push(word.text.to_string());
+                }
+                corners.push(ObjCorner {
+                    position,
+                    texcoord,
+                    normal,
+                });
+            }
+            check_corner_count(keyword, corners.len())?;
+            match keyword {
+                "f" => ObjEvent::Face(corners),
+                "l" => ObjEvent::Line(corners),
+                _ => ObjEvent::Point(corners),
+            }
+        }
+        "s" => ObjEvent::Smoothing(parse_smoothing_group(&mut words)?),
+        "o" | "g" => {
+            let name = rest_of_line(words);
+            if strict && keyword == "o" && name.is_empty() {
+                return Err(SyntaxError::missing("Expected object name"));
+            }
+            if keyword == "o" {
+                ObjEvent::Object(name)
+            } else {
+                ObjEvent::Group(name)
+            }
+        }
+        "usemtl" => {
+            let material = rest_of_line(words);
+            if strict && material.is_empty() {
+                return Err(SyntaxError::missing("Expected material name"));
+            }
+            ObjEvent::UseMtl(material)
+        }
+        "mtllib" => ObjEvent::MtlLib(raw_rest_of_line(line, keyword)),
+        _ => {
+            if strict && !IGNORED_STATEMENTS.contains(&keyword) {
+                return Err(first.error("Unknown OBJ statement"));
+            }
+            ObjEvent::Other {
+                keyword: keyword.to_string(),
+                params: raw_rest_of_line(line, keyword),
+            }
+        }
+    };
+
+    Ok((Some(event), clamped))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use err::AssetError::Parse;
+    use obj::load;
+    use obj::parse::parse;
+
+    #[test]
+    fn test_events() {
+        let obj = "mtllib a.mtl\nv 0 0 0\nv 1 0 0 1 0.5 0.5\nv 0 1 0\nvt 0.5\n\n\
+                   o quad # comment\nusemtl A\ns off\nf 1/1 2/1 -1/1\ncall x.obj\n";
+        let mut events = Vec::new();
+        let warnings = parse_events(
+            obj.as_bytes(),
+            Path::new("test.obj"),
+            &LoadOptions::new(),
+            |line, event| {
+                events.push((line, event));
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(10, events.len());
+        assert_eq!((1, ObjEvent::MtlLib("a.mtl".to_string())), events[0]);
+        assert_eq!(
+            ObjEvent::Vertex {
+                position: [1.0, 0.0, 0.0],
+                color: Some([1.0, 0.5, 0.5]),
+            },
+            events[2].1
+        );
+        assert_eq!(ObjEvent::Texcoord([0.5, 0.0, 0.0]), events[4].1);
+        assert_eq!((7, ObjEvent::Object("quad".to_string())), events[5]);
+        assert_eq!(ObjEvent::Smoothing(0), events[7].1);
+        let corner = |position| ObjCorner {
+            position,
+            texcoord: Some(0),
+            normal: None,
+        };
+        assert_eq!(
+            ObjEvent::Face(vec![corner(0), corner(1), corner(2)]),
+            events[8].1
+        );
+        assert_eq!(
+            ObjEvent::Other {
+                keyword: "call".to_string(),
+                params: "x.obj".to_string(),
+            },
+            events[9].1
+        );
+    }
+
+    #[test]
+    fn test_events_agree_with_load() {
+        let mut faces = 0;
+        let mut positions = 0;
+        read_events("tests/cube.obj", &LoadOptions::new(), |_, event| {
+            match event {
+                ObjEvent::Face(corners) => faces += corners.len() - 2,
+                ObjEvent::Vertex { .. } => positions += 1,
+                _ => (),
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        let scene = load("tests/cube.obj").unwrap();
+        assert_eq!(8, positions);
+        assert_eq!(scene.stats.triangle_count, faces);
+    }
+
+    #[test]
+    fn test_lenient_events() {
+        let obj = "v 0 0 0\nv one 0 0\nf 1 2 3\n";
+        let options = LoadOptions::new().parse_mode(ParseMode::Lenient);
+        let mut events = Vec::new();
+        let warnings = parse_events(obj.as_bytes(), Path::new("test.obj"), &options, |_, e| {
+            events.push(e);
+            Ok(())
+        })
+        .unwrap();
+
+        // The malformed vertex is skipped, so the face refers to the first one
+        assert_eq!(2, events.len());
+        assert_eq!(3, warnings.len());
+        let strict = LoadOptions::new().parse_mode(ParseMode::Strict);
+        let error = parse_events(
+            "v 0 0 0 1 1 1\n".as_bytes(),
+            Path::new("test.obj"),
+            &strict,
+            |_, _| Ok(()),
+        );
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn test_too_few_corners_rejected_like_load() {
+        for obj in ["v 0 0 0\nv 1 0 0\nf 1 2\n", "v 0 0 0\nl 1\n"].iter() {
+            let events = parse_events(
+                obj.as_bytes(),
+                Path::new("test.obj"),
+                &LoadOptions::new(),
+                |_, _| Ok(()),
+            );
+            let loaded = parse(obj.as_bytes(), Path::new("test.obj"), &LoadOptions::new());
+            match (events, loaded) {
+                (
+                    Err(Parse { line, .. }),
+                    Err(Parse {
+                        line: load_line, ..
+                    }),
+                ) => {
+                    assert_eq!(load_line, line)
+                }
+                results => panic!("Expected both to fail: {:?}", results),
+            }
+        }
+
+        let mut events = Vec::new();
+        parse_events(
+            "v 0 0 0\nv 1 0 0\nl 1 2\np 1\n".as_bytes(),
+            Path::new("test.obj"),
+            &LoadOptions::new(),
+            |_, event| {
+                events.push(event);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(4, events.len());
+    }
+}
@@ -0,0 +1,263 @@
+//! Fetching OBJ files over HTTP, along with their MTL files and textures.
+//!
+//! Each load mirrors the files into a new private directory in the temporary
+//! directory, with the same layout as on the server, so the regular loader
+//! can read them and texture maps of loaded materials point to local copies.
+//! The directory is kept after loading, since the scene refers to it.
+
+use err::{AssetError, Result};
+use obj::mtl::{self, is_map_keyword, map_file_name};
+use obj::options::{Encoding, LoadOptions};
+use obj::parse::{
+    decode_line, open_file, raw_rest_of_line, read_lines, split_quoted, strip_comment,
+};
+use std::fs::{create_dir_all, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use tempdir::TempDir;
+use ureq;
+use url::Url;
+use vfs::DiskFileSystem;
+
+/// Checks if the given path to load is an HTTP or HTTPS URL.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Downloads the OBJ at the given URL, the MTL files it declares and the
+/// texture maps of their materials, returning the local path of the OBJ.
+///
+/// MTL files and textures that cannot be downloaded are skipped, so that
+/// loading reports them as missing.
+pub fn mirror(url: &str, options: &LoadOptions) -> Result<PathBuf> {
+    let url = Url::parse(url).map_err(|err| download_error(url, err.to_string()))?;
+    let dir = TempDir::new()?;
+    let root = dir.path().join(host_dir_name(&url));
+    let obj = root.join(url.path().trim_start_matches('/'));
+
+    download(&url, &obj)?;
+
+    for libs in material_libs(&obj)? {
+        // Like local files, a single library may contain spaces
+        let names = split_quoted(&libs);
+        let whole = if names.len() > 1 {
+            fetch_relative(&url, &root, &obj, &options.remap_path(&libs))
+        } else {
+            None
+        };
+        let libs = match whole {
+            Some(lib) => vec![lib],
+            None => names
+                .iter()
+                .filter_map(|lib| {
+                    let lib = options.remap_path(lib);
+                    fetch_relative(&url, &root, &obj, &lib)
+                        .or_else(|| fetch_relative(&url, &root, &obj, &format!("{}.gz", lib)))
+                })
+                .collect(),
+        };
+
+        for (lib_url, lib) in libs {
+            for material in mtl::parse_file(&lib, options)?.materials {
+                for (keyword, params) in material.params.iter() {
+                    if is_map_keyword(keyword) {
+                        let map = options.remap_path(&map_file_name(params));
+                        fetch_relative(&lib_url, &root, &lib, &map);
+                    }
+                }
+            }
+        }
+    }
+
+    dir.into_path();
+    Ok(obj)
+}
+
+/// Name of the directory in the mirror that holds the files of the host.
+fn host_dir_name(url: &Url) -> String {
+    let host = url.host_str().unwrap_or("localhost");
+    match url.port() {
+        Some(port) => format!("{}-{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+/// Gets the parameters of all `mtllib` statements in the downloaded OBJ.
+fn material_libs(obj: &Path) -> Result<Vec<String>> {
+    let mut libs = Vec::new();
+    for line in read_lines(open_file(obj, &DiskFileSystem)?) {
+        let line = line?;
+        let line = decode_line(&line, Encoding::Lossy).unwrap_or_default();
+        let line = strip_comment(&line);
+        if line.split_whitespace().next() == Some("mtllib") {
+            libs.push(raw_rest_of_line(line, "mtllib"));
+        }
+    }
+    Ok(libs)
+}
+
+/// Downloads the file referenced with the given relative path from a file
+/// at `base_url`, which has been mirrored to `base`. Returns the URL and local
+/// path of the file, or `None` if it could not be downloaded or would end up
+/// outside of the mirror.
+fn fetch_relative(base_url: &Url, root: &Path, base: &Path, path: &str) -> Option<(Url, PathBuf)> {
+    if path.is_empty() || Path::new(path).is_absolute() || path.contains(':') {
+        return None;
+    }
+
+    let url = base_url.join(path).ok()?;
+    let local = lexically_normalize(&base.parent()?.join(path));
+    if !local.starts_with(root) {
+        return None;
+    }
+
+    match download(&url, &local) {
+        Ok(()) => Some((url, local)),
+        Err(err) => {
+            warn!("{} could not be downloaded: {}", url, err);
+            None
+        }
+    }
+}
+
+/// Removes `.` and `..` from the given path without accessing the file system.
+fn lexically_normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn download(url: &Url, to: &Path) -> Result<()> {
+    let response = ureq::get(url.as_str())
+        .call()
+        .map_err(|err| download_error(url.as_str(), err.to_string()))?;
+
+    if let Some(dir) = to.parent() {
+        create_dir_all(dir).map_err(AssetError::Read)?;
+    }
+    let mut file = File::create(to).map_err(AssetError::Read)?;
+    io::copy(&mut response.into_reader(), &mut file).map_err(AssetError::Read)?;
+    Ok(())
+}
+
+fn download_error(url: &str, reason: String) -> AssetError {
+    AssetError::Download {
+        url: url.to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use obj::load::load;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves the files in `tests` over HTTP on a background thread,
+    /// returning the base URL.
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/assets/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let request: Vec<String> = BufReader::new(&stream)
+                    .lines()
+                    .map(|line| line.unwrap())
+                    .take_while(|line| !line.is_empty())
+                    .collect();
+                let path = request[0].split_whitespace().nth(1).unwrap();
+                let file = Path::new("tests").join(path.trim_start_matches("/assets/"));
+                let response = match fs::read(file) {
+                    Ok(body) => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend(body);
+                        response
+                    }
+                    Err(_) => {
+                        b"HTTP/1.1 404 Not Found\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                stream.write_all(&response).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_load_from_url() {
+        let url = serve();
+        let scene = load(format!("{}cube.obj", url)).unwrap();
+
+        assert_eq!(
+            load("tests/cube.obj").unwrap().stats.without_timings(),
+            scene.stats.without_timings()
+        );
+        assert!(scene.material("Material").is_some());
+        let source = scene.extras[0].source.as_ref().unwrap();
+        assert_eq!(PathBuf::from(format!("{}cube.obj", url)), source.path);
+    }
+
+    #[test]
+    fn test_mirror_per_load() {
+        let url = format!("{}cube.obj", serve());
+        let first = mirror(&url, &LoadOptions::new()).unwrap();
+        let second = mirror(&url, &LoadOptions::new()).unwrap();
+        let dirs: Vec<_> = [&first, &second]
+            .iter()
+            .map(|obj| obj.ancestors().nth(3).unwrap().to_path_buf())
+            .collect();
+        let mtls_mirrored =
+            first.with_extension("mtl").is_file() && second.with_extension("mtl").is_file();
+        #[cfg(unix)]
+        let modes: Vec<_> = {
+            use std::os::unix::fs::PermissionsExt;
+            dirs.iter()
+                .map(|dir| fs::metadata(dir).map(|meta| meta.permissions().mode() & 0o777))
+                .collect()
+        };
+        for dir in dirs.iter() {
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        assert_ne!(dirs[0], dirs[1]);
+        assert!(mtls_mirrored);
+        #[cfg(unix)]
+        for mode in modes {
+            assert_eq!(0o700, mode.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_references_stay_in_mirror() {
+        let base = Url::parse("http://localhost/assets/model.obj").unwrap();
+        let root = Path::new("/tmp/aitios-http/localhost");
+        let obj = root.join("assets/model.obj");
+
+        assert_eq!(
+            None,
+            fetch_relative(&base, root, &obj, "../../../etc/passwd")
+        );
+        assert_eq!(None, fetch_relative(&base, root, &obj, "C:/textures/a.png"));
+        assert_eq!(
+            root.join("textures/a.png"),
+            lexically_normalize(&obj.parent().unwrap().join("../textures/./a.png"))
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Material libraries that are loaded once and shared by many OBJ files,
+//! such as the master MTL of an environment kit.
+//!
+//! ```no_run
+//! # extern crate aitios_asset;
+//! use aitios_asset::obj::{self, LoadOptions, MaterialLibrary};
+//!
+//! # fn main() {
+//! let library = MaterialLibrary::load("kit/materials.mtl").unwrap();
+//! let options = LoadOptions::new()
+//!     .material_library(library)
+//!     .ignore_mtllib(true);
+//! let wall = obj::load_with("kit/wall.obj", &options).unwrap();
+//! let floor = obj::load_with("kit/floor.obj", &options).unwrap();
+//! # }
+//! ```
+
+use err::Result;
+use loaded::MaterialProperties;
+use obj::load::load_mtl_definitions;
+use obj::options::LoadOptions;
+use scene::Material;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Materials by name that OBJ files loaded with
+/// `LoadOptions::material_library` bind to with `usemtl`.
+///
+/// Every scene loaded with the library shares the same `Rc` for each of its
+/// materials.
+#[derive(Clone, Default)]
+pub struct MaterialLibrary {
+    materials: BTreeMap<String, (Rc<Material>, MaterialProperties)>,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> MaterialLibrary {
+        MaterialLibrary::default()
+    }
+
+    /// Loads the materials defined in the MTL file at the given path, with
+    /// texture maps resolved relative to the directory of the MTL file.
+    pub fn load<P: Into<PathBuf>>(from: P) -> Result<MaterialLibrary> {
+        MaterialLibrary::load_with(from, &LoadOptions::default())
+    }
+
+    /// Loads the materials in the MTL file at the given path like `load`, but
+    /// with the given options. If a material is defined more than once, only
+    /// the first definition is kept.
+    pub fn load_with<P: Into<PathBuf>>(from: P, options: &LoadOptions) -> Result<MaterialLibrary> {
+        let mut library = MaterialLibrary::new();
+        for (name, material, properties) in load_mtl_definitions(&from.into(), options)? {
+            library.materials.insert(name, (material, properties));
+        }
+        Ok(library)
+    }
+
+    /// Adds the given material under the given name, replacing a material
+    /// with the same name.
+    pub fn insert<S: Into<String>>(
+        &mut self,
+        name: S,
+        material: Rc<Material>,
+        properties: MaterialProperties,
+    ) {
+        self.materials.insert(name.into(), (material, properties));
+    }
+
+    /// Gets the material with the given name along with its properties.
+    pub fn get(&self, name: &str) -> Option<(&Rc<Material>, &MaterialProperties)> {
+        self.materials.get(name).map(|entry| (&entry.0, &entry.1))
+    }
+
+    /// Gets the names of the materials in lexicographic order.
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.materials.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+}
+
+impl fmt::Debug for MaterialLibrary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&String> = self.names().collect();
+        write!(f, "MaterialLibrary {{ materials: {:?} }}", names)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use obj::load::load_with;
+    use vfs::MemoryFileSystem;
+
+    #[test]
+    fn test_shared_material_library() {
+        let files = MemoryFileSystem::new();
+        files.insert(
+            "kit/materials.mtl",
+            "newmtl Brick\nmap_Kd brick.png\nnewmtl Wood\nmap_Kd wood.png\n",
+        );
+        files.insert("kit/brick.png", "");
+        files.insert("kit/wood.png", "");
+        files.insert(
+            "kit/wall.obj",
+            "mtllib wall.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl Brick\nf 1 2 3\n",
+        );
+        files.insert("kit/wall.mtl", "newmtl Brick\nmap_Kd missing.png\n");
+        files.insert(
+            "kit/floor.obj",
+            "mtllib gone.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl Wood\nf 1 2 3\n\
+             o Other\nusemtl Brick\nf 1 2 3\n",
+        );
+
+        let options = LoadOptions::new().file_system(files.clone());
+        let library = MaterialLibrary::load_with("kit/materials.mtl", &options).unwrap();
+        assert_eq!(2, library.len());
+
+        // The library wins over the broken definition in the OBJ's own MTL
+        let options = options.material_library(library);
+        let wall = load_with("kit/wall.obj", &options).unwrap();
+        assert!(wall.textures[0].ends_with("kit/brick.png"));
+
+        assert!(load_with("kit/floor.obj", &options).is_err());
+        let options = options.ignore_mtllib(true);
+        let floor = load_with("kit/floor.obj", &options).unwrap();
+        assert!(floor.warnings.is_empty());
+        assert_eq!(2, floor.materials.len());
+        assert!(Rc::ptr_eq(
+            &wall.materials["Brick"],
+            &floor.entities[1].material
+        ));
+        assert_eq!("Wood", floor.entities[0].material.name().as_str());
+    }
+}
@@ -1,73 +1,871 @@
+use adjacency::FaceAdjacency;
+use bounds::Bounds;
+use collision;
+use coords;
 use err::{AssetError::*, Result};
+use float::parse_f32;
+use instance;
+use loaded::{
+    ColorSpace, EntityExtras, EntityStats, LoadStats, LoadTimings, LoadedScene, MaterialProperties,
+    ReflectionMap, Source, Warning,
+};
+use lod::LodLevel;
+use maps;
+use normalize::{self, nfc};
+#[cfg(feature = "http")]
+use obj::http;
+use obj::mtl::{self, map_file_name, MtlMaterial};
+use obj::options::{
+    CoordinateSystem, LoadOptions, MissingTexcoords, MissingTextures, TextureCheck, Winding,
+};
+use obj::parse::{self, split_quoted};
 use scene::{DeinterleavedIndexedMeshBuf, Entity, Material, MaterialBuilder};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
 use std::iter::repeat;
+use std::mem;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use tobj;
+use std::time::Instant;
+use surfel;
+use tempdir::TempDir;
+use validate::validate_mesh;
+use vector;
+use vfs::FileSystem;
+use weld;
+use winding;
 
 /// Loads the entities stored in the OBJ file at the given path, also loading
 /// associated materials from the MTL file referenced in the OBJ.
-pub fn load<P: Into<PathBuf>>(from: P) -> Result<Vec<Entity>> {
+///
+/// Along with the entities, the materials, referenced textures, warnings and
+/// statistics are returned, as well as per-entity data that cannot be stored
+/// in entities.
+pub fn load<P: Into<PathBuf>>(from: P) -> Result<LoadedScene> {
+    load_with(from, &LoadOptions::default())
+}
+
+/// Loads only the entities stored in the OBJ file at the given path,
+/// discarding everything else that `load` returns.
+pub fn load_entities<P: Into<PathBuf>>(from: P) -> Result<Vec<Entity>> {
+    Ok(load(from)?.entities)
+}
+
+/// Loads the OBJ file at the given path like `load`, but with the given options.
+///
+/// With the `http` feature, the path may also be an HTTP or HTTPS URL. The
+/// OBJ is then downloaded along with the MTL files and textures it references
+/// relative to the URL, and loaded from a mirror in the temporary directory.
+pub fn load_with<P: Into<PathBuf>>(from: P, options: &LoadOptions) -> Result<LoadedScene> {
     let from = from.into();
-    let (models, materials) = tobj::load_obj(&from)?;
+    #[cfg(feature = "http")]
+    {
+        if let Some(url) = from.to_str().filter(|path| http::is_url(path)) {
+            // The mirror is always on disk
+            let options = &LoadOptions {
+                file_system: None,
+                ..options.clone()
+            };
+            let mut scene = load_with(http::mirror(url, options)?, options)?;
+            for extras in scene.extras.iter_mut() {
+                if let Some(ref mut source) = extras.source {
+                    source.path = from.clone();
+                }
+            }
+            return Ok(scene);
+        }
+    }
 
-    let materials = convert_materials(materials, &from)?;
-    let models = convert_models(models, &materials);
+    let start = Instant::now();
+    let obj = parse::parse_file(&from, options)?;
+    load_parsed(&from, obj, options, start)
+}
 
-    Ok(models)
+/// Loads the OBJ file at the given path with the given options in chunks,
+/// handing each chunk to the given function as a scene of its own, so that
+/// files larger than the memory budget set with
+/// `LoadOptions::memory_budget` can be processed.
+///
+/// A chunk is handed out as soon as the parsed data exceeds the budget,
+/// objects larger than the budget are continued in the next chunk. Vertex
+/// data is kept until the end, since faces may refer to any vertex defined
+/// before them. If it exceeds the budget on its own, loading fails. Without
+/// a budget, the whole file is one chunk.
+///
+/// Each chunk loads the material libraries declared so far. With
+/// `LoadOptions::recenter`, each chunk is recentered on its own, see
+/// `LoadedScene::append` to combine chunks. Unlike `load_with`, only local
+/// files are supported.
+pub fn load_chunks<P, F>(from: P, options: &LoadOptions, mut chunk: F) -> Result<()>
+where
+    P: Into<PathBuf>,
+    F: FnMut(LoadedScene) -> Result<()>,
+{
+    let from = from.into();
+    let mut start = Instant::now();
+    let reader = parse::open_file(&from, options.vfs())?;
+    let last = parse::parse_chunks(reader, &from, options, |obj| {
+        chunk(load_parsed(&from, obj, options, start)?)?;
+        start = Instant::now();
+        Ok(())
+    })?;
+    chunk(load_parsed(&from, last, options, start)?)
 }
 
-fn convert_models<I>(models: I, materials: &Vec<Rc<Material>>) -> Vec<Entity>
+/// Loads the materials of parsed OBJ data and converts it into a scene,
+/// with parsing having started at `start`.
+fn load_parsed(
+    from: &Path,
+    mut obj: parse::ObjData,
+    options: &LoadOptions,
+    start: Instant,
+) -> Result<LoadedScene> {
+    // The unit recorded in the frame header overrides the source unit
+    let with_unit;
+    let options = match obj.unit {
+        Some(unit) => {
+            with_unit = options.clone().units(unit, options.target_unit);
+            &with_unit
+        }
+        None => options,
+    };
+    if options.normalize_unicode {
+        for model in obj.models.iter_mut() {
+            model.name = nfc(&model.name);
+            model.material = model.material.as_ref().map(|m| nfc(m));
+        }
+    }
+    let mut warnings = mem::replace(&mut obj.warnings, Vec::new());
+    let parsed = Instant::now();
+
+    let libs = if options.ignore_mtllib {
+        Vec::new()
+    } else {
+        material_lib_paths(from, &obj, options, &mut warnings)
+    };
+    let (materials, mut material_ids) =
+        load_material_libs(&libs, options, &mut obj.stats.bytes_parsed, &mut warnings)?;
+    if options.merge_duplicate_materials {
+        let merged = merge_duplicate_materials(
+            &materials,
+            &mut material_ids,
+            options.merge_tolerance,
+            &mut warnings,
+        );
+        for model in obj.models.iter_mut() {
+            if let Some(kept) = model.material.as_ref().and_then(|m| merged.get(m)) {
+                model.material = Some(kept.clone());
+            }
+        }
+    }
+
+    let (mut materials, mut properties): (Vec<_>, Vec<_>) =
+        convert_materials(materials, from, options, &mut warnings)?
+            .into_iter()
+            .unzip();
+    if let Some(ref library) = options.material_library {
+        // Materials of the library take precedence over the ones of the OBJ
+        for name in obj
+            .models
+            .iter()
+            .filter_map(|model| model.material.as_ref())
+        {
+            if let Some((material, library_properties)) = library.get(name) {
+                let bound = match material_ids.get(name) {
+                    Some(&id) => Rc::ptr_eq(&materials[id], material),
+                    None => false,
+                };
+                if !bound {
+                    material_ids.insert(name.clone(), materials.len());
+                    materials.push(Rc::clone(material));
+                    properties.push(library_properties.clone());
+                }
+            }
+        }
+    }
+    let materials_loaded = Instant::now();
+
+    let mut scene = convert_models(obj.models, obj.stats, materials, &material_ids, options)?;
+    for (name, &id) in material_ids.iter() {
+        scene
+            .material_properties
+            .insert(name.clone(), properties[id].clone());
+    }
+    // Dissolve maps are only known from the properties
+    scene.collect_textures();
+    scene.origin = obj.origin.map(|origin| scene_origin(origin, options));
+    scene.warnings.extend(warnings);
+    scene.stats.timings = LoadTimings {
+        parse: parsed - start,
+        materials: materials_loaded - parsed,
+        convert: materials_loaded.elapsed(),
+    };
+
+    for (entity, extras) in scene.entities.iter().zip(scene.extras.iter_mut()) {
+        extras.source = Some(Source {
+            path: from.to_path_buf(),
+            object: entity.name.clone(),
+        });
+    }
+
+    if options.intern_names {
+        scene.intern_names();
+    }
+
+    Ok(scene)
+}
+
+/// Converts the offset subtracted from positions in the OBJ into the scene,
+/// in the same way as the positions.
+fn scene_origin(origin: [f64; 3], options: &LoadOptions) -> [f64; 3] {
+    let unit_scale = options.source_unit.precise_scale_to(options.target_unit);
+    let mut origin = coords::vector_to_scene(origin, options.coordinate_system);
+    for coordinate in origin.iter_mut() {
+        *coordinate *= unit_scale;
+    }
+
+    match options.transform {
+        Some(matrix) => {
+            let mut transformed = [0.0; 3];
+            for (row, component) in transformed.iter_mut().enumerate() {
+                *component = (0..3)
+                    .map(|col| f64::from(matrix[col][row]) * origin[col])
+                    .sum();
+            }
+            transformed
+        }
+        None => origin,
+    }
+}
+
+/// Loads multiple OBJ files with the given options and combines them into a
+/// single scene, in the order of the given paths.
+///
+/// The file and object each entity originates from is recorded in the
+/// `source` of its entity extras. See `LoadedScene::append` for how materials
+/// with the same name in different files are handled.
+pub fn load_many<I, P>(paths: I, options: &LoadOptions) -> Result<LoadedScene>
 where
-    I: IntoIterator<Item = tobj::Model>,
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
 {
-    // Default material if object or group does not have a material
-    let no_material = Rc::new(MaterialBuilder::new().name("NoMaterial").build());
+    let mut scene = LoadedScene::default();
+    for path in paths {
+        let loaded = load_with(path, options)?;
+        scene.append(loaded);
+    }
+    Ok(scene)
+}
+
+/// Loads the materials defined in the MTL file at the given path, in the
+/// order of definition, so they can be shared between multiple meshes.
+///
+/// Texture maps are resolved relative to the directory of the MTL file.
+pub fn load_mtl<P: Into<PathBuf>>(from: P) -> Result<Vec<Rc<Material>>> {
+    load_mtl_with(from, &LoadOptions::default())
+}
 
-    models
+/// Loads the materials in the MTL file at the given path like `load_mtl`, but
+/// with the given options.
+///
+/// If a material is defined more than once, only the first definition is
+/// returned.
+pub fn load_mtl_with<P: Into<PathBuf>>(
+    from: P,
+    options: &LoadOptions,
+) -> Result<Vec<Rc<Material>>> {
+    Ok(load_mtl_definitions(&from.into(), options)?
         .into_iter()
-        .map(|m| {
-            Entity {
-                name: m.name,
-                // Reference same material for each with same index,
-                // If no index, add a synthetic no_material with default properties.
-                material: m
-                    .mesh
-                    .material_id
-                    .map(|id| Rc::clone(&materials[id]))
-                    .unwrap_or_else(|| Rc::clone(&no_material)),
-                // DeinterleavedIndexedMeshBuf has format compatible to tobj,
-                // just move the vectors and we are done
-                mesh: tobj_mesh_to_aitios_mesh(m.mesh),
+        .map(|(_, material, _)| material)
+        .collect())
+}
+
+/// Loads the first definition of each material in the MTL file at the given
+/// path, in the order of definition, along with the name it is defined with
+/// and its properties.
+pub(crate) fn load_mtl_definitions(
+    from: &Path,
+    options: &LoadOptions,
+) -> Result<Vec<(String, Rc<Material>, MaterialProperties)>> {
+    let mut warnings = Vec::new();
+
+    let (materials, material_ids) =
+        load_material_libs(&[from.to_path_buf()], options, &mut 0, &mut warnings)?;
+    for warning in warnings.iter() {
+        warn!("{}", warning);
+    }
+
+    let mut first_definitions: Vec<usize> = material_ids.values().cloned().collect();
+    first_definitions.sort();
+
+    let mut materials: Vec<_> = materials.into_iter().map(Some).collect();
+    let materials: Vec<MtlMaterial> = first_definitions
+        .into_iter()
+        .filter_map(|id| materials[id].take())
+        .collect();
+    // Overrides may rename materials, keep the name they are used with
+    let names: Vec<String> = materials.iter().map(|m| m.name.clone()).collect();
+
+    Ok(names
+        .into_iter()
+        .zip(convert_materials(materials, from, options, &mut warnings)?)
+        .map(|(name, (material, properties))| (name, material, properties))
+        .collect())
+}
+
+/// Determines the paths of the MTL files to load for the given OBJ.
+///
+/// These are the paths in all `mtllib` statements without duplicates, unless
+/// MTL discovery is enabled and there are no statements or they refer to a
+/// non-existing file.
+fn material_lib_paths(
+    obj_file: &Path,
+    obj: &parse::ObjData,
+    options: &LoadOptions,
+    warnings: &mut Vec<Warning>,
+) -> Vec<PathBuf> {
+    let obj_parent = obj_file.parent().unwrap_or_else(|| Path::new("."));
+    let mut declared_libs: Vec<PathBuf> = Vec::new();
+    for libs in obj.material_libs.iter() {
+        for lib in declared_material_libs(obj_parent, libs, options) {
+            // Some exporters repeat the statement for each object
+            if !declared_libs.contains(&lib) {
+                declared_libs.push(lib);
+            }
+        }
+    }
+
+    let file_system = options.vfs();
+    if !declared_libs.is_empty() && declared_libs.iter().all(|lib| file_system.is_file(lib)) {
+        return declared_libs;
+    }
+
+    if !options.discover_mtl {
+        // Let loading fail with the declared paths
+        return declared_libs;
+    }
+
+    // Report the first library that is missing, if any
+    let declared = declared_libs
+        .iter()
+        .find(|lib| !file_system.is_file(lib))
+        .cloned();
+    let discovered = discover_mtl(obj_file, file_system);
+    match (&declared, &discovered) {
+        (&Some(ref declared), &Some(ref discovered)) => warn!(
+            "MTL {:?} referenced in {:?} not found, using {:?} instead",
+            declared, obj_file, discovered
+        ),
+        (&None, &Some(ref discovered)) => warn!(
+            "No MTL referenced in {:?}, using {:?}",
+            obj_file, discovered
+        ),
+        (&Some(ref declared), &None) => warn!(
+            "MTL {:?} referenced in {:?} not found and no replacement could be discovered",
+            declared, obj_file
+        ),
+        (&None, &None) => (),
+    }
+
+    if let Some(ref discovered) = discovered {
+        warnings.push(Warning::MtlDiscovered {
+            declared: declared.clone(),
+            discovered: discovered.clone(),
+        });
+    }
+
+    // Keep the declared libraries that exist, only the missing ones are
+    // replaced with the discovered library
+    let mut libs: Vec<PathBuf> = Vec::new();
+    for lib in declared_libs.iter() {
+        let lib = if file_system.is_file(lib) {
+            lib
+        } else {
+            match discovered {
+                Some(ref discovered) => discovered,
+                None => continue,
+            }
+        };
+        if !libs.contains(lib) {
+            libs.push(lib.clone());
+        }
+    }
+    if libs.is_empty() {
+        libs.extend(discovered);
+    }
+    libs
+}
+
+/// Splits the rest of an `mtllib` statement into the paths of the listed
+/// libraries.
+///
+/// The statement may list multiple libraries separated by spaces, with file
+/// names that contain spaces in double quotes. However, if the whole statement
+/// refers to an existing file, it is treated as a single unquoted file name
+/// that contains spaces. Likewise, consecutive words are joined if they refer
+/// to an existing file, e.g. `mtllib base.mtl my scene.mtl`.
+fn declared_material_libs(obj_parent: &Path, libs: &str, options: &LoadOptions) -> Vec<PathBuf> {
+    let file_system = options.vfs();
+    let lib_path = |name: &str| obj_parent.join(options.remap_path(name));
+    let whole = lib_path(libs);
+    if file_system.is_file(&whole) {
+        return vec![whole];
+    }
+
+    let words = split_quoted(libs);
+    let mut names = Vec::new();
+    let mut start = 0;
+    while start < words.len() {
+        // Take as many words as form the name of an existing file
+        let end = (start + 2..=words.len())
+            .rev()
+            .find(|&end| file_system.is_file(&lib_path(&words[start..end].join(" "))))
+            .unwrap_or(start + 1);
+        names.push(words[start..end].join(" "));
+        start = end;
+    }
+
+    names
+        .iter()
+        .map(|lib| lib_path(lib))
+        .map(|lib| {
+            if file_system.is_file(&lib) {
+                lib
+            } else if let Some(compressed) = gzip_variant(&lib, file_system) {
+                compressed
+            } else {
+                find_variant(&lib, options).unwrap_or(lib)
             }
         })
         .collect()
 }
 
-fn tobj_mesh_to_aitios_mesh(mesh: tobj::Mesh) -> Rc<DeinterleavedIndexedMeshBuf> {
-    let tobj::Mesh {
+/// Gets the path with `.gz` appended if a file exists there, e.g. because the
+/// MTL referenced by an OBJ has been compressed.
+fn gzip_variant(path: &Path, file_system: &dyn FileSystem) -> Option<PathBuf> {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+    let compressed = PathBuf::from(compressed);
+
+    if file_system.is_file(&compressed) {
+        Some(compressed)
+    } else {
+        None
+    }
+}
+
+/// Loads the given MTL files and merges their materials.
+///
+/// If a material is defined in multiple files, the definition in the file
+/// listed first is used and a warning is recorded.
+fn load_material_libs(
+    libs: &[PathBuf],
+    options: &LoadOptions,
+    bytes_parsed: &mut u64,
+    warnings: &mut Vec<Warning>,
+) -> Result<(Vec<MtlMaterial>, HashMap<String, usize>)> {
+    let mut materials = Vec::new();
+    let mut material_ids = HashMap::new();
+
+    for lib in libs {
+        let mut library = mtl::parse_file(lib, options)?;
+        if options.normalize_unicode {
+            for material in library.materials.iter_mut() {
+                material.name = nfc(&material.name);
+            }
+            library.ids = library
+                .ids
+                .into_iter()
+                .map(|(name, id)| (nfc(&name), id))
+                .collect();
+        }
+
+        let offset = materials.len();
+        *bytes_parsed += library.bytes;
+        warnings.extend(library.warnings);
+
+        for (name, id) in library.ids {
+            if material_ids.contains_key(&name) {
+                warn!(
+                    "Material {} in {:?} was already defined in another MTL, ignoring it",
+                    name, lib
+                );
+                warnings.push(Warning::MaterialRedefined {
+                    material: name,
+                    library: lib.clone(),
+                });
+            } else {
+                material_ids.insert(name, offset + id);
+            }
+        }
+
+        materials.extend(library.materials);
+    }
+
+    Ok((materials, material_ids))
+}
+
+/// Removes materials from `material_ids` that have the same statements as
+/// a material defined before them, returning the name of the earlier
+/// material for the name of each removed material.
+///
+/// Map paths are only compared as written, so materials in different
+/// directories are never merged if texture paths are kept as authored.
+/// Numbers in statements may differ by the given tolerance.
+fn merge_duplicate_materials(
+    materials: &[MtlMaterial],
+    material_ids: &mut HashMap<String, usize>,
+    tolerance: f32,
+    warnings: &mut Vec<Warning>,
+) -> HashMap<String, String> {
+    let mut ids: Vec<usize> = material_ids.values().cloned().collect();
+    ids.sort();
+
+    // Kept materials by directory and keywords of their statements
+    let mut kept: HashMap<_, Vec<&MtlMaterial>> = HashMap::new();
+    let mut merged = HashMap::new();
+    for id in ids {
+        let material = &materials[id];
+        let mut keywords: Vec<_> = material.params.keys().collect();
+        keywords.sort();
+        let candidates = kept
+            .entry((material.library.parent(), keywords))
+            .or_insert_with(Vec::new);
+
+        let same = candidates.iter().find(|kept| {
+            kept.params
+                .iter()
+                .all(|(keyword, value)| same_statement(value, &material.params[keyword], tolerance))
+        });
+        match same {
+            Some(kept) => {
+                warnings.push(Warning::MaterialMerged {
+                    material: material.name.clone(),
+                    into: kept.name.clone(),
+                });
+                material_ids.remove(&material.name);
+                merged.insert(material.name.clone(), kept.name.clone());
+            }
+            None => candidates.push(material),
+        }
+    }
+
+    merged
+}
+
+/// Checks if the parameters of two MTL statements are equal, or consist of
+/// the same number of numbers that differ by at most the given tolerance.
+fn same_statement(a: &str, b: &str, tolerance: f32) -> bool {
+    if a == b {
+        return true;
+    }
+    let numbers =
+        |params: &str| -> Option<Vec<f32>> { params.split_whitespace().map(parse_f32).collect() };
+    match (numbers(a), numbers(b)) {
+        (Some(a), Some(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|(a, b)| (a - b).abs() <= tolerance)
+        }
+        _ => false,
+    }
+}
+
+/// Looks for an MTL file with the same stem next to the given OBJ file, or
+/// otherwise for the only MTL file in the same directory.
+fn discover_mtl(obj_file: &Path, file_system: &dyn FileSystem) -> Option<PathBuf> {
+    let same_stem = obj_file.with_extension("mtl");
+    if file_system.is_file(&same_stem) {
+        return Some(same_stem);
+    }
+
+    let obj_parent = obj_file.parent().unwrap_or_else(|| &Path::new("."));
+    let obj_parent = if obj_parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        obj_parent
+    };
+
+    let mut mtl_files = file_system
+        .read_dir(obj_parent)
+        .ok()?
+        .into_iter()
+        .filter(|path| {
+            file_system.is_file(path)
+                && path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| e.eq_ignore_ascii_case("mtl"))
+                    .unwrap_or(false)
+        });
+
+    match (mtl_files.next(), mtl_files.next()) {
+        (Some(only), None) => Some(only),
+        _ => None,
+    }
+}
+
+fn convert_models(
+    models: Vec<parse::Model>,
+    parse_stats: LoadStats,
+    materials: Vec<Rc<Material>>,
+    material_ids: &HashMap<String, usize>,
+    options: &LoadOptions,
+) -> Result<LoadedScene> {
+    // Default material if object or group does not have a material
+    let no_material = Rc::new(MaterialBuilder::new().name("NoMaterial").build());
+    let mut scene = LoadedScene::default();
+    let mut no_material_used = false;
+    let mut generated_normals = 0;
+    let mut welded_vertices = 0;
+
+    for mut model in models {
+        // Reference same material for each with same name,
+        // If no name or unknown name, add a synthetic no_material with default properties.
+        let material = match model.material {
+            Some(ref name) => match material_ids.get(name) {
+                Some(&id) => Rc::clone(&materials[id]),
+                None => {
+                    scene.warnings.push(Warning::UnknownMaterial {
+                        entity: model.name.clone(),
+                        material: name.clone(),
+                    });
+                    no_material_used = true;
+                    Rc::clone(&no_material)
+                }
+            },
+            None => {
+                no_material_used = true;
+                Rc::clone(&no_material)
+            }
+        };
+
+        let face_sizes = if options.preserve_polygons {
+            Some(model.mesh.face_sizes.clone())
+        } else {
+            None
+        };
+        let colors = if model.mesh.colors.is_empty() {
+            None
+        } else {
+            Some(mem::replace(&mut model.mesh.colors, Vec::new()))
+        };
+
+        let texcoord_ws = if model.mesh.texcoord_ws.is_empty() {
+            None
+        } else {
+            Some(mem::replace(&mut model.mesh.texcoord_ws, Vec::new()))
+        };
+
+        let precise_positions =
+            if options.double_precision && !model.mesh.precise_positions.is_empty() {
+                Some(mem::replace(&mut model.mesh.precise_positions, Vec::new()))
+            } else {
+                None
+            };
+
+        let collision_for = collision::render_name(&model.name);
+        scene.extras.push(EntityExtras {
+            face_sizes,
+            colors,
+            texcoord_ws,
+            lines: mem::replace(&mut model.mesh.lines, Vec::new()),
+            points: mem::replace(&mut model.mesh.points, Vec::new()),
+            adjacency: None,
+            surfels: None,
+            precise_positions,
+            bounds: None,
+            instance_transform: None,
+            lod: collision_for
+                .as_ref()
+                .map_or_else(|| LodLevel::parse(&model.name), |_| None),
+            collision_for,
+            source: None,
+            name_symbol: None,
+            material_symbol: None,
+        });
+        if model.mesh.normals.is_empty() {
+            generated_normals += model.mesh.positions.len() / 3;
+        }
+
+        let name = model.name;
+        let mut mesh = convert_mesh(model.mesh, options).map_err(|err| match err {
+            InvalidData(msg) => InvalidData(format!("{} in entity {}", msg, name)),
+            err => err,
+        })?;
+
+        let extras = scene.extras.last_mut().unwrap();
+        if options.coordinate_system != CoordinateSystem::default() {
+            coords::to_scene(
+                &mut mesh,
+                extras.face_sizes.as_ref(),
+                options.coordinate_system,
+            );
+        }
+        let unit_scale = options.source_unit.scale_to(options.target_unit);
+        if unit_scale != 1.0 {
+            coords::scale(&mut mesh, unit_scale);
+        }
+        if options.flip_v {
+            coords::flip_v(&mut mesh);
+        }
+        if let Some(matrix) = options.transform {
+            coords::apply_matrix(&mut mesh, extras.face_sizes.as_ref(), matrix);
+        }
+        if let Some(ref mut precise) = extras.precise_positions {
+            coords::precise_to_scene(precise, options.coordinate_system);
+            let unit_scale = options.source_unit.precise_scale_to(options.target_unit);
+            for coordinate in precise.iter_mut() {
+                *coordinate *= unit_scale;
+            }
+            if let Some(matrix) = options.transform {
+                coords::precise_apply_matrix(precise, matrix);
+            }
+            // Rounding once is more precise than converting in single precision
+            for (position, &precise) in mesh.positions.iter_mut().zip(precise.iter()) {
+                *position = precise as f32;
+            }
+        }
+        match options.winding {
+            Winding::Keep => (),
+            Winding::Detect => {
+                let triangles = winding::misoriented_triangles(&mesh, extras.face_sizes.as_ref());
+                if triangles > 0 {
+                    scene.warnings.push(Warning::MisorientedFaces {
+                        entity: name.clone(),
+                        triangles,
+                    });
+                }
+            }
+            Winding::Flip => winding::flip(&mut mesh, extras.face_sizes.as_ref()),
+            Winding::Orient => {
+                winding::orient(&mut mesh, extras.face_sizes.as_ref());
+            }
+        }
+        if let Some(epsilon) = options.weld_epsilon {
+            welded_vertices += weld::weld(&mut mesh, extras, epsilon);
+        }
+        if options.face_adjacency {
+            extras.adjacency = Some(FaceAdjacency::build(&mesh.positions, &mesh.indices));
+        }
+        if let Some(density) = options.surfel_density {
+            extras.surfels = Some(surfel::sample(&mesh, density));
+        }
+        if options.bounds {
+            extras.bounds = Bounds::of(&mesh.positions);
+        }
+        if options.validate_geometry {
+            for finding in validate_mesh(&mesh) {
+                scene.warnings.push(Warning::InvalidGeometry {
+                    entity: name.clone(),
+                    finding,
+                });
+            }
+        }
+
+        scene.entities.push(Entity {
+            name,
+            material,
+            mesh: Rc::new(mesh),
+        });
+    }
+
+    if options.detect_instances {
+        let transforms = instance::share_instances(&mut scene.entities);
+        for (extras, transform) in scene.extras.iter_mut().zip(transforms) {
+            if transform.filter(|&t| t != instance::IDENTITY).is_some() {
+                extras.precise_positions = None;
+            }
+            extras.instance_transform = transform;
+        }
+    }
+
+    // If names are duplicated, keep the material that entities reference
+    for (name, &id) in material_ids.iter() {
+        scene
+            .materials
+            .insert(name.clone(), Rc::clone(&materials[id]));
+    }
+    if no_material_used {
+        scene
+            .materials
+            .entry(no_material.name().to_string())
+            .or_insert(no_material);
+    }
+
+    for material in scene.materials.values() {
+        for (_, path) in material.maps().iter() {
+            let path = PathBuf::from(path);
+            if !scene.textures.contains(&path) {
+                scene.textures.push(path);
+            }
+        }
+    }
+
+    scene.stats = LoadStats {
+        vertex_count: scene
+            .entities
+            .iter()
+            .map(|e| e.mesh.positions.len() / 3)
+            .sum(),
+        triangle_count: scene
+            .entities
+            .iter()
+            .map(|e| e.mesh.indices.len() / 3)
+            .sum(),
+        material_count: scene.materials.len(),
+        generated_normals,
+        welded_vertices: parse_stats.welded_vertices + welded_vertices,
+        entities: scene
+            .entities
+            .iter()
+            .map(|e| EntityStats {
+                name: e.name.clone(),
+                vertex_count: e.mesh.positions.len() / 3,
+                triangle_count: e.mesh.indices.len() / 3,
+            })
+            .collect(),
+        ..parse_stats
+    };
+
+    Ok(scene)
+}
+
+fn convert_mesh(mesh: parse::Mesh, options: &LoadOptions) -> Result<DeinterleavedIndexedMeshBuf> {
+    let parse::Mesh {
         positions,
-        normals,
+        mut normals,
         mut texcoords,
         indices,
         ..
     } = mesh;
 
     if normals.len() == 0 {
-        // If mesh does not define any normals, panic
-        panic!("Tried to load OBJ file without normals");
-
-        // TODO instead of panicking, calculate the normals
+        // If mesh does not define any normals, calculate them. Vertices have
+        // been split by the parser where smoothing groups end, so averaging
+        // over adjacent faces keeps hard edges hard.
+        normals = generate_normals(&positions, &indices);
     }
 
     if texcoords.len() == 0 {
-        // If no texcoords defined, assume them as (0.0, 0.0)
-        let zero_texcoords = repeat(0.0).take((positions.len() / 3) * 2);
-
-        texcoords.extend(zero_texcoords);
+        texcoords = match options.missing_texcoords {
+            // Assume them as (0.0, 0.0)
+            MissingTexcoords::Zero => repeat(0.0).take((positions.len() / 3) * 2).collect(),
+            MissingTexcoords::Planar => planar_texcoords(&positions),
+            MissingTexcoords::Box => box_texcoords(&positions, &normals),
+            MissingTexcoords::Error => {
+                return Err(InvalidData(
+                    "OBJ does not define texture coordinates".to_string(),
+                ))
+            }
+        };
     }
 
-    Rc::new(DeinterleavedIndexedMeshBuf {
+    // DeinterleavedIndexedMeshBuf has format compatible to the parser,
+    // just move the vectors and we are done
+    Ok(DeinterleavedIndexedMeshBuf {
         positions,
         normals,
         texcoords,
@@ -75,20 +873,154 @@ fn tobj_mesh_to_aitios_mesh(mesh: tobj::Mesh) -> Rc<DeinterleavedIndexedMeshBuf>
     })
 }
 
-fn convert_materials<I>(materials: I, obj_file: &Path) -> Result<Vec<Rc<Material>>>
+/// Gets the minimum and the size of the bounding box of the given positions.
+fn bounds(positions: &[f32]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [::std::f32::INFINITY; 3];
+    let mut max = [::std::f32::NEG_INFINITY; 3];
+
+    for position in positions.chunks(3) {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+
+    let size = [max[0] - min[0], max[1] - min[1], max[2] - min[2]];
+    (min, size)
+}
+
+/// Projects the given positions onto the plane spanned by the two axes along
+/// which the bounding box is largest, mapping the bounding box to `[0, 1]`.
+fn planar_texcoords(positions: &[f32]) -> Vec<f32> {
+    let (min, size) = bounds(positions);
+    let scale = size[0].max(size[1]).max(size[2]);
+    let scale = if scale > 0.0 { scale } else { 1.0 };
+
+    // Drop the axis with the smallest extent
+    let mut axes = [0, 1, 2];
+    // Extents are NaN for infinite positions
+    axes.sort_by(|&a, &b| size[b].partial_cmp(&size[a]).unwrap_or(Ordering::Equal));
+    let (u_axis, v_axis) = (axes[0].min(axes[1]), axes[0].max(axes[1]));
+
+    positions
+        .chunks(3)
+        .flat_map(|p| {
+            vec![
+                (p[u_axis] - min[u_axis]) / scale,
+                (p[v_axis] - min[v_axis]) / scale,
+            ]
+        })
+        .collect()
+}
+
+/// Projects each position along the axis its normal is most closely
+/// aligned with, mapping the bounding box to `[0, 1]`.
+fn box_texcoords(positions: &[f32], normals: &[f32]) -> Vec<f32> {
+    let (min, size) = bounds(positions);
+    let scale = size[0].max(size[1]).max(size[2]);
+    let scale = if scale > 0.0 { scale } else { 1.0 };
+
+    positions
+        .chunks(3)
+        .zip(normals.chunks(3))
+        .flat_map(|(p, n)| {
+            let (u_axis, v_axis) = if n[0].abs() >= n[1].abs() && n[0].abs() >= n[2].abs() {
+                (2, 1)
+            } else if n[1].abs() >= n[2].abs() {
+                (0, 2)
+            } else {
+                (0, 1)
+            };
+
+            vec![
+                (p[u_axis] - min[u_axis]) / scale,
+                (p[v_axis] - min[v_axis]) / scale,
+            ]
+        })
+        .collect()
+}
+
+/// Calculates vertex normals by averaging the normals of all faces that
+/// contain the vertex, weighted by face area.
+fn generate_normals(positions: &[f32], indices: &[u32]) -> Vec<f32> {
+    let mut normals = vec![0.0; positions.len()];
+
+    for triangle in indices.chunks(3) {
+        let corner = |i: usize| {
+            let idx = triangle[i] as usize * 3;
+            [positions[idx], positions[idx + 1], positions[idx + 2]]
+        };
+        // Not normalized, so larger faces get a larger weight
+        let face_normal = vector::face_normal(corner(0), corner(1), corner(2));
+
+        for &vertex in triangle {
+            let idx = vertex as usize * 3;
+            normals[idx] += face_normal[0];
+            normals[idx + 1] += face_normal[1];
+            normals[idx + 2] += face_normal[2];
+        }
+    }
+
+    for normal in normals.chunks_mut(3) {
+        let len = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+        if len > 0.0 {
+            normal[0] /= len;
+            normal[1] /= len;
+            normal[2] /= len;
+        }
+    }
+
+    normals
+}
+
+/// Converts MTL materials into aitios materials, along with the properties
+/// that aitios materials do not have. Materials with overrides in the options
+/// are replaced.
+fn convert_materials<I>(
+    materials: I,
+    obj_file: &Path,
+    options: &LoadOptions,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<(Rc<Material>, MaterialProperties)>>
 where
-    I: IntoIterator<Item = tobj::Material>,
+    I: IntoIterator<Item = MtlMaterial>,
 {
     let obj_parent = obj_file.parent().unwrap_or_else(|| &Path::new("."));
+    // Materials often share maps, only check each path once
+    let mut probed = HashMap::new();
+    let mut placeholder = None;
 
+    let overrides = &options.material_overrides;
     materials
         .into_iter()
-        .map(|m| tobj_to_aitios_mat(m, obj_parent))
+        .map(|m| {
+            if let Some(replacement) = overrides.replacement(&m.name) {
+                return Ok((replacement, MaterialProperties::default()));
+            }
+            let converted = mtl_to_aitios_mat(
+                m,
+                obj_parent,
+                options,
+                &mut probed,
+                &mut placeholder,
+                warnings,
+            )?;
+            Ok(match overrides.rewritten(&converted.0) {
+                Some(rewritten) => (rewritten, MaterialProperties::default()),
+                None => converted,
+            })
+        })
         .collect()
 }
 
-fn resolve(path: &str, base: &Path) -> Result<PathBuf> {
-    let mut path: &Path = path.as_ref();
+fn resolve(
+    path: &str,
+    base: &Path,
+    options: &LoadOptions,
+    probed: &mut HashMap<PathBuf, bool>,
+) -> Result<PathBuf> {
+    let remapped = options.remap_path(path);
+    let mut path: &Path = remapped.as_ref();
 
     if path.as_os_str().is_empty() {
         return Err(InvalidData(
@@ -97,10 +1029,27 @@ fn resolve(path: &str, base: &Path) -> Result<PathBuf> {
         ));
     }
 
-    match path.canonicalize() {
-        // If could be canonicalized, it must exist, return it
-        Ok(path) => Ok(path),
-        Err(_) => {
+    // Authored paths are never canonicalized, only checked for existence
+    let check = match (options.authored_texture_paths, options.texture_check) {
+        (true, TextureCheck::Canonicalize) => TextureCheck::Metadata,
+        (_, check) => check,
+    };
+
+    if check == TextureCheck::Skip {
+        return Ok(base.join(path));
+    }
+
+    let authored = if options.authored_texture_paths {
+        base.join(path)
+    } else {
+        path.to_path_buf()
+    };
+
+    let file_system = options.vfs();
+    match probe(&authored, check, probed, file_system) {
+        // If could be probed, it must exist, return it
+        Some(path) => Ok(path),
+        None => {
             // Try stripping first path component and interpreting as relative
             // instead of absolute
             if path.is_absolute() {
@@ -113,104 +1062,1032 @@ fn resolve(path: &str, base: &Path) -> Result<PathBuf> {
             let mut relative_to_base = PathBuf::from(base);
             relative_to_base.push(path);
 
-            match relative_to_base.canonicalize() {
-                Ok(path) => Ok(path),
-                Err(_) => Err(InvalidData(format!(
-                    "OBJ/MTL referenced non-existing file: {:?}",
-                    path
-                ))),
+            // Maybe it is just encoded or capitalized differently
+            if let Some(found) = find_variant(&relative_to_base, options) {
+                relative_to_base = found;
             }
+
+            if let Some(found) = probe(&relative_to_base, check, probed, file_system) {
+                return Ok(found);
+            }
+
+            // Last resort are the search directories, first with the relative
+            // path, then with only the file name
+            let file_name = remapped.rsplit('/').next().unwrap_or("");
+            for dir in options.search_dirs.iter() {
+                for candidate in &[dir.join(path), dir.join(file_name)] {
+                    if let Some(found) = probe(candidate, check, probed, file_system) {
+                        return Ok(found);
+                    }
+                }
+            }
+
+            Err(InvalidData(format!(
+                "OBJ/MTL referenced non-existing file: {:?}",
+                path
+            )))
         }
     }
 }
 
-fn tobj_to_aitios_mat(source_mat: tobj::Material, base_dir: &Path) -> Result<Rc<Material>> {
-    let mut mat = MaterialBuilder::new().name(source_mat.name);
+/// Looks for a file that matches the given path when ignoring Unicode
+/// normalization or case, if enabled in the options.
+fn find_variant(path: &Path, options: &LoadOptions) -> Option<PathBuf> {
+    let file_system = options.vfs();
+    match (options.normalize_unicode, options.case_insensitive_paths) {
+        (false, false) => None,
+        (true, false) => normalize::find_file(path, nfc, file_system),
+        (false, true) => normalize::find_file(path, normalize::lowercase, file_system),
+        (true, true) => normalize::find_file(path, |name| nfc(name).to_lowercase(), file_system),
+    }
+}
+
+/// A 1x1 magenta PNG, to make missing textures stand out.
+const PLACEHOLDER_PNG: &[u8] = &[
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00, 0x00, 0x90, 0x77, 0x53,
+    0xde, 0x00, 0x00, 0x00, 0x0c, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8, 0xcf, 0xf0, 0x1f,
+    0x00, 0x04, 0x00, 0x01, 0xff, 0x22, 0x0a, 0x3a, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e,
+    0x44, 0xae, 0x42, 0x60, 0x82,
+];
 
-    if !source_mat.diffuse_texture.is_empty() {
-        mat = mat.diffuse_color_map(resolve(&source_mat.diffuse_texture, base_dir)?);
+/// Writes the placeholder texture to the given file system, unless the load
+/// already did, and returns its path. It goes into a new private temporary
+/// directory, which is kept like mirrored downloads since the loaded
+/// materials reference the texture.
+fn placeholder_texture(
+    placeholder: &mut Option<PathBuf>,
+    file_system: &dyn FileSystem,
+) -> Result<PathBuf> {
+    if let Some(ref path) = *placeholder {
+        return Ok(path.clone());
     }
+    let path = TempDir::new()?
+        .into_path()
+        .join("aitios-missing-texture.png");
+    file_system
+        .create_dir_all(path.parent().unwrap())
+        .map_err(Read)?;
+    file_system
+        .create(&path)
+        .and_then(|mut png| png.write_all(PLACEHOLDER_PNG))
+        .map_err(Read)?;
+    *placeholder = Some(path.clone());
+    Ok(path)
+}
 
-    if !source_mat.ambient_texture.is_empty() {
-        mat = mat.ambient_color_map(resolve(&source_mat.ambient_texture, base_dir)?);
+/// Checks if a file exists at the given path, returning the path to use for it.
+///
+/// Results of metadata checks are remembered in `probed`.
+fn probe(
+    path: &Path,
+    check: TextureCheck,
+    probed: &mut HashMap<PathBuf, bool>,
+    file_system: &dyn FileSystem,
+) -> Option<PathBuf> {
+    if check == TextureCheck::Canonicalize {
+        return file_system.canonicalize(path).ok();
     }
 
-    if !source_mat.specular_texture.is_empty() {
-        mat = mat.specular_color_map(resolve(&source_mat.specular_texture, base_dir)?);
+    let exists = *probed
+        .entry(path.to_path_buf())
+        .or_insert_with(|| file_system.exists(path));
+
+    if exists {
+        Some(path.to_path_buf())
+    } else {
+        None
     }
+}
 
-    let other = &source_mat.unknown_param;
-    let bump = other.get("bump") // official name
-        .or_else(|| other.get("map_bump")) // also seen this
-        .or_else(|| other.get("bump_map")); // this one is just silly
+fn mtl_to_aitios_mat(
+    source_mat: MtlMaterial,
+    base_dir: &Path,
+    options: &LoadOptions,
+    probed: &mut HashMap<PathBuf, bool>,
+    placeholder: &mut Option<PathBuf>,
+    warnings: &mut Vec<Warning>,
+) -> Result<(Rc<Material>, MaterialProperties)> {
+    let material_name = source_mat.name.clone();
+    let base_dir = if options.authored_texture_paths {
+        source_mat.library.parent().unwrap_or(base_dir)
+    } else {
+        base_dir
+    };
+    let mut mat = MaterialBuilder::new().name(material_name.clone());
 
-    if let Some(bump) = bump {
-        mat = mat.bump_map(resolve(&bump, base_dir)?);
+    let mut resolve_map = |params: Option<&String>| match params.map(|p| map_file_name(p)) {
+        Some(ref path) if !path.is_empty() => match resolve(path, base_dir, options, probed) {
+            Ok(resolved) => Ok(Some(resolved)),
+            Err(err) => {
+                let substitute = match options.missing_textures {
+                    MissingTextures::Error => {
+                        return Err(match err {
+                            InvalidData(msg) => {
+                                InvalidData(format!("{} in material {}", msg, material_name))
+                            }
+                            err => err,
+                        })
+                    }
+                    MissingTextures::Drop => None,
+                    MissingTextures::KeepPath => Some(base_dir.join(options.remap_path(path))),
+                    MissingTextures::Placeholder => {
+                        Some(placeholder_texture(placeholder, options.vfs())?)
+                    }
+                };
+
+                warn!(
+                    "Texture {:?} of material {} not found, using {:?} instead",
+                    path, material_name, substitute
+                );
+                warnings.push(Warning::MissingTexture {
+                    material: material_name.clone(),
+                    path: PathBuf::from(path),
+                });
+                Ok(substitute)
+            }
+        },
+        _ => Ok(None),
+    };
+
+    let mut material_maps = Vec::new();
+    for &(key, keywords) in mtl::MAP_KEYWORDS {
+        if let Some(path) = resolve_map(source_mat.map_params(keywords))? {
+            mat = maps::with_map(mat, key, path);
+            material_maps.push(key.to_string());
+        }
     }
 
-    let displacement = other.get("disp") // official name
-        .or_else(|| other.get("map_disp")) // maybe some people also use this?
-        .or_else(|| other.get("disp_map"));
+    let mut properties = source_mat.properties();
+    properties.dissolve_map = resolve_map(source_mat.property_map_params("map_d"))?;
+    properties.shininess_map = resolve_map(source_mat.property_map_params("map_Ns"))?;
+    properties.occlusion_map = resolve_map(source_mat.property_map_params("map_ao"))?;
+    properties.clearcoat_map = resolve_map(source_mat.property_map_params("map_Pc"))?;
+    properties.clearcoat_roughness_map = resolve_map(source_mat.property_map_params("map_Pcr"))?;
+    for params in source_mat.reflections.iter() {
+        if let Some(path) = resolve_map(Some(params))? {
+            properties.reflection_maps.push(ReflectionMap {
+                path,
+                options: mtl::texture_options(params),
+            });
+        }
+    }
 
-    // While bump and displacement are standardized,
-    // what follows isnt
+    let unknown = properties.unknown_params.clone();
+    for (keyword, params) in unknown.iter() {
+        let key = match options.unknown_params.map_key(keyword, params) {
+            Some(key) => key,
+            None => continue,
+        };
+        let is_material_map = mtl::MAP_KEYWORDS.iter().any(|&(k, _)| k == key);
+        let is_set = match properties.map_slot(&key) {
+            Some(slot) => slot.is_some(),
+            None if is_material_map => material_maps.contains(&key),
+            None => {
+                warn!(
+                    "Ignoring {} of material {}, {} is not a supported map",
+                    keyword, material_name, key
+                );
+                continue;
+            }
+        };
+        if is_set {
+            continue;
+        }
+
+        let path = match resolve_map(Some(params))? {
+            Some(path) => path,
+            None => continue,
+        };
+        // Saved as the map instead
+        properties.unknown_params.remove(keyword);
+        let texture_options = mtl::texture_options(params);
+        if !texture_options.is_empty() {
+            properties.map_options.insert(key.clone(), texture_options);
+        }
+        match properties.map_slot(&key) {
+            Some(slot) => *slot = Some(path),
+            None => {
+                mat = maps::with_map(mat, &key, path);
+                material_maps.push(key);
+            }
+        }
+    }
 
-    if let Some(displacement) = displacement {
-        mat = mat.displacement_map(resolve(&displacement, base_dir)?);
+    let material = mat.build();
+    let maps: Vec<(&str, PathBuf)> = material
+        .maps()
+        .iter()
+        .map(|&(key, ref path)| (key, PathBuf::from(path)))
+        .chain(
+            properties
+                .maps()
+                .into_iter()
+                .map(|(key, path)| (key, path.clone())),
+        )
+        .collect();
+    for (key, path) in maps {
+        let color_space = options
+            .map_color_spaces
+            .get(key)
+            .cloned()
+            .or_else(|| properties.map_options.get(key).and_then(|o| o.color_space))
+            .or_else(|| ColorSpace::from_file_name(&path))
+            .unwrap_or_else(|| ColorSpace::default_for(key));
+        properties.color_spaces.insert(key.to_string(), color_space);
     }
 
-    // There is a built-in source_math.normal_texture in tobj.
-    // However, it falsely uses map_Ns
-    // Hence, use the unknown "norm", which is recommended for normals
-    let normal = other.get("norm") // official, inofficial name
-        .or_else(|| other.get("map_norm")) // maybe some people also use this?
-        .or_else(|| other.get("map_normal"))
-        .or_else(|| other.get("normal"))
-        .or_else(|| other.get("normal_map"));
+    Ok((Rc::new(material), properties))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use obj::{save_scene, save_scene_with, MaterialOverrides, SaveOptions, Unit, UnknownParams};
+    use std::cell::RefCell;
+    use std::fs;
+    use tempdir::tempdir_scope;
+    use validate::GeometryProblem;
+    use vfs::MemoryFileSystem;
 
-    if let Some(normal) = normal {
-        mat = mat.normal_map(resolve(&normal, base_dir)?);
+    #[test]
+    fn test_missing_texcoords_policy() {
+        let error = load_with(
+            "tests/cube.obj",
+            &LoadOptions::new().missing_texcoords(MissingTexcoords::Error),
+        );
+        assert!(error.is_err());
+
+        let options = LoadOptions::new().missing_texcoords(MissingTexcoords::Box);
+        let scene = load_with("tests/cube.obj", &options).unwrap();
+        let texcoords = &scene.entities[0].mesh.texcoords;
+
+        assert_eq!(
+            scene.entities[0].mesh.positions.len() / 3 * 2,
+            texcoords.len()
+        );
+        assert!(texcoords.iter().all(|&t| t >= 0.0 && t <= 1.0));
+        assert!(texcoords.iter().any(|&t| t == 1.0));
+
+        let files = MemoryFileSystem::new();
+        files.insert("infinite.obj", "v inf 0 0\nv inf 1 0\nv inf 1 1\nf 1 2 3\n");
+        let options = LoadOptions::new()
+            .file_system(files)
+            .missing_texcoords(MissingTexcoords::Planar);
+        let scene = load_with("infinite.obj", &options).unwrap();
+        assert_eq!(6, scene.entities[0].mesh.texcoords.len());
+    }
+
+    #[test]
+    fn test_texture_check_modes() {
+        let base = Path::new("tests");
+        let mut probed = HashMap::new();
+
+        let metadata = LoadOptions::new().texture_check(TextureCheck::Metadata);
+        assert_eq!(
+            PathBuf::from("tests/cube.mtl"),
+            resolve("cube.mtl", base, &metadata, &mut probed).unwrap()
+        );
+        assert!(resolve("missing.png", base, &metadata, &mut probed).is_err());
+        assert_eq!(Some(&false), probed.get(Path::new("tests/missing.png")));
+
+        let skip = LoadOptions::new().texture_check(TextureCheck::Skip);
+        assert_eq!(
+            PathBuf::from("tests/missing.png"),
+            resolve("missing.png", base, &skip, &mut probed).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_authored_texture_paths() {
+        let options = LoadOptions::new().authored_texture_paths(true);
+        assert_eq!(
+            PathBuf::from("src/../tests/cube.mtl"),
+            resolve(
+                "../tests/cube.mtl",
+                Path::new("src"),
+                &options,
+                &mut HashMap::new()
+            )
+            .unwrap()
+        );
+
+        // Relative to the MTL instead of the OBJ
+        let material = MtlMaterial {
+            name: "Cube".to_string(),
+            params: vec![("map_Kd".to_string(), "cube.obj".to_string())]
+                .into_iter()
+                .collect(),
+            library: PathBuf::from("tests/cube.mtl"),
+            ..MtlMaterial::default()
+        };
+        let material = mtl_to_aitios_mat(
+            material,
+            Path::new("src"),
+            &options,
+            &mut HashMap::new(),
+            &mut None,
+            &mut Vec::new(),
+        )
+        .unwrap()
+        .0;
+        let maps: Vec<_> = material
+            .maps()
+            .iter()
+            .map(|(_, p)| PathBuf::from(p))
+            .collect();
+        assert_eq!(vec![PathBuf::from("tests/cube.obj")], maps);
     }
 
-    let roughness = other.get("map_Pr") // official, inofficial name
-        .or_else(|| other.get("map_PR")) // maybe some people also use this?
-        .or_else(|| other.get("map_pr"))
-        .or_else(|| other.get("map_pR"))
-        .or_else(|| other.get("Pr_map"));
+    #[test]
+    fn test_search_dirs_and_remapping() {
+        let mut probed = HashMap::new();
+        let options = LoadOptions::new()
+            .texture_check(TextureCheck::Metadata)
+            .remap_prefix("C:\\textures\\", "tests/")
+            .search_dir("tests");
 
-    if let Some(roughness) = roughness {
-        mat = mat.roughness_map(resolve(&roughness, base_dir)?);
+        assert_eq!(
+            PathBuf::from("tests/cube.mtl"),
+            resolve(
+                "C:\\textures\\cube.mtl",
+                Path::new("src"),
+                &options,
+                &mut probed
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("tests/cube.mtl"),
+            resolve("D:\\art\\cube.mtl", Path::new("src"), &options, &mut probed).unwrap()
+        );
+        assert!(resolve(
+            "D:\\art\\missing.png",
+            Path::new("src"),
+            &options,
+            &mut probed
+        )
+        .is_err());
     }
 
-    let metallic = other.get("map_Pm") // official, inofficial name
-        .or_else(|| other.get("map_PM")) // maybe some people also use this?
-        .or_else(|| other.get("map_pm"))
-        .or_else(|| other.get("map_pM"))
-        .or_else(|| other.get("Pm_map"));
+    #[test]
+    fn test_backslash_separators() {
+        let mut probed = HashMap::new();
+        let options = LoadOptions::new().texture_check(TextureCheck::Metadata);
 
-    if let Some(metallic) = metallic {
-        mat = mat.metallic_map(resolve(&metallic, base_dir)?);
+        assert_eq!(
+            PathBuf::from("tests/cube.mtl"),
+            resolve("tests\\/cube.mtl", Path::new("."), &options, &mut probed).unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("tests/cube.mtl"),
+            resolve(
+                "..\\tests\\cube.mtl",
+                Path::new("src"),
+                &options,
+                &mut probed
+            )
+            .unwrap()
+            .strip_prefix("src/..")
+            .unwrap()
+        );
     }
 
-    let sheen = other.get("map_Ps") // official, inofficial name
-        .or_else(|| other.get("map_PS")) // maybe some people also use this?
-        .or_else(|| other.get("map_ps"))
-        .or_else(|| other.get("map_pS"))
-        .or_else(|| other.get("Ps_map"));
+    #[test]
+    fn test_missing_textures_policy() {
+        let material = MtlMaterial {
+            name: "Rusty".to_string(),
+            params: vec![("map_Kd".to_string(), "rust.png".to_string())]
+                .into_iter()
+                .collect(),
+            ..MtlMaterial::default()
+        };
+        let convert = |policy| {
+            let options = LoadOptions::new().missing_textures(policy);
+            mtl_to_aitios_mat(
+                material.clone(),
+                Path::new("tests"),
+                &options,
+                &mut HashMap::new(),
+                &mut None,
+                &mut Vec::new(),
+            )
+            .map(|(material, _)| material)
+        };
 
-    if let Some(sheen) = sheen {
-        mat = mat.sheen_map(resolve(&sheen, base_dir)?);
+        assert!(convert(MissingTextures::Error).is_err());
+        assert!(convert(MissingTextures::Drop).unwrap().maps().is_empty());
+
+        let kept = convert(MissingTextures::KeepPath).unwrap();
+        let maps: Vec<_> = kept.maps().iter().map(|(_, p)| PathBuf::from(p)).collect();
+        assert_eq!(vec![PathBuf::from("tests/rust.png")], maps);
+
+        let placeholder = convert(MissingTextures::Placeholder).unwrap();
+        let maps = placeholder.maps();
+        assert_eq!(1, maps.len());
+        let path = Path::new(&maps[0].1);
+        assert!(path.is_file());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_params_hook() {
+        let material = MtlMaterial {
+            name: "Studio".to_string(),
+            params: vec![
+                ("Kd", "1 1 1"),
+                ("xRough", "cube.obj"),
+                ("xCavity", "-imfchan r cube.mtl"),
+                ("xGloss", "cube.obj"),
+                ("map_Pr", "cube.mtl"),
+            ]
+            .into_iter()
+            .map(|(keyword, params)| (keyword.to_string(), params.to_string()))
+            .collect(),
+            ..MtlMaterial::default()
+        };
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let hook_seen = Rc::clone(&seen);
+        let options =
+            LoadOptions::new().unknown_params(UnknownParams::callback(move |keyword, _| {
+                hook_seen.borrow_mut().push(keyword.to_string());
+                match keyword {
+                    "xRough" => Some("map_Pr".to_string()),
+                    "xCavity" => Some("map_ao".to_string()),
+                    "xGloss" => Some("map_Gloss".to_string()),
+                    _ => None,
+                }
+            }));
+        let (material, properties) = mtl_to_aitios_mat(
+            material,
+            Path::new("tests"),
+            &options,
+            &mut HashMap::new(),
+            &mut None,
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        assert_eq!(vec!["xCavity", "xGloss", "xRough"], *seen.borrow());
+        // Statements that were not loaded as maps are kept as they are
+        let unknown: Vec<&String> = properties.unknown_params.keys().collect();
+        assert_eq!(vec!["xGloss", "xRough"], unknown);
+        // The known statement wins over the mapped one
+        let maps = material.maps();
+        assert_eq!(1, maps.len());
+        assert!(maps[0].1.ends_with("cube.mtl"));
+        assert!(properties.occlusion_map.unwrap().ends_with("cube.mtl"));
+        assert_eq!(
+            Some("r"),
+            properties.map_options["map_ao"].channel.as_deref()
+        );
     }
 
-    let emissive = other.get("map_Ke") // official, inofficial name
-        .or_else(|| other.get("map_KE")) // maybe some people also use this?
-        .or_else(|| other.get("map_ke"))
-        .or_else(|| other.get("map_kE"))
-        .or_else(|| other.get("Ke_map"));
+    #[test]
+    fn test_material_overrides() {
+        let calibrated = MaterialBuilder::new()
+            .name("Calibrated")
+            .diffuse_color_map("tests/cube.mtl")
+            .build();
+        let options = LoadOptions::new()
+            .material_overrides(MaterialOverrides::new().replace("Material", calibrated));
+        let scene = load_with("tests/cube.obj", &options).unwrap();
 
-    if let Some(emissive) = emissive {
-        mat = mat.emissive_map(resolve(&emissive, base_dir)?);
+        let material = &scene.materials["Material"];
+        assert_eq!("Calibrated", material.name().as_str());
+        assert!(Rc::ptr_eq(material, &scene.entities[0].material));
+        assert_eq!(
+            MaterialProperties::default(),
+            scene.material_properties["Material"]
+        );
+        assert_eq!(vec![PathBuf::from("tests/cube.mtl")], scene.textures);
+
+        let rewrite = MaterialOverrides::new().rewrite(|material| {
+            Some(
+                MaterialBuilder::from(material)
+                    .name(format!("{}-calibrated", material.name()))
+                    .build(),
+            )
+        });
+        let options = LoadOptions::new().material_overrides(rewrite);
+        let materials = load_mtl_with("tests/cube.mtl", &options).unwrap();
+        assert_eq!("Material-calibrated", materials[0].name().as_str());
     }
 
-    Ok(Rc::new(mat.build()))
+    #[test]
+    fn test_multiple_material_libs() {
+        let scene = load("tests/cube_two_mtllibs.obj").unwrap();
+
+        assert!(scene.material("Material").is_some());
+        assert!(scene.material("Extra").is_some());
+        assert_eq!(
+            "Extra",
+            scene.entities.last().unwrap().material.name().as_str()
+        );
+        assert!(scene.warnings.iter().any(|w| match *w {
+            Warning::MaterialRedefined { ref material, .. } => material == "Material",
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_multiple_mtllib_statements() {
+        let scene = load("tests/cube_two_mtllib_statements.obj").unwrap();
+
+        assert_eq!(
+            vec!["Extra", "Material"],
+            scene.materials.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "Extra",
+            scene.entities.last().unwrap().material.name().as_str()
+        );
+        // Redefinition in the second file, the repeated statement is not loaded again
+        let redefinitions = scene
+            .warnings
+            .iter()
+            .filter(|w| match **w {
+                Warning::MaterialRedefined { .. } => true,
+                _ => false,
+            })
+            .count();
+        assert_eq!(1, redefinitions);
+    }
+
+    #[test]
+    fn test_load_mtl() {
+        let materials = load_mtl("tests/cube_extra.mtl").unwrap();
+        let names: Vec<_> = materials.iter().map(|m| m.name().as_str()).collect();
+        assert_eq!(vec!["Extra", "Material"], names);
+    }
+
+    #[test]
+    fn test_material_properties() {
+        let scene = load("tests/cube.obj").unwrap();
+        let properties = &scene.material_properties["Material"];
+        assert_eq!(Some([0.64; 3]), properties.diffuse);
+        assert_eq!(Some(96.078_43), properties.shininess);
+        assert_eq!(Some(1.0), properties.dissolve);
+        assert_eq!(Some(2), properties.illumination);
+    }
+
+    #[test]
+    fn test_discovery_keeps_existing_libraries() {
+        let files = MemoryFileSystem::new();
+        files.insert(
+            "scene.obj",
+            "mtllib base.mtl missing.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\n\
+             usemtl Base\nf 1 2 3\nusemtl Discovered\nf 1 3 2\n",
+        );
+        files.insert("base.mtl", "newmtl Base\nKd 1 0 0\n");
+        files.insert("scene.mtl", "newmtl Discovered\nKd 0 1 0\n");
+        let options = LoadOptions::new().file_system(files).discover_mtl(true);
+        let scene = load_with("scene.obj", &options).unwrap();
+
+        assert_eq!(
+            Some([1.0, 0.0, 0.0]),
+            scene.material_properties["Base"].diffuse
+        );
+        assert_eq!(
+            Some([0.0, 1.0, 0.0]),
+            scene.material_properties["Discovered"].diffuse
+        );
+    }
+
+    #[test]
+    fn test_load_many_records_source() {
+        let scene = load_many(
+            vec!["tests/cube.obj", "tests/cube_without_mtl.obj"],
+            &LoadOptions::new().discover_mtl(true),
+        )
+        .unwrap();
+
+        assert_eq!(scene.entities.len(), scene.extras.len());
+        let sources: Vec<_> = scene
+            .extras
+            .iter()
+            .map(|e| e.source.as_ref().unwrap().path.clone())
+            .collect();
+        assert_eq!(PathBuf::from("tests/cube.obj"), sources[0]);
+        assert_eq!(
+            &PathBuf::from("tests/cube_without_mtl.obj"),
+            sources.last().unwrap()
+        );
+        assert_eq!(
+            scene.entities.len(),
+            scene.from_source("tests/cube.obj").count()
+                + scene.from_source("tests/cube_without_mtl.obj").count()
+        );
+    }
+
+    #[test]
+    fn test_gzip_compressed_obj_and_mtl() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let compress = |from: &str, to: &Path, replace: (&str, &str)| {
+            let text = fs::read_to_string(from)?.replace(replace.0, replace.1);
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(text.as_bytes())?;
+            fs::write(to, encoder.finish()?)
+        };
+        let compressed = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_gzip.obj.gz");
+            compress(
+                "tests/cube.obj",
+                &obj_path,
+                ("mtllib cube.mtl", "mtllib cube_gzip.mtl"),
+            )?;
+            compress("tests/cube.mtl", &dir.join("cube_gzip.mtl.gz"), ("", ""))?;
+            load(&obj_path)
+        })
+        .unwrap();
+
+        let uncompressed = load("tests/cube.obj").unwrap();
+        // Counts decompressed bytes, including the renamed mtllib
+        assert_eq!(
+            LoadStats {
+                bytes_parsed: uncompressed.stats.bytes_parsed + "_gzip".len() as u64,
+                ..uncompressed.stats.without_timings()
+            },
+            compressed.stats.without_timings()
+        );
+        assert_eq!(
+            uncompressed.materials.keys().collect::<Vec<_>>(),
+            compressed.materials.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_face_adjacency() {
+        let scene = load("tests/cube.obj").unwrap();
+        assert_eq!(None, scene.extras[0].adjacency);
+
+        let options = LoadOptions::new().face_adjacency(true);
+        let scene = load_with("tests/cube.obj", &options).unwrap();
+        let adjacency = scene.extras[0].adjacency.as_ref().unwrap();
+
+        // The cube is closed, so every triangle has three neighbors
+        assert_eq!(12, adjacency.face_count());
+        assert_eq!(0, adjacency.boundary_edges());
+        for face in 0..adjacency.face_count() {
+            assert!(adjacency.neighbors(face).iter().all(|n| n.is_some()));
+        }
+    }
+
+    #[test]
+    fn test_ingestion_stats() {
+        let line_bytes = |path| {
+            fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|line| line.len() as u64)
+                .sum::<u64>()
+        };
+        let stats = load("tests/cube.obj").unwrap().stats;
+
+        assert_eq!(
+            line_bytes("tests/cube.obj") + line_bytes("tests/cube.mtl"),
+            stats.bytes_parsed
+        );
+        assert_eq!(1, stats.entities.len());
+        assert_eq!(stats.triangle_count, stats.entities[0].triangle_count);
+        assert_eq!(Some(&stats.entities[0]), stats.largest_entity());
+
+        let twice = load_many(vec!["tests/cube.obj"; 2], &LoadOptions::new()).unwrap();
+        assert_eq!(2 * stats.bytes_parsed, twice.stats.bytes_parsed);
+        assert_eq!(2, twice.stats.entities.len());
+    }
+
+    #[test]
+    fn test_surfels() {
+        let options = LoadOptions::new().surfel_density(100.0);
+        let scene = load_with("tests/cube.obj", &options).unwrap();
+        let surfels = scene.extras[0].surfels.as_ref().unwrap();
+
+        assert!(!surfels.is_empty());
+        assert!(surfels.iter().all(|s| s.face < 12));
+        assert_eq!(None, load("tests/cube.obj").unwrap().extras[0].surfels);
+    }
+
+    #[test]
+    fn test_validate_geometry() {
+        let options = LoadOptions::new().validate_geometry(true);
+        let scene = load_with("tests/cube.obj", &options).unwrap();
+        assert!(scene.warnings.is_empty());
+
+        let files = MemoryFileSystem::new();
+        files.insert(
+            "cube_invalid.obj",
+            "v 0 0 0\nv 1 0 0\nv nan 0 0\nf 1 2 3\nf 1 2 2\n",
+        );
+        let scene = load_with("cube_invalid.obj", &options.file_system(files)).unwrap();
+
+        let problems: Vec<_> = scene
+            .warnings
+            .into_iter()
+            .filter_map(|warning| match warning {
+                Warning::InvalidGeometry { finding, .. } => Some(finding.problem),
+                _ => None,
+            })
+            .collect();
+        assert!(problems.contains(&GeometryProblem::NonFinitePosition));
+        assert!(problems.contains(&GeometryProblem::ZeroAreaTriangle));
+    }
+
+    #[test]
+    fn test_merge_duplicate_materials() {
+        let obj_path = "cube_duplicates.obj";
+        let obj = "mtllib cube_duplicates.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                   o A\nusemtl Red\nf 1 2 3\no B\nusemtl Red.001\nf 1 2 3\n\
+                   o C\nusemtl Blue\nf 1 2 3\n";
+        let mtl = "newmtl Red\nKd 1 0 0\nnewmtl Red.001\nKd 1 0 0\nnewmtl Blue\nKd 0 0 1\n";
+        let files = MemoryFileSystem::new();
+        files.insert(obj_path, obj);
+        files.insert("cube_duplicates.mtl", mtl);
+
+        let options = LoadOptions::new().file_system(files);
+        let merged = load_with(obj_path, &options.clone().merge_duplicate_materials(true));
+        let unmerged = load_with(obj_path, &options);
+
+        let merged = merged.unwrap();
+        let entities = &merged.entities;
+        assert!(Rc::ptr_eq(&entities[0].material, &entities[1].material));
+        assert!(!Rc::ptr_eq(&entities[0].material, &entities[2].material));
+        assert_eq!(
+            vec!["Blue", "Red"],
+            merged.materials.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![Warning::MaterialMerged {
+                material: "Red.001".to_string(),
+                into: "Red".to_string(),
+            }],
+            merged.warnings
+        );
+
+        let unmerged = unmerged.unwrap();
+        assert_eq!(3, unmerged.materials.len());
+
+        assert!(same_statement("1 0 0", "1.0001 0 0", 0.001));
+        assert!(!same_statement("1 0 0", "1.0001 0 0", 0.0));
+        assert!(!same_statement("1 0", "1 0 0", 1.0));
+        assert!(same_statement("a.png", "a.png", 0.0));
+    }
+
+    #[test]
+    fn test_coordinate_system() {
+        let y_up = load("tests/cube.obj").unwrap();
+        let z_up = load_with(
+            "tests/cube.obj",
+            &LoadOptions::new().coordinate_system(CoordinateSystem::ZUpRightHanded),
+        )
+        .unwrap();
+        let (original, converted) = (&y_up.entities[0].mesh, &z_up.entities[0].mesh);
+        assert_eq!(&[1.0, -1.0, -1.0], &original.positions[0..3]);
+        assert_eq!(&[1.0, -1.0, 1.0], &converted.positions[0..3]);
+
+        let save_options = SaveOptions::new().coordinate_system(CoordinateSystem::ZUpRightHanded);
+        let reloaded = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_z_up.obj");
+            save_scene_with(&z_up, Some(&obj_path), None, &save_options)?;
+            load(&obj_path)
+        })
+        .unwrap();
+        assert_eq!(original.positions, reloaded.entities[0].mesh.positions);
+    }
+
+    #[test]
+    fn test_units() {
+        let centimeters = load_with(
+            "tests/cube.obj",
+            &LoadOptions::new().units(Unit::Meters, Unit::Centimeters),
+        )
+        .unwrap();
+        assert_eq!(
+            &[100.0, -100.0, -100.0],
+            &centimeters.entities[0].mesh.positions[0..3]
+        );
+
+        let save_options = SaveOptions::new().units(Unit::Centimeters, Unit::Meters);
+        let reloaded = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_cm.obj");
+            save_scene_with(&centimeters, Some(&obj_path), None, &save_options)?;
+            load(&obj_path)
+        })
+        .unwrap();
+        assert_eq!(
+            &[1.0, -1.0, -1.0],
+            &reloaded.entities[0].mesh.positions[0..3]
+        );
+    }
+
+    #[test]
+    fn test_bounds() {
+        let scene = load_with("tests/cube.obj", &LoadOptions::new().bounds(true)).unwrap();
+        let bounds = scene.extras[0].bounds.unwrap();
+        // The cube is slightly distorted
+        for (min, max) in bounds.min.iter().zip(bounds.max.iter()) {
+            assert!((min + 1.0).abs() < 1e-5 && (max - 1.0).abs() < 1e-5);
+        }
+        assert!((bounds.radius - 3.0f32.sqrt()).abs() < 1e-5);
+        assert_eq!(Some(bounds), scene.bounds());
+        assert_eq!(None, load("tests/cube.obj").unwrap().extras[0].bounds);
+    }
+
+    #[test]
+    fn test_shift_origin() {
+        let options = LoadOptions::new().shift_origin(100000.0);
+        let (scene, unshifted, saved_obj, reloaded, many) = tempdir_scope(|dir| {
+            let geo_path = dir.join("geo_referenced.obj");
+            fs::write(
+                &geo_path,
+                "v 512345.678 5234567.891 12.5\nv 512346.678 5234567.891 12.5\n\
+                 v 512345.678 5234568.891 12.5\nf 1 2 3\n",
+            )?;
+            let scene = load_with(&geo_path, &options)?;
+            let unshifted = load(&geo_path)?;
+            let saved_path = dir.join("geo_referenced_saved.obj");
+            save_scene(&scene, Some(&saved_path), None)?;
+            let saved_obj = fs::read_to_string(&saved_path)?;
+            let reloaded = load_with(&saved_path, &options)?;
+            let many = load_many(vec![geo_path, PathBuf::from("tests/cube.obj")], &options)?;
+            Ok((scene, unshifted, saved_obj, reloaded, many))
+        })
+        .unwrap();
+
+        assert_eq!(Some([512346.0, 5234568.0, 0.0]), scene.origin);
+        let positions = &scene.entities[0].mesh.positions;
+        assert!((positions[0] + 0.322).abs() < 1e-6);
+        assert!((positions[1] + 0.109).abs() < 1e-6);
+        assert_eq!(None, unshifted.origin);
+
+        assert!(saved_obj.contains("v 512345.678"));
+        assert_eq!(scene.origin, reloaded.origin);
+        assert_eq!(positions, &reloaded.entities[0].mesh.positions);
+
+        // The cube is moved to the origin of the first file
+        assert_eq!(scene.origin, many.origin);
+        assert_eq!(1.0 - 512346.0, many.entities[1].mesh.positions[0]);
+    }
+
+    #[test]
+    fn test_frame_header() {
+        let options = SaveOptions::new()
+            .units(Unit::Meters, Unit::Centimeters)
+            .frame_header(true);
+        let (scene, saved_obj, reloaded, unframed) = tempdir_scope(|dir| {
+            let geo_path = dir.join("geo_framed.obj");
+            fs::write(
+                &geo_path,
+                "v 512345.678 5234567.891 12.5\nv 512346.678 5234567.891 12.5\n\
+                 v 512345.678 5234568.891 12.5\nf 1 2 3\n",
+            )?;
+            let scene = load_with(&geo_path, &LoadOptions::new().shift_origin(100000.0))?;
+            let saved_path = dir.join("geo_framed_saved.obj");
+            save_scene_with(&scene, Some(&saved_path), None, &options)?;
+            let saved_obj = fs::read_to_string(&saved_path)?;
+            let reloaded = load_with(&saved_path, &LoadOptions::new().frame_header(true))?;
+            let unframed = load(&saved_path)?;
+            Ok((scene, saved_obj, reloaded, unframed))
+        })
+        .unwrap();
+
+        assert!(
+            saved_obj.contains("# aitios frame origin 51234600 523456800 0 meters_per_unit 0.01\n")
+        );
+
+        // Positions are converted back to meters with the unit of the header
+        let origin = reloaded.origin.unwrap();
+        for (expected, coordinate) in [512346.0, 5234568.0, 0.0].iter().zip(origin.iter()) {
+            assert!((expected - coordinate).abs() < 1e-6);
+        }
+        let expected = &scene.entities[0].mesh.positions;
+        let positions = &reloaded.entities[0].mesh.positions;
+        for (expected, position) in expected.iter().zip(positions.iter()) {
+            assert!((expected - position).abs() < 1e-5);
+        }
+
+        assert_eq!(None, unframed.origin);
+        assert!((unframed.entities[0].mesh.positions[0] + 32.2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_double_precision() {
+        let (scene, saved_obj, recentered) = tempdir_scope(|dir| {
+            let geo_path = dir.join("geo_precise.obj");
+            fs::write(
+                &geo_path,
+                "v 512345.678 5234567.891 12.5\nv 512346.678 5234567.891 12.5\n\
+                 v 512345.678 5234568.891 12.5\nf 1 2 3\n",
+            )?;
+            let scene = load_with(&geo_path, &LoadOptions::new().double_precision(true))?;
+            let saved_path = dir.join("geo_precise_saved.obj");
+            save_scene(&scene, Some(&saved_path), None)?;
+            let saved_obj = fs::read_to_string(&saved_path)?;
+            let recentered = load_with(&geo_path, &LoadOptions::new().recenter(true))?;
+            Ok((scene, saved_obj, recentered))
+        })
+        .unwrap();
+
+        let precise = scene.extras[0].precise_positions.as_ref().unwrap();
+        assert_eq!(&[512345.678, 5234567.891, 12.5], &precise[0..3]);
+        assert_eq!(None, scene.origin);
+        assert!(saved_obj
+            .contains("v 512345.678 5234567.891 12.5\nv 512346.678 5234567.891 12.5\n"));
+
+        assert_eq!(Some([512346.0, 5234568.0, 13.0]), recentered.origin);
+        assert_eq!(None, recentered.extras[0].precise_positions);
+        let positions = &recentered.entities[0].mesh.positions;
+        assert!((positions[0] + 0.322).abs() < 1e-6);
+        assert!((positions[4] + 0.109).abs() < 1e-6);
+        assert_eq!(-0.5, positions[2]);
+    }
+
+    #[test]
+    fn test_load_chunks() {
+        let chunked_path = "chunked.obj";
+        let mut obj = String::from("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\n");
+        for object in 0..20 {
+            obj.push_str(&format!("o part{}\n", object));
+            for _ in 0..10 {
+                obj.push_str("f 1 2 3\nf 2 4 3\n");
+            }
+        }
+        let files = MemoryFileSystem::new();
+        files.insert(chunked_path, obj);
+        let options = LoadOptions::new().file_system(files);
+        let mut chunks = Vec::new();
+        let chunked = load_chunks(
+            chunked_path,
+            &options.clone().memory_budget(2048),
+            |scene| {
+                chunks.push(scene);
+                Ok(())
+            },
+        );
+        let whole = load_with(chunked_path, &options.clone().memory_budget(2048));
+        let tiny = load_chunks(chunked_path, &options.memory_budget(16), |_| Ok(()));
+
+        chunked.unwrap();
+        assert!(chunks.len() > 1);
+        let triangles: usize = chunks
+            .iter()
+            .flat_map(|scene| scene.entities.iter())
+            .map(|entity| entity.mesh.indices.len() / 3)
+            .sum();
+        assert_eq!(400, triangles);
+        assert!(whole.is_err());
+        // Vertex data alone exceeds the budget
+        assert!(tiny.is_err());
+    }
+
+    #[test]
+    fn test_flip_v() {
+        let original = load("tests/cube.obj").unwrap();
+        let flipped = load_with("tests/cube.obj", &LoadOptions::new().flip_v(true)).unwrap();
+        let texcoords = &original.entities[0].mesh.texcoords;
+        let flipped_texcoords = &flipped.entities[0].mesh.texcoords;
+        for (original, flipped) in texcoords.chunks(2).zip(flipped_texcoords.chunks(2)) {
+            assert_eq!(original[0], flipped[0]);
+            assert_eq!(1.0 - original[1], flipped[1]);
+        }
+
+        let reloaded = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_flip_v.obj");
+            save_scene_with(
+                &flipped,
+                Some(&obj_path),
+                None,
+                &SaveOptions::new().flip_v(true),
+            )?;
+            load(&obj_path)
+        })
+        .unwrap();
+        assert_eq!(texcoords, &reloaded.entities[0].mesh.texcoords);
+    }
+
+    #[test]
+    fn test_interned_names() {
+        let scene = load_many(
+            vec!["tests/cube.obj", "tests/cube.obj"],
+            &LoadOptions::new().intern_names(true),
+        )
+        .unwrap();
+
+        let first = &scene.extras[0];
+        let last = scene.extras.last().unwrap();
+        assert_eq!(first.name_symbol, last.name_symbol);
+        assert_eq!(first.material_symbol, last.material_symbol);
+        assert_eq!(
+            scene.entities[0].name,
+            scene.names.resolve(first.name_symbol.unwrap())
+        );
+        assert_eq!(
+            Some(first.material_symbol.unwrap()),
+            scene.names.get(scene.entities[0].material.name())
+        );
+        assert!(load("tests/cube.obj").unwrap().names.is_empty());
+    }
 }
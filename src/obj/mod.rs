@@ -1,5 +1,29 @@
+mod background;
+mod events;
+#[cfg(feature = "http")]
+mod http;
+mod library;
 mod load;
+mod mtl;
+mod options;
+mod parse;
+mod profile;
 mod save;
 
-pub use self::load::load;
-pub use self::save::save;
+pub use self::background::{load_staged, PendingTextures, TextureStatus};
+pub use self::events::{parse_events, read_events, ObjCorner, ObjEvent};
+pub use self::library::MaterialLibrary;
+pub use self::load::{
+    load, load_chunks, load_entities, load_many, load_mtl, load_mtl_with, load_with,
+};
+pub use self::options::{
+    CollisionExport, CoordinateSystem, Encoding, LineEndings, LoadOptions, MaterialEquality,
+    MaterialNaming, MaterialOverrides, MissingMaps, MissingTexcoords, MissingTextures, NameFilter,
+    ParseMode, SaveOptions, SplitPolicy, TextureCheck, Unit, UnknownParams, Winding,
+};
+pub use self::profile::Profiles;
+pub use self::save::{
+    collect_materials, plan_outputs, save, save_scene, save_scene_with, save_to, save_to_with,
+    save_with, write_geometry, write_material, write_mtl, CollectedMaterials, IndexBases,
+    OutputPlan,
+};
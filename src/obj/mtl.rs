@@ -0,0 +1,726 @@
+//! In-crate parser for MTL files.
+//!
+//! Statements are kept as text and only interpreted when converting into
+//! aitios materials, but numeric statements are checked while parsing so
+//! that errors can point to the offending line.
+
+use err::Result;
+use float::parse_f32;
+use loaded::{ColorSpace, MaterialProperties, TextureOptions, Warning};
+use obj::options::{LoadOptions, ParseMode};
+use obj::parse::{
+    decode_line, open_file, parse_floats, raw_rest_of_line, read_lines, rest_of_line, split_quoted,
+    strip_comment, ParseResult, SyntaxError, Word, Words,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// A material defined with `newmtl` in an MTL file.
+#[derive(Debug, Clone, Default)]
+pub struct MtlMaterial {
+    pub name: String,
+    /// Rest of the line after the keyword, keyed by keyword, for each
+    /// statement of the material. If a keyword occurs more than once,
+    /// the last statement wins.
+    pub params: HashMap<String, String>,
+    /// Parameters of all `refl` statements of the material in order, since
+    /// a cube map takes one statement for each face.
+    pub reflections: Vec<String>,
+    /// Path of the MTL file the material is defined in.
+    pub library: PathBuf,
+}
+
+impl MtlMaterial {
+    /// Interprets the color and factor statements of the material, and the
+    /// options of its maps. Malformed statements, which are only kept when
+    /// ignoring errors, are left out. Map paths are left to be resolved.
+    pub fn properties(&self) -> MaterialProperties {
+        let params = &self.params;
+        let color = |keyword: &str| params.get(keyword).and_then(|value| rgb(value));
+        let scalar = |keyword: &str| {
+            params
+                .get(keyword)
+                .and_then(|value| value.split_whitespace().next())
+                .and_then(parse_f32)
+        };
+        let dissolve = params
+            .get("d")
+            .and_then(|value| {
+                // A halo dissolve depends on the view angle, use its factor
+                let value = value.trim_start();
+                value
+                    .strip_prefix("-halo")
+                    .unwrap_or(value)
+                    .split_whitespace()
+                    .next()
+            })
+            .and_then(parse_f32)
+            .or_else(|| scalar("Tr").map(|transparency| 1.0 - transparency));
+
+        let mut properties = MaterialProperties {
+            ambient: color("Ka"),
+            diffuse: color("Kd"),
+            specular: color("Ks"),
+            emissive: color("Ke"),
+            transmission_filter: color("Tf"),
+            shininess: scalar("Ns"),
+            optical_density: scalar("Ni"),
+            dissolve,
+            illumination: params
+                .get("illum")
+                .and_then(|value| value.split_whitespace().next())
+                .and_then(|word| word.parse().ok()),
+            dissolve_map: None,
+            shininess_map: None,
+            occlusion_map: None,
+            clearcoat: scalar("Pc"),
+            clearcoat_roughness: scalar("Pcr"),
+            anisotropy: scalar("aniso"),
+            anisotropy_rotation: scalar("anisor"),
+            clearcoat_map: None,
+            clearcoat_roughness_map: None,
+            reflection_maps: Vec::new(),
+            color_spaces: BTreeMap::new(),
+            unknown_params: BTreeMap::new(),
+            map_options: MAP_KEYWORDS
+                .iter()
+                .chain(PROPERTY_MAP_KEYWORDS)
+                .filter_map(|&(key, keywords)| {
+                    let options = texture_options(self.map_params(keywords)?);
+                    Some((key.to_string(), options)).filter(|(_, options)| !options.is_empty())
+                })
+                .collect(),
+        };
+        properties.unknown_params = params
+            .iter()
+            .filter(|&(keyword, _)| !self.is_stored(keyword, &properties))
+            .map(|(keyword, value)| (keyword.clone(), value.clone()))
+            .collect();
+        properties
+    }
+
+    /// Checks if the statement with the given keyword is kept in the material
+    /// or the given properties in a form that is written back when saving.
+    /// Statements that are understood but not kept, like `Pr` or `decal`,
+    /// are left to `MaterialProperties::unknown_params` instead.
+    fn is_stored(&self, keyword: &str, properties: &MaterialProperties) -> bool {
+        match keyword {
+            "Ka" => properties.ambient.is_some(),
+            "Kd" => properties.diffuse.is_some(),
+            "Ks" => properties.specular.is_some(),
+            "Ke" => properties.emissive.is_some(),
+            "Tf" => properties.transmission_filter.is_some(),
+            "Ns" => properties.shininess.is_some(),
+            "Ni" => properties.optical_density.is_some(),
+            "illum" => properties.illumination.is_some(),
+            "Pc" => properties.clearcoat.is_some(),
+            "Pcr" => properties.clearcoat_roughness.is_some(),
+            "aniso" => properties.anisotropy.is_some(),
+            "anisor" => properties.anisotropy_rotation.is_some(),
+            // Saved as a plain dissolve, which loses a halo
+            "d" => {
+                properties.dissolve.is_some() && !self.params["d"].trim_start().starts_with("-halo")
+            }
+            "Tr" => !self.params.contains_key("d") && properties.dissolve.is_some(),
+            "refl" => true,
+            // Only the preferred spelling of a map is loaded
+            _ => MAP_KEYWORDS
+                .iter()
+                .chain(PROPERTY_MAP_KEYWORDS)
+                .filter_map(|&(_, keywords)| {
+                    keywords
+                        .iter()
+                        .find(|&&keyword| self.params.contains_key(keyword))
+                })
+                .any(|&loaded| loaded == keyword),
+        }
+    }
+
+    /// Gets the parameters of the first statement with one of the given
+    /// keywords, e.g. from `MAP_KEYWORDS`.
+    pub fn map_params(&self, keywords: &[&str]) -> Option<&String> {
+        keywords
+            .iter()
+            .find_map(|&keyword| self.params.get(keyword))
+    }
+
+    /// Gets the parameters of the statement that sets the map with the given
+    /// key in `PROPERTY_MAP_KEYWORDS`, e.g. `map_AO` for `map_ao`.
+    pub fn property_map_params(&self, key: &str) -> Option<&String> {
+        PROPERTY_MAP_KEYWORDS
+            .iter()
+            .find(|&&(property_key, _)| property_key == key)
+            .and_then(|&(_, keywords)| self.map_params(keywords))
+    }
+}
+
+/// Materials read from an MTL file.
+#[derive(Debug, Clone, Default)]
+pub struct MtlLibrary {
+    /// Materials in the order of definition.
+    pub materials: Vec<MtlMaterial>,
+    /// Index into `materials` by name.
+    pub ids: HashMap<String, usize>,
+    /// Redefined materials and malformed lines that were skipped.
+    pub warnings: Vec<Warning>,
+    /// Number of bytes in the lines of the file, without line endings.
+    pub bytes: u64,
+}
+
+/// Statements with a single number as parameter.
+const SCALAR_KEYWORDS: &[&str] = &["Ns", "Ni", "d", "Tr", "sharpness"];
+
+/// Statements with an RGB color as parameter.
+const COLOR_KEYWORDS: &[&str] = &["Ka", "Kd", "Ks", "Ke", "Tf"];
+
+/// Statements that are part of the MTL specification, anything else is an
+/// extension and rejected in strict mode.
+const SPEC_KEYWORDS: &[&str] = &[
+    "newmtl",
+    "Ka",
+    "Kd",
+    "Ks",
+    "Tf",
+    "illum",
+    "d",
+    "Ns",
+    "sharpness",
+    "Ni",
+    "map_Ka",
+    "map_Kd",
+    "map_Ks",
+    "map_Ns",
+    "map_d",
+    "map_aat",
+    "disp",
+    "decal",
+    "bump",
+    "refl",
+];
+
+/// Keys of the maps of aitios materials, each with the MTL keywords that
+/// set the map, in order of preference.
+pub const MAP_KEYWORDS: &[(&str, &[&str])] = &[
+    ("map_Kd", &["map_Kd"]),
+    ("map_Ka", &["map_Ka"]),
+    ("map_Ks", &["map_Ks"]),
+    // The official name, then names seen in the wild, the last one is just silly
+    ("bump", &["bump", "map_bump", "bump_map"]),
+    ("disp", &["disp", "map_disp", "disp_map"]),
+    // While bump and displacement are standardized, what follows isnt.
+    // Some exporters write normal maps as map_Ns, which is actually the
+    // specular exponent map, hence use "norm", which is recommended for normals
+    (
+        "norm",
+        &["norm", "map_norm", "map_normal", "normal", "normal_map"],
+    ),
+    (
+        "map_Pr",
+        &["map_Pr", "map_PR", "map_pr", "map_pR", "Pr_map"],
+    ),
+    (
+        "map_Pm",
+        &["map_Pm", "map_PM", "map_pm", "map_pM", "Pm_map"],
+    ),
+    (
+        "map_Ps",
+        &["map_Ps", "map_PS", "map_ps", "map_pS", "Ps_map"],
+    ),
+    (
+        "map_Ke",
+        &["map_Ke", "map_KE", "map_ke", "map_kE", "Ke_map"],
+    ),
+];
+
+/// Keys of the maps in `MaterialProperties`, each with the MTL keywords that
+/// set the map, in order of preference.
+pub const PROPERTY_MAP_KEYWORDS: &[(&str, &[&str])] = &[
+    ("map_d", &["map_d"]),
+    ("map_Ns", &["map_Ns"]),
+    // Clearcoat of the PBR extension, with the same spellings as its other maps
+    (
+        "map_Pc",
+        &["map_Pc", "map_PC", "map_pc", "map_pC", "Pc_map"],
+    ),
+    (
+        "map_Pcr",
+        &["map_Pcr", "map_PCR", "map_pcr", "map_pCR", "Pcr_map"],
+    ),
+    // Ambient occlusion is not standardized either, game engine exporters
+    // mostly agree on map_ao but differ in case
+    (
+        "map_ao",
+        &[
+            "map_ao",
+            "map_AO",
+            "map_Ao",
+            "occlusion",
+            "map_occlusion",
+            "ao_map",
+        ],
+    ),
+];
+
+/// Statements of the MTL specification that reference a texture map.
+const SPEC_MAP_KEYWORDS: &[&str] = &[
+    "map_Ka", "map_Kd", "map_Ks", "map_Ns", "map_d", "disp", "decal", "bump", "refl",
+];
+
+/// Parses the MTL file at the given path.
+pub fn parse_file(path: &Path, options: &LoadOptions) -> Result<MtlLibrary> {
+    parse(open_file(path, options.vfs())?, path, options)
+}
+
+/// Parses MTL statements from the given reader. The path is only used to
+/// report the location of errors.
+pub fn parse<R: BufRead>(reader: R, path: &Path, options: &LoadOptions) -> Result<MtlLibrary> {
+    let mut library = MtlLibrary::default();
+    let mut skip_budget = options.skip_budget();
+
+    for (line_idx, bytes) in read_lines(reader).enumerate() {
+        let bytes = bytes?;
+        let line_number = line_idx + 1;
+        library.bytes += bytes.len() as u64;
+
+        let (line, result) = match decode_line(&bytes, options.encoding) {
+            Ok(line) => {
+                let result =
+                    statement(strip_comment(&line), path, &mut library, options.parse_mode);
+                (line, result)
+            }
+            Err(err) => (String::from_utf8_lossy(&bytes).into_owned(), Err(err)),
+        };
+        let line = strip_comment(&line);
+
+        match result {
+            Ok(()) => (),
+            Err(err) if skip_budget > 0 => {
+                skip_budget -= 1;
+                let warning = err.skip(path, line_number, line);
+                library.warnings.push(warning);
+            }
+            Err(err) => return Err(err.locate(path, line_number, line)),
+        }
+    }
+
+    Ok(library)
+}
+
+fn statement(
+    line: &str,
+    path: &Path,
+    library: &mut MtlLibrary,
+    mode: ParseMode,
+) -> ParseResult<()> {
+    let materials = &mut library.materials;
+    let ids = &mut library.ids;
+
+    let mut words = Words::new(line);
+    let first = match words.next() {
+        Some(first) => first,
+        // Empty line
+        None => return Ok(()),
+    };
+    let keyword = first.text;
+
+    if keyword == "newmtl" {
+        let name = rest_of_line(words);
+        if name.is_empty() {
+            return Err(SyntaxError::missing("Expected material name"));
+        }
+
+        if ids.contains_key(&name) {
+            library.warnings.push(Warning::MaterialRedefined {
+                material: name.clone(),
+                library: path.to_path_buf(),
+            });
+        } else {
+            ids.insert(name.clone(), materials.len());
+        }
+
+        // Keep the redefinition so later statements have somewhere to go
+        materials.push(MtlMaterial {
+            name,
+            params: HashMap::new(),
+            reflections: Vec::new(),
+            library: path.to_path_buf(),
+        });
+        return Ok(());
+    }
+
+    if mode == ParseMode::Strict {
+        if !SPEC_KEYWORDS.contains(&keyword) {
+            return Err(first.error("Statement is not part of the MTL specification"));
+        }
+        if SPEC_MAP_KEYWORDS.contains(&keyword) {
+            let params_offset = first.offset + keyword.len();
+            check_map_options(line[params_offset..].trim_end(), params_offset)?;
+        }
+    }
+
+    if keyword == "d" && words.clone().next().map(|word| word.text) == Some("-halo") {
+        parse_floats::<[f32; 1], _>(&mut words.clone().skip(1))?;
+    } else if SCALAR_KEYWORDS.contains(&keyword) {
+        parse_floats::<[f32; 1], _>(&mut words.clone())?;
+    } else if COLOR_KEYWORDS.contains(&keyword) {
+        check_color(&mut words.clone())?;
+    } else if keyword == "illum" {
+        if let Some(word) = words.clone().next() {
+            word.parse::<u8>()
+                .map_err(|_| word.error("Malformed illumination model"))?;
+        }
+    }
+
+    // Statements before the first newmtl have no material to go to
+    match materials.last_mut() {
+        Some(material) => {
+            let params = raw_rest_of_line(line, keyword);
+            if keyword == "refl" {
+                material.reflections.push(params.clone());
+            }
+            material.params.insert(keyword.to_string(), params);
+        }
+        None if mode == ParseMode::Strict => {
+            return Err(first.error("MTL statement before first newmtl"))
+        }
+        None => (),
+    }
+
+    Ok(())
+}
+
+/// Number of arguments of each option that can precede the file name in map
+/// statements. Options with up to three numbers are listed with one argument,
+/// further numbers are skipped separately.
+const MAP_OPTIONS: &[(&str, usize)] = &[
+    ("-blendu", 1),
+    ("-blendv", 1),
+    ("-bm", 1),
+    ("-boost", 1),
+    ("-cc", 1),
+    ("-clamp", 1),
+    ("-imfchan", 1),
+    ("-mm", 2),
+    ("-o", 1),
+    ("-s", 1),
+    ("-t", 1),
+    ("-texres", 1),
+    ("-type", 1),
+    // Not part of the specification, but written when saving
+    ("-colorspace", 1),
+];
+
+/// Gets the file name from the parameters of a map statement, skipping any
+/// options before it.
+///
+/// The file name is either quoted, or it is the whole rest of the statement
+/// after the options, which may contain spaces.
+pub fn map_file_name(params: &str) -> String {
+    let (_, rest) = split_map_options(params);
+    if rest.starts_with('"') {
+        split_quoted(rest).into_iter().next().unwrap_or_default()
+    } else {
+        rest.to_string()
+    }
+}
+
+/// Parses the options before the file name of a map statement. Options with
+/// malformed arguments are left out.
+pub fn texture_options(params: &str) -> TextureOptions {
+    let (options, _) = split_map_options(params);
+    let mut parsed = TextureOptions::default();
+
+    for (option, args) in options {
+        let on = |args: &[&str]| match args.first() {
+            Some(&"on") => Some(true),
+            Some(&"off") => Some(false),
+            _ => None,
+        };
+        let numbers: Option<Vec<f32>> = args.iter().map(|arg| parse_f32(arg)).collect();
+        let numbers = numbers.unwrap_or_default();
+        let vector = |fill: f32| {
+            let mut vector = [fill; 3];
+            for (component, &number) in vector.iter_mut().zip(numbers.iter()) {
+                *component = number;
+            }
+            Some(vector).filter(|_| !numbers.is_empty())
+        };
+
+        match option {
+            "-blendu" => parsed.blend_u = on(&args),
+            "-blendv" => parsed.blend_v = on(&args),
+            "-bm" => parsed.bump_multiplier = numbers.first().cloned(),
+            "-boost" => parsed.boost = numbers.first().cloned(),
+            "-cc" => parsed.color_correction = on(&args),
+            "-clamp" => parsed.clamp = on(&args),
+            "-imfchan" => parsed.channel = args.first().map(|channel| channel.to_string()),
+            "-mm" if numbers.len() == 2 => parsed.range = Some([numbers[0], numbers[1]]),
+            "-o" => parsed.offset = vector(0.0),
+            "-s" => parsed.scale = vector(1.0),
+            "-t" => parsed.turbulence = vector(0.0),
+            "-texres" => parsed.resolution = args.first().and_then(|arg| arg.parse().ok()),
+            "-type" => parsed.map_type = args.first().map(|map_type| map_type.to_string()),
+            "-colorspace" => {
+                parsed.color_space = args.first().and_then(|arg| ColorSpace::parse(arg))
+            }
+            _ => (),
+        }
+    }
+
+    parsed
+}
+
+/// Splits the parameters of a map statement into the options before the
+/// file name, each with its arguments, and the rest.
+fn split_map_options(params: &str) -> (Vec<(&str, Vec<&str>)>, &str) {
+    let mut rest = params.trim();
+    let mut options = Vec::new();
+
+    loop {
+        let option = rest.split_whitespace().next().unwrap_or("");
+        let arg_count = match MAP_OPTIONS.iter().find(|&&(name, _)| name == option) {
+            Some(&(_, arg_count)) => arg_count,
+            None => break,
+        };
+
+        let mut args: Vec<&str> = rest.split_whitespace().skip(1).take(arg_count).collect();
+        rest = skip_words(rest, 1 + arg_count);
+        // Vectors have up to three components, skip the optional ones
+        if option == "-o" || option == "-s" || option == "-t" {
+            for _ in 0..2 {
+                match rest.split_whitespace().next() {
+                    Some(word) if parse_f32(word).is_some() => {
+                        args.push(word);
+                        rest = skip_words(rest, 1);
+                    }
+                    _ => break,
+                }
+            }
+        }
+        options.push((option, args));
+    }
+
+    (options, rest)
+}
+
+/// Checks that all options before the file name of a map statement are known
+/// and that there is a file name. The parameters start at the given byte
+/// offset in the line and must not end with whitespace.
+fn check_map_options(params: &str, offset: usize) -> ParseResult<()> {
+    let (_, rest) = split_map_options(params);
+    // What is left after the options is a suffix of the parameters
+    match Words::at(rest, offset + params.len() - rest.len()).next() {
+        Some(word) if word.starts_with('-') => Err(word.error("Unknown map option")),
+        Some(_) => Ok(()),
+        None => Err(SyntaxError::missing("Expected file name")),
+    }
+}
+
+/// Checks if statements with the given keyword reference a texture map,
+/// including the unofficial names that are understood when loading.
+#[cfg(feature = "http")]
+pub fn is_map_keyword(keyword: &str) -> bool {
+    SPEC_MAP_KEYWORDS.contains(&keyword)
+        || keyword.to_lowercase().contains("map")
+        || keyword.starts_with("norm")
+        || keyword == "occlusion"
+}
+
+/// Skips the given number of whitespace-separated words at the start of the text.
+fn skip_words(text: &str, count: usize) -> &str {
+    let mut rest = text.trim_start();
+    for _ in 0..count {
+        let word_end = rest.find(char::is_whitespace).unwrap_or_else(|| rest.len());
+        rest = rest[word_end..].trim_start();
+    }
+    rest
+}
+
+/// Checks an RGB color, which may also be a single value for all channels,
+/// or given as `spectral` or `xyz`.
+fn check_color<'a, I: Iterator<Item = Word<'a>> + Clone>(words: &mut I) -> ParseResult<()> {
+    match words.clone().next().map(|word| word.text) {
+        Some("spectral") => Ok(()),
+        Some("xyz") => {
+            words.next();
+            parse_floats::<[f32; 1], _>(words).map(|_| ())
+        }
+        _ => {
+            parse_floats::<[f32; 1], _>(words)?;
+            // Green and blue are optional and default to red
+            for word in words.take(2) {
+                parse_f32(&word).ok_or_else(|| word.error("Malformed number"))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Parses an RGB color with optional green and blue channels that default
+/// to red. Spectral and CIE XYZ colors are not supported.
+fn rgb(value: &str) -> Option<[f32; 3]> {
+    let mut words = value.split_whitespace();
+    let red = parse_f32(words.next()?)?;
+    let mut color = [red; 3];
+    for channel in color.iter_mut().skip(1) {
+        if let Some(word) = words.next() {
+            *channel = parse_f32(word)?;
+        }
+    }
+    Some(color)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use err::AssetError::Parse;
+
+    #[test]
+    fn test_params_kept_by_material() {
+        let mtl = "newmtl A\nKd 1 0 0\nmap_Kd tex ture.png\nnewmtl B\nNs 10\n";
+        let library = parse(mtl.as_bytes(), Path::new("test.mtl"), &LoadOptions::new()).unwrap();
+
+        assert_eq!(2, library.materials.len());
+        assert_eq!(Some(&1), library.ids.get("B"));
+        assert_eq!("tex ture.png", library.materials[0].params["map_Kd"]);
+        assert_eq!("10", library.materials[1].params["Ns"]);
+    }
+
+    #[test]
+    fn test_properties() {
+        let mtl = "newmtl A\nKd 1 0.5 0\nKa 0.2\nNs 10\nTr 0.25\nillum 2\nTf spectral tf.rfl\n\
+                   newmtl B\nd -halo 0.5\nTr 0.9\n";
+        let library = parse(mtl.as_bytes(), Path::new("test.mtl"), &LoadOptions::new()).unwrap();
+
+        let a = library.materials[0].properties();
+        assert_eq!(Some([1.0, 0.5, 0.0]), a.diffuse);
+        assert_eq!(Some([0.2; 3]), a.ambient);
+        assert_eq!(Some(10.0), a.shininess);
+        assert_eq!(Some(0.75), a.dissolve);
+        assert_eq!(Some(2), a.illumination);
+        assert_eq!(None, a.transmission_filter);
+        assert_eq!(None, a.specular);
+
+        assert_eq!(Some(0.5), library.materials[1].properties().dissolve);
+    }
+
+    #[test]
+    fn test_unknown_params_keep_unstored_statements() {
+        let mtl = "newmtl A\nKd 1 0 0\nPr 0.4\nsharpness 60\ndecal d.png\nbump b.png\n\
+                   map_bump other.png\nTf spectral tf.rfl\nd -halo 0.5\nxTwoSided\n";
+        let library = parse(mtl.as_bytes(), Path::new("test.mtl"), &LoadOptions::new()).unwrap();
+
+        let properties = library.materials[0].properties();
+        let unknown: Vec<&str> = properties
+            .unknown_params
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        assert_eq!(
+            vec![
+                "Pr",
+                "Tf",
+                "d",
+                "decal",
+                "map_bump",
+                "sharpness",
+                "xTwoSided"
+            ],
+            unknown
+        );
+    }
+
+    #[test]
+    fn test_error_location() {
+        let mtl = "newmtl A\n\n  Kd 1 O 0\n";
+        match parse(mtl.as_bytes(), Path::new("test.mtl"), &LoadOptions::new()).unwrap_err() {
+            Parse {
+                line,
+                column,
+                token,
+                ..
+            } => assert_eq!((3, 8, "O".to_string()), (line, column, token)),
+            err => panic!("Unexpected error: {}", err),
+        }
+
+        let mtl = "newmtl A\nmap_Kd  -clamp on -foo tex.png\n";
+        let strict = LoadOptions::new().parse_mode(ParseMode::Strict);
+        match parse(mtl.as_bytes(), Path::new("test.mtl"), &strict).unwrap_err() {
+            Parse { column, token, .. } => assert_eq!((19, "-foo".to_string()), (column, token)),
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_lenient_skips_malformed_lines() {
+        let mtl = "newmtl A\nNs ten\nNi 1.5\n";
+        let options = LoadOptions::new().parse_mode(ParseMode::Lenient);
+        let library = parse(mtl.as_bytes(), Path::new("test.mtl"), &options).unwrap();
+
+        assert!(!library.materials[0].params.contains_key("Ns"));
+        assert_eq!("1.5", library.materials[0].params["Ni"]);
+        assert_eq!(1, library.warnings.len());
+    }
+
+    #[test]
+    fn test_strict_rejects_extensions() {
+        let strict = LoadOptions::new().parse_mode(ParseMode::Strict);
+        let token = |mtl: &str| match parse(mtl.as_bytes(), Path::new("test.mtl"), &strict) {
+            Err(Parse { token, .. }) => token,
+            Ok(_) => String::new(),
+            Err(err) => panic!("Unexpected error: {}", err),
+        };
+
+        let spec = "newmtl A\nKd 1 0 0\nd -halo 0.5\nmap_Kd -s 2 2 -clamp on tex.png\n";
+        assert_eq!("", token(spec));
+        assert_eq!("Tr", token("newmtl A\nTr 0.5\n"));
+        assert_eq!("map_bump", token("newmtl A\nmap_bump tex.png\n"));
+        assert_eq!("-foo", token("newmtl A\nmap_Kd -foo 1 tex.png\n"));
+        assert_eq!("", token("newmtl A\nmap_Kd -s 2 2\n"));
+        assert_eq!("", token("newmtl A\nmap_Kd -clamp on texture\n"));
+        assert_eq!("", token("newmtl A\nbump -bm 0.3 bump\n"));
+        assert_eq!("-foo", token("newmtl A\nmap_Kd -clamp on -foo tex.png\n"));
+        let missing = parse(
+            "newmtl A\nmap_Kd -clamp on\n".as_bytes(),
+            Path::new("test.mtl"),
+            &strict,
+        );
+        assert!(missing.is_err());
+
+        assert!(parse(spec.as_bytes(), Path::new("test.mtl"), &LoadOptions::new()).is_ok());
+    }
+
+    #[test]
+    fn test_map_file_name() {
+        assert_eq!("my texture.png", map_file_name("my texture.png"));
+        assert_eq!("my texture.png", map_file_name("\"my texture.png\""));
+        assert_eq!(
+            "bricks 2.png",
+            map_file_name("-s 2 2 -o 0.5 -bm 0.3 -clamp on bricks 2.png")
+        );
+        assert_eq!("1.png", map_file_name("-mm 0 1 1.png"));
+    }
+
+    #[test]
+    fn test_texture_options() {
+        let options = texture_options("-bm 0.3 -o 0.5 0.25 -clamp on -imfchan l bump 2.png");
+        assert_eq!(Some(0.3), options.bump_multiplier);
+        assert_eq!(Some([0.5, 0.25, 0.0]), options.offset);
+        assert_eq!(Some(true), options.clamp);
+        assert_eq!(Some("l".to_string()), options.channel);
+        assert_eq!(None, options.scale);
+        assert_eq!(
+            "-bm 0.3 -clamp on -imfchan l -o 0.5 0.25 0",
+            options.to_string()
+        );
+        assert_eq!(options, texture_options(&options.to_string()));
+        assert!(texture_options("bump.png").is_empty());
+
+        let mtl = "newmtl A\nmap_bump -bm 2 bump.png\nmap_Kd tex.png\n";
+        let library = parse(mtl.as_bytes(), Path::new("test.mtl"), &LoadOptions::new()).unwrap();
+        let map_options = library.materials[0].properties().map_options;
+        assert_eq!(vec!["bump"], map_options.keys().collect::<Vec<_>>());
+        assert_eq!(Some(2.0), map_options["bump"].bump_multiplier);
+    }
+}
@@ -0,0 +1,1519 @@
+use coords;
+use loaded::{ColorSpace, MaterialProperties};
+use normalize::separators;
+use obj::library::MaterialLibrary;
+use scene::{Entity, Material};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+use vfs::{DiskFileSystem, FileSystem};
+
+/// Controls how OBJ files are converted into entities by `load_with`.
+///
+/// The default options load files the same way as `load`.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    pub(crate) preserve_polygons: bool,
+    pub(crate) regenerate_normals: bool,
+    pub(crate) discover_mtl: bool,
+    pub(crate) texcoord_w: bool,
+    pub(crate) missing_texcoords: MissingTexcoords,
+    pub(crate) missing_textures: MissingTextures,
+    pub(crate) parse_mode: ParseMode,
+    pub(crate) encoding: Encoding,
+    pub(crate) max_skipped_lines: Option<usize>,
+    pub(crate) object_filter: NameFilter,
+    pub(crate) split_policy: SplitPolicy,
+    pub(crate) max_entity_vertices: Option<u32>,
+    pub(crate) memory_budget: Option<usize>,
+    pub(crate) texture_check: TextureCheck,
+    pub(crate) authored_texture_paths: bool,
+    pub(crate) normalize_unicode: bool,
+    pub(crate) intern_names: bool,
+    pub(crate) face_adjacency: bool,
+    pub(crate) surfel_density: Option<f32>,
+    pub(crate) bounds: bool,
+    pub(crate) validate_geometry: bool,
+    pub(crate) merge_duplicate_materials: bool,
+    pub(crate) merge_tolerance: f32,
+    pub(crate) detect_instances: bool,
+    pub(crate) weld_epsilon: Option<f32>,
+    pub(crate) coordinate_system: CoordinateSystem,
+    pub(crate) source_unit: Unit,
+    pub(crate) target_unit: Unit,
+    pub(crate) transform: Option<[[f32; 4]; 4]>,
+    pub(crate) flip_v: bool,
+    pub(crate) winding: Winding,
+    pub(crate) origin_threshold: Option<f64>,
+    pub(crate) frame_header: bool,
+    pub(crate) double_precision: bool,
+    pub(crate) recenter: bool,
+    pub(crate) case_insensitive_paths: bool,
+    pub(crate) path_remappings: Vec<(String, String)>,
+    pub(crate) search_dirs: Vec<PathBuf>,
+    pub(crate) file_system: Option<Rc<dyn FileSystem>>,
+    pub(crate) unknown_params: UnknownParams,
+    pub(crate) material_overrides: MaterialOverrides,
+    pub(crate) material_library: Option<Rc<MaterialLibrary>>,
+    pub(crate) ignore_mtllib: bool,
+    pub(crate) map_color_spaces: BTreeMap<String, ColorSpace>,
+}
+
+impl LoadOptions {
+    pub fn new() -> LoadOptions {
+        LoadOptions::default()
+    }
+
+    /// If set, the vertex count of each face is recorded in the `face_sizes`
+    /// of the entity extras, so that quads and n-gons can be written back
+    /// unchanged with `save_scene`.
+    pub fn preserve_polygons(mut self, preserve: bool) -> LoadOptions {
+        self.preserve_polygons = preserve;
+        self
+    }
+
+    /// If set, normals in the OBJ are ignored and generated from the faces
+    /// instead, like for files that do not specify normals at all.
+    ///
+    /// Generated normals are smooth between faces in the same smoothing
+    /// group, as declared with `s` statements, and flat for faces that
+    /// have smoothing turned off.
+    pub fn regenerate_normals(mut self, regenerate: bool) -> LoadOptions {
+        self.regenerate_normals = regenerate;
+        self
+    }
+
+    /// If set, OBJ files without an `mtllib` statement, or with one that refers
+    /// to a non-existing file, do not fail to load. Instead, an MTL file with the
+    /// same stem next to the OBJ is used, or, if there is none, the only MTL in
+    /// the directory of the OBJ. The choice is logged as a warning. Declared
+    /// MTL files that exist are still loaded along with it.
+    pub fn discover_mtl(mut self, discover: bool) -> LoadOptions {
+        self.discover_mtl = discover;
+        self
+    }
+
+    /// If set, the optional third component of `vt` statements is kept in the
+    /// `texcoord_ws` of the entity extras, one value per vertex, provided that
+    /// any `vt` statement in the file has one.
+    ///
+    /// By default, it is dropped and only u and v end up in the entity mesh.
+    /// Either way, `vt` statements with one, two or three components are
+    /// accepted, with missing components being zero.
+    pub fn texcoord_w(mut self, keep: bool) -> LoadOptions {
+        self.texcoord_w = keep;
+        self
+    }
+
+    /// Sets what happens to objects without texture coordinates, by default
+    /// they are all set to zero.
+    pub fn missing_texcoords(mut self, policy: MissingTexcoords) -> LoadOptions {
+        self.missing_texcoords = policy;
+        self
+    }
+
+    /// Sets what happens to texture maps that cannot be found, by default
+    /// they are left out of the material. A warning is recorded either way,
+    /// unless loading fails.
+    pub fn missing_textures(mut self, policy: MissingTextures) -> LoadOptions {
+        self.missing_textures = policy;
+        self
+    }
+
+    /// Sets how strictly OBJ and MTL files are checked, see `ParseMode`.
+    pub fn parse_mode(mut self, mode: ParseMode) -> LoadOptions {
+        self.parse_mode = mode;
+        self
+    }
+
+    /// Sets how the bytes of OBJ and MTL files are decoded, by default lines
+    /// that are not valid UTF-8 are reported as malformed.
+    pub fn encoding(mut self, encoding: Encoding) -> LoadOptions {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets how many malformed lines are skipped in each OBJ or MTL file
+    /// before loading fails, recording a warning with the line number and
+    /// content of each skipped line.
+    ///
+    /// By default, no lines are skipped, except in lenient mode, where any
+    /// number of lines is skipped.
+    pub fn max_skipped_lines(mut self, max: usize) -> LoadOptions {
+        self.max_skipped_lines = Some(max);
+        self
+    }
+
+    /// Number of malformed lines that may be skipped in each file.
+    pub(crate) fn skip_budget(&self) -> usize {
+        match (self.max_skipped_lines, self.parse_mode) {
+            (Some(max), _) => max,
+            (None, ParseMode::Lenient) => usize::max_value(),
+            (None, _) => 0,
+        }
+    }
+
+    /// Sets which objects and groups are loaded. The faces, lines and points
+    /// of other objects are skipped without parsing them.
+    ///
+    /// Vertex data is always parsed, since any object may refer to it.
+    pub fn object_filter(mut self, filter: NameFilter) -> LoadOptions {
+        self.object_filter = filter;
+        self
+    }
+
+    /// Sets which statements start a new entity, by default both `o` and `g`.
+    pub fn split_policy(mut self, policy: SplitPolicy) -> LoadOptions {
+        self.split_policy = policy;
+        self
+    }
+
+    /// Limits the number of vertices of each entity, continuing in another
+    /// entity with the same name and material when the corners of a face,
+    /// line or point could exceed it, e.g. to load scans with more vertices
+    /// than fit into `u32` indices. By default, entities are only split when
+    /// indices would overflow.
+    ///
+    /// A single element with more corners than the limit still goes into
+    /// one entity. Each split is reported as a warning.
+    pub fn max_entity_vertices(mut self, max_vertices: u32) -> LoadOptions {
+        self.max_entity_vertices = Some(max_vertices);
+        self
+    }
+
+    /// Limits the estimated number of bytes the parsed data of an OBJ may
+    /// take up before converting it, e.g. for untrusted files. Loading fails
+    /// if the limit is exceeded, except with `load_chunks`, which hands out
+    /// what was parsed so far instead.
+    pub fn memory_budget(mut self, bytes: usize) -> LoadOptions {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Sets how the existence of texture maps is checked, by default each map
+    /// path is canonicalized.
+    pub fn texture_check(mut self, check: TextureCheck) -> LoadOptions {
+        self.texture_check = check;
+        self
+    }
+
+    /// If set, map paths are kept as written in the MTL instead of being
+    /// canonicalized, with relative paths joined to the directory of the MTL,
+    /// e.g. for relocatable bundles or network shares where canonicalization
+    /// fails or yields unwanted paths.
+    ///
+    /// Unless the texture check is `Skip`, the existence of each map is still
+    /// checked with its metadata.
+    pub fn authored_texture_paths(mut self, authored: bool) -> LoadOptions {
+        self.authored_texture_paths = authored;
+        self
+    }
+
+    /// If set, names of entities and materials are converted to Unicode NFC.
+    /// Texture and MTL paths that cannot be found are also looked up by
+    /// comparing them with the files on disk in NFC, so that e.g. decomposed
+    /// file names written on macOS match composed references in the MTL.
+    pub fn normalize_unicode(mut self, normalize: bool) -> LoadOptions {
+        self.normalize_unicode = normalize;
+        self
+    }
+
+    /// If set, the names of entities and materials and the keys of texture
+    /// maps are stored once in the `names` of the loaded scene, and the entity
+    /// extras hold symbols for the entity and material names, which are
+    /// faster to compare than the names themselves.
+    pub fn intern_names(mut self, intern: bool) -> LoadOptions {
+        self.intern_names = intern;
+        self
+    }
+
+    /// If set, the triangles adjacent to each triangle are found while
+    /// loading and stored in the `adjacency` of the entity extras.
+    pub fn face_adjacency(mut self, build: bool) -> LoadOptions {
+        self.face_adjacency = build;
+        self
+    }
+
+    /// Samples the surface of each entity with roughly the given number of
+    /// blue-noise distributed surfels per unit of area while loading, storing
+    /// them in the `surfels` of the entity extras.
+    ///
+    /// By default, no surfels are generated.
+    pub fn surfel_density(mut self, density: f32) -> LoadOptions {
+        self.surfel_density = Some(density);
+        self
+    }
+
+    /// If set, the bounding box and sphere of each entity are computed after
+    /// loading and kept in the `bounds` of the entity extras.
+    pub fn bounds(mut self, compute: bool) -> LoadOptions {
+        self.bounds = compute;
+        self
+    }
+
+    /// If set, the mesh of each entity is checked for NaN or infinite values,
+    /// out-of-range indices, triangles without area and normals without unit
+    /// length after loading. Each kind of problem is recorded as a warning
+    /// for each entity.
+    pub fn validate_geometry(mut self, validate: bool) -> LoadOptions {
+        self.validate_geometry = validate;
+        self
+    }
+
+    /// If set, materials with the same statements as a material defined
+    /// earlier, only with another name, are replaced with the earlier material,
+    /// so that entities share a single `Rc<Material>`. The merged materials
+    /// are left out of the loaded materials and recorded as warnings.
+    pub fn merge_duplicate_materials(mut self, merge: bool) -> LoadOptions {
+        self.merge_duplicate_materials = merge;
+        self
+    }
+
+    /// Sets how much numbers in MTL statements may differ for materials to
+    /// be merged by `merge_duplicate_materials`, e.g. `Kd 0.8 0.8 0.8` and
+    /// `Kd 0.8001 0.8 0.8`. By default, statements have to match exactly.
+    pub fn merge_tolerance(mut self, tolerance: f32) -> LoadOptions {
+        self.merge_tolerance = tolerance;
+        self
+    }
+
+    /// If set, entities whose vertices equal the vertices of an earlier
+    /// entity after a rotation and translation share the mesh of the earlier
+    /// entity. The transform from the shared mesh into the scene is then kept
+    /// in `EntityExtras::instance_transform` of each of these entities.
+    ///
+    /// Mirrored copies are detected too, if their faces are reversed so that
+    /// they face outwards.
+    pub fn detect_instances(mut self, detect: bool) -> LoadOptions {
+        self.detect_instances = detect;
+        self
+    }
+
+    /// Merges vertices of each entity whose position, normal, texture
+    /// coordinates and vertex color each differ by at most `epsilon` per
+    /// component, even if the OBJ refers to them with different indices.
+    /// Use zero to only merge vertices with identical attributes.
+    pub fn weld_vertices(mut self, epsilon: f32) -> LoadOptions {
+        self.weld_epsilon = Some(epsilon);
+        self
+    }
+
+    /// Sets the coordinate system the OBJ was authored in. Positions and
+    /// normals are converted from it into the Y-up, right-handed system of
+    /// loaded entities.
+    pub fn coordinate_system(mut self, system: CoordinateSystem) -> LoadOptions {
+        self.coordinate_system = system;
+        self
+    }
+
+    /// Scales positions from the unit the OBJ was authored in to the unit
+    /// used for loaded entities. By default both are meters and nothing is
+    /// scaled.
+    pub fn units(mut self, source: Unit, target: Unit) -> LoadOptions {
+        self.source_unit = source;
+        self.target_unit = target;
+        self
+    }
+
+    /// Sets a column-major matrix that positions and normals of all entities
+    /// are transformed with, after converting coordinate systems and units,
+    /// e.g. to place several files in the same world.
+    ///
+    /// Faces are reversed if the matrix mirrors, so they keep facing outwards.
+    pub fn transform(mut self, matrix: [[f32; 4]; 4]) -> LoadOptions {
+        self.transform = Some(matrix);
+        self
+    }
+
+    /// Sets the transform from a scale, a rotation as a quaternion in
+    /// `[x, y, z, w]` order and a translation, applied in that order.
+    pub fn translate_rotate_scale(
+        self,
+        translation: [f32; 3],
+        rotation: [f32; 4],
+        scale: [f32; 3],
+    ) -> LoadOptions {
+        self.transform(coords::trs_matrix(translation, rotation, scale))
+    }
+
+    /// If set, the V texture coordinate of each vertex is replaced with one
+    /// minus V, for files written by tools that put the texture origin in
+    /// the top left corner.
+    pub fn flip_v(mut self, flip: bool) -> LoadOptions {
+        self.flip_v = flip;
+        self
+    }
+
+    /// Sets how the winding order of faces is checked or changed after
+    /// transforming, by default it is kept as authored.
+    pub fn winding(mut self, winding: Winding) -> LoadOptions {
+        self.winding = winding;
+        self
+    }
+
+    /// If set, positions are parsed in double precision and each coordinate
+    /// of the first position with a magnitude of at least `threshold` is
+    /// rounded and subtracted from that coordinate of all positions, before
+    /// converting them to single precision. This keeps geo-referenced files
+    /// with coordinates in the millions precise.
+    ///
+    /// The subtracted offset is kept in `LoadedScene::origin`, converted
+    /// like positions, and added back when saving the scene.
+    pub fn shift_origin(mut self, threshold: f64) -> LoadOptions {
+        self.origin_threshold = Some(threshold);
+        self
+    }
+
+    /// If set, a `# aitios frame` header comment as written with
+    /// `SaveOptions::frame_header` is read. Its origin ends up in
+    /// `LoadedScene::origin`, in addition to any offset subtracted with
+    /// `shift_origin`, and its unit replaces the source unit set with `units`.
+    pub fn frame_header(mut self, read: bool) -> LoadOptions {
+        self.frame_header = read;
+        self
+    }
+
+    /// If set, positions are parsed in double precision and kept in
+    /// `EntityExtras::precise_positions`, converted into the scene like the
+    /// positions of the mesh. Saving writes them instead of the mesh
+    /// positions as long as these are left unchanged.
+    ///
+    /// Entities sharing the mesh of another entity keep no precise positions,
+    /// see `detect_instances`.
+    pub fn double_precision(mut self, keep: bool) -> LoadOptions {
+        self.double_precision = keep;
+        self
+    }
+
+    /// If set, the center of the bounding box of all positions in the file,
+    /// rounded to whole units, is subtracted from the positions in double
+    /// precision. The offset is kept in `LoadedScene::origin` like with
+    /// `shift_origin`.
+    pub fn recenter(mut self, recenter: bool) -> LoadOptions {
+        self.recenter = recenter;
+        self
+    }
+
+    /// Checks if positions are kept in double precision while parsing.
+    pub(crate) fn keeps_precise(&self) -> bool {
+        self.double_precision || self.recenter
+    }
+
+    /// If set, `mtllib` and texture map paths that cannot be found are looked
+    /// up again ignoring case, comparing each component of the path with the
+    /// files on disk, e.g. for assets authored on Windows.
+    pub fn case_insensitive_paths(mut self, case_insensitive: bool) -> LoadOptions {
+        self.case_insensitive_paths = case_insensitive;
+        self
+    }
+
+    /// Adds a rule that replaces the given prefix of `mtllib` and texture map
+    /// paths with another prefix before the paths are resolved, e.g. to load
+    /// files referencing `X:/projects/` from `/mnt/projects/`.
+    ///
+    /// If multiple rules match a path, the one with the longest prefix wins.
+    /// Backslashes in paths are converted to slashes before matching, so
+    /// `X:/projects/` also matches `X:\projects\`.
+    pub fn remap_prefix<S, T>(mut self, old_prefix: S, new_prefix: T) -> LoadOptions
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        self.path_remappings
+            .push((old_prefix.into(), new_prefix.into()));
+        self
+    }
+
+    /// Adds a directory to look for texture maps in that cannot be found
+    /// otherwise, e.g. because they were referenced with an absolute path on
+    /// another machine.
+    ///
+    /// The directories are searched in the order they were added, first for
+    /// the path as it is given in the MTL, then for only its file name.
+    pub fn search_dir<P: Into<PathBuf>>(mut self, dir: P) -> LoadOptions {
+        self.search_dirs.push(dir.into());
+        self
+    }
+
+    /// Sets the file system that OBJ and MTL files are read from and that
+    /// texture maps are looked up in, by default the disk. Textures
+    /// generated for missing maps are written to it as well.
+    ///
+    /// Downloads with the `http` feature always use the disk.
+    pub fn file_system<F: FileSystem + 'static>(mut self, file_system: F) -> LoadOptions {
+        self.file_system = Some(Rc::new(file_system));
+        self
+    }
+
+    /// The file system set with `file_system`, or the disk.
+    pub(crate) fn vfs(&self) -> &dyn FileSystem {
+        match self.file_system {
+            Some(ref file_system) => &**file_system,
+            None => &DiskFileSystem,
+        }
+    }
+
+    /// Sets what happens to MTL statements that are not understood when
+    /// converting materials, by default they are ignored. Their parameters
+    /// are still available through `load_mtl`.
+    pub fn unknown_params(mut self, unknown_params: UnknownParams) -> LoadOptions {
+        self.unknown_params = unknown_params;
+        self
+    }
+
+    /// Sets materials that replace the materials defined in MTL files while
+    /// loading, e.g. to swap placeholder materials for calibrated ones. This
+    /// also applies to `load_mtl_with`.
+    pub fn material_overrides(mut self, overrides: MaterialOverrides) -> LoadOptions {
+        self.material_overrides = overrides;
+        self
+    }
+
+    /// Sets a library of materials that `usemtl` statements bind to by name,
+    /// taking precedence over materials with the same name in the MTL files
+    /// of the OBJ. The library is loaded only once, and scenes loaded with it
+    /// share its materials.
+    pub fn material_library(mut self, library: MaterialLibrary) -> LoadOptions {
+        self.material_library = Some(Rc::new(library));
+        self
+    }
+
+    /// If set, `mtllib` statements are ignored, so that materials only come
+    /// from the library set with `material_library`. Entities using other
+    /// materials get the default material, as if the material was unknown.
+    pub fn ignore_mtllib(mut self, ignore: bool) -> LoadOptions {
+        self.ignore_mtllib = ignore;
+        self
+    }
+
+    /// Tags all maps with the given MTL keyword with the given color space,
+    /// e.g. `map_Ks` for assets that store specular intensity in linear
+    /// space.
+    ///
+    /// Otherwise, maps are tagged with the color space from the non-standard
+    /// `-colorspace` option, or else the one indicated by a suffix of the file
+    /// name, like `_srgb` or `_linear`, or else the one that is conventional
+    /// for the map. The tags are kept in the `color_spaces` of the material
+    /// properties.
+    pub fn map_color_space<S: Into<String>>(
+        mut self,
+        key: S,
+        color_space: ColorSpace,
+    ) -> LoadOptions {
+        self.map_color_spaces.insert(key.into(), color_space);
+        self
+    }
+
+    /// Normalizes the separators of the given path and applies the best
+    /// matching prefix remapping, if any.
+    pub(crate) fn remap_path(&self, path: &str) -> String {
+        let path = separators(path);
+        let remapping = self
+            .path_remappings
+            .iter()
+            .map(|remapping| (separators(&remapping.0), &remapping.1))
+            .filter(|remapping| path.starts_with(remapping.0.as_str()))
+            .max_by_key(|remapping| remapping.0.len());
+
+        match remapping {
+            Some((old_prefix, new_prefix)) => {
+                format!("{}{}", new_prefix, &path[old_prefix.len()..])
+            }
+            None => path,
+        }
+    }
+}
+
+/// Determines how texture coordinates are obtained for objects in an OBJ that
+/// do not define any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingTexcoords {
+    /// Sets all texture coordinates to `(0.0, 0.0)`.
+    Zero,
+    /// Projects positions onto the plane along the smallest extent of the
+    /// bounding box of the object.
+    Planar,
+    /// Projects positions onto one of the sides of the bounding box of the
+    /// object, depending on the direction of the vertex normal.
+    Box,
+    /// Fails loading with an error.
+    Error,
+}
+
+impl Default for MissingTexcoords {
+    fn default() -> MissingTexcoords {
+        MissingTexcoords::Zero
+    }
+}
+
+/// Determines what happens to texture maps referenced in an MTL that do not exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingTextures {
+    /// Fails loading with an error.
+    Error,
+    /// Leaves the map out of the material.
+    Drop,
+    /// Keeps the map with the path it would have if it existed. Note that
+    /// saving a material with a missing map fails.
+    KeepPath,
+    /// Replaces the map with a magenta placeholder texture, written to a new
+    /// private directory in the temporary directory once per load.
+    Placeholder,
+}
+
+impl Default for MissingTextures {
+    fn default() -> MissingTextures {
+        MissingTextures::Drop
+    }
+}
+
+/// Determines how OBJ and MTL files that do not conform to the specification
+/// are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseMode {
+    /// Fails on malformed statements, but accepts common extensions like
+    /// vertex colors and ignores unknown statements.
+    Normal,
+    /// Fails on anything that is not part of the specification, including
+    /// extensions and unknown statements, vendor MTL keywords such as `Tr`
+    /// or `map_bump`, unknown map options and superfluous vertex values.
+    Strict,
+    /// Skips malformed statements and clamps out-of-range indices into range,
+    /// recording a warning for each line that was skipped or fixed.
+    Lenient,
+}
+
+impl Default for ParseMode {
+    fn default() -> ParseMode {
+        ParseMode::Normal
+    }
+}
+
+/// Orientation of the axes of a coordinate system.
+///
+/// Faces are wound counter-clockwise when looking at their front in every
+/// system, so converting between right- and left-handed systems also reverses
+/// the order of the vertices of each face.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoordinateSystem {
+    /// Y points up, Z towards the viewer, as specified for OBJ.
+    YUpRightHanded,
+    /// Y points up, Z away from the viewer.
+    YUpLeftHanded,
+    /// Z points up, Y away from the viewer, e.g. in Blender.
+    ZUpRightHanded,
+    /// Z points up, Y towards the viewer.
+    ZUpLeftHanded,
+}
+
+impl Default for CoordinateSystem {
+    fn default() -> CoordinateSystem {
+        CoordinateSystem::YUpRightHanded
+    }
+}
+
+/// Determines how the winding order of faces, which decides the side they
+/// face, is treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Winding {
+    /// Keeps the winding of all faces.
+    Keep,
+    /// Keeps the winding, but reports triangles that are wound against their
+    /// neighbors or make a closed part of the mesh face inwards, as a warning
+    /// when loading or in the log when saving.
+    Detect,
+    /// Reverses all faces and negates all normals.
+    Flip,
+    /// Reverses faces so that each connected part of a mesh is wound
+    /// consistently, facing outwards if it is closed, or else in the
+    /// direction of the normals. Normals pointing away from the faces around
+    /// their vertex are negated.
+    Orient,
+}
+
+impl Default for Winding {
+    fn default() -> Winding {
+        Winding::Keep
+    }
+}
+
+/// A unit of length for positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Millimeters,
+    Centimeters,
+    Meters,
+    Inches,
+}
+
+impl Unit {
+    /// Length of the unit in meters.
+    pub fn meters(self) -> f32 {
+        self.precise_meters() as f32
+    }
+
+    /// Length of the unit in meters in double precision, for origins.
+    pub(crate) fn precise_meters(self) -> f64 {
+        match self {
+            Unit::Millimeters => 0.001,
+            Unit::Centimeters => 0.01,
+            Unit::Meters => 1.0,
+            Unit::Inches => 0.0254,
+        }
+    }
+
+    /// Factor to multiply lengths in this unit with to get lengths in the
+    /// given unit.
+    pub fn scale_to(self, target: Unit) -> f32 {
+        if self == target {
+            1.0
+        } else {
+            self.meters() / target.meters()
+        }
+    }
+
+    /// Like `scale_to`, but in double precision.
+    pub(crate) fn precise_scale_to(self, target: Unit) -> f64 {
+        if self == target {
+            1.0
+        } else {
+            self.precise_meters() / target.precise_meters()
+        }
+    }
+}
+
+impl Default for Unit {
+    fn default() -> Unit {
+        Unit::Meters
+    }
+}
+
+/// Determines how the lines of OBJ and MTL files are decoded into text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// Treats lines that are not valid UTF-8 as malformed.
+    Utf8,
+    /// Replaces invalid UTF-8 sequences with the replacement character `�`.
+    Lossy,
+    /// Decodes lines that are not valid UTF-8 as Latin-1, which older
+    /// exporters tend to write for accented object and material names.
+    Latin1Fallback,
+}
+
+impl Default for Encoding {
+    fn default() -> Encoding {
+        Encoding::Utf8
+    }
+}
+
+/// Determines which statements in an OBJ start a new entity.
+///
+/// Except when merging, a `usemtl` that switches to another material in the
+/// middle of an object or group also starts a new entity with the same name,
+/// so that no material is lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitPolicy {
+    /// Starts a new entity for each `o` and each `g` statement.
+    ObjectsAndGroups,
+    /// Starts a new entity for each `o` statement, groups are ignored.
+    Objects,
+    /// Starts a new entity for each `g` statement, objects are ignored.
+    Groups,
+    /// Starts a new entity only when `usemtl` switches to another material,
+    /// objects and groups are ignored. Entities are named after the object or
+    /// group the material run starts in.
+    MaterialRuns,
+    /// Loads the whole file as a single entity, named after the object or
+    /// group of the first element and with the last material that was used.
+    Merged,
+}
+
+impl Default for SplitPolicy {
+    fn default() -> SplitPolicy {
+        SplitPolicy::ObjectsAndGroups
+    }
+}
+
+/// Determines how texture maps referenced in MTL files are checked for existence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureCheck {
+    /// Canonicalizes each map path, which fails for missing files and resolves
+    /// symbolic links.
+    Canonicalize,
+    /// Only queries the metadata of each distinct map path once, keeping paths
+    /// as they are instead of canonicalizing them. On network file systems,
+    /// this is a lot faster for scenes with many maps.
+    Metadata,
+    /// Does not access the file system at all. Relative map paths are assumed
+    /// to be relative to the OBJ and no texture is reported missing.
+    Skip,
+}
+
+impl Default for TextureCheck {
+    fn default() -> TextureCheck {
+        TextureCheck::Canonicalize
+    }
+}
+
+/// Selects objects or groups by name. Objects without a name are called
+/// `unnamed`.
+#[derive(Clone)]
+pub enum NameFilter {
+    /// Matches every name.
+    All,
+    /// Matches any of the given names exactly.
+    Names(Vec<String>),
+    /// Matches names against a pattern, where `*` stands for any number of
+    /// characters and `?` for a single character.
+    Glob(String),
+    /// Matches names the given function returns `true` for.
+    Predicate(Rc<dyn Fn(&str) -> bool>),
+}
+
+impl NameFilter {
+    /// Creates a filter matching any of the given names exactly.
+    pub fn names<I, S>(names: I) -> NameFilter
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        NameFilter::Names(names.into_iter().map(Into::into).collect())
+    }
+
+    /// Creates a filter matching names against the given glob pattern.
+    pub fn glob<S: Into<String>>(pattern: S) -> NameFilter {
+        NameFilter::Glob(pattern.into())
+    }
+
+    /// Creates a filter matching names the given function returns `true` for.
+    pub fn predicate<F>(predicate: F) -> NameFilter
+    where
+        F: Fn(&str) -> bool + 'static,
+    {
+        NameFilter::Predicate(Rc::new(predicate))
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match *self {
+            NameFilter::All => true,
+            NameFilter::Names(ref names) => names.iter().any(|n| n == name),
+            NameFilter::Glob(ref pattern) => {
+                let pattern: Vec<char> = pattern.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                glob_matches(&pattern, &name)
+            }
+            NameFilter::Predicate(ref predicate) => predicate(name),
+        }
+    }
+}
+
+impl Default for NameFilter {
+    fn default() -> NameFilter {
+        NameFilter::All
+    }
+}
+
+impl fmt::Debug for NameFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NameFilter::All => write!(f, "All"),
+            NameFilter::Names(ref names) => write!(f, "Names({:?})", names),
+            NameFilter::Glob(ref pattern) => write!(f, "Glob({:?})", pattern),
+            NameFilter::Predicate(_) => write!(f, "Predicate(..)"),
+        }
+    }
+}
+
+/// Determines what happens to MTL statements that are not understood when
+/// converting materials, such as studio-specific extensions.
+#[derive(Clone)]
+pub enum UnknownParams {
+    /// Ignores the statements.
+    Ignore,
+    /// Calls the given function with the keyword and the rest of the line of
+    /// each statement, in order of the keywords. If it returns the key of a
+    /// map, e.g. `map_Pr` or `map_ao`, the statement is loaded as that map,
+    /// unless the material already sets the map with a known statement.
+    Callback(Rc<UnknownParamCallback>),
+}
+
+/// Function mapping an MTL statement to a map key, see
+/// `UnknownParams::Callback`.
+pub type UnknownParamCallback = dyn Fn(&str, &str) -> Option<String>;
+
+impl UnknownParams {
+    /// Creates a hook that maps statements with the given function.
+    pub fn callback<F>(callback: F) -> UnknownParams
+    where
+        F: Fn(&str, &str) -> Option<String> + 'static,
+    {
+        UnknownParams::Callback(Rc::new(callback))
+    }
+
+    /// Gets the key of the map that the statement with the given keyword and
+    /// parameters sets, if any.
+    pub fn map_key(&self, keyword: &str, params: &str) -> Option<String> {
+        match *self {
+            UnknownParams::Ignore => None,
+            UnknownParams::Callback(ref callback) => callback(keyword, params),
+        }
+    }
+}
+
+impl Default for UnknownParams {
+    fn default() -> UnknownParams {
+        UnknownParams::Ignore
+    }
+}
+
+impl fmt::Debug for UnknownParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnknownParams::Ignore => write!(f, "Ignore"),
+            UnknownParams::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+/// Materials replacing the materials defined in MTL files while loading, see
+/// `LoadOptions::material_overrides`.
+///
+/// Replaced materials keep their MTL name as key in `LoadedScene::materials`,
+/// so that entities using them by that name still find them. The
+/// `material_properties` of replaced materials are left empty.
+#[derive(Clone, Default)]
+pub struct MaterialOverrides {
+    replacements: BTreeMap<String, Rc<Material>>,
+    rewrite: Option<Rc<MaterialRewrite>>,
+}
+
+/// Function returning a replacement for a loaded material, see
+/// `MaterialOverrides::rewrite`.
+pub type MaterialRewrite = dyn Fn(&Material) -> Option<Material>;
+
+impl MaterialOverrides {
+    pub fn new() -> MaterialOverrides {
+        MaterialOverrides::default()
+    }
+
+    /// Replaces the material with the given name in MTL files with the
+    /// given material. The MTL definition is not converted at all, so
+    /// missing textures of a placeholder do not matter.
+    pub fn replace<S: Into<String>>(mut self, name: S, material: Material) -> MaterialOverrides {
+        self.replacements.insert(name.into(), Rc::new(material));
+        self
+    }
+
+    /// Calls the given function with each converted material that is not
+    /// replaced by name, replacing it with the returned material, if any.
+    pub fn rewrite<F>(mut self, rewrite: F) -> MaterialOverrides
+    where
+        F: Fn(&Material) -> Option<Material> + 'static,
+    {
+        self.rewrite = Some(Rc::new(rewrite));
+        self
+    }
+
+    /// Gets the replacement of the material with the given name, if any.
+    pub(crate) fn replacement(&self, name: &str) -> Option<Rc<Material>> {
+        self.replacements.get(name).cloned()
+    }
+
+    /// Gets the replacement of the given converted material from the
+    /// rewrite function, if any.
+    pub(crate) fn rewritten(&self, material: &Material) -> Option<Rc<Material>> {
+        self.rewrite
+            .as_ref()
+            .and_then(|rewrite| rewrite(material))
+            .map(Rc::new)
+    }
+}
+
+impl fmt::Debug for MaterialOverrides {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&String> = self.replacements.keys().collect();
+        let rewrite = if self.rewrite.is_some() {
+            "Some(..)"
+        } else {
+            "None"
+        };
+        write!(
+            f,
+            "MaterialOverrides {{ replacements: {:?}, rewrite: {} }}",
+            names, rewrite
+        )
+    }
+}
+
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((&'*', rest)) => (0..=name.len()).any(|skip| glob_matches(rest, &name[skip..])),
+        Some((&'?', rest)) => !name.is_empty() && glob_matches(rest, &name[1..]),
+        Some((&c, rest)) => name.first() == Some(&c) && glob_matches(rest, &name[1..]),
+    }
+}
+
+/// Controls how entities are written by `save_with`, `save_scene_with` and
+/// `save_to_with`.
+///
+/// The default options save files the same way as `save`.
+#[derive(Debug, Clone, Default)]
+pub struct SaveOptions {
+    pub(crate) preserve_quads: bool,
+    pub(crate) mtl_per_entity: bool,
+    pub(crate) sort_by_material: bool,
+    pub(crate) normalize_unicode: bool,
+    pub(crate) material_naming: MaterialNaming,
+    pub(crate) material_equality: MaterialEquality,
+    pub(crate) coordinate_system: CoordinateSystem,
+    pub(crate) source_unit: Unit,
+    pub(crate) target_unit: Unit,
+    pub(crate) lod_naming: bool,
+    pub(crate) collision_export: CollisionExport,
+    pub(crate) flip_v: bool,
+    pub(crate) winding: Winding,
+    pub(crate) origin: Option<[f64; 3]>,
+    pub(crate) frame_header: bool,
+    pub(crate) precision: Option<usize>,
+    pub(crate) line_endings: LineEndings,
+    pub(crate) omit_header_comments: bool,
+    pub(crate) omit_normals: bool,
+    pub(crate) omit_texcoords: bool,
+    pub(crate) required_maps: Vec<String>,
+    pub(crate) missing_maps: MissingMaps,
+    pub(crate) map_fills: BTreeMap<String, [u8; 4]>,
+    pub(crate) constant_map_size: Option<u32>,
+    pub(crate) material_properties: BTreeMap<String, MaterialProperties>,
+    pub(crate) mtl_file_name: Option<String>,
+    pub(crate) map_base: Option<PathBuf>,
+    pub(crate) file_system: Option<Rc<dyn FileSystem>>,
+}
+
+impl SaveOptions {
+    pub fn new() -> SaveOptions {
+        SaveOptions::default()
+    }
+
+    /// If set, pairs of consecutive triangles that share their first vertex
+    /// and an edge and lie in the same plane are written as a single quad.
+    ///
+    /// Entities saved with `save_scene_with` that have face sizes preserved
+    /// from loading are always written with their original polygons.
+    pub fn preserve_quads(mut self, preserve: bool) -> SaveOptions {
+        self.preserve_quads = preserve;
+        self
+    }
+
+    /// If set, a separate MTL file is written for each entity, containing only
+    /// the material of that entity, and each object in the OBJ references its
+    /// own MTL with an `mtllib` statement.
+    ///
+    /// The MTL path passed to the save function then serves as a template, e.g.
+    /// `scene.mtl` results in `scene-Cube.mtl`, `scene-Sphere.mtl` and so on.
+    pub fn mtl_per_entity(mut self, mtl_per_entity: bool) -> SaveOptions {
+        self.mtl_per_entity = mtl_per_entity;
+        self
+    }
+
+    /// If set, entities are reordered so that entities with equal materials
+    /// are written next to each other, and `usemtl` is only written when the
+    /// material changes.
+    ///
+    /// Materials keep the order in which they are first used, as do the
+    /// entities sharing a material.
+    pub fn sort_by_material(mut self, sort: bool) -> SaveOptions {
+        self.sort_by_material = sort;
+        self
+    }
+
+    /// If set, entity names, material names and texture paths are written
+    /// in Unicode NFC.
+    pub fn normalize_unicode(mut self, normalize: bool) -> SaveOptions {
+        self.normalize_unicode = normalize;
+        self
+    }
+
+    /// Sets how materials are renamed when different materials have the same
+    /// name, by default the entity name is appended.
+    pub fn material_naming(mut self, naming: MaterialNaming) -> SaveOptions {
+        self.material_naming = naming;
+        self
+    }
+
+    /// Sets which materials are saved only once, by default materials that
+    /// are equal in name and maps.
+    pub fn material_equality(mut self, equality: MaterialEquality) -> SaveOptions {
+        self.material_equality = equality;
+        self
+    }
+
+    /// Sets the coordinate system to write positions and normals in,
+    /// converting them from the Y-up, right-handed system of entities.
+    pub fn coordinate_system(mut self, system: CoordinateSystem) -> SaveOptions {
+        self.coordinate_system = system;
+        self
+    }
+
+    /// Scales positions from the unit of the entities to the unit to write
+    /// the OBJ in. By default both are meters and nothing is scaled.
+    pub fn units(mut self, source: Unit, target: Unit) -> SaveOptions {
+        self.source_unit = source;
+        self.target_unit = target;
+        self
+    }
+
+    /// If set, entities in a level of detail group are written with names
+    /// like `chair_LOD1`, so that engines detect the groups. The group and
+    /// level are taken from the entity extras, if saving a loaded scene, or
+    /// else from entity names following another convention, e.g. `chair-lod1`.
+    ///
+    /// Saving fails if two entities have the same level in the same group.
+    pub fn lod_naming(mut self, enforce: bool) -> SaveOptions {
+        self.lod_naming = enforce;
+        self
+    }
+
+    /// Sets how entities that are collision geometry for other entities are
+    /// written, see `EntityExtras::collision_for`. When saving entities
+    /// without extras, they are recognized by name.
+    pub fn collision_export(mut self, export: CollisionExport) -> SaveOptions {
+        self.collision_export = export;
+        self
+    }
+
+    /// If set, one minus the V texture coordinate is written instead of V,
+    /// for tools that put the texture origin in the top left corner.
+    pub fn flip_v(mut self, flip: bool) -> SaveOptions {
+        self.flip_v = flip;
+        self
+    }
+
+    /// Sets how the winding order of faces is checked or changed before
+    /// writing them, by default it is written as it is.
+    pub fn winding(mut self, winding: Winding) -> SaveOptions {
+        self.winding = winding;
+        self
+    }
+
+    /// Sets an offset that is added to all positions in double precision
+    /// when writing them, before converting coordinate systems and units.
+    ///
+    /// Scenes saved with `save_scene_with` default to their own
+    /// `LoadedScene::origin`.
+    pub fn origin(mut self, origin: [f64; 3]) -> SaveOptions {
+        self.origin = Some(origin);
+        self
+    }
+
+    /// If set, positions are written relative to the origin, which is
+    /// recorded in a header comment along with the unit of the positions,
+    /// e.g. `# aitios frame origin 512346 5234568 0 meters_per_unit 1`.
+    /// Other tools can read the frame from the header, and so can `load_with`
+    /// with `LoadOptions::frame_header`.
+    pub fn frame_header(mut self, write: bool) -> SaveOptions {
+        self.frame_header = write;
+        self
+    }
+
+    /// Rounds written numbers to at most the given number of decimal places,
+    /// e.g. `0.333` instead of `0.33333334` with three places. By default,
+    /// numbers are written with as many digits as needed to read back the
+    /// same value.
+    pub fn precision(mut self, decimal_places: usize) -> SaveOptions {
+        self.precision = Some(decimal_places);
+        self
+    }
+
+    /// Sets the line endings of written OBJ and MTL files, by default `\n`.
+    pub fn line_endings(mut self, line_endings: LineEndings) -> SaveOptions {
+        self.line_endings = line_endings;
+        self
+    }
+
+    /// If unset, OBJ and MTL files start without the comment naming the
+    /// exporter. The frame header is written regardless.
+    pub fn header_comments(mut self, write: bool) -> SaveOptions {
+        self.omit_header_comments = !write;
+        self
+    }
+
+    /// If unset, normals are left out and faces only reference positions
+    /// and texture coordinates.
+    pub fn normals(mut self, write: bool) -> SaveOptions {
+        self.omit_normals = !write;
+        self
+    }
+
+    /// If unset, texture coordinates are left out and faces only reference
+    /// positions and normals.
+    pub fn texcoords(mut self, write: bool) -> SaveOptions {
+        self.omit_texcoords = !write;
+        self
+    }
+
+    /// Requires every saved material to have a map with the given MTL
+    /// keyword, e.g. `norm` or `map_Pr`, or `map_d` for a map of the
+    /// `MaterialProperties`. Materials without it are handled according to
+    /// `missing_maps`, and saving fails for keywords of other maps.
+    pub fn require_map<S: Into<String>>(mut self, key: S) -> SaveOptions {
+        self.required_maps.push(key.into());
+        self
+    }
+
+    /// Sets what happens to materials that lack a required map, by default
+    /// saving fails.
+    pub fn missing_maps(mut self, policy: MissingMaps) -> SaveOptions {
+        self.missing_maps = policy;
+        self
+    }
+
+    /// Sets the RGBA color of the texture generated for a missing map with
+    /// the given MTL keyword, see `MissingMaps::Fill`.
+    pub fn map_fill<S: Into<String>>(mut self, key: S, rgba: [u8; 4]) -> SaveOptions {
+        self.map_fills.insert(key.into(), rgba);
+        self
+    }
+
+    /// If set, saved materials with a scalar color or factor in their
+    /// properties, e.g. `Kd` or `Pr`, but no map for it get a map with a
+    /// texture of that constant color, with the given width and height in
+    /// texels, for renderers that only support textured materials. The
+    /// textures are written next to the OBJ, or to the map base when saving
+    /// with `save_to_with`.
+    pub fn constant_maps(mut self, size: u32) -> SaveOptions {
+        self.constant_map_size = Some(size.max(1));
+        self
+    }
+
+    /// Sets the properties of materials by name, of which the options of
+    /// texture maps are written along with the maps, e.g. `-bm 0.3` in
+    /// `bump -bm 0.3 bump.png`.
+    ///
+    /// Scenes saved with `save_scene_with` default to their own
+    /// `LoadedScene::material_properties`.
+    pub fn material_properties(
+        mut self,
+        properties: BTreeMap<String, MaterialProperties>,
+    ) -> SaveOptions {
+        self.material_properties = properties;
+        self
+    }
+
+    /// Sets the file name of the MTL that OBJs saved with `save_to_with`
+    /// reference in their `mtllib` statement. Without it, there is no
+    /// `mtllib` statement, but materials are still used by name, e.g. for
+    /// loading with `LoadOptions::material_library`.
+    pub fn mtl_file_name<S: Into<String>>(mut self, name: S) -> SaveOptions {
+        self.mtl_file_name = Some(name.into());
+        self
+    }
+
+    /// Sets the existing directory that map paths in MTLs saved with
+    /// `save_to_with` are written relative to, and that textures generated
+    /// for missing maps are written to. Without it, map paths are written as
+    /// they are and missing maps cannot be filled.
+    pub fn map_base<P: Into<PathBuf>>(mut self, dir: P) -> SaveOptions {
+        self.map_base = Some(dir.into());
+        self
+    }
+
+    /// Sets the file system that OBJ and MTL files and generated textures
+    /// are written to and that texture maps are looked up in, by default the
+    /// disk. Checks for free space and permissions only apply to the disk.
+    pub fn file_system<F: FileSystem + 'static>(mut self, file_system: F) -> SaveOptions {
+        self.file_system = Some(Rc::new(file_system));
+        self
+    }
+
+    /// The file system set with `file_system`, or the disk.
+    pub(crate) fn vfs(&self) -> &dyn FileSystem {
+        match self.file_system {
+            Some(ref file_system) => &**file_system,
+            None => &DiskFileSystem,
+        }
+    }
+
+    /// Color of the texture generated for a missing map with the given key,
+    /// a neutral value for the slot unless set with `map_fill`.
+    pub(crate) fn map_fill_color(&self, key: &str) -> [u8; 4] {
+        if let Some(&rgba) = self.map_fills.get(key) {
+            return rgba;
+        }
+        match key {
+            "map_Kd" => [204, 204, 204, 255],
+            // Opaque and unoccluded
+            "map_d" | "map_ao" => [255, 255, 255, 255],
+            // Flat surface in tangent space
+            "norm" => [128, 128, 255, 255],
+            // Fully rough
+            "map_Pr" => [255, 255, 255, 255],
+            _ => [0, 0, 0, 255],
+        }
+    }
+}
+
+/// Determines what happens to saved materials without a map required with
+/// `SaveOptions::require_map`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingMaps {
+    /// Fails saving with an error before anything is written.
+    Error,
+    /// Logs a warning and saves the material without the map.
+    Warn,
+    /// Writes a texture with a single texel next to the OBJ and uses it for
+    /// the map, see `SaveOptions::map_fill`.
+    Fill,
+}
+
+impl Default for MissingMaps {
+    fn default() -> MissingMaps {
+        MissingMaps::Error
+    }
+}
+
+/// Line endings of saved files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEndings {
+    /// Ends lines with `\n`, as usual on Unix.
+    Lf,
+    /// Ends lines with `\r\n`, as usual on Windows.
+    CrLf,
+}
+
+impl Default for LineEndings {
+    fn default() -> LineEndings {
+        LineEndings::Lf
+    }
+}
+
+/// Determines where collision geometry is saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionExport {
+    /// Saves collision geometry like all other entities.
+    Inline,
+    /// Saves collision geometry into a separate OBJ next to the OBJ, with
+    /// `_collision` appended to the file stem, and the materials of the
+    /// collision geometry into a separate MTL in the same way.
+    Separate,
+    /// Saves collision geometry along with the other entities, but named
+    /// like `UCX_chair_01` after the entity it belongs to, as expected by
+    /// Unreal.
+    Unreal,
+}
+
+impl Default for CollisionExport {
+    fn default() -> CollisionExport {
+        CollisionExport::Inline
+    }
+}
+
+/// Determines the new names of materials that have the same name as another
+/// material that was saved before, but different properties.
+///
+/// Each strategy proposes a name for the first attempt, then another one for
+/// each following attempt, until the name is not used by any other material.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaterialNaming {
+    /// Appends the entity name, then also a numeric suffix starting at two,
+    /// e.g. `iron-bunny`, `iron-bunny-2`, `iron-bunny-3`.
+    EntitySuffix,
+    /// Appends a numeric suffix starting at two, e.g. `iron-2`, `iron-3`.
+    NumericSuffix,
+    /// Calls the given function with the material, the entity using it and
+    /// the attempt, starting at one. The function must eventually return a
+    /// name that is not used yet, or saving never finishes.
+    #[serde(skip)]
+    Callback(Rc<NamingCallback>),
+}
+
+/// Function proposing a material name, see `MaterialNaming::Callback`.
+pub type NamingCallback = dyn Fn(&Material, &Entity, u32) -> String;
+
+impl MaterialNaming {
+    /// Creates a strategy that names materials with the given function.
+    pub fn callback<F>(callback: F) -> MaterialNaming
+    where
+        F: Fn(&Material, &Entity, u32) -> String + 'static,
+    {
+        MaterialNaming::Callback(Rc::new(callback))
+    }
+
+    /// Proposes a name for the material of the given entity in the given
+    /// attempt, starting at one.
+    pub fn candidate(&self, material: &Material, entity: &Entity, attempt: u32) -> String {
+        match *self {
+            MaterialNaming::EntitySuffix if attempt <= 1 => {
+                format!("{}-{}", material.name(), entity.name)
+            }
+            MaterialNaming::EntitySuffix => {
+                format!("{}-{}-{}", material.name(), entity.name, attempt)
+            }
+            MaterialNaming::NumericSuffix => format!("{}-{}", material.name(), attempt + 1),
+            MaterialNaming::Callback(ref callback) => callback(material, entity, attempt),
+        }
+    }
+}
+
+impl Default for MaterialNaming {
+    fn default() -> MaterialNaming {
+        MaterialNaming::EntitySuffix
+    }
+}
+
+impl fmt::Debug for MaterialNaming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MaterialNaming::EntitySuffix => write!(f, "EntitySuffix"),
+            MaterialNaming::NumericSuffix => write!(f, "NumericSuffix"),
+            MaterialNaming::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+/// Determines which materials of saved entities are the same material, which
+/// is then saved only once under the name it was first saved with.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaterialEquality {
+    /// Materials with equal names and maps are the same.
+    Exact,
+    /// Materials with equal names and maps referring to the same files are
+    /// the same, even if the paths differ, e.g. `a/../tex.png` and `tex.png`.
+    CanonicalMaps,
+    /// Materials with maps referring to the same files are the same,
+    /// regardless of their names.
+    SameMaps,
+    /// Materials with maps referring to the same files and properties with
+    /// colors and factors that differ by at most the given tolerance are the
+    /// same, regardless of their names, see `MaterialProperties::similar`.
+    /// Materials without properties are only the same as each other.
+    SimilarProperties(f32),
+    /// Calls the given function with a material that is already saved and
+    /// the material of an entity, which is the same if it returns true.
+    #[serde(skip)]
+    Callback(Rc<EqualityCallback>),
+}
+
+/// Function comparing materials, see `MaterialEquality::Callback`.
+pub type EqualityCallback = dyn Fn(&Material, &Material) -> bool;
+
+impl MaterialEquality {
+    /// Creates a comparison that compares materials with the given function.
+    pub fn callback<F>(callback: F) -> MaterialEquality
+    where
+        F: Fn(&Material, &Material) -> bool + 'static,
+    {
+        MaterialEquality::Callback(Rc::new(callback))
+    }
+
+    /// Gathers what is compared of the given material and its properties,
+    /// resolving the paths of its maps in the given file system if needed.
+    pub(crate) fn compared<'a>(
+        &self,
+        material: Material,
+        properties: Option<&'a MaterialProperties>,
+        file_system: &dyn FileSystem,
+    ) -> ComparedMaterial<'a> {
+        let canonical_maps = match *self {
+            MaterialEquality::CanonicalMaps
+            | MaterialEquality::SameMaps
+            | MaterialEquality::SimilarProperties(_) => canonical_maps(&material, file_system),
+            MaterialEquality::Exact | MaterialEquality::Callback(_) => Vec::new(),
+        };
+        ComparedMaterial {
+            material,
+            properties,
+            canonical_maps,
+        }
+    }
+
+    /// Checks if the material of an entity is the same as a saved material.
+    pub(crate) fn same(&self, saved: &ComparedMaterial, material: &ComparedMaterial) -> bool {
+        let same_maps = || saved.canonical_maps == material.canonical_maps;
+        match *self {
+            MaterialEquality::Exact => saved.material == material.material,
+            MaterialEquality::CanonicalMaps => {
+                saved.material.name() == material.material.name() && same_maps()
+            }
+            MaterialEquality::SameMaps => same_maps(),
+            MaterialEquality::SimilarProperties(tolerance) => {
+                let similar = match (saved.properties, material.properties) {
+                    (Some(saved), Some(properties)) => saved.similar(properties, tolerance),
+                    (saved, properties) => saved.is_none() && properties.is_none(),
+                };
+                similar && same_maps()
+            }
+            MaterialEquality::Callback(ref callback) => {
+                callback(&saved.material, &material.material)
+            }
+        }
+    }
+}
+
+/// A material along with what `MaterialEquality` compares of it, gathered
+/// once for each material with `MaterialEquality::compared`.
+pub(crate) struct ComparedMaterial<'a> {
+    pub(crate) material: Material,
+    properties: Option<&'a MaterialProperties>,
+    /// Keys and canonical paths of the maps sorted by key, if compared.
+    canonical_maps: Vec<(String, PathBuf)>,
+}
+
+impl<'a> ComparedMaterial<'a> {
+    /// Replaces the material, e.g. with a renamed copy, keeping the gathered
+    /// maps and properties.
+    pub(crate) fn with_material(self, material: Material) -> ComparedMaterial<'a> {
+        ComparedMaterial { material, ..self }
+    }
+}
+
+/// Keys and canonical paths of the maps of the material, sorted by key.
+/// Paths of files that do not exist are kept as they are.
+fn canonical_maps(material: &Material, file_system: &dyn FileSystem) -> Vec<(String, PathBuf)> {
+    let mut maps: Vec<_> = material
+        .maps()
+        .iter()
+        .map(|&(key, ref path)| {
+            let path = PathBuf::from(path);
+            let path = file_system.canonicalize(&path).unwrap_or(path);
+            (key.to_string(), path)
+        })
+        .collect();
+    maps.sort();
+    maps
+}
+
+impl Default for MaterialEquality {
+    fn default() -> MaterialEquality {
+        MaterialEquality::Exact
+    }
+}
+
+impl fmt::Debug for MaterialEquality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MaterialEquality::Exact => write!(f, "Exact"),
+            MaterialEquality::CanonicalMaps => write!(f, "CanonicalMaps"),
+            MaterialEquality::SameMaps => write!(f, "SameMaps"),
+            MaterialEquality::SimilarProperties(tolerance) => {
+                write!(f, "SimilarProperties({:?})", tolerance)
+            }
+            MaterialEquality::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
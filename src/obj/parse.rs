@@ -0,0 +1,1792 @@
+//! In-crate parser for the geometry part of OBJ files.
+//!
+//! Faces are triangulated as fans, but the number of vertices of each
+//! face is kept so polygons can be reconstructed when saving.
+
+use err::{AssetError, AssetError::*, Result};
+use flate2::bufread::MultiGzDecoder;
+use float::{parse_f32, parse_f64, Decimal};
+use loaded::{LoadStats, Warning};
+use obj::options::{Encoding, LoadOptions, ParseMode, SplitPolicy, Unit};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read};
+use std::mem;
+use std::ops::Deref;
+use std::path::Path;
+use std::result;
+use vector::face_normal;
+use vfs::FileSystem;
+
+/// Geometry and material references read from an OBJ file, not yet converted
+/// into aitios entities.
+#[derive(Debug, Clone, Default)]
+pub struct ObjData {
+    /// Rest of each `mtllib` statement in the order of appearance, each of
+    /// which may list multiple files separated by spaces.
+    pub material_libs: Vec<String>,
+    pub models: Vec<Model>,
+    /// Problems found while parsing, including skipped malformed lines.
+    pub warnings: Vec<Warning>,
+    /// Statement counts and fix-ups, the element counts are left at zero.
+    pub stats: LoadStats,
+    /// Offset of the positions from the origin, read from the frame header or
+    /// subtracted from all positions if requested in the load options and
+    /// the positions are far from the origin.
+    pub origin: Option<[f64; 3]>,
+    /// Unit of the positions, if read from the frame header.
+    pub unit: Option<Unit>,
+}
+
+/// An object or group in an OBJ file, or the part of it that uses one material.
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    pub name: String,
+    /// Name from the last `usemtl` statement that applied to this model.
+    /// Unless models are merged, this is the only material of the model.
+    pub material: Option<String>,
+    pub mesh: Mesh,
+}
+
+/// Vertex data of a model, with one vertex for each unique combination of
+/// position, texture coordinate and normal indices used in the faces.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<f32>,
+    /// Empty if no face in the model referenced a normal.
+    pub normals: Vec<f32>,
+    /// Empty if no face in the model referenced a texture coordinate.
+    pub texcoords: Vec<f32>,
+    /// Third texture coordinate component of each vertex, only filled if
+    /// requested in the load options, some `vt` statement in the file had
+    /// three components and some face in the model referenced a texture
+    /// coordinate.
+    pub texcoord_ws: Vec<f32>,
+    /// RGB vertex colors, specified after the position in `v` statements.
+    /// Empty if no position used in the model had a color.
+    pub colors: Vec<f32>,
+    /// Triangle indices, faces with more than three vertices are split into fans.
+    pub indices: Vec<u32>,
+    /// Number of vertices of each face, in the order the faces appear in `indices`.
+    pub face_sizes: Vec<u32>,
+    /// Vertex indices of each polyline from `l` statements.
+    pub lines: Vec<Vec<u32>>,
+    /// Vertex indices of points from `p` statements.
+    pub points: Vec<u32>,
+    /// Positions in double precision, only filled if requested in the load
+    /// options.
+    pub precise_positions: Vec<f64>,
+}
+
+impl Mesh {
+    /// Estimates the number of bytes used by the mesh.
+    fn memory(&self) -> usize {
+        let floats = self.positions.len()
+            + self.normals.len()
+            + self.texcoords.len()
+            + self.texcoord_ws.len()
+            + self.colors.len();
+        let indices = self.indices.len()
+            + self.face_sizes.len()
+            + self.points.len()
+            + self.lines.iter().map(|line| line.len()).sum::<usize>();
+        floats * mem::size_of::<f32>()
+            + indices * mem::size_of::<u32>()
+            + self.precise_positions.len() * mem::size_of::<f64>()
+    }
+}
+
+/// Parses the OBJ file at the given path.
+pub fn parse_file(path: &Path, options: &LoadOptions) -> Result<ObjData> {
+    parse(open_file(path, options.vfs())?, path, options)
+}
+
+/// First bytes of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Opens an OBJ or MTL file in the given file system for reading,
+/// decompressing it on the fly if it is gzip-compressed.
+pub fn open_file(path: &Path, file_system: &dyn FileSystem) -> Result<Box<dyn BufRead>> {
+    let file = file_system.open(path).map_err(AssetError::Read)?;
+    let mut reader = BufReader::new(file);
+
+    let is_gzip = reader
+        .fill_buf()
+        .map_err(AssetError::Read)?
+        .starts_with(&GZIP_MAGIC);
+    if is_gzip {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Parses OBJ statements from the given reader. The path is only used to
+/// report the location of errors.
+///
+/// Fails if the parsed data exceeds the memory budget of the options.
+pub fn parse<R: BufRead>(reader: R, path: &Path, options: &LoadOptions) -> Result<ObjData> {
+    parse_chunks(reader, path, options, |_| {
+        Err(over_budget(path, options.memory_budget.unwrap_or(0)))
+    })
+}
+
+/// Parses OBJ statements like `parse`, but hands the models parsed so far to
+/// the given function whenever the parsed data exceeds the memory budget of
+/// the options, and returns the rest.
+pub fn parse_chunks<R, F>(
+    mut reader: R,
+    path: &Path,
+    options: &LoadOptions,
+    mut chunk: F,
+) -> Result<ObjData>
+where
+    R: BufRead,
+    F: FnMut(ObjData) -> Result<()>,
+{
+    let mut parser = Parser {
+        attributes: Attributes::default(),
+        data: ObjData::default(),
+        model: ModelBuilder::new("unnamed".to_string(), None),
+        // Smoothing is off unless specified otherwise
+        smoothing_group: 0,
+        options,
+        path,
+        line_number: 0,
+        unknown_statements: HashSet::new(),
+        skipping: !options.object_filter.matches("unnamed"),
+        name: "unnamed".to_string(),
+        origin: None,
+        frame_origin: None,
+        pending_memory: 0,
+        counted_models: 0,
+    };
+
+    let mut skip_budget = options.skip_budget();
+
+    let mut line_number = 0;
+    loop {
+        let mut bytes = Vec::new();
+        match options.memory_budget {
+            // Read no more of a line than fits into the budget, so that a
+            // huge line fails before it is held in memory as a whole
+            Some(budget) => {
+                while !read_line_within(
+                    &mut reader,
+                    &mut bytes,
+                    budget.saturating_sub(parser.memory()),
+                )? {
+                    match parser.take_chunk(budget) {
+                        Some(data) => chunk(data)?,
+                        None => return Err(over_budget(path, budget)),
+                    }
+                }
+            }
+            None => {
+                read_line_within(&mut reader, &mut bytes, usize::MAX)?;
+            }
+        }
+        if bytes.is_empty() {
+            break;
+        }
+        trim_line_ending(&mut bytes);
+
+        line_number += 1;
+        parser.line_number = line_number;
+        parser.data.stats.bytes_parsed += bytes.len() as u64;
+
+        let (line, result) = match decode_line(&bytes, options.encoding) {
+            Ok(line) => {
+                let result = match line.trim_start().strip_prefix(FRAME_HEADER) {
+                    Some(frame) if options.frame_header => {
+                        parser.frame(frame, line.len() - frame.len())
+                    }
+                    _ => parser.statement(strip_comment(&line)),
+                };
+                (line, result)
+            }
+            Err(err) => (String::from_utf8_lossy(&bytes).into_owned(), Err(err)),
+        };
+        let line = strip_comment(&line);
+
+        match result {
+            Ok(()) => (),
+            Err(err) if skip_budget > 0 => {
+                skip_budget -= 1;
+                let warning = err.skip(path, line_number, line);
+                parser.data.warnings.push(warning);
+            }
+            Err(err) => return Err(err.locate(path, line_number, line)),
+        }
+
+        if let Some(budget) = options.memory_budget {
+            if parser.memory() > budget {
+                match parser.take_chunk(budget) {
+                    Some(data) => chunk(data)?,
+                    None => return Err(over_budget(path, budget)),
+                }
+            }
+        }
+    }
+
+    Ok(parser.finish())
+}
+
+fn over_budget(path: &Path, budget: usize) -> AssetError {
+    InvalidData(format!(
+        "{:?} needs more than the memory budget of {} bytes",
+        path, budget
+    ))
+}
+
+/// Start of the comment that records the origin and unit of the positions
+/// in the file, see `frame_header`.
+pub const FRAME_HEADER: &str = "# aitios frame";
+
+/// Formats the comment recording that the positions in the file are relative
+/// to the given origin and measured in the given unit.
+pub fn frame_header(origin: [f64; 3], unit: Unit) -> String {
+    format!(
+        "{} origin {} {} {} meters_per_unit {}\n",
+        FRAME_HEADER,
+        Decimal(origin[0]),
+        Decimal(origin[1]),
+        Decimal(origin[2]),
+        Decimal(unit.precise_meters())
+    )
+}
+
+/// Statements that are interpreted by the parser.
+const SUPPORTED_STATEMENTS: &[&str] = &[
+    "v", "vt", "vn", "f", "l", "p", "s", "o", "g", "usemtl", "mtllib",
+];
+
+/// Statements of the OBJ specification that are accepted, but ignored, even
+/// in strict mode.
+pub const IGNORED_STATEMENTS: &[&str] = &[
+    "vp",
+    "cstype",
+    "deg",
+    "bmat",
+    "step",
+    "curv",
+    "curv2",
+    "surf",
+    "parm",
+    "trim",
+    "hole",
+    "scrv",
+    "sp",
+    "end",
+    "con",
+    "mg",
+    "bevel",
+    "c_interp",
+    "d_interp",
+    "lod",
+    "usemap",
+    "maplib",
+    "shadow_obj",
+    "trace_obj",
+    "ctech",
+    "stech",
+    "call",
+    "csh",
+];
+
+/// Reads the lines of an OBJ or MTL file without decoding them, with line
+/// endings removed.
+pub fn read_lines<R: BufRead>(reader: R) -> impl Iterator<Item = Result<Vec<u8>>> {
+    reader.split(b'\n').map(|line| {
+        let mut line = line.map_err(AssetError::Read)?;
+        trim_line_ending(&mut line);
+        Ok(line)
+    })
+}
+
+/// Appends the rest of the current line to `line`, including the line
+/// ending, until `line` holds `limit` bytes. Returns whether the line ended
+/// within the limit. Nothing is appended at the end of the file.
+fn read_line_within<R: BufRead>(reader: &mut R, line: &mut Vec<u8>, limit: usize) -> Result<bool> {
+    let limit = limit.saturating_sub(line.len());
+    let read = reader
+        .by_ref()
+        .take(limit as u64)
+        .read_until(b'\n', line)
+        .map_err(AssetError::Read)?;
+    Ok(read < limit || line.last() == Some(&b'\n'))
+}
+
+fn trim_line_ending(line: &mut Vec<u8>) {
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+}
+
+/// Decodes a line read with `read_lines` into text.
+pub fn decode_line(bytes: &[u8], encoding: Encoding) -> ParseResult<String> {
+    match (String::from_utf8(bytes.to_vec()), encoding) {
+        (Ok(line), _) => Ok(line),
+        // Point at the first replacement character in the lossy line
+        (Err(err), Encoding::Utf8) => Err(SyntaxError::new(
+            "\u{fffd}",
+            err.utf8_error().valid_up_to(),
+            "Invalid UTF-8, set an encoding in the load options to load files in other encodings",
+        )),
+        (Err(_), Encoding::Lossy) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+        (Err(_), Encoding::Latin1Fallback) => Ok(bytes.iter().map(|&b| char::from(b)).collect()),
+    }
+}
+
+/// Removes everything after a hash, which starts a comment.
+pub fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(comment_start) => &line[..comment_start],
+        None => line,
+    }
+}
+
+/// An error in a single statement of an OBJ or MTL file, without the
+/// information where in the file the statement is.
+#[derive(Debug)]
+pub struct SyntaxError {
+    /// Offending part of the statement, empty if something is missing at
+    /// the end of the statement.
+    token: String,
+    /// Byte offset of the token in the line, `None` if something is missing.
+    offset: Option<usize>,
+    message: String,
+}
+
+impl SyntaxError {
+    /// Creates an error pointing at the given token, which starts at the
+    /// given byte offset in the line.
+    pub fn new<S: Into<String>>(token: &str, offset: usize, message: S) -> SyntaxError {
+        SyntaxError {
+            token: token.to_string(),
+            offset: Some(offset),
+            message: message.into(),
+        }
+    }
+
+    /// Creates an error for something that is missing at the end of the
+    /// statement.
+    pub fn missing<S: Into<String>>(message: S) -> SyntaxError {
+        SyntaxError {
+            token: String::new(),
+            offset: None,
+            message: message.into(),
+        }
+    }
+
+    /// Turns this into a warning that the given line of the given file was
+    /// skipped.
+    pub fn skip(self, path: &Path, line_number: usize, line: &str) -> Warning {
+        warn!(
+            "Skipping malformed line {} in {:?}: {}",
+            line_number, path, self.message
+        );
+        Warning::SkippedLine {
+            path: path.to_path_buf(),
+            line: line_number,
+            content: line.trim().to_string(),
+            reason: self.message,
+        }
+    }
+
+    /// Turns this into an `AssetError` pointing at the offending token in
+    /// the given line of the given file.
+    pub fn locate(self, path: &Path, line_number: usize, line: &str) -> AssetError {
+        let byte_offset = match self.offset {
+            Some(offset) if line.is_char_boundary(offset) => offset,
+            Some(_) => 0,
+            None => line.trim_end().len(),
+        };
+
+        Parse {
+            path: path.to_path_buf(),
+            line: line_number,
+            column: line[..byte_offset].chars().count() + 1,
+            token: self.token,
+            message: self.message,
+        }
+    }
+}
+
+pub type ParseResult<T> = result::Result<T, SyntaxError>;
+
+/// A word of a statement along with its byte offset in the line, so that
+/// errors can point at it, e.g. at the second `5` in `f 15 5`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Word<'a> {
+    pub text: &'a str,
+    pub offset: usize,
+}
+
+impl<'a> Word<'a> {
+    /// Creates an error pointing at this word.
+    pub fn error<S: Into<String>>(self, message: S) -> SyntaxError {
+        SyntaxError::new(self.text, self.offset, message)
+    }
+
+    /// Gets the part of this word in the given byte range of its text, e.g.
+    /// an index of a corner.
+    pub fn slice(self, start: usize, end: usize) -> Word<'a> {
+        Word {
+            text: &self.text[start..end],
+            offset: self.offset + start,
+        }
+    }
+}
+
+impl<'a> Deref for Word<'a> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.text
+    }
+}
+
+/// Splits text at whitespace like `str::split_whitespace`, but yields each
+/// word along with its offset in the line.
+#[derive(Debug, Clone)]
+pub struct Words<'a> {
+    text: &'a str,
+    /// Byte offset of `text` in the line.
+    offset: usize,
+}
+
+impl<'a> Words<'a> {
+    /// Splits a whole line.
+    pub fn new(line: &'a str) -> Words<'a> {
+        Words::at(line, 0)
+    }
+
+    /// Splits text that starts at the given byte offset in the line.
+    pub fn at(text: &'a str, offset: usize) -> Words<'a> {
+        Words { text, offset }
+    }
+}
+
+impl<'a> Iterator for Words<'a> {
+    type Item = Word<'a>;
+
+    fn next(&mut self) -> Option<Word<'a>> {
+        let rest = self.text.trim_start();
+        self.offset += self.text.len() - rest.len();
+        if rest.is_empty() {
+            self.text = rest;
+            return None;
+        }
+
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = Word {
+            text: &rest[..len],
+            offset: self.offset,
+        };
+        self.text = &rest[len..];
+        self.offset += len;
+        Some(word)
+    }
+}
+
+/// State of the parser between statements.
+struct Parser<'a> {
+    attributes: Attributes,
+    data: ObjData,
+    model: ModelBuilder,
+    smoothing_group: u32,
+    options: &'a LoadOptions,
+    path: &'a Path,
+    /// Number of the line being parsed, starting at one.
+    line_number: usize,
+    /// Unknown statements that have already been warned about.
+    unknown_statements: HashSet<String>,
+    /// Set while in an object that does not match the object filter.
+    skipping: bool,
+    /// Name of the last object or group that names entities with the split policy.
+    name: String,
+    /// Offset subtracted from positions, chosen at the first position if
+    /// shifting the origin.
+    origin: Option<[f64; 3]>,
+    /// Origin read from the frame header.
+    frame_origin: Option<[f64; 3]>,
+    /// Estimated bytes used by the first `counted_models` finished models.
+    pending_memory: usize,
+    counted_models: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn statement(&mut self, line: &str) -> ParseResult<()> {
+        let options = self.options;
+        let strict = options.parse_mode == ParseMode::Strict;
+
+        let mut words = Words::new(line);
+        let first = words.next();
+        let keyword = first.map(|word| word.text);
+
+        if let Some(keyword) = keyword {
+            let stats = &mut self.data.stats;
+            if SUPPORTED_STATEMENTS.contains(&keyword) || IGNORED_STATEMENTS.contains(&keyword) {
+                *stats.directives.entry(keyword.to_string()).or_insert(0) += 1;
+            } else {
+                stats.unknown_directives += 1;
+            }
+        }
+
+        // Elements of objects that are filtered out are not even parsed
+        if self.skipping {
+            match keyword {
+                Some("f") | Some("l") | Some("p") => return Ok(()),
+                _ => (),
+            }
+        }
+
+        let corners = match keyword {
+            Some("f") | Some("l") | Some("p") => self.parse_corners(words.clone())?,
+            _ => Vec::new(),
+        };
+
+        // Indices of vertices must fit into u32, so large objects are continued
+        // in another entity before any corner of an element could exceed it
+        let max_vertices = options.max_entity_vertices.unwrap_or(u32::MAX) as usize;
+        if !corners.is_empty()
+            && self.model.has_elements()
+            && self.model.vertex_count() + corners.len() > max_vertices
+        {
+            self.split_model();
+        }
+
+        let attributes = &mut self.attributes;
+        let model = &mut self.model;
+
+        match keyword {
+            Some("v") => {
+                let position = if options.origin_threshold.is_some() || options.keeps_precise() {
+                    let mut precise = parse_precise_position(&mut words)?;
+                    if let Some(threshold) = options.origin_threshold {
+                        let origin = self.origin.get_or_insert_with(|| {
+                            let mut origin = [0.0; 3];
+                            for (origin, &coordinate) in origin.iter_mut().zip(precise.iter()) {
+                                if coordinate.abs() >= threshold {
+                                    *origin = coordinate.round();
+                                }
+                            }
+                            origin
+                        });
+                        for (coordinate, origin) in precise.iter_mut().zip(origin.iter()) {
+                            *coordinate -= origin;
+                        }
+                    }
+                    if options.keeps_precise() {
+                        attributes.precise_positions.push(precise);
+                    }
+                    [precise[0] as f32, precise[1] as f32, precise[2] as f32]
+                } else {
+                    parse_floats(&mut words)?
+                };
+
+                let color = parse_vertex_color(words, strict)?;
+                attributes.positions.push(position);
+                attributes.colors.push(color);
+            }
+            Some("vt") => {
+                attributes.has_texcoord_w |= words.clone().nth(2).is_some();
+                let texcoord = parse_texcoord(&mut words)?;
+                if strict {
+                    expect_end(&mut words)?;
+                }
+                attributes.texcoords.push(texcoord);
+            }
+            Some("vn") => {
+                let normal = parse_floats(&mut words)?;
+                if strict {
+                    expect_end(&mut words)?;
+                }
+                attributes.normals.push(normal);
+            }
+            Some("f") => {
+                if corners.len() >= 3 && is_degenerate(&corners, attributes) {
+                    self.data.warnings.push(Warning::DegenerateFace {
+                        path: self.path.to_path_buf(),
+                        line: self.line_number,
+                    });
+                }
+                let corner_count = corners.len();
+                let vertices_before = model.vertex_count();
+                model.add_face(
+                    corners,
+                    self.smoothing_group,
+                    attributes,
+                    options.regenerate_normals,
+                )?;
+
+                let stats = &mut self.data.stats;
+                stats.welded_vertices += corner_count - (model.vertex_count() - vertices_before);
+                if corner_count > 3 {
+                    stats.triangulated_faces += 1;
+                }
+            }
+            Some("l") => model.add_line(corners, attributes)?,
+            Some("p") => model.add_points(corners, attributes),
+            Some("s") => self.smoothing_group = parse_smoothing_group(&mut words)?,
+            Some("o") | Some("g") => {
+                let name = rest_of_line(words);
+                if strict && keyword == Some("o") && name.is_empty() {
+                    return Err(SyntaxError::missing("Expected object name"));
+                }
+
+                let name = if name.is_empty() {
+                    "unnamed".to_string()
+                } else {
+                    name
+                };
+                self.skipping = !options.object_filter.matches(&name);
+
+                let is_object = keyword == Some("o");
+                let (names, splits) = match options.split_policy {
+                    SplitPolicy::ObjectsAndGroups => (true, true),
+                    SplitPolicy::Objects => (is_object, is_object),
+                    SplitPolicy::Groups => (!is_object, !is_object),
+                    SplitPolicy::MaterialRuns | SplitPolicy::Merged => (true, false),
+                };
+
+                if !names {
+                    return Ok(());
+                }
+                self.name = name.clone();
+
+                if splits && model.has_elements() {
+                    let material = model.material().cloned();
+                    let finished = mem::replace(model, ModelBuilder::new(name, material));
+                    let keep_texcoord_w = options.texcoord_w && attributes.has_texcoord_w;
+                    self.data.models.push(finished.finish(keep_texcoord_w));
+                } else if !model.has_elements() {
+                    model.rename(name);
+                }
+            }
+            Some("usemtl") => {
+                let material = rest_of_line(words);
+                if strict && material.is_empty() {
+                    return Err(SyntaxError::missing("Expected material name"));
+                }
+                let switches = model.material() != Some(&material);
+
+                if options.split_policy != SplitPolicy::Merged && switches && model.has_elements() {
+                    let next = ModelBuilder::new(self.name.clone(), Some(material));
+                    let finished = mem::replace(model, next);
+                    let keep_texcoord_w = options.texcoord_w && attributes.has_texcoord_w;
+                    self.data.models.push(finished.finish(keep_texcoord_w));
+                } else {
+                    model.use_material(material);
+                }
+            }
+            Some("mtllib") => self
+                .data
+                .material_libs
+                .push(raw_rest_of_line(line, "mtllib")),
+            Some(keyword) if !IGNORED_STATEMENTS.contains(&keyword) => {
+                if let (true, Some(first)) = (strict, first) {
+                    return Err(first.error("Unknown OBJ statement"));
+                }
+
+                // Only warn once for each kind of statement
+                if self.unknown_statements.insert(keyword.to_string()) {
+                    self.data.warnings.push(Warning::UnknownStatement {
+                        path: self.path.to_path_buf(),
+                        line: self.line_number,
+                        statement: keyword.to_string(),
+                    });
+                }
+            }
+            // Ignore empty lines and statements that are not supported
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Parses the rest of a frame header comment after `FRAME_HEADER`, which
+    /// starts at the given byte offset in the line.
+    fn frame(&mut self, header: &str, offset: usize) -> ParseResult<()> {
+        let mut words = Words::at(header, offset);
+        expect_word(&mut words, "origin")?;
+        let origin = parse_precise_position(&mut words)?;
+        expect_word(&mut words, "meters_per_unit")?;
+        let word = words
+            .next()
+            .ok_or_else(|| SyntaxError::missing("Expected more numbers"))?;
+        let meters = parse_f64(&word).ok_or_else(|| word.error("Malformed number"))?;
+        expect_end(&mut words)?;
+
+        let units = [
+            Unit::Millimeters,
+            Unit::Centimeters,
+            Unit::Meters,
+            Unit::Inches,
+        ];
+        let unit = units
+            .iter()
+            .find(|unit| (meters / unit.precise_meters() - 1.0).abs() < 1e-4)
+            .ok_or_else(|| word.error("Unsupported unit scale"))?;
+
+        self.frame_origin = Some(origin);
+        self.data.unit = Some(*unit);
+        Ok(())
+    }
+
+    /// Parses the corners of a face, line or point statement, clamping
+    /// indices in lenient mode.
+    fn parse_corners(&mut self, words: Words) -> ParseResult<Vec<Corner>> {
+        let clamp = self.options.parse_mode == ParseMode::Lenient;
+        let mut corners = Vec::new();
+
+        for word in words {
+            let (corner, clamped) = parse_corner(word, self.attributes.lens(), clamp)?;
+            if clamped {
+                self.data.warnings.push(Warning::IndexClamped {
+                    path: self.path.to_path_buf(),
+                    line: self.line_number,
+                    corner: word.text.to_string(),
+                });
+            }
+            corners.push(corner);
+        }
+
+        Ok(corners)
+    }
+
+    /// Finishes the current model and continues it in a new model with the
+    /// same name and material.
+    fn split_model(&mut self) {
+        let name = self.model.name.clone();
+        let material = self.model.material().cloned();
+        let finished = mem::replace(&mut self.model, ModelBuilder::new(name.clone(), material));
+        let keep_texcoord_w = self.keeps_texcoord_w();
+        self.data.models.push(finished.finish(keep_texcoord_w));
+        self.data.warnings.push(Warning::EntitySplit {
+            path: self.path.to_path_buf(),
+            line: self.line_number,
+            entity: name,
+        });
+    }
+
+    /// Estimates the number of bytes used by vertex data.
+    fn attributes_memory(&self) -> usize {
+        let attributes = &self.attributes;
+        attributes.positions.len() * mem::size_of::<[f32; 3]>()
+            + attributes.colors.len() * mem::size_of::<Option<[f32; 3]>>()
+            + attributes.texcoords.len() * mem::size_of::<[f32; 3]>()
+            + attributes.normals.len() * mem::size_of::<[f32; 3]>()
+            + attributes.precise_positions.len() * mem::size_of::<[f64; 3]>()
+    }
+
+    /// Estimates the number of bytes used by the parsed data.
+    fn memory(&mut self) -> usize {
+        for model in self.data.models[self.counted_models..].iter() {
+            self.pending_memory += model.mesh.memory();
+        }
+        self.counted_models = self.data.models.len();
+        self.attributes_memory() + self.pending_memory + self.model.memory()
+    }
+
+    /// Takes the models parsed so far to free memory, finishing the current
+    /// model first if it is the only one. Returns `None` if there is nothing
+    /// to take or the vertex data on its own exceeds the budget.
+    fn take_chunk(&mut self, budget: usize) -> Option<ObjData> {
+        if self.attributes_memory() > budget {
+            return None;
+        }
+        if self.data.models.is_empty() {
+            if !self.model.has_elements() {
+                return None;
+            }
+            self.split_model();
+        }
+        Some(self.take_data())
+    }
+
+    /// Takes the finished models along with the warnings and statistics
+    /// since the last chunk, recentering the models if requested.
+    fn take_data(&mut self) -> ObjData {
+        let next = ObjData {
+            material_libs: self.data.material_libs.clone(),
+            unit: self.data.unit,
+            ..ObjData::default()
+        };
+        let mut data = mem::replace(&mut self.data, next);
+        self.pending_memory = 0;
+        self.counted_models = 0;
+
+        let mut origin = self.origin;
+        if self.options.recenter {
+            if let Some(center) = recenter(&mut data.models) {
+                let shift = origin.unwrap_or_default();
+                origin = Some([
+                    shift[0] + center[0],
+                    shift[1] + center[1],
+                    shift[2] + center[2],
+                ]);
+            }
+        }
+        // Positions shifted to the origin of the frame header may be shifted again
+        let origin = match (self.frame_origin, origin) {
+            (Some(frame), Some(shift)) => Some([
+                frame[0] + shift[0],
+                frame[1] + shift[1],
+                frame[2] + shift[2],
+            ]),
+            (frame, shift) => frame.or(shift),
+        };
+        data.origin = origin.filter(|origin| origin.iter().any(|&c| c != 0.0));
+
+        data
+    }
+
+    fn finish(mut self) -> ObjData {
+        if self.model.has_elements() {
+            let model = mem::replace(&mut self.model, ModelBuilder::new(String::new(), None));
+            let keep_texcoord_w = self.keeps_texcoord_w();
+            self.data.models.push(model.finish(keep_texcoord_w));
+        }
+        self.take_data()
+    }
+
+    /// Checks if the third texture coordinate component is requested and
+    /// present in any `vt` statement so far.
+    fn keeps_texcoord_w(&self) -> bool {
+        self.options.texcoord_w && self.attributes.has_texcoord_w
+    }
+}
+
+pub fn rest_of_line(words: Words) -> String {
+    words.map(|word| word.text).collect::<Vec<_>>().join(" ")
+}
+
+/// Gets everything after the given keyword at the start of the line, with
+/// whitespace in between words preserved, e.g. for file names.
+pub fn raw_rest_of_line(line: &str, keyword: &str) -> String {
+    line.trim_start()[keyword.len()..].trim().to_string()
+}
+
+/// Splits the given text at whitespace, except for whitespace inside double
+/// quotes. The quotes themselves are removed, e.g. `a "b c"` yields `a` and
+/// `b c`.
+pub fn split_quoted(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+
+    for c in text.chars() {
+        if c == '"' {
+            quoted = !quoted;
+            in_word = true;
+        } else if c.is_whitespace() && !quoted {
+            if in_word {
+                words.push(word.clone());
+                word.clear();
+                in_word = false;
+            }
+        } else {
+            word.push(c);
+            in_word = true;
+        }
+    }
+
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Surrounds a file name that contains whitespace with double quotes, so it
+/// can be told apart from a list of file names.
+pub fn quote_file_name(name: &str) -> String {
+    if name.chars().any(|c| c.is_whitespace()) {
+        format!("\"{}\"", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Parses as many floats as fit into the returned array, ignoring any
+/// additional components.
+pub fn parse_floats<'a, A, I>(words: &mut I) -> ParseResult<A>
+where
+    A: Default + AsMut<[f32]>,
+    I: Iterator<Item = Word<'a>>,
+{
+    let mut values = A::default();
+
+    for value in values.as_mut().iter_mut() {
+        *value = match words.next() {
+            Some(word) => parse_f32(&word).ok_or_else(|| word.error("Malformed number"))?,
+            None => return Err(SyntaxError::missing("Expected more numbers")),
+        };
+    }
+
+    Ok(values)
+}
+
+/// Parses the coordinates of a `v` statement in double precision.
+fn parse_precise_position<'a, I>(words: &mut I) -> ParseResult<[f64; 3]>
+where
+    I: Iterator<Item = Word<'a>>,
+{
+    let mut position = [0.0; 3];
+    for coordinate in position.iter_mut() {
+        *coordinate = match words.next() {
+            Some(word) => parse_f64(&word).ok_or_else(|| word.error("Malformed number"))?,
+            None => return Err(SyntaxError::missing("Expected more numbers")),
+        };
+    }
+    Ok(position)
+}
+
+/// Fails unless the next word is the given keyword.
+fn expect_word<'a, I: Iterator<Item = Word<'a>>>(words: &mut I, keyword: &str) -> ParseResult<()> {
+    let message = format!("Expected {}", keyword);
+    match words.next() {
+        Some(word) if word.text == keyword => Ok(()),
+        Some(word) => Err(word.error(message)),
+        None => Err(SyntaxError::missing(message)),
+    }
+}
+
+/// Fails if there are any words left, which is only checked in strict mode,
+/// since some exporters write additional values.
+pub fn expect_end<'a, I: Iterator<Item = Word<'a>>>(words: &mut I) -> ParseResult<()> {
+    match words.next() {
+        Some(word) => Err(word.error("Unexpected value")),
+        None => Ok(()),
+    }
+}
+
+/// Checks that a face has at least three and a line at least two corners.
+pub fn check_corner_count(keyword: &str, count: usize) -> ParseResult<()> {
+    let (element, minimum, spelled) = match keyword {
+        "f" => ("face", 3, "three"),
+        "l" => ("line", 2, "two"),
+        _ => return Ok(()),
+    };
+    if count < minimum {
+        return Err(SyntaxError::missing(format!(
+            "OBJ {} has {} vertices, but at least {} are required",
+            element, count, spelled
+        )));
+    }
+    Ok(())
+}
+
+/// Parses the components of a `vt` statement. Only u is required, v and w
+/// default to zero if missing.
+pub fn parse_texcoord(words: &mut Words) -> ParseResult<[f32; 3]> {
+    let [u] = parse_floats::<[f32; 1], _>(words)?;
+    let mut texcoord = [u, 0.0, 0.0];
+
+    for (component, word) in texcoord[1..].iter_mut().zip(words) {
+        *component = parse_f32(&word).ok_or_else(|| word.error("Malformed number"))?;
+    }
+
+    Ok(texcoord)
+}
+
+/// Parses the optional vertex color after the position of a `v` statement.
+/// In strict mode, only the weight of the specification may follow.
+pub fn parse_vertex_color<'a, I>(words: I, strict: bool) -> ParseResult<Option<[f32; 3]>>
+where
+    I: Iterator<Item = Word<'a>>,
+{
+    // Colors are a non-standard extension, e.g. v 1 0 0 1.0 0.5 0.5
+    let rest: Vec<Word> = words.collect();
+    if rest.len() >= 3 {
+        if strict {
+            return Err(rest[0].error("Vertex colors are not part of the OBJ specification"));
+        }
+        Ok(Some(parse_floats(&mut rest.into_iter())?))
+    } else {
+        // The only other value allowed is the weight
+        if strict {
+            let mut rest = rest.into_iter();
+            if let Some(weight) = rest.next() {
+                parse_f32(&weight).ok_or_else(|| weight.error("Malformed number"))?;
+            }
+            expect_end(&mut rest)?;
+        }
+        Ok(None)
+    }
+}
+
+/// Parses the group of an `s` statement, with zero for `off`.
+pub fn parse_smoothing_group<'a, I>(words: &mut I) -> ParseResult<u32>
+where
+    I: Iterator<Item = Word<'a>>,
+{
+    match words.next() {
+        None => Ok(0),
+        Some(group) if group.text == "off" => Ok(0),
+        Some(group) => group
+            .parse()
+            .map_err(|_| group.error("Malformed OBJ smoothing group")),
+    }
+}
+
+/// Converts a one-based OBJ index into a zero-based index into a list
+/// with the given length.
+///
+/// Negative indices are relative to the end of the list, e.g. `-1` refers
+/// to the most recently defined element. If `clamp` is set, indices that
+/// are out of range are clamped into range, which is indicated by the
+/// returned flag.
+fn parse_index(index: Word, len: usize, clamp: bool) -> ParseResult<(usize, bool)> {
+    let parsed: i64 = index
+        .parse()
+        .map_err(|_| index.error("Malformed OBJ index"))?;
+
+    let resolved = if parsed < 0 {
+        len as i64 + parsed
+    } else {
+        parsed - 1
+    };
+
+    if parsed == 0 || resolved < 0 || resolved >= len as i64 {
+        if clamp && len > 0 {
+            let clamped = resolved.max(0).min(len as i64 - 1);
+            return Ok((clamped as usize, true));
+        }
+
+        return Err(index.error(format!(
+            "OBJ index out of range, only {} elements have been defined so far",
+            len
+        )));
+    }
+
+    Ok((resolved as usize, false))
+}
+
+/// Checks if a face has no area, e.g. because it uses the same position twice
+/// or all positions lie on a line.
+fn is_degenerate(corners: &[Corner], attributes: &Attributes) -> bool {
+    let position = |corner: &Corner| attributes.positions[corner.0];
+    let first = position(&corners[0]);
+
+    // Sum of the cross products of the fan triangles
+    let mut area_normal = [0.0; 3];
+    for pair in corners[1..].windows(2) {
+        let normal = face_normal(first, position(&pair[0]), position(&pair[1]));
+        for (sum, component) in area_normal.iter_mut().zip(normal.iter()) {
+            *sum += component;
+        }
+    }
+
+    area_normal == [0.0; 3]
+}
+
+/// Zero-based position, texcoord and normal indices of a corner of a face,
+/// line or point statement.
+pub type Corner = (usize, Option<usize>, Option<usize>);
+
+/// Parses a vertex of a face, line or point statement, e.g. `1/2/3`, into its
+/// zero-based position, texcoord and normal indices, given the numbers of
+/// positions, texcoords and normals defined so far. The returned flag
+/// indicates whether any index had to be clamped.
+pub fn parse_corner(corner: Word, lens: [usize; 3], clamp: bool) -> ParseResult<(Corner, bool)> {
+    let mut start = 0;
+    let mut parts = corner.split('/').map(|part| {
+        let part = corner.slice(start, start + part.len());
+        start += part.len() + 1;
+        part
+    });
+    let mut clamped = false;
+    let mut index = |idx: Word, len: usize| {
+        parse_index(idx, len, clamp).map(|(idx, was_clamped)| {
+            clamped |= was_clamped;
+            idx
+        })
+    };
+
+    let position = match parts.next() {
+        Some(idx) => index(idx, lens[0])?,
+        None => unreachable!("split always yields at least one element"),
+    };
+    let texcoord = match parts.next() {
+        Some(idx) if !idx.is_empty() => Some(index(idx, lens[1])?),
+        _ => None,
+    };
+    let normal = match parts.next() {
+        Some(idx) if !idx.is_empty() => Some(index(idx, lens[2])?),
+        _ => None,
+    };
+
+    Ok(((position, texcoord, normal), clamped))
+}
+
+/// Position, texcoord and normal indices of a vertex, and its smoothing key.
+///
+/// The smoothing key is only relevant for vertices without normals, which get
+/// their normals generated later. Such vertices are shared only inside the
+/// same smoothing group. Vertices of faces without smoothing get a key that is
+/// unique to their face, so they are never shared and can get flat normals.
+type VertexKey = (usize, Option<usize>, Option<usize>, u64);
+
+/// Bit set in smoothing keys of faces without smoothing, the other bits
+/// hold the face index.
+const FLAT_FACE_BIT: u64 = 1 << 63;
+
+/// Vertex attributes defined so far in the file, referenced by index in faces.
+#[derive(Default)]
+struct Attributes {
+    positions: Vec<[f32; 3]>,
+    /// Has the same length as `positions`.
+    colors: Vec<Option<[f32; 3]>>,
+    /// u, v and w, with zeroes for components not given in the file.
+    texcoords: Vec<[f32; 3]>,
+    /// Whether any `vt` statement had a third component.
+    has_texcoord_w: bool,
+    normals: Vec<[f32; 3]>,
+    /// Positions in double precision if kept, minus the shifted origin.
+    precise_positions: Vec<[f64; 3]>,
+}
+
+impl Attributes {
+    /// Numbers of positions, texcoords and normals.
+    fn lens(&self) -> [usize; 3] {
+        [
+            self.positions.len(),
+            self.texcoords.len(),
+            self.normals.len(),
+        ]
+    }
+}
+
+struct ModelBuilder {
+    name: String,
+    material: Option<String>,
+    positions: Vec<f32>,
+    precise_positions: Vec<f64>,
+    colors: Vec<Option<[f32; 3]>>,
+    texcoords: Vec<Option<[f32; 3]>>,
+    normals: Vec<Option<[f32; 3]>>,
+    indices: Vec<u32>,
+    face_sizes: Vec<u32>,
+    lines: Vec<Vec<u32>>,
+    points: Vec<u32>,
+    vertices: HashMap<VertexKey, u32>,
+}
+
+impl ModelBuilder {
+    fn new(name: String, material: Option<String>) -> ModelBuilder {
+        ModelBuilder {
+            name,
+            material,
+            positions: Vec::new(),
+            precise_positions: Vec::new(),
+            colors: Vec::new(),
+            texcoords: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+            face_sizes: Vec::new(),
+            lines: Vec::new(),
+            points: Vec::new(),
+            vertices: HashMap::new(),
+        }
+    }
+
+    /// Checks if any faces, lines or points have been added.
+    fn has_elements(&self) -> bool {
+        !self.face_sizes.is_empty() || !self.lines.is_empty() || !self.points.is_empty()
+    }
+
+    fn vertex_count(&self) -> usize {
+        self.positions.len() / 3
+    }
+
+    /// Estimates the number of bytes used so far, including the lookup of
+    /// vertices by attribute indices.
+    fn memory(&self) -> usize {
+        let vertex_count = self.vertex_count();
+        let vertex_size = 3 * mem::size_of::<f32>()
+            + mem::size_of::<Option<[f32; 3]>>() * 3
+            + mem::size_of::<(VertexKey, u32)>();
+        vertex_count * vertex_size
+            + self.precise_positions.len() * mem::size_of::<f64>()
+            + (self.indices.len() + self.face_sizes.len() + self.points.len())
+                * mem::size_of::<u32>()
+            + self.lines.len() * mem::size_of::<Vec<u32>>()
+    }
+
+    fn material(&self) -> Option<&String> {
+        self.material.as_ref()
+    }
+
+    fn rename(&mut self, name: String) {
+        self.name = name;
+    }
+
+    fn use_material(&mut self, material: String) {
+        self.material = Some(material);
+    }
+
+    /// Adds a face, ignoring its normal indices if `ignore_normals` is set.
+    fn add_face(
+        &mut self,
+        corners: Vec<Corner>,
+        smoothing_group: u32,
+        attributes: &Attributes,
+        ignore_normals: bool,
+    ) -> ParseResult<()> {
+        check_corner_count("f", corners.len())?;
+
+        let mut face = Vec::new();
+
+        for (position, texcoord, normal) in corners {
+            let normal = if ignore_normals { None } else { normal };
+
+            let smoothing_key = match (normal, smoothing_group) {
+                (Some(_), _) => 0,
+                (None, 0) => FLAT_FACE_BIT | self.face_sizes.len() as u64,
+                (None, group) => u64::from(group),
+            };
+
+            let vertex = self.vertex((position, texcoord, normal, smoothing_key), attributes);
+            face.push(vertex);
+        }
+
+        // Split into a triangle fan around the first vertex
+        for i in 1..(face.len() - 1) {
+            self.indices.push(face[0]);
+            self.indices.push(face[i]);
+            self.indices.push(face[i + 1]);
+        }
+        self.face_sizes.push(face.len() as u32);
+
+        Ok(())
+    }
+
+    fn add_line(&mut self, corners: Vec<Corner>, attributes: &Attributes) -> ParseResult<()> {
+        check_corner_count("l", corners.len())?;
+
+        let mut line = Vec::new();
+
+        for (position, texcoord, _) in corners {
+            // Lines have no normals and no smoothing, so zero keys are free to use
+            line.push(self.vertex((position, texcoord, None, 0), attributes));
+        }
+
+        self.lines.push(line);
+        Ok(())
+    }
+
+    fn add_points(&mut self, corners: Vec<Corner>, attributes: &Attributes) {
+        for (position, texcoord, _) in corners {
+            let point = self.vertex((position, texcoord, None, 0), attributes);
+            self.points.push(point);
+        }
+    }
+
+    /// Gets the index of the vertex with the given combination of attribute
+    /// indices, adding it first if it has not been used before.
+    fn vertex(&mut self, key: VertexKey, attributes: &Attributes) -> u32 {
+        if let Some(&idx) = self.vertices.get(&key) {
+            return idx;
+        }
+
+        let (position, texcoord, normal, _) = key;
+        let idx = (self.positions.len() / 3) as u32;
+        self.positions
+            .extend_from_slice(&attributes.positions[position]);
+        if let Some(precise) = attributes.precise_positions.get(position) {
+            self.precise_positions.extend_from_slice(precise);
+        }
+        self.colors.push(attributes.colors[position]);
+        self.texcoords
+            .push(texcoord.map(|t| attributes.texcoords[t]));
+        self.normals.push(normal.map(|n| attributes.normals[n]));
+        self.vertices.insert(key, idx);
+
+        idx
+    }
+
+    /// Turns the added elements into a model, keeping the third texture
+    /// coordinate component only if `keep_texcoord_w` is set.
+    fn finish(self, keep_texcoord_w: bool) -> Model {
+        let ModelBuilder {
+            name,
+            material,
+            positions,
+            precise_positions,
+            colors,
+            texcoords,
+            normals,
+            indices,
+            face_sizes,
+            lines,
+            points,
+            ..
+        } = self;
+
+        Model {
+            name,
+            material,
+            mesh: Mesh {
+                positions,
+                normals: flatten_partial(normals, 0.0),
+                texcoords: flatten_partial(
+                    texcoords.iter().map(|t| t.map(|t| [t[0], t[1]])).collect(),
+                    0.0,
+                ),
+                texcoord_ws: if keep_texcoord_w && texcoords.iter().any(|t| t.is_some()) {
+                    flatten_partial(texcoords.iter().map(|t| t.map(|t| [t[2]])).collect(), 0.0)
+                } else {
+                    Vec::new()
+                },
+                // Positions without color are white
+                colors: flatten_partial(colors, 1.0),
+                indices,
+                face_sizes,
+                lines,
+                points,
+                precise_positions,
+            },
+        }
+    }
+}
+
+/// Subtracts the rounded center of the bounding box of the precise positions
+/// of all models from both their precise and single precision positions, and
+/// returns the center, or `None` if there are no positions.
+fn recenter(models: &mut [Model]) -> Option<[f64; 3]> {
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for position in models
+        .iter()
+        .flat_map(|model| model.mesh.precise_positions.chunks(3))
+    {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+    if min[0] > max[0] {
+        return None;
+    }
+
+    let mut center = [0.0; 3];
+    for (axis, center) in center.iter_mut().enumerate() {
+        *center = ((min[axis] + max[axis]) * 0.5).round();
+    }
+    for mesh in models.iter_mut().map(|model| &mut model.mesh) {
+        let positions = mesh.positions.chunks_mut(3);
+        for (precise, position) in mesh.precise_positions.chunks_mut(3).zip(positions) {
+            for axis in 0..3 {
+                precise[axis] -= center[axis];
+                position[axis] = precise[axis] as f32;
+            }
+        }
+    }
+    Some(center)
+}
+
+/// Flattens per-vertex attributes into a single vector. If no vertex has the
+/// attribute, the result is empty. If only some have it, the others get the
+/// given fill value for each component.
+fn flatten_partial<A>(attributes: Vec<Option<A>>, fill: f32) -> Vec<f32>
+where
+    A: Default + AsMut<[f32]> + AsRef<[f32]>,
+{
+    if attributes.iter().all(|a| a.is_none()) {
+        return Vec::new();
+    }
+
+    let mut flat = Vec::new();
+    for attribute in attributes {
+        let attribute = attribute.unwrap_or_else(|| {
+            let mut filled = A::default();
+            for component in filled.as_mut().iter_mut() {
+                *component = fill;
+            }
+            filled
+        });
+        flat.extend_from_slice(attribute.as_ref());
+    }
+    flat
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use obj::options::NameFilter;
+
+    #[test]
+    fn test_quad_is_split_into_fan_and_size_kept() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\no Quad\nf 1 2 3 4\n";
+        let data = parse(
+            obj.as_bytes(),
+            Path::new("test.obj"),
+            &LoadOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(1, data.models.len());
+        let mesh = &data.models[0].mesh;
+        assert_eq!("Quad", data.models[0].name);
+        assert_eq!(vec![4], mesh.face_sizes);
+        assert_eq!(vec![0, 1, 2, 0, 2, 3], mesh.indices);
+        assert!(mesh.normals.is_empty());
+        assert!(mesh.texcoords.is_empty());
+    }
+
+    #[test]
+    fn test_negative_indices_relative_to_end() {
+        let obj = "v 0 0 0\nv 5 5 5\nv 1 0 0\nv 1 1 0\nvn 0 0 1\nf -3//-1 -2//-1 -1//-1\n";
+        let data = parse(
+            obj.as_bytes(),
+            Path::new("test.obj"),
+            &LoadOptions::default(),
+        )
+        .unwrap();
+        let mesh = &data.models[0].mesh;
+
+        assert_eq!(
+            vec![5.0, 5.0, 5.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0],
+            mesh.positions
+        );
+        assert_eq!(
+            vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0],
+            mesh.normals
+        );
+        let err = parse(
+            "v 0 0 0\nf -2 -1 -1\n".as_bytes(),
+            Path::new("test.obj"),
+            &LoadOptions::default(),
+        )
+        .unwrap_err();
+        match err {
+            Parse {
+                line,
+                column,
+                token,
+                ..
+            } => assert_eq!((2, 3, "-2".to_string()), (line, column, token)),
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_vertices_only_shared_inside_smoothing_groups() {
+        let geometry = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+        let parse_vertex_count = |smoothing: &str| {
+            let obj = format!("{}s {}\nf 1 2 3\nf 1 3 4\n", geometry, smoothing);
+            let data = parse(
+                obj.as_bytes(),
+                Path::new("test.obj"),
+                &LoadOptions::default(),
+            )
+            .unwrap();
+            data.models[0].mesh.positions.len() / 3
+        };
+
+        assert_eq!(4, parse_vertex_count("1"));
+        assert_eq!(6, parse_vertex_count("off"));
+    }
+
+    #[test]
+    fn test_lines_and_points_kept_without_faces() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\no Path\nl 1 2 3\np 3\n";
+        let data = parse(
+            obj.as_bytes(),
+            Path::new("test.obj"),
+            &LoadOptions::default(),
+        )
+        .unwrap();
+        let mesh = &data.models[0].mesh;
+
+        assert_eq!("Path", data.models[0].name);
+        assert!(mesh.indices.is_empty());
+        assert_eq!(vec![vec![0, 1, 2]], mesh.lines);
+        assert_eq!(vec![2], mesh.points);
+    }
+
+    #[test]
+    fn test_parse_modes() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nvx 1\nf 1 2 x\nf 1 2 4\n";
+        let parse_with = |mode| {
+            let options = LoadOptions::new().parse_mode(mode);
+            parse(obj.as_bytes(), Path::new("test.obj"), &options)
+        };
+
+        assert!(parse_with(ParseMode::Normal).is_err());
+        match parse_with(ParseMode::Strict).unwrap_err() {
+            Parse { line, token, .. } => assert_eq!((4, "vx".to_string()), (line, token)),
+            err => panic!("Unexpected error: {}", err),
+        }
+
+        let data = parse_with(ParseMode::Lenient).unwrap();
+        assert_eq!(vec![3], data.models[0].mesh.face_sizes);
+        assert_eq!(vec![0, 1, 2], data.models[0].mesh.indices);
+        match data.warnings.as_slice() {
+            [Warning::UnknownStatement { line: 4, .. }, Warning::SkippedLine { line: 5, .. }, Warning::IndexClamped { line: 6, .. }] => {
+                ()
+            }
+            warnings => panic!("Unexpected warnings: {:?}", warnings),
+        }
+    }
+
+    #[test]
+    fn test_skip_budget() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 x\nf 1 2 3\nf 1 y 3\n";
+        let parse_with = |max| {
+            let options = LoadOptions::new().max_skipped_lines(max);
+            parse(obj.as_bytes(), Path::new("test.obj"), &options)
+        };
+
+        match parse_with(1).unwrap_err() {
+            Parse { line, .. } => assert_eq!(6, line),
+            err => panic!("Unexpected error: {}", err),
+        }
+
+        let data = parse_with(2).unwrap();
+        assert_eq!(vec![3], data.models[0].mesh.face_sizes);
+        let skipped: Vec<_> = data
+            .warnings
+            .iter()
+            .filter_map(|w| match *w {
+                Warning::SkippedLine {
+                    line, ref content, ..
+                } => Some((line, content.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![(4, "f 1 2 x"), (6, "f 1 y 3")], skipped);
+    }
+
+    #[test]
+    fn test_degenerate_faces_and_unknown_statements_reported() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 2 0 0\nfoo\nfoo bar\nf 1 2 3\nf 1 1 2\n";
+        let data = parse(obj.as_bytes(), Path::new("test.obj"), &LoadOptions::new()).unwrap();
+
+        let reported: Vec<_> = data
+            .warnings
+            .iter()
+            .map(|w| match *w {
+                Warning::UnknownStatement { line, .. } => ("unknown", line),
+                Warning::DegenerateFace { line, .. } => ("degenerate", line),
+                _ => ("other", 0),
+            })
+            .collect();
+        assert_eq!(
+            vec![("unknown", 4), ("degenerate", 6), ("degenerate", 7)],
+            reported
+        );
+    }
+
+    #[test]
+    fn test_directive_and_fix_up_stats() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\ns 1\ng Quad\nusemtl A\n\
+                   f 1 2 3 4\nf 1 3 4\nfoo\ncstype bezier\n";
+        let data = parse(obj.as_bytes(), Path::new("test.obj"), &LoadOptions::new()).unwrap();
+        let stats = &data.stats;
+
+        assert_eq!(4, stats.directive_count("v"));
+        assert_eq!(2, stats.directive_count("f"));
+        assert_eq!(1, stats.directive_count("usemtl"));
+        assert_eq!(1, stats.directive_count("cstype"));
+        assert_eq!(0, stats.directive_count("vn"));
+        assert_eq!(1, stats.unknown_directives);
+        assert_eq!(1, stats.triangulated_faces);
+        assert_eq!(3, stats.welded_vertices);
+    }
+
+    #[test]
+    fn test_non_utf8_names() {
+        let obj = b"v 0 0 0\r\nv 1 0 0\r\nv 1 1 0\r\no Caf\xe9\r\nf 1 2 3\r\n";
+        let parse_with = |encoding| {
+            let options = LoadOptions::new().encoding(encoding);
+            parse(&obj[..], Path::new("test.obj"), &options)
+        };
+
+        match parse_with(Encoding::Utf8) {
+            Err(Parse { line, column, .. }) => assert_eq!((4, 6), (line, column)),
+            _ => panic!("Expected parse error"),
+        }
+        let lossy = parse_with(Encoding::Lossy).unwrap();
+        assert_eq!("Caf\u{fffd}", lossy.models[0].name);
+        let latin1 = parse_with(Encoding::Latin1Fallback).unwrap();
+        assert_eq!("Caf\u{e9}", latin1.models[0].name);
+    }
+
+    #[test]
+    fn test_split_quoted() {
+        assert_eq!(
+            vec!["a.mtl", "my lib.mtl", "b.mtl"],
+            split_quoted("a.mtl \"my lib.mtl\"   b.mtl")
+        );
+        assert_eq!(
+            vec!["my lib.mtl"],
+            split_quoted(&quote_file_name("my lib.mtl"))
+        );
+    }
+
+    #[test]
+    fn test_split_policies() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\n\
+                   o Chair\ng Legs\nusemtl Wood\nf 1 2 3\ng Seat\nf 1 2 3\n\
+                   usemtl Fabric\nf 1 2 3\no Table\nf 1 2 3\n";
+        let split = |policy| {
+            let options = LoadOptions::new().split_policy(policy);
+            parse(obj.as_bytes(), Path::new("test.obj"), &options)
+                .unwrap()
+                .models
+                .into_iter()
+                .map(|m| (m.name, m.material.unwrap(), m.mesh.face_sizes.len()))
+                .collect::<Vec<_>>()
+        };
+        let model =
+            |name: &str, material: &str, faces| (name.to_string(), material.to_string(), faces);
+
+        assert_eq!(
+            vec![
+                model("Legs", "Wood", 1),
+                model("Seat", "Wood", 1),
+                model("Seat", "Fabric", 1),
+                model("Table", "Fabric", 1),
+            ],
+            split(SplitPolicy::ObjectsAndGroups)
+        );
+        assert_eq!(
+            vec![
+                model("Chair", "Wood", 2),
+                model("Chair", "Fabric", 1),
+                model("Table", "Fabric", 1),
+            ],
+            split(SplitPolicy::Objects)
+        );
+        assert_eq!(
+            vec![
+                model("Legs", "Wood", 1),
+                model("Seat", "Wood", 1),
+                model("Seat", "Fabric", 2),
+            ],
+            split(SplitPolicy::Groups)
+        );
+        assert_eq!(
+            vec![model("Legs", "Wood", 2), model("Seat", "Fabric", 2)],
+            split(SplitPolicy::MaterialRuns)
+        );
+        assert_eq!(vec![model("Legs", "Fabric", 4)], split(SplitPolicy::Merged));
+    }
+
+    #[test]
+    fn test_max_entity_vertices() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                   o Scan\nusemtl Stone\nf 1 2 3\nf 1 3 4\nf 1 2 4\np 1 2\n";
+        let options = LoadOptions::new().max_entity_vertices(6);
+        let data = parse(obj.as_bytes(), Path::new("test.obj"), &options).unwrap();
+
+        let models: Vec<_> = data
+            .models
+            .iter()
+            .map(|m| {
+                (
+                    m.name.as_str(),
+                    m.mesh.positions.len() / 3,
+                    m.mesh.indices.len(),
+                )
+            })
+            .collect();
+        assert_eq!(vec![("Scan", 6, 6), ("Scan", 5, 3)], models);
+        assert!(data
+            .models
+            .iter()
+            .all(|m| m.material == Some("Stone".to_string())));
+
+        let splits: Vec<_> = data
+            .warnings
+            .iter()
+            .filter_map(|w| match *w {
+                Warning::EntitySplit { line, .. } => Some(line),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![9], splits);
+    }
+
+    #[test]
+    fn test_object_filter_skips_other_objects() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3\no Chair\nf 1 2 3\no Table\nf 1 2 x\n";
+        let options = LoadOptions::new().object_filter(NameFilter::glob("Ch*"));
+        let data = parse(obj.as_bytes(), Path::new("test.obj"), &options).unwrap();
+
+        assert_eq!(1, data.models.len());
+        assert_eq!("Chair", data.models[0].name);
+    }
+
+    #[test]
+    fn test_error_column() {
+        let column =
+            |obj: &str| match parse(obj.as_bytes(), Path::new("test.obj"), &LoadOptions::new()) {
+                Err(Parse { column, token, .. }) => (column, token),
+                result => panic!("Unexpected result: {:?}", result.map(|_| ())),
+            };
+        let positions = "v 0 0 0\n".repeat(15);
+
+        // The out of range texcoord index, not the position index before it
+        assert_eq!(
+            (11, "15".to_string()),
+            column(&format!("{}vt 0 0\nf 15/1 14/15 13/1\n", positions))
+        );
+        assert_eq!((7, "f".to_string()), column("v 0 0 0\nf 1 1 f\n"));
+        assert_eq!((6, "".to_string()), column("v 0 0 0\nf 1 1\n"));
+    }
+
+    #[test]
+    fn test_texcoord_w() {
+        let parse_with = |obj: &str, keep| {
+            let options = LoadOptions::new().texcoord_w(keep);
+            let data = parse(obj.as_bytes(), Path::new("test.obj"), &options).unwrap();
+            data.models[0].mesh.texcoord_ws.clone()
+        };
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0 0\nvt 1 0 0.5\nvt 1 1\nf 1/1 2/2 3/3\n";
+
+        assert_eq!(vec![0.0, 0.5, 0.0], parse_with(obj, true));
+        assert!(parse_with(obj, false).is_empty());
+        assert!(parse_with(&obj.replace(" 0.5", ""), true).is_empty());
+        assert!(parse_with("v 0 0 0\nv 1 0 0\nv 1 1 0\nvt 0 0 1\nf 1 2 3\n", true).is_empty());
+    }
+
+    #[test]
+    fn test_vertex_colors() {
+        let obj = "v 0 0 0 1 0 0\nv 1 0 0\nv 1 1 0 0 0.5 1\no Colored\nf 1 2 3\n\
+                   v 0 0 1\nv 1 0 1\nv 1 1 1\no Plain\nf 4 5 6\n";
+        let data = parse(obj.as_bytes(), Path::new("test.obj"), &LoadOptions::new()).unwrap();
+
+        // Positions without a color are white
+        assert_eq!(
+            vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.5, 1.0],
+            data.models[0].mesh.colors
+        );
+        assert!(data.models[1].mesh.colors.is_empty());
+    }
+
+    #[test]
+    fn test_line_bounded_by_memory_budget() {
+        use std::io;
+
+        let options = LoadOptions::new().memory_budget(1024);
+        let obj = "v 0 0 0\r\nv 1 0 0\r\nv 1 1 0\r\nf 1 2 3";
+        let data = parse(obj.as_bytes(), Path::new("test.obj"), &options).unwrap();
+        assert_eq!(vec![3], data.models[0].mesh.face_sizes);
+        assert_eq!(obj.len() as u64 - 6, data.stats.bytes_parsed);
+
+        // Without a line ending, reading the line would never finish
+        let endless = b"v 0 0 0\n# ".chain(io::repeat(b'x'));
+        match parse(BufReader::new(endless), Path::new("test.obj"), &options) {
+            Err(InvalidData(message)) => assert!(message.contains("memory budget")),
+            result => panic!("Unexpected result: {:?}", result.map(|_| ())),
+        }
+    }
+}
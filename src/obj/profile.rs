@@ -0,0 +1,391 @@
+//! Named sets of load and save options, read from TOML or JSON files.
+//!
+//! A profile file contains load profiles in the `load` table and save
+//! profiles in the `save` table, e.g.:
+//!
+//! ```toml
+//! [load.blender]
+//! preserve_polygons = true
+//! parse_mode = "lenient"
+//! split_policy = "objects"
+//! remap_prefix = { "X:/projects/" = "/mnt/projects/" }
+//! search_dirs = ["/mnt/projects/textures"]
+//!
+//! [save.archive]
+//! preserve_quads = true
+//! sort_by_material = true
+//! ```
+//!
+//! Each setting corresponds to the setter of the same name on `LoadOptions`
+//! or `SaveOptions`, and settings that are left out keep their defaults.
+
+use err::{AssetError, Result};
+use obj::options::{
+    CollisionExport, CoordinateSystem, Encoding, LineEndings, LoadOptions, MaterialEquality,
+    MaterialNaming, MissingMaps, MissingTexcoords, MissingTextures, NameFilter, ParseMode,
+    SaveOptions, SplitPolicy, TextureCheck, Unit, Winding,
+};
+use serde_json;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use toml;
+
+/// Load and save profiles by name.
+#[derive(Debug, Clone, Default)]
+pub struct Profiles {
+    path: PathBuf,
+    file: ProfileFile,
+}
+
+impl Profiles {
+    /// Reads profiles from the file at the given path, which is parsed as JSON
+    /// if it has a `json` extension and as TOML otherwise.
+    pub fn from_file<P: Into<PathBuf>>(path: P) -> Result<Profiles> {
+        let path = path.into();
+        let mut text = String::new();
+        File::open(&path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(AssetError::Read)?;
+
+        let is_json = path
+            .extension()
+            .map(|e| e.eq_ignore_ascii_case("json"))
+            .unwrap_or(false);
+        if is_json {
+            Profiles::from_json(&text, path)
+        } else {
+            Profiles::from_toml(&text, path)
+        }
+    }
+
+    /// Parses profiles from TOML. The path is only used to report errors.
+    pub fn from_toml<P: Into<PathBuf>>(text: &str, path: P) -> Result<Profiles> {
+        let path = path.into();
+        match toml::from_str(text) {
+            Ok(file) => Ok(Profiles { path, file }),
+            Err(err) => Err(profile_error(&path, err.to_string())),
+        }
+    }
+
+    /// Parses profiles from JSON. The path is only used to report errors.
+    pub fn from_json<P: Into<PathBuf>>(text: &str, path: P) -> Result<Profiles> {
+        let path = path.into();
+        match serde_json::from_str(text) {
+            Ok(file) => Ok(Profiles { path, file }),
+            Err(err) => Err(profile_error(&path, err.to_string())),
+        }
+    }
+
+    /// Gets the load options of the load profile with the given name.
+    pub fn load_options(&self, name: &str) -> Result<LoadOptions> {
+        match self.file.load.get(name) {
+            Some(profile) => Ok(profile.options()),
+            None => Err(profile_error(
+                &self.path,
+                format!("No load profile named \"{}\"", name),
+            )),
+        }
+    }
+
+    /// Gets the save options of the save profile with the given name.
+    pub fn save_options(&self, name: &str) -> Result<SaveOptions> {
+        match self.file.save.get(name) {
+            Some(profile) => Ok(profile.options()),
+            None => Err(profile_error(
+                &self.path,
+                format!("No save profile named \"{}\"", name),
+            )),
+        }
+    }
+
+    /// Names of all load profiles, in alphabetical order.
+    pub fn load_profile_names(&self) -> impl Iterator<Item = &str> {
+        self.file.load.keys().map(|k| k.as_str())
+    }
+
+    /// Names of all save profiles, in alphabetical order.
+    pub fn save_profile_names(&self) -> impl Iterator<Item = &str> {
+        self.file.save.keys().map(|k| k.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ProfileFile {
+    load: BTreeMap<String, LoadProfile>,
+    save: BTreeMap<String, SaveProfile>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LoadProfile {
+    preserve_polygons: Option<bool>,
+    regenerate_normals: Option<bool>,
+    discover_mtl: Option<bool>,
+    texcoord_w: Option<bool>,
+    missing_texcoords: Option<MissingTexcoords>,
+    missing_textures: Option<MissingTextures>,
+    parse_mode: Option<ParseMode>,
+    encoding: Option<Encoding>,
+    max_skipped_lines: Option<usize>,
+    /// Names of objects to load, see `NameFilter::Names`.
+    objects: Option<Vec<String>>,
+    /// Pattern for objects to load, see `NameFilter::Glob`.
+    objects_matching: Option<String>,
+    split_policy: Option<SplitPolicy>,
+    max_entity_vertices: Option<u32>,
+    memory_budget: Option<usize>,
+    texture_check: Option<TextureCheck>,
+    authored_texture_paths: Option<bool>,
+    normalize_unicode: Option<bool>,
+    intern_names: Option<bool>,
+    face_adjacency: Option<bool>,
+    surfel_density: Option<f32>,
+    bounds: Option<bool>,
+    validate_geometry: Option<bool>,
+    merge_duplicate_materials: Option<bool>,
+    merge_tolerance: Option<f32>,
+    detect_instances: Option<bool>,
+    weld_vertices: Option<f32>,
+    coordinate_system: Option<CoordinateSystem>,
+    source_unit: Option<Unit>,
+    target_unit: Option<Unit>,
+    transform: Option<[[f32; 4]; 4]>,
+    flip_v: Option<bool>,
+    winding: Option<Winding>,
+    shift_origin: Option<f64>,
+    frame_header: Option<bool>,
+    double_precision: Option<bool>,
+    recenter: Option<bool>,
+    case_insensitive_paths: Option<bool>,
+    remap_prefix: BTreeMap<String, String>,
+    search_dirs: Vec<PathBuf>,
+}
+
+impl LoadProfile {
+    fn options(&self) -> LoadOptions {
+        let defaults = LoadOptions::default();
+        let mut options = LoadOptions::new()
+            .preserve_polygons(self.preserve_polygons.unwrap_or(defaults.preserve_polygons))
+            .regenerate_normals(
+                self.regenerate_normals
+                    .unwrap_or(defaults.regenerate_normals),
+            )
+            .discover_mtl(self.discover_mtl.unwrap_or(defaults.discover_mtl))
+            .texcoord_w(self.texcoord_w.unwrap_or(defaults.texcoord_w))
+            .missing_texcoords(self.missing_texcoords.unwrap_or(defaults.missing_texcoords))
+            .missing_textures(self.missing_textures.unwrap_or(defaults.missing_textures))
+            .parse_mode(self.parse_mode.unwrap_or(defaults.parse_mode))
+            .encoding(self.encoding.unwrap_or(defaults.encoding))
+            .split_policy(self.split_policy.unwrap_or(defaults.split_policy))
+            .texture_check(self.texture_check.unwrap_or(defaults.texture_check))
+            .authored_texture_paths(
+                self.authored_texture_paths
+                    .unwrap_or(defaults.authored_texture_paths),
+            )
+            .normalize_unicode(self.normalize_unicode.unwrap_or(defaults.normalize_unicode))
+            .intern_names(self.intern_names.unwrap_or(defaults.intern_names))
+            .face_adjacency(self.face_adjacency.unwrap_or(defaults.face_adjacency))
+            .bounds(self.bounds.unwrap_or(defaults.bounds))
+            .validate_geometry(self.validate_geometry.unwrap_or(defaults.validate_geometry))
+            .merge_duplicate_materials(
+                self.merge_duplicate_materials
+                    .unwrap_or(defaults.merge_duplicate_materials),
+            )
+            .merge_tolerance(self.merge_tolerance.unwrap_or(defaults.merge_tolerance))
+            .detect_instances(self.detect_instances.unwrap_or(defaults.detect_instances))
+            .coordinate_system(self.coordinate_system.unwrap_or(defaults.coordinate_system))
+            .units(
+                self.source_unit.unwrap_or(defaults.source_unit),
+                self.target_unit.unwrap_or(defaults.target_unit),
+            )
+            .flip_v(self.flip_v.unwrap_or(defaults.flip_v))
+            .winding(self.winding.unwrap_or(defaults.winding))
+            .frame_header(self.frame_header.unwrap_or(defaults.frame_header))
+            .double_precision(self.double_precision.unwrap_or(defaults.double_precision))
+            .recenter(self.recenter.unwrap_or(defaults.recenter))
+            .case_insensitive_paths(
+                self.case_insensitive_paths
+                    .unwrap_or(defaults.case_insensitive_paths),
+            );
+
+        if let Some(max) = self.max_skipped_lines {
+            options = options.max_skipped_lines(max);
+        }
+
+        if let Some(max) = self.max_entity_vertices {
+            options = options.max_entity_vertices(max);
+        }
+
+        if let Some(bytes) = self.memory_budget {
+            options = options.memory_budget(bytes);
+        }
+
+        if let Some(matrix) = self.transform {
+            options = options.transform(matrix);
+        }
+
+        if let Some(threshold) = self.shift_origin {
+            options = options.shift_origin(threshold);
+        }
+
+        if let Some(density) = self.surfel_density {
+            options = options.surfel_density(density);
+        }
+
+        if let Some(epsilon) = self.weld_vertices {
+            options = options.weld_vertices(epsilon);
+        }
+
+        if let Some(ref names) = self.objects {
+            options = options.object_filter(NameFilter::names(names.iter().cloned()));
+        } else if let Some(ref pattern) = self.objects_matching {
+            options = options.object_filter(NameFilter::glob(pattern.as_str()));
+        }
+
+        for (old_prefix, new_prefix) in self.remap_prefix.iter() {
+            options = options.remap_prefix(old_prefix.as_str(), new_prefix.as_str());
+        }
+
+        for dir in self.search_dirs.iter() {
+            options = options.search_dir(dir.as_path());
+        }
+
+        options
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SaveProfile {
+    preserve_quads: Option<bool>,
+    mtl_per_entity: Option<bool>,
+    sort_by_material: Option<bool>,
+    normalize_unicode: Option<bool>,
+    material_naming: Option<MaterialNaming>,
+    material_equality: Option<MaterialEquality>,
+    coordinate_system: Option<CoordinateSystem>,
+    source_unit: Option<Unit>,
+    target_unit: Option<Unit>,
+    lod_naming: Option<bool>,
+    collision_export: Option<CollisionExport>,
+    flip_v: Option<bool>,
+    winding: Option<Winding>,
+    origin: Option<[f64; 3]>,
+    frame_header: Option<bool>,
+    precision: Option<usize>,
+    line_endings: Option<LineEndings>,
+    header_comments: Option<bool>,
+    normals: Option<bool>,
+    texcoords: Option<bool>,
+    required_maps: Vec<String>,
+    missing_maps: Option<MissingMaps>,
+    map_fill: BTreeMap<String, [u8; 4]>,
+    constant_maps: Option<u32>,
+}
+
+impl SaveProfile {
+    fn options(&self) -> SaveOptions {
+        let defaults = SaveOptions::default();
+        let mut options = SaveOptions::new()
+            .preserve_quads(self.preserve_quads.unwrap_or(defaults.preserve_quads))
+            .mtl_per_entity(self.mtl_per_entity.unwrap_or(defaults.mtl_per_entity))
+            .sort_by_material(self.sort_by_material.unwrap_or(defaults.sort_by_material))
+            .normalize_unicode(self.normalize_unicode.unwrap_or(defaults.normalize_unicode))
+            .coordinate_system(self.coordinate_system.unwrap_or(defaults.coordinate_system))
+            .units(
+                self.source_unit.unwrap_or(defaults.source_unit),
+                self.target_unit.unwrap_or(defaults.target_unit),
+            )
+            .lod_naming(self.lod_naming.unwrap_or(defaults.lod_naming))
+            .collision_export(self.collision_export.unwrap_or(defaults.collision_export))
+            .flip_v(self.flip_v.unwrap_or(defaults.flip_v))
+            .winding(self.winding.unwrap_or(defaults.winding))
+            .frame_header(self.frame_header.unwrap_or(defaults.frame_header))
+            .line_endings(self.line_endings.unwrap_or(defaults.line_endings))
+            .header_comments(
+                self.header_comments
+                    .unwrap_or(!defaults.omit_header_comments),
+            )
+            .normals(self.normals.unwrap_or(!defaults.omit_normals))
+            .texcoords(self.texcoords.unwrap_or(!defaults.omit_texcoords))
+            .missing_maps(self.missing_maps.unwrap_or(defaults.missing_maps));
+
+        if let Some(ref naming) = self.material_naming {
+            options = options.material_naming(naming.clone());
+        }
+
+        if let Some(ref equality) = self.material_equality {
+            options = options.material_equality(equality.clone());
+        }
+
+        if let Some(origin) = self.origin {
+            options = options.origin(origin);
+        }
+
+        if let Some(decimal_places) = self.precision {
+            options = options.precision(decimal_places);
+        }
+
+        for key in self.required_maps.iter() {
+            options = options.require_map(key.as_str());
+        }
+
+        for (key, &rgba) in self.map_fill.iter() {
+            options = options.map_fill(key.as_str(), rgba);
+        }
+
+        if let Some(size) = self.constant_maps {
+            options = options.constant_maps(size);
+        }
+
+        options
+    }
+}
+
+fn profile_error<S: Into<String>>(path: &Path, reason: S) -> AssetError {
+    AssetError::Profile {
+        path: path.to_path_buf(),
+        reason: reason.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_toml_and_json_profiles() {
+        let toml = "[load.blender]\nparse_mode = \"lenient\"\nobjects = [\"Chair\"]\n\
+                    remap_prefix = { \"X:/\" = \"/mnt/\" }\n\n[save.archive]\npreserve_quads = true\n\
+                    precision = 4\nline_endings = \"cr_lf\"\nnormals = false\n";
+        let profiles = Profiles::from_toml(toml, "profiles.toml").unwrap();
+
+        let load = profiles.load_options("blender").unwrap();
+        assert_eq!(ParseMode::Lenient, load.parse_mode);
+        assert!(load.object_filter.matches("Chair"));
+        assert!(!load.object_filter.matches("Table"));
+        assert_eq!("/mnt/tex.png", load.remap_path("X:/tex.png"));
+        let archive = profiles.save_options("archive").unwrap();
+        assert!(archive.preserve_quads);
+        assert_eq!(Some(4), archive.precision);
+        assert_eq!(LineEndings::CrLf, archive.line_endings);
+        assert!(archive.omit_normals && !archive.omit_texcoords);
+        assert!(profiles.save_options("blender").is_err());
+
+        let json = r#"{ "save": { "sorted": { "sort_by_material": true } } }"#;
+        let profiles = Profiles::from_json(json, "profiles.json").unwrap();
+        assert!(profiles.save_options("sorted").unwrap().sort_by_material);
+    }
+
+    #[test]
+    fn test_unknown_settings_rejected() {
+        let toml = "[load.typo]\npreserve_polgons = true\n";
+        match Profiles::from_toml(toml, "profiles.toml") {
+            Err(AssetError::Profile { .. }) => (),
+            _ => panic!("Expected profile error"),
+        }
+    }
+}
@@ -1,15 +1,33 @@
+use collision;
+use coords;
 use err::{AssetError, Result};
+use float::{parse_f32, Rounded};
+use instance;
+use loaded::{ColorSpace, EntityExtras, LoadedScene, MaterialProperties, TextureOptions};
+use lod::LodLevel;
+use maps;
+use normalize::nfc;
+use obj::options::{
+    CollisionExport, ComparedMaterial, CoordinateSystem, LineEndings, MissingMaps, SaveOptions,
+    Winding,
+};
+use obj::parse::{frame_header, quote_file_name};
 use pathdiff::diff_paths;
-use scene::{Entity, MaterialBuilder};
+use scene::{DeinterleavedIndexedMeshBuf, Entity, Material, MaterialBuilder};
 use std::borrow::Borrow;
-use std::fs::{canonicalize, File};
-use std::io::Write;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use texel;
+use vector::{face_normal, length};
+use winding;
 
 /// Exports the given iterator over entities (or references, boxes, etc.) to the given OBJ/MTL files.
 /// If one of the files should not be exported, leave it as None.
 ///
-/// FIXME mtl output does only work when obj output also specified
+/// Without an OBJ, the MTL holds every material of the entities, with map
+/// paths relative to the MTL.
 pub fn save<I, E, P>(
     entities: I,
     obj_output_path: Option<P>,
@@ -20,237 +38,1567 @@ where
     E: Borrow<Entity>,
     P: Into<PathBuf>,
 {
-    let obj_output_path = obj_output_path.map(|p| p.into());
-    let mtl_output_path = mtl_output_path.map(|p| p.into());
-    let mut mtl_file = None;
-    let mut persisted_materials = Vec::new();
+    save_with(
+        entities,
+        obj_output_path,
+        mtl_output_path,
+        &SaveOptions::default(),
+    )
+}
+
+/// Exports entities to the given OBJ/MTL files like `save`, but with the given options.
+pub fn save_with<I, E, P>(
+    entities: I,
+    obj_output_path: Option<P>,
+    mtl_output_path: Option<P>,
+    options: &SaveOptions,
+) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Borrow<Entity>,
+    P: Into<PathBuf>,
+{
+    save_entities(
+        entities.into_iter().map(|e| (e, None)),
+        obj_output_path.map(|p| p.into()),
+        mtl_output_path.map(|p| p.into()),
+        options,
+    )
+}
+
+/// Exports the given entities into the given writers instead of files, e.g.
+/// into in-memory buffers, sockets or archives. If no MTL should be
+/// exported, leave it as None.
+///
+/// The OBJ has no `mtllib` statement and map paths are written as they are,
+/// see `save_to_with` for writing them differently.
+pub fn save_to<I, E, W, M>(entities: I, obj: W, mtl: Option<M>) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Borrow<Entity>,
+    W: Write,
+    M: Write,
+{
+    save_to_with(entities, obj, mtl, &SaveOptions::default())
+}
+
+/// Exports entities into the given writers like `save_to`, but with the
+/// given options.
+///
+/// The `mtllib` statement and the map paths are controlled with
+/// `SaveOptions::mtl_file_name` and `SaveOptions::map_base`. Since there is
+/// only one writer for each kind of file, collision geometry is always written
+/// inline and `SaveOptions::mtl_per_entity` has no effect. Filling missing
+/// maps requires a map base to write the generated textures to.
+pub fn save_to_with<I, E, W, M>(
+    entities: I,
+    obj: W,
+    mtl: Option<M>,
+    options: &SaveOptions,
+) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Borrow<Entity>,
+    W: Write,
+    M: Write,
+{
+    let mut entities: Vec<(E, Option<&EntityExtras>)> =
+        entities.into_iter().map(|e| (e, None)).collect();
+    if options.sort_by_material {
+        sort_by_material(&mut entities);
+    }
+    let base = match options.map_base {
+        Some(ref dir) => Some(options.vfs().canonicalize(dir)?),
+        None => None,
+    };
+    let output_dir = options.map_base.clone().unwrap_or_default();
+    if options.lod_naming {
+        check_lod_levels(&entities, &output_dir, options)?;
+    }
+
+    let mut materials = collect_materials(entities.iter().map(|e| e.0.borrow()), options);
+    let completed = complete_maps(
+        &mut materials.materials,
+        &output_dir,
+        options.map_base.as_deref(),
+        options,
+    )?;
+    let options = completed.as_ref().unwrap_or(options);
+
+    let libs = MtlLibs {
+        shared: options.mtl_file_name.clone().filter(|_| mtl.is_some()),
+        per_entity: Vec::new(),
+        use_materials: mtl.is_some(),
+    };
+    if let Some(mtl) = mtl {
+        write_mtl_relative_to(
+            &mut LineEndingWriter::new(mtl, options),
+            materials.materials.iter(),
+            base.as_deref(),
+            options,
+        )?;
+    }
+    let mut obj = LineEndingWriter::new(obj, options);
+    write_obj(&mut obj, &entities, &materials, &libs, options)
+}
+
+/// Exports a loaded scene to the given OBJ/MTL files like `save`, but also
+/// considers the extra data of each entity, e.g. writing polygons that were
+/// preserved on load as faces with more than three vertices.
+pub fn save_scene<P>(
+    scene: &LoadedScene,
+    obj_output_path: Option<P>,
+    mtl_output_path: Option<P>,
+) -> Result<()>
+where
+    P: Into<PathBuf>,
+{
+    save_scene_with(
+        scene,
+        obj_output_path,
+        mtl_output_path,
+        &SaveOptions::default(),
+    )
+}
+
+/// Exports a loaded scene to the given OBJ/MTL files like `save_scene`, but with the given options.
+pub fn save_scene_with<P>(
+    scene: &LoadedScene,
+    obj_output_path: Option<P>,
+    mtl_output_path: Option<P>,
+    options: &SaveOptions,
+) -> Result<()>
+where
+    P: Into<PathBuf>,
+{
+    let with_origin;
+    let options = match scene.origin {
+        Some(origin) if options.origin.is_none() => {
+            with_origin = options.clone().origin(origin);
+            &with_origin
+        }
+        _ => options,
+    };
+    let with_properties;
+    let options = if options.material_properties.is_empty() {
+        with_properties = options
+            .clone()
+            .material_properties(scene.material_properties.clone());
+        &with_properties
+    } else {
+        options
+    };
+    save_entities(
+        scene.entities.iter().zip(scene.extras.iter().map(Some)),
+        obj_output_path.map(|p| p.into()),
+        mtl_output_path.map(|p| p.into()),
+        options,
+    )
+}
+
+fn save_entities<'a, I, E>(
+    entities: I,
+    obj_output_path: Option<PathBuf>,
+    mtl_output_path: Option<PathBuf>,
+    options: &SaveOptions,
+) -> Result<()>
+where
+    I: IntoIterator<Item = (E, Option<&'a EntityExtras>)>,
+    E: Borrow<Entity>,
+{
+    // Entities are needed more than once, to plan the output files and to write them
+    let mut entities: Vec<_> = entities.into_iter().collect();
+    let collision_entities = if options.collision_export == CollisionExport::Separate {
+        let (collision_entities, render_entities) = entities
+            .into_iter()
+            .partition(|&(ref entity, extras)| collision_for(entity.borrow(), extras).is_some());
+        entities = render_entities;
+        for &(ref entity, extras) in collision_entities.iter() {
+            let entity = entity.borrow();
+            let render = collision_for(entity, extras).unwrap_or_default();
+            if !entities.iter().any(|e| e.0.borrow().name == render) {
+                warn!(
+                    "Collision geometry {} belongs to {}, which is not saved",
+                    entity.name, render
+                );
+            }
+        }
+        collision_entities
+    } else {
+        Vec::new()
+    };
+    if options.sort_by_material {
+        sort_by_material(&mut entities);
+    }
+
+    let plan = plan_outputs(
+        entities.iter().map(|e| e.0.borrow()),
+        obj_output_path,
+        mtl_output_path,
+        options,
+    );
+    if options.lod_naming {
+        let path = plan.paths().next().cloned().unwrap_or_default();
+        check_lod_levels(&entities, &path, options)?;
+    }
+    let file_system = options.vfs();
+    let sizes = estimated_sizes(&plan, &entities);
+    let sizes: Vec<_> = sizes
+        .iter()
+        .map(|&(ref path, size)| (path.as_path(), size))
+        .collect();
+    file_system.check_outputs(&sizes)?;
+
+    // Map paths are relative to the OBJ, or to the MTLs if only saving materials
+    let anchor_path = match plan.paths().next() {
+        Some(anchor_path) => anchor_path,
+        None => return Ok(()),
+    };
+
+    let mut materials = collect_materials(entities.iter().map(|e| e.0.borrow()), options);
+    let anchor_dir = anchor_path.parent().unwrap_or_else(|| Path::new(""));
+    let completed = complete_maps(
+        &mut materials.materials,
+        anchor_path,
+        Some(anchor_dir),
+        options,
+    )?;
+    let options = completed.as_ref().unwrap_or(options);
+
+    let create = |path: &Path| -> Result<_> {
+        Ok(LineEndingWriter::new(file_system.create(path)?, options))
+    };
+    let mut obj = match plan.obj {
+        Some(ref obj_output_path) => Some(create(obj_output_path)?),
+        None => None,
+    };
+    let mut mtl = match plan.mtl {
+        Some(ref mtl_output_path) => Some(create(mtl_output_path)?),
+        None => None,
+    };
+    let mut entity_mtls = Vec::with_capacity(plan.entity_mtls.len());
+    for entity_mtl_path in plan.entity_mtls.iter() {
+        entity_mtls.push(create(entity_mtl_path)?);
+    }
+    let mut base = file_system.canonicalize(anchor_path)?;
+    base.pop();
+
+    if let Some(ref mut mtl) = mtl {
+        write_mtl(mtl, materials.materials.iter(), &base, options)?;
+    }
+    for (entity_idx, entity_mtl) in entity_mtls.iter_mut().enumerate() {
+        let material = materials.material_of(entity_idx);
+        write_mtl(entity_mtl, Some(material), &base, options)?;
+    }
+
+    if let Some(ref mut obj) = obj {
+        let mut libs = MtlLibs {
+            shared: None,
+            per_entity: Vec::with_capacity(plan.entity_mtls.len()),
+            use_materials: plan.writes_materials(),
+        };
+        if let Some(ref mtl_output_path) = plan.mtl {
+            libs.shared = Some(relative_path(mtl_output_path, &base, options)?);
+        }
+        for entity_mtl_path in plan.entity_mtls.iter() {
+            libs.per_entity
+                .push(relative_path(entity_mtl_path, &base, options)?);
+        }
+        write_obj(obj, &entities, &materials, &libs, options)?;
+    }
+
+    if !collision_entities.is_empty() {
+        let options = SaveOptions {
+            collision_export: CollisionExport::Inline,
+            ..options.clone()
+        };
+        save_entities(
+            collision_entities,
+            plan.obj.as_ref().map(|path| collision_path(path)),
+            plan.mtl.as_ref().map(|path| collision_path(path)),
+            &options,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Path of the companion file holding collision geometry, with `_collision`
+/// appended to the file stem.
+fn collision_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+    file_name.push("_collision");
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+/// Translates the line feeds written when saving into the line endings of the
+/// options.
+struct LineEndingWriter<W> {
+    inner: W,
+    line_endings: LineEndings,
+}
+
+impl<W: Write> LineEndingWriter<W> {
+    fn new(inner: W, options: &SaveOptions) -> LineEndingWriter<W> {
+        LineEndingWriter {
+            inner,
+            line_endings: options.line_endings,
+        }
+    }
+}
+
+impl<W: Write> Write for LineEndingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.line_endings {
+            LineEndings::Lf => self.inner.write(buf),
+            LineEndings::CrLf => {
+                for (idx, line) in buf.split(|&byte| byte == b'\n').enumerate() {
+                    if idx > 0 {
+                        self.inner.write_all(b"\r\n")?;
+                    }
+                    self.inner.write_all(line)?;
+                }
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// MTLs referenced by a written OBJ, relative to the OBJ.
+struct MtlLibs {
+    /// Referenced at the start of the OBJ.
+    shared: Option<String>,
+    /// Referenced at the start of each object, or empty.
+    per_entity: Vec<String>,
+    /// Whether objects select their materials with `usemtl`.
+    use_materials: bool,
+}
+
+/// Writes an OBJ referencing the given MTLs, using the materials collected
+/// with `collect_materials` for the same entities.
+fn write_obj<W, E>(
+    obj: &mut W,
+    entities: &[(E, Option<&EntityExtras>)],
+    materials: &CollectedMaterials,
+    libs: &MtlLibs,
+    options: &SaveOptions,
+) -> Result<()>
+where
+    W: Write,
+    E: Borrow<Entity>,
+{
+    // Write header
+    if !options.omit_header_comments {
+        obj.write_all("# aitios procedurally weathered OBJ file\n".as_bytes())?;
+    }
+    if options.frame_header {
+        let origin = file_origin(options).unwrap_or([0.0; 3]);
+        obj.write_all(frame_header(origin, options.target_unit).as_bytes())?;
+    }
+    if let Some(ref mtl_lib) = libs.shared {
+        obj.write_all("mtllib ".as_bytes())?;
+        obj.write_all(quote_file_name(mtl_lib).as_bytes())?;
+        obj.write_all("\n".as_bytes())?;
+    }
+    obj.write_all("\n".as_bytes())?;
+
+    let names = object_names(entities, options);
+    let mut last_material_name = None;
+    let mut idx_bases = IndexBases::new();
+
+    for (entity_idx, &(ref entity, extras)) in entities.iter().enumerate() {
+        let entity = entity.borrow();
+        let material = materials.material_of(entity_idx);
+
+        obj.write_all("o ".as_bytes())?;
+        obj.write_all(names[entity_idx].as_bytes())?;
+        obj.write_all("\n".as_bytes())?;
+
+        if let Some(entity_mtl_lib) = libs.per_entity.get(entity_idx) {
+            obj.write_all(format!("mtllib {}\n", quote_file_name(entity_mtl_lib)).as_bytes())?;
+        }
+
+        // Materials stay in effect over objects, sorted entities can reuse them
+        let material_changed = last_material_name.as_deref() != Some(&material.name()[..]);
+        if libs.use_materials && (material_changed || !options.sort_by_material) {
+            obj.write_all(format!("usemtl {}\n", material.name()).as_bytes())?;
+        }
+        last_material_name = Some(material.name().to_string());
+
+        write_geometry(obj, entity, extras, &mut idx_bases, options)?;
+        obj.write_all("\n".as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Adds the constant maps of `SaveOptions::constant_maps` and handles
+/// materials without the maps required by the options according to
+/// `SaveOptions::missing_maps`, writing generated textures into the given
+/// directory. Generating textures fails without a directory. Errors refer to
+/// the given output path.
+fn complete_maps(
+    materials: &mut [Material],
+    output_path: &Path,
+    texture_dir: Option<&Path>,
+    options: &SaveOptions,
+) -> Result<Option<SaveOptions>> {
+    let unsupported = options.required_maps.iter().find(|key| {
+        !maps::is_material_map(key) && MaterialProperties::default().map_slot(key).is_none()
+    });
+    if let Some(key) = unsupported {
+        return Err(AssetError::Preflight {
+            path: output_path.to_path_buf(),
+            reason: format!("Required map {} is not supported", key),
+        });
+    }
+
+    let mut filled_properties = None;
+    for material in materials.iter_mut() {
+        if let Some(size) = options.constant_map_size {
+            add_constant_maps(material, output_path, texture_dir, size, options)?;
+        }
+
+        let properties = options.material_properties.get(material.name());
+        let property_maps = properties.map(|p| p.maps()).unwrap_or_default();
+        let missing: Vec<&String> = options
+            .required_maps
+            .iter()
+            .filter(|key| {
+                !material.maps().iter().any(|&(k, _)| k == key.as_str())
+                    && !property_maps.iter().any(|&(k, _)| k == key.as_str())
+            })
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+
+        match options.missing_maps {
+            MissingMaps::Error => {
+                return Err(AssetError::Preflight {
+                    path: output_path.to_path_buf(),
+                    reason: format!("Material {} has no {} map", material.name(), missing[0]),
+                })
+            }
+            MissingMaps::Warn => {
+                for key in missing {
+                    warn!("Material {} has no {} map", material.name(), key);
+                }
+            }
+            MissingMaps::Fill => {
+                let texture_dir = texture_dir.ok_or_else(|| AssetError::Preflight {
+                    path: output_path.to_path_buf(),
+                    reason: format!(
+                        "Material {} has no {} map and there is no directory to write a \
+                         generated one to, set a map base",
+                        material.name(),
+                        missing[0]
+                    ),
+                })?;
+                let mut builder = MaterialBuilder::from(&*material);
+                let mut properties = properties.cloned().unwrap_or_default();
+                for key in missing {
+                    let rgba = options.map_fill_color(key);
+                    let texture = texture_dir.join(format!(
+                        "fill-{}-{:02x}{:02x}{:02x}{:02x}.png",
+                        key, rgba[0], rgba[1], rgba[2], rgba[3]
+                    ));
+                    if !options.vfs().exists(&texture) {
+                        texel::write_png(options.vfs(), &texture, rgba, 1)?;
+                    }
+                    match properties.map_slot(key) {
+                        Some(slot) => *slot = Some(texture),
+                        None => builder = maps::with_map(builder, key, texture),
+                    }
+                }
+                *material = builder.build();
+                filled_properties
+                    .get_or_insert_with(|| options.material_properties.clone())
+                    .insert(material.name().to_string(), properties);
+            }
+        }
+    }
+    // Filled maps of the properties are written from a copy of the options
+    Ok(filled_properties.map(|properties| options.clone().material_properties(properties)))
+}
+
+/// MTL statements with a scalar color or factor and the keyword of the map
+/// that replaces them.
+const CONSTANT_MAP_SCALARS: &[(&str, &str)] = &[
+    ("Kd", "map_Kd"),
+    ("Ka", "map_Ka"),
+    ("Ks", "map_Ks"),
+    ("Ke", "map_Ke"),
+    ("Pr", "map_Pr"),
+    ("Pm", "map_Pm"),
+    ("Ps", "map_Ps"),
+];
+
+/// Adds textures of a constant color for the scalar colors and factors in
+/// the properties of the material that have no map, see
+/// `SaveOptions::constant_maps`.
+fn add_constant_maps(
+    material: &mut Material,
+    output_path: &Path,
+    texture_dir: Option<&Path>,
+    size: u32,
+    options: &SaveOptions,
+) -> Result<()> {
+    let properties = match options.material_properties.get(material.name()) {
+        Some(properties) => properties,
+        None => return Ok(()),
+    };
+    let mut builder = MaterialBuilder::from(&*material);
+    for &(scalar, key) in CONSTANT_MAP_SCALARS {
+        if material.maps().iter().any(|&(k, _)| k == key) {
+            continue;
+        }
+        let rgba = match constant_color(properties, scalar) {
+            Some(rgba) => rgba,
+            None => continue,
+        };
+
+        let texture_dir = texture_dir.ok_or_else(|| AssetError::Preflight {
+            path: output_path.to_path_buf(),
+            reason: format!(
+                "Material {} needs a constant {} map and there is no directory to write \
+                 it to, set a map base",
+                material.name(),
+                key
+            ),
+        })?;
+        let texture = texture_dir.join(format!(
+            "{}-{:02x}{:02x}{:02x}-{}.png",
+            key, rgba[0], rgba[1], rgba[2], size
+        ));
+        if !options.vfs().exists(&texture) {
+            texel::write_png(options.vfs(), &texture, rgba, size)?;
+        }
+        builder = maps::with_map(builder, key, texture);
+    }
+    *material = builder.build();
+    Ok(())
+}
+
+/// Gets a scalar color, or a factor that is used for all channels, of the
+/// given properties in eight bits per channel.
+fn constant_color(properties: &MaterialProperties, keyword: &str) -> Option<[u8; 4]> {
+    let rgb = match keyword {
+        "Kd" => properties.diffuse?,
+        "Ka" => properties.ambient?,
+        "Ks" => properties.specular?,
+        "Ke" => properties.emissive?,
+        // Factors of the PBR extension are not interpreted when loading
+        _ => {
+            let params = properties.unknown_params.get(keyword)?;
+            [parse_f32(params.split_whitespace().next()?)?; 3]
+        }
+    };
+    let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some([byte(rgb[0]), byte(rgb[1]), byte(rgb[2]), 255])
+}
+
+/// Orders entities so that entities with equal materials are next to each
+/// other, keeping the order of first use of each material.
+fn sort_by_material<E, X>(entities: &mut Vec<(E, X)>)
+where
+    E: Borrow<Entity>,
+{
+    let mut first_used: Vec<Rc<Material>> = Vec::new();
+    let groups: Vec<usize> = entities
+        .iter()
+        .map(|entity| {
+            let material = &entity.0.borrow().material;
+            match first_used.iter().position(|m| **m == **material) {
+                Some(group) => group,
+                None => {
+                    first_used.push(Rc::clone(material));
+                    first_used.len() - 1
+                }
+            }
+        })
+        .collect();
+
+    let mut grouped: Vec<_> = groups.into_iter().zip(entities.drain(..)).collect();
+    // Stable, so entities with the same material keep their order
+    grouped.sort_by_key(|grouped| grouped.0);
+    entities.extend(grouped.into_iter().map(|grouped| grouped.1));
+}
+
+/// Materials to save for a sequence of entities, see `collect_materials`.
+#[derive(Debug, Clone, Default)]
+pub struct CollectedMaterials {
+    /// Distinct materials in the order of first use, with unique names.
+    pub materials: Vec<Material>,
+    /// Index into `materials` for each entity.
+    pub assignments: Vec<usize>,
+}
+
+impl CollectedMaterials {
+    /// Gets the material that is saved for the entity with the given index.
+    pub fn material_of(&self, entity_idx: usize) -> &Material {
+        &self.materials[self.assignments[entity_idx]]
+    }
+}
+
+/// Determines the materials that are written to the MTL when saving the
+/// given entities, without writing anything.
+///
+/// Materials that are the same according to the material equality of the
+/// options are only saved once. If different materials have the same name,
+/// later ones are renamed according to the material naming of the options,
+/// by default by appending the entity name, and if that is not enough, a
+/// numeric suffix, e.g. `iron`, `iron-bunny`, `iron-bunny-2`. Comparing the
+/// name of the material of each entity with the name of its collected
+/// material shows which materials will be renamed.
+pub fn collect_materials<I, E>(entities: I, options: &SaveOptions) -> CollectedMaterials
+where
+    I: IntoIterator<Item = E>,
+    E: Borrow<Entity>,
+{
+    let mut collected = CollectedMaterials::default();
+    // Saved materials, each with what is compared of it
+    let mut persisted: Vec<ComparedMaterial> = Vec::new();
+    let equality = &options.material_equality;
+
+    for entity in entities {
+        let entity = entity.borrow();
+        let properties = options.material_properties.get(entity.material.name());
+        let compared = equality.compared((*entity.material).clone(), properties, options.vfs());
+
+        // The same material with the same maps can be shared, no need for
+        // duplication
+        if let Some(material_idx) = persisted.iter().position(|m| equality.same(m, &compared)) {
+            collected.assignments.push(material_idx);
+            continue;
+        }
+
+        let taken = |name: &str| persisted.iter().any(|m| m.material.name() == name);
+        let material = if taken(entity.material.name()) {
+            // On a collision, where the name is the same but the maps are different,
+            // ask the naming strategy for names until one is unique,
+            // e.g. iron => iron-bunny => iron-bunny-2 => iron-bunny-3
+            let naming = &options.material_naming;
+            let mut attempt = 1;
+            let mut unique_name = naming.candidate(&entity.material, entity, attempt);
+            while taken(&unique_name) {
+                attempt += 1;
+                unique_name = naming.candidate(&entity.material, entity, attempt);
+            }
+            MaterialBuilder::from(&*entity.material)
+                .name(unique_name)
+                .build()
+        } else {
+            (*entity.material).clone()
+        };
+        let material = if options.normalize_unicode {
+            MaterialBuilder::from(&material)
+                .name(nfc(material.name()))
+                .build()
+        } else {
+            material
+        };
+
+        let compared = compared.with_material(material);
+        let material_idx = match persisted.iter().position(|m| equality.same(m, &compared)) {
+            Some(material_idx) => material_idx,
+            None => {
+                persisted.push(compared);
+                persisted.len() - 1
+            }
+        };
+        collected.assignments.push(material_idx);
+    }
+
+    collected.materials = persisted.into_iter().map(|m| m.material).collect();
+    collected
+}
+
+/// Paths of the files that saving a sequence of entities creates, see
+/// `plan_outputs`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputPlan {
+    pub obj: Option<PathBuf>,
+    /// The MTL shared by all entities, `None` if no MTL is saved or there is
+    /// one for each entity.
+    pub mtl: Option<PathBuf>,
+    /// Path of the MTL of each entity if saving one MTL per entity, otherwise
+    /// empty.
+    pub entity_mtls: Vec<PathBuf>,
+}
+
+impl OutputPlan {
+    /// Checks if materials are saved at all.
+    pub fn writes_materials(&self) -> bool {
+        self.mtl.is_some() || !self.entity_mtls.is_empty()
+    }
+
+    /// Iterates over the paths of all files that are created or overwritten.
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.obj
+            .iter()
+            .chain(self.mtl.iter())
+            .chain(self.entity_mtls.iter())
+    }
+}
+
+/// Determines the files that saving the given entities to the given OBJ and
+/// MTL paths creates, without writing anything.
+///
+/// With one MTL per entity, the given MTL path only serves as a template
+/// for the paths of the entity MTLs.
+pub fn plan_outputs<I, E>(
+    entities: I,
+    obj_output_path: Option<PathBuf>,
+    mtl_output_path: Option<PathBuf>,
+    options: &SaveOptions,
+) -> OutputPlan
+where
+    I: IntoIterator<Item = E>,
+    E: Borrow<Entity>,
+{
+    let mut plan = OutputPlan {
+        obj: obj_output_path,
+        mtl: None,
+        entity_mtls: Vec::new(),
+    };
+
+    if let Some(mtl_output_path) = mtl_output_path {
+        if !options.mtl_per_entity {
+            plan.mtl = Some(mtl_output_path);
+        } else {
+            for entity in entities {
+                let entity_name = entity_name(entity.borrow(), None, options);
+                let path = entity_mtl_path(&mtl_output_path, &entity_name, &plan.entity_mtls);
+                plan.entity_mtls.push(path);
+            }
+        }
+    }
+
+    plan
+}
+
+/// Lists the planned files along with a rough estimate of the size of each
+/// file in bytes.
+fn estimated_sizes<E: Borrow<Entity>>(
+    plan: &OutputPlan,
+    entities: &[(E, Option<&EntityExtras>)],
+) -> Vec<(PathBuf, u64)> {
+    // Generous sizes of a material and of a single line of each kind
+    const MATERIAL_SIZE: u64 = 1024;
+    const VERTEX_LINE_SIZE: u64 = 48;
+    const FACE_LINE_SIZE: u64 = 48;
+
+    let mut outputs = Vec::new();
+
+    if let Some(ref mtl_output_path) = plan.mtl {
+        let size = MATERIAL_SIZE * entities.len() as u64;
+        outputs.push((mtl_output_path.clone(), size));
+    }
+    for entity_mtl_path in plan.entity_mtls.iter() {
+        outputs.push((entity_mtl_path.clone(), MATERIAL_SIZE));
+    }
+
+    if let Some(ref obj_output_path) = plan.obj {
+        let size = entities
+            .iter()
+            .map(|&(ref entity, _)| {
+                let mesh = &entity.borrow().mesh;
+                let vertex_lines =
+                    mesh.positions.len() / 3 + mesh.texcoords.len() / 2 + mesh.normals.len() / 3;
+                let face_lines = mesh.indices.len() / 3;
+                vertex_lines as u64 * VERTEX_LINE_SIZE + face_lines as u64 * FACE_LINE_SIZE
+            })
+            .sum();
+        outputs.push((obj_output_path.clone(), size));
+    }
+
+    outputs
+}
+
+/// Gets the name of the entity as it is saved.
+fn entity_name(entity: &Entity, extras: Option<&EntityExtras>, options: &SaveOptions) -> String {
+    let name = match lod_level(entity, extras, options) {
+        Some(lod) => lod.name(),
+        None => entity.name.clone(),
+    };
+    if options.normalize_unicode {
+        nfc(&name)
+    } else {
+        name
+    }
+}
+
+/// Gets the names of the objects in the OBJ, with collision geometry named
+/// after the entity it belongs to, if saving for Unreal.
+fn object_names<E: Borrow<Entity>>(
+    entities: &[(E, Option<&EntityExtras>)],
+    options: &SaveOptions,
+) -> Vec<String> {
+    let mut names: Vec<String> = entities
+        .iter()
+        .map(|&(ref entity, extras)| entity_name(entity.borrow(), extras, options))
+        .collect();
+    if options.collision_export != CollisionExport::Unreal {
+        return names;
+    }
+
+    let mut numbers: BTreeMap<String, usize> = BTreeMap::new();
+    for (idx, &(ref entity, extras)) in entities.iter().enumerate() {
+        let entity = entity.borrow();
+        let render = match collision_for(entity, extras) {
+            Some(render) => render,
+            None => continue,
+        };
+        // Follow the render entity if it is renamed on export
+        let render_idx = entities.iter().position(|&(ref other, extras)| {
+            other.borrow().name == render && collision_for(other.borrow(), extras).is_none()
+        });
+        let render_name = match render_idx {
+            Some(render_idx) => names[render_idx].clone(),
+            None => {
+                warn!(
+                    "Collision geometry {} belongs to {}, which is not saved",
+                    entity.name, render
+                );
+                render
+            }
+        };
+        let number = numbers.entry(render_name.clone()).or_insert(0);
+        *number += 1;
+        names[idx] = collision::unreal_name(&entity.name, &render_name, *number);
+    }
+    names
+}
 
-    if let Some(ref mtl_output_path) = mtl_output_path {
-        let mut mtl = File::create(&mtl_output_path).map_err(AssetError::from)?;
+/// Gets the name of the entity that the entity is collision geometry for.
+fn collision_for(entity: &Entity, extras: Option<&EntityExtras>) -> Option<String> {
+    match extras {
+        Some(extras) => extras.collision_for.clone(),
+        None => collision::render_name(&entity.name),
+    }
+}
 
-        // Write header
-        mtl.write("# aitios procedurally weathered MTL file\n".as_bytes())?;
-        mtl_file = Some(mtl);
+/// Gets the level of detail group of the entity, if LOD naming is enforced.
+/// Collision geometry is never part of a group.
+fn lod_level(
+    entity: &Entity,
+    extras: Option<&EntityExtras>,
+    options: &SaveOptions,
+) -> Option<LodLevel> {
+    if !options.lod_naming || collision_for(entity, extras).is_some() {
+        return None;
+    }
+    extras
+        .and_then(|extras| extras.lod.clone())
+        .or_else(|| LodLevel::parse(&entity.name))
+}
 
-        // TODO give materials unique names if properties are different but name is the same
+/// Fails if two entities claim the same level of the same group, and warns
+/// about groups with missing levels.
+fn check_lod_levels<E: Borrow<Entity>>(
+    entities: &[(E, Option<&EntityExtras>)],
+    path: &Path,
+    options: &SaveOptions,
+) -> Result<()> {
+    let mut groups: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+    for &(ref entity, extras) in entities.iter() {
+        if let Some(lod) = lod_level(entity.borrow(), extras, options) {
+            let levels = groups.entry(lod.group.clone()).or_default();
+            if levels.contains(&lod.level) {
+                return Err(AssetError::Preflight {
+                    path: path.to_path_buf(),
+                    reason: format!("More than one entity is named {}", lod.name()),
+                });
+            }
+            levels.push(lod.level);
+        }
     }
 
-    if let Some(obj_output_path) = obj_output_path {
-        let mut obj = File::create(&obj_output_path)?;
-        let mut base = canonicalize(&obj_output_path)?;
-        base.pop();
+    for (group, levels) in groups.iter() {
+        let max = levels.iter().cloned().max().unwrap_or(0);
+        if levels.len() as u64 != u64::from(max) + 1 {
+            warn!("LOD group {} has levels missing below {}", group, max);
+        }
+    }
 
-        // Make it a relative path
-        let mtl_lib = if let Some(ref mtl) = mtl_output_path {
-            let mtl = canonicalize(mtl)?;
-            let relative_mtl_path = diff_paths(&mtl, &base).ok_or_else(|| {
-                AssetError::InvalidData(
+    Ok(())
+}
+
+const MTL_HEADER: &str = "# aitios procedurally weathered MTL file\n";
+
+/// Writes an MTL with the given materials, e.g. the ones collected with
+/// `collect_materials`. Map paths are written relative to the given base
+/// directory, which usually is the directory of the OBJ.
+pub fn write_mtl<W, I, M>(
+    mtl: &mut W,
+    materials: I,
+    base: &Path,
+    options: &SaveOptions,
+) -> Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = M>,
+    M: Borrow<Material>,
+{
+    write_mtl_relative_to(mtl, materials, Some(base), options)
+}
+
+/// Writes an MTL like `write_mtl`, but writes map paths as they are if there
+/// is no base directory.
+fn write_mtl_relative_to<W, I, M>(
+    mtl: &mut W,
+    materials: I,
+    base: Option<&Path>,
+    options: &SaveOptions,
+) -> Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = M>,
+    M: Borrow<Material>,
+{
+    if !options.omit_header_comments {
+        mtl.write_all(MTL_HEADER.as_bytes())?;
+    }
+    for material in materials {
+        write_material_relative_to(mtl, material.borrow(), base, options)?;
+    }
+    Ok(())
+}
+
+/// Writes a `newmtl` statement for the given material, followed by its properties.
+/// Map paths are written relative to the given base directory.
+pub fn write_material<W: Write>(
+    mtl: &mut W,
+    material: &Material,
+    base: &Path,
+    options: &SaveOptions,
+) -> Result<()> {
+    write_material_relative_to(mtl, material, Some(base), options)
+}
+
+/// Writes a material like `write_material`, but writes map paths as they are
+/// if there is no base directory.
+fn write_material_relative_to<W: Write>(
+    mtl: &mut W,
+    material: &Material,
+    base: Option<&Path>,
+    options: &SaveOptions,
+) -> Result<()> {
+    let properties = options.material_properties.get(material.name());
+    // Color spaces are only written if they differ from the conventional one
+    let map_options = |key: &str| {
+        let mut texture_options = properties
+            .and_then(|properties| properties.map_options.get(key))
+            .cloned()
+            .unwrap_or_default();
+        if let Some(&color_space) = properties.and_then(|p| p.color_spaces.get(key)) {
+            texture_options.color_space =
+                Some(color_space).filter(|&c| c != ColorSpace::default_for(key));
+        }
+        Some(texture_options).filter(|options| !options.is_empty())
+    };
+    let mut mtl_maps: Vec<(&str, &PathBuf, Option<TextureOptions>)> = material
+        .maps()
+        .iter()
+        .map(|&(key, ref path)| (key, path, map_options(key)))
+        .collect();
+    // Some maps have no representation in materials, but in the properties
+    if let Some(properties) = properties {
+        let property_maps = properties.maps().into_iter();
+        mtl_maps.extend(property_maps.map(|(key, path)| (key, path, map_options(key))));
+        let reflections = properties.reflection_maps.iter();
+        mtl_maps.extend(reflections.map(|map| {
+            let options = Some(map.options.clone()).filter(|options| !options.is_empty());
+            ("refl", &map.path, options)
+        }));
+    }
+    mtl.write_all(format!("\nnewmtl {}\n", material.name()).as_bytes())?;
+    let places = options.precision;
+    let no_properties = MaterialProperties::default();
+    let p = properties.unwrap_or(&no_properties);
+    let scalar = |value: f32| Rounded(value, places).to_string();
+    let color = |c: [f32; 3]| {
+        format!(
+            "{} {} {}",
+            Rounded(c[0], places),
+            Rounded(c[1], places),
+            Rounded(c[2], places)
+        )
+    };
+    let statements = [
+        ("Ns", p.shininess.map(&scalar)),
+        ("Ka", p.ambient.map(&color)),
+        ("Kd", p.diffuse.map(&color)),
+        ("Ks", p.specular.map(&color)),
+        ("Ke", p.emissive.map(&color)),
+        ("Tf", p.transmission_filter.map(&color)),
+        ("Ni", p.optical_density.map(&scalar)),
+        ("d", p.dissolve.map(&scalar)),
+        // Materials without loaded properties keep the model written before
+        ("illum", Some(p.illumination.unwrap_or(1).to_string())),
+        // Factors of the PBR extension
+        ("Pc", p.clearcoat.map(&scalar)),
+        ("Pcr", p.clearcoat_roughness.map(&scalar)),
+        ("aniso", p.anisotropy.map(&scalar)),
+        ("anisor", p.anisotropy_rotation.map(&scalar)),
+    ];
+    for &(key, ref params) in statements.iter() {
+        if let Some(ref params) = *params {
+            mtl.write_all(format!("{} {}\n", key, params).as_bytes())?;
+        }
+    }
+    if let Some(properties) = properties {
+        for (keyword, params) in properties.unknown_params.iter() {
+            let line = format!("{} {}", keyword, params);
+            mtl.write_all(format!("{}\n", line.trim_end()).as_bytes())?;
+        }
+    }
+
+    for (map_mtl_key, map_path, texture_options) in mtl_maps {
+        let map_path = match base {
+            Some(base) => {
+                let map_path = options.vfs().canonicalize(map_path)?;
+                diff_paths(&map_path, base).expect(&format!(
+                    "Path {:?} could not be expressed relative to OBJ parent directory {:?}",
+                    map_path, base
+                ))
+            }
+            None => map_path.clone(),
+        };
+        let map_path = map_path
+            .to_str()
+            .expect("Could not make UTF-8 string out of texture filename");
+        let map_path = if options.normalize_unicode {
+            nfc(map_path)
+        } else {
+            map_path.to_string()
+        };
+        let map_path = quote_file_name(&map_path);
+        let map_line = match texture_options {
+            Some(ref texture_options) => format!(
+                "{key} {options} {value}\n",
+                key = map_mtl_key,
+                options = texture_options,
+                value = map_path
+            ),
+            None => format!("{key} {value}\n", key = map_mtl_key, value = map_path),
+        };
+        mtl.write_all(map_line.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Expresses the existing file at the given path relative to the given base directory.
+fn relative_path(path: &Path, base: &Path, options: &SaveOptions) -> Result<String> {
+    let canonical = options.vfs().canonicalize(path)?;
+    let relative = diff_paths(&canonical, base).ok_or_else(|| {
+        AssetError::InvalidData(format!(
+            "Output path \"{path}\" cannot be expressed relative to directory that contains the OBJ \"{base}\".",
+            path = path.display(),
+            base = base.display()
+        ))
+    })?;
+
+    relative.to_str().map(|p| p.to_string()).ok_or_else(|| {
+        AssetError::InvalidData("Mtl path could not be converted to UTF-8 string.".to_string())
+    })
+}
+
+/// Builds the path of the MTL file for a single entity by appending the entity
+/// name to the file stem of the given MTL path, e.g. `scene.mtl` becomes
+/// `scene-Cube.mtl`. A numeric suffix is added if the path has already been used.
+fn entity_mtl_path(mtl_output_path: &Path, entity_name: &str, used: &[PathBuf]) -> PathBuf {
+    let stem = mtl_output_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("materials");
+
+    // Entity names may contain characters that are not allowed in file names
+    let entity_name: String = entity_name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let unique_name_base = format!("{}-{}", stem, entity_name);
+    let mut path = mtl_output_path.with_file_name(format!("{}.mtl", unique_name_base));
+    let mut suffix = 1;
+    while used.contains(&path) {
+        suffix += 1;
+        path = mtl_output_path.with_file_name(format!("{}-{}.mtl", unique_name_base, suffix));
+    }
+
+    path
+}
+
+/// One-based OBJ indices of the first position, texcoord and normal of the
+/// entity that is currently being written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexBases {
+    pub position: usize,
+    pub texcoord: usize,
+    pub normal: usize,
+}
+
+impl IndexBases {
+    /// Index bases of the first entity in an OBJ.
+    pub fn new() -> IndexBases {
+        IndexBases {
+            position: 1,
+            texcoord: 1,
+            normal: 1,
+        }
+    }
+
+    /// Moves the bases past the vertex data of the given mesh.
+    pub fn advance(&mut self, mesh: &DeinterleavedIndexedMeshBuf) {
+        self.position += mesh.positions.len() / 3;
+        self.texcoord += mesh.texcoords.len() / 2;
+        self.normal += mesh.normals.len() / 3;
+    }
+}
+
+impl Default for IndexBases {
+    fn default() -> IndexBases {
+        IndexBases::new()
+    }
+}
+
+/// Converts the origin set in the options like positions, so it can be added
+/// to the written positions.
+fn file_origin(options: &SaveOptions) -> Option<[f64; 3]> {
+    let unit_scale = options.source_unit.precise_scale_to(options.target_unit);
+    options.origin.map(|origin| {
+        let mut origin = coords::vector_from_scene(origin, options.coordinate_system);
+        for coordinate in origin.iter_mut() {
+            *coordinate *= unit_scale;
+        }
+        origin
+    })
+}
+
+/// Converts the double precision positions in the extras like positions of
+/// the mesh, if they are present and still round to the mesh positions.
+fn precise_positions(
+    mesh: &DeinterleavedIndexedMeshBuf,
+    extras: Option<&EntityExtras>,
+    options: &SaveOptions,
+) -> Option<Vec<f64>> {
+    let precise = extras?.precise_positions.as_ref()?;
+    let unchanged = precise.len() == mesh.positions.len()
+        && precise
+            .iter()
+            .zip(mesh.positions.iter())
+            .all(|(&precise, &position)| precise as f32 == position);
+    if !unchanged {
+        return None;
+    }
+
+    let mut precise = precise.clone();
+    coords::precise_from_scene(&mut precise, options.coordinate_system);
+    let unit_scale = options.source_unit.precise_scale_to(options.target_unit);
+    for coordinate in precise.iter_mut() {
+        *coordinate *= unit_scale;
+    }
+    Some(precise)
+}
+
+/// Writes the vertex data and elements of the given entity as OBJ
+/// statements, then advances the index bases past the written vertices.
+///
+/// Only geometry is written, the `o` and `usemtl` statements for the entity
+/// are left to the caller.
+pub fn write_geometry<W: Write>(
+    obj: &mut W,
+    entity: &Entity,
+    extras: Option<&EntityExtras>,
+    idx_bases: &mut IndexBases,
+    options: &SaveOptions,
+) -> Result<()> {
+    let converted;
+    let unit_scale = options.source_unit.scale_to(options.target_unit);
+    let instance_transform = extras
+        .and_then(|e| e.instance_transform)
+        .filter(|&transform| transform != instance::IDENTITY);
+    let precise = match instance_transform {
+        Some(_) => None,
+        None => precise_positions(&entity.mesh, extras, options),
+    };
+    let entity = if instance_transform.is_some()
+        || options.coordinate_system != CoordinateSystem::default()
+        || unit_scale != 1.0
+        || options.flip_v
+        || options.winding == Winding::Flip
+        || options.winding == Winding::Orient
+        || (options.omit_normals && !entity.mesh.normals.is_empty())
+        || (options.omit_texcoords && !entity.mesh.texcoords.is_empty())
+    {
+        let mut mesh = (*entity.mesh).clone();
+        if options.omit_normals {
+            mesh.normals.clear();
+        }
+        if options.omit_texcoords {
+            mesh.texcoords.clear();
+        }
+        let face_sizes = extras.and_then(|e| e.face_sizes.as_ref());
+        // Shared meshes of instances are placed first, which also reverses
+        // faces of mirrored instances
+        if let Some(transform) = instance_transform {
+            coords::apply_matrix(&mut mesh, face_sizes, transform);
+        }
+        coords::from_scene(&mut mesh, face_sizes, options.coordinate_system);
+        coords::scale(&mut mesh, unit_scale);
+        if options.flip_v {
+            coords::flip_v(&mut mesh);
+        }
+        match options.winding {
+            Winding::Flip => winding::flip(&mut mesh, face_sizes),
+            Winding::Orient => {
+                winding::orient(&mut mesh, face_sizes);
+            }
+            Winding::Keep | Winding::Detect => (),
+        }
+        converted = Entity {
+            mesh: Rc::new(mesh),
+            ..entity.clone()
+        };
+        &converted
+    } else {
+        entity
+    };
+    if options.winding == Winding::Detect {
+        let face_sizes = extras.and_then(|e| e.face_sizes.as_ref());
+        let triangles = winding::misoriented_triangles(&entity.mesh, face_sizes);
+        if triangles > 0 {
+            warn!(
+                "Entity {} has {} triangles wound the wrong way",
+                entity.name, triangles
+            );
+        }
+    }
+
+    // Vertex colors are ignored if the mesh has been edited after loading
+    let colors = extras
+        .and_then(|e| e.colors.as_ref())
+        .filter(|c| c.len() == entity.mesh.positions.len());
+
+    // The origin is added in double precision, unless recorded in the header
+    let origin = if options.frame_header {
+        None
+    } else {
+        file_origin(options)
+    };
+    let places = options.precision;
+    let coordinates = |vertex: usize| {
+        let p = &entity.mesh.positions[vertex * 3..vertex * 3 + 3];
+        match (precise.as_ref(), origin) {
+            (Some(precise), origin) => {
+                let p = &precise[vertex * 3..vertex * 3 + 3];
+                let origin = origin.unwrap_or_default();
+                format!(
+                    "{} {} {}",
+                    Rounded(p[0] + origin[0], places),
+                    Rounded(p[1] + origin[1], places),
+                    Rounded(p[2] + origin[2], places)
+                )
+            }
+            (None, Some(origin)) => format!(
+                "{} {} {}",
+                Rounded(f64::from(p[0]) + origin[0], places),
+                Rounded(f64::from(p[1]) + origin[1], places),
+                Rounded(f64::from(p[2]) + origin[2], places)
+            ),
+            (None, None) => format!(
+                "{} {} {}",
+                Rounded(p[0], places),
+                Rounded(p[1], places),
+                Rounded(p[2], places)
+            ),
+        }
+    };
+    let vertex_count = entity.mesh.positions.len() / 3;
+
+    if let Some(colors) = colors {
+        let position_lines = (0..vertex_count).zip(colors.chunks(3)).map(|(v, c)| {
+            format!(
+                "v {} {} {} {}\n",
+                coordinates(v),
+                Rounded(c[0], places),
+                Rounded(c[1], places),
+                Rounded(c[2], places)
+            )
+        });
+
+        for position_line in position_lines {
+            obj.write_all(position_line.as_bytes())?;
+        }
+    } else {
+        let position_lines = (0..vertex_count).map(|v| format!("v {}\n", coordinates(v)));
+
+        for position_line in position_lines {
+            obj.write_all(position_line.as_bytes())?;
+        }
+    }
+
+    let texcoord_ws = extras
+        .and_then(|e| e.texcoord_ws.as_ref())
+        .filter(|w| w.len() * 2 == entity.mesh.texcoords.len());
+
+    if let Some(texcoord_ws) = texcoord_ws {
+        let texcoord_lines =
+            entity
+                .mesh
+                .texcoords
+                .chunks(2)
+                .zip(texcoord_ws.iter())
+                .map(|(t, w)| {
                     format!(
-                        "Output path for MTL \"{mtl_path}\" cannot be expressed relative to directory that contains the OBJ \"{obj_path}\".",
-                        mtl_path = mtl_output_path.as_ref().unwrap().to_str().unwrap(),
-                        obj_path = obj_output_path.to_str().unwrap()
+                        "vt {} {} {}\n",
+                        Rounded(t[0], places),
+                        Rounded(t[1], places),
+                        Rounded(*w, places)
                     )
-                )
-            })?;
+                });
+
+        for texcoord_line in texcoord_lines {
+            obj.write_all(texcoord_line.as_bytes())?;
+        }
+    } else {
+        let texcoord_lines = entity
+            .mesh
+            .texcoords
+            .chunks(2)
+            .map(|t| format!("vt {} {}\n", Rounded(t[0], places), Rounded(t[1], places)));
+
+        for texcoord_line in texcoord_lines {
+            obj.write_all(texcoord_line.as_bytes())?;
+        }
+    }
+
+    let normal_lines = entity.mesh.normals.chunks(3).map(|n| {
+        format!(
+            "vn {} {} {}\n",
+            Rounded(n[0], places),
+            Rounded(n[1], places),
+            Rounded(n[2], places)
+        )
+    });
+
+    for normal_line in normal_lines {
+        obj.write_all(normal_line.as_bytes())?;
+    }
+
+    let face_sizes = extras.and_then(|e| e.face_sizes.as_ref());
+    write_faces(obj, &entity.mesh, face_sizes, options, idx_bases)?;
+    if let Some(extras) = extras {
+        write_lines_and_points(obj, &entity.mesh, extras, idx_bases)?;
+    }
+
+    idx_bases.advance(&entity.mesh);
+    Ok(())
+}
+
+/// Writes `f` statements for the faces of the given mesh.
+///
+/// If face sizes are given and match the triangle count, the triangles are
+/// interpreted as fans and merged back into polygons. Otherwise, every triangle
+/// is written as a separate face, unless `preserve_quads` is set and it forms
+/// a quad with the following triangle.
+fn write_faces<W: Write>(
+    obj: &mut W,
+    mesh: &DeinterleavedIndexedMeshBuf,
+    face_sizes: Option<&Vec<u32>>,
+    options: &SaveOptions,
+    idx_bases: &IndexBases,
+) -> Result<()> {
+    // Mesh may have been edited after loading, ignore outdated face sizes
+    let face_sizes = face_sizes.filter(|sizes| {
+        sizes.iter().all(|&s| s >= 3)
+            && sizes.iter().map(|&s| (s as usize - 2) * 3).sum::<usize>() == mesh.indices.len()
+    });
+
+    match face_sizes {
+        Some(face_sizes) => {
+            let mut triangles = mesh.indices.chunks(3);
+            let mut polygon = Vec::new();
+            for &face_size in face_sizes {
+                polygon.clear();
+                for fan_idx in 0..(face_size - 2) {
+                    // Cannot fail, triangle count was checked above
+                    let triangle = triangles.next().unwrap();
+                    if fan_idx == 0 {
+                        polygon.extend_from_slice(triangle);
+                    } else {
+                        polygon.push(triangle[2]);
+                    }
+                }
+                obj.write_all(face_line(&polygon, mesh, idx_bases).as_bytes())?;
+            }
+        }
+        None if options.preserve_quads => {
+            let mut triangles = mesh.indices.chunks(3).peekable();
+            while let Some(triangle) = triangles.next() {
+                let quad = triangles
+                    .peek()
+                    .and_then(|next| merge_quad(triangle, next, &mesh.positions));
+
+                match quad {
+                    Some(quad) => {
+                        // Skip the second triangle, it is part of the quad
+                        triangles.next();
+                        obj.write_all(face_line(&quad, mesh, idx_bases).as_bytes())?;
+                    }
+                    None => obj.write_all(face_line(triangle, mesh, idx_bases).as_bytes())?,
+                }
+            }
+        }
+        None => {
+            for triangle in mesh.indices.chunks(3) {
+                obj.write_all(face_line(triangle, mesh, idx_bases).as_bytes())?;
+            }
+        }
+    }
 
-            let relative_mtl_path = relative_mtl_path
-                .to_str()
-                .ok_or(AssetError::InvalidData(
-                    "Mtl path could not be converted to UTF-8 string.".to_string(),
-                ))?
-                .to_string();
+    Ok(())
+}
 
-            Some(relative_mtl_path)
+/// Writes `l` and `p` statements for the lines and points in the given extras.
+fn write_lines_and_points<W: Write>(
+    obj: &mut W,
+    mesh: &DeinterleavedIndexedMeshBuf,
+    extras: &EntityExtras,
+    idx_bases: &IndexBases,
+) -> Result<()> {
+    let vertex = |idx: u32| {
+        let idx = idx as usize;
+        if mesh.texcoords.is_empty() {
+            format!(" {}", idx_bases.position + idx)
         } else {
-            None
-        };
-
-        // Write header
-        obj.write("# aitios procedurally weathered OBJ file\n".as_bytes())?;
-        if let Some(ref mtl_lib) = mtl_lib {
-            obj.write("mtllib ".as_bytes())?;
-            obj.write(mtl_lib.as_bytes())?;
-            obj.write("\n".as_bytes())?;
+            format!(" {}/{}", idx_bases.position + idx, idx_bases.texcoord + idx)
         }
-        obj.write("\n".as_bytes())?;
-
-        let mut position_idx_base = 1_usize;
-        let mut texcoord_idx_base = 1_usize;
-        let mut normals_idx_base = 1_usize;
-
-        for entity in entities.into_iter() {
-            let entity = entity.borrow();
+    };
 
-            let material = if persisted_materials.contains(&*entity.material) {
-                // An exact same material with same maps can be shared,
-                // no need for duplication
-                (*entity.material).clone()
-            } else if persisted_materials
-                .iter()
-                .any(|m| m.name() == entity.material.name())
-            {
-                // On a collision, where the name is the same but the maps are different,
-                // make the name unique by appending the entity name
-                // If that is not enough for uniqueness, try adding a numeric suffix until
-                // the name is finally unique.
-                // e.g. iron => iron-bunny => iron-bunny-2 => iron-bunny-3
-                let unique_name_base = format!("{}-{}", entity.material.name(), entity.name);
-                let mut unique_name = unique_name_base.clone();
-                let mut suffix = 1;
-                while persisted_materials.iter().any(|m| m.name() == &unique_name) {
-                    suffix += 1; // start at two, since 1 is the one without suffix
-                    unique_name = format!("{}-{}", unique_name_base, suffix);
-                }
-                MaterialBuilder::from(&*entity.material)
-                    .name(unique_name)
-                    .build()
-            } else {
-                (*entity.material).clone()
-            };
-
-            obj.write("o ".as_bytes())?;
-            obj.write(entity.name.as_bytes())?;
-            obj.write("\n".as_bytes())?;
+    for line in extras.lines.iter() {
+        let mut statement = String::from("l");
+        for &idx in line {
+            statement.push_str(&vertex(idx));
+        }
+        statement.push('\n');
+        obj.write_all(statement.as_bytes())?;
+    }
 
-            let position_lines = entity
-                .mesh
-                .positions
-                .chunks(3)
-                .map(|p| format!("v {} {} {}\n", p[0], p[1], p[2]));
+    if !extras.points.is_empty() {
+        let mut statement = String::from("p");
+        for &idx in extras.points.iter() {
+            statement.push_str(&vertex(idx));
+        }
+        statement.push('\n');
+        obj.write_all(statement.as_bytes())?;
+    }
 
-            for position_line in position_lines {
-                obj.write(position_line.as_bytes())?;
-            }
+    Ok(())
+}
 
-            let texcoord_lines = entity
-                .mesh
-                .texcoords
-                .chunks(2)
-                .map(|t| format!("vt {} {}\n", t[0], t[1]));
+/// Checks if the given triangles have been obtained from splitting a quad into
+/// a fan, that is, they share the first vertex and an edge and lie in the
+/// same plane. If so, the quad is returned.
+fn merge_quad(first: &[u32], second: &[u32], positions: &[f32]) -> Option<[u32; 4]> {
+    // Allow for some rounding error when comparing the triangle normals
+    const MIN_NORMAL_COS: f32 = 0.9999;
 
-            for texcoord_line in texcoord_lines {
-                obj.write(texcoord_line.as_bytes())?;
-            }
+    let is_fan = first[0] == second[0] && first[2] == second[1] && !first.contains(&second[2]);
+    if !is_fan {
+        return None;
+    }
 
-            let normal_lines = entity
-                .mesh
-                .normals
-                .chunks(3)
-                .map(|n| format!("vn {} {} {}\n", n[0], n[1], n[2]));
+    let first_normal = triangle_normal(first, positions);
+    let second_normal = triangle_normal(second, positions);
+    let cos = first_normal[0] * second_normal[0]
+        + first_normal[1] * second_normal[1]
+        + first_normal[2] * second_normal[2];
 
-            for normal_line in normal_lines {
-                obj.write(normal_line.as_bytes())?;
-            }
+    if cos >= MIN_NORMAL_COS {
+        Some([first[0], first[1], first[2], second[2]])
+    } else {
+        None
+    }
+}
 
-            if mtl_lib.is_some() {
-                obj.write(format!("usemtl {}\n", material.name()).as_bytes())?;
-            }
+/// Calculates the normalized face normal of a triangle, or a zero vector if it
+/// is degenerate.
+fn triangle_normal(triangle: &[u32], positions: &[f32]) -> [f32; 3] {
+    let position = |idx: u32| {
+        let idx = idx as usize * 3;
+        [positions[idx], positions[idx + 1], positions[idx + 2]]
+    };
+    let normal = face_normal(
+        position(triangle[0]),
+        position(triangle[1]),
+        position(triangle[2]),
+    );
+    let len = length(normal);
 
-            {
-                let face_lines = entity.mesh.indices.chunks(3).map(|tri_indices| {
-                    assert!(entity.mesh.texcoords.len() > 0);
-                    match (
-                        !entity.mesh.positions.is_empty(),
-                        !entity.mesh.texcoords.is_empty(),
-                        !entity.mesh.normals.is_empty(),
-                    ) {
-                        (true, true, true) => format!(
-                            "f {}/{}/{} {}/{}/{} {}/{}/{}\n",
-                            position_idx_base + (tri_indices[0] as usize),
-                            texcoord_idx_base + (tri_indices[0] as usize),
-                            normals_idx_base + (tri_indices[0] as usize),
-                            position_idx_base + (tri_indices[1] as usize),
-                            texcoord_idx_base + (tri_indices[1] as usize),
-                            normals_idx_base + (tri_indices[1] as usize),
-                            position_idx_base + (tri_indices[2] as usize),
-                            texcoord_idx_base + (tri_indices[2] as usize),
-                            normals_idx_base + (tri_indices[2] as usize)
-                        ),
-                        (true, true, false) => format!(
-                            "f {}/{} {}/{} {}/{}\n",
-                            position_idx_base + (tri_indices[0] as usize),
-                            texcoord_idx_base + (tri_indices[0] as usize),
-                            position_idx_base + (tri_indices[1] as usize),
-                            texcoord_idx_base + (tri_indices[1] as usize),
-                            position_idx_base + (tri_indices[2] as usize),
-                            texcoord_idx_base + (tri_indices[2] as usize)
-                        ),
-                        (true, false, true) => format!(
-                            "f {}//{} {}//{} {}//{}\n",
-                            position_idx_base + (tri_indices[0] as usize),
-                            normals_idx_base + (tri_indices[0] as usize),
-                            position_idx_base + (tri_indices[1] as usize),
-                            normals_idx_base + (tri_indices[1] as usize),
-                            position_idx_base + (tri_indices[2] as usize),
-                            normals_idx_base + (tri_indices[2] as usize)
-                        ),
-                        (true, false, false) => format!(
-                            "f {} {} {}\n",
-                            position_idx_base + (tri_indices[0] as usize),
-                            position_idx_base + (tri_indices[1] as usize),
-                            position_idx_base + (tri_indices[2] as usize)
-                        ),
-                        (false, _, _) => {
-                            unimplemented!("OBJ cannot contain mesh that does not define positions")
-                        }
-                    }
-                });
+    if len == 0.0 {
+        [0.0, 0.0, 0.0]
+    } else {
+        [normal[0] / len, normal[1] / len, normal[2] / len]
+    }
+}
 
-                for face_line in face_lines {
-                    obj.write(face_line.as_bytes())?;
-                }
-            }
+fn face_line(face: &[u32], mesh: &DeinterleavedIndexedMeshBuf, idx_bases: &IndexBases) -> String {
+    if mesh.positions.is_empty() {
+        unimplemented!("OBJ cannot contain mesh that does not define positions")
+    }
 
-            obj.write("\n".as_bytes())?;
-
-            position_idx_base += entity.mesh.positions.len() / 3;
-            texcoord_idx_base += entity.mesh.texcoords.len() / 2;
-            normals_idx_base += entity.mesh.normals.len() / 3;
-
-            if let Some(ref mut mtl) = mtl_file {
-                if !persisted_materials.contains(&material) {
-                    let mtl_maps = material.maps();
-                    mtl.write(format!("\nnewmtl {}\n", material.name()).as_bytes())?;
-                    //mtl.write(format!("Ns {}\n", material.shininess).as_bytes())?;
-                    //mtl.write(format!("Ka {} {} {}\n", material.ambient[0], material.ambient[1], material.ambient[2]).as_bytes())?;
-                    //mtl.write(format!("Kd {} {} {}\n", material.diffuse[0], material.diffuse[1], material.diffuse[2]).as_bytes())?;
-                    //mtl.write(format!("Ks {} {} {}\n", material.specular[0], material.specular[1], material.specular[2]).as_bytes())?;
-                    //mtl.write("Ke 0.000000 0.000000 0.000000\n".as_bytes())?;
-                    //mtl.write("Ni 1.000000\n".as_bytes())?;
-                    //mtl.write("d 1.000000\n".as_bytes())?;
-                    mtl.write("illum 1\n".as_bytes())?;
-
-                    for (map_mtl_key, map_path) in mtl_maps.iter() {
-                        let map_path = canonicalize(map_path)?;
-                        let map_path = diff_paths(&map_path, &base)
-                            .expect(&format!("Path {:?} could not be expressed relative to OBJ parent directory {:?}", map_path, base));
-                        let map_path = map_path
-                            .to_str()
-                            .expect("Could not make UTF-8 string out of texture filename");
-                        let map_line =
-                            format!("{key} {value}\n", key = map_mtl_key, value = map_path);
-                        mtl.write(map_line.as_bytes())?;
-                    }
-                }
-            }
+    let mut line = String::from("f");
+    for &idx in face {
+        let idx = idx as usize;
+        let position = idx_bases.position + idx;
+        let texcoord = idx_bases.texcoord + idx;
+        let normal = idx_bases.normal + idx;
 
-            persisted_materials.push(material);
-        }
+        let vertex = match (!mesh.texcoords.is_empty(), !mesh.normals.is_empty()) {
+            (true, true) => format!(" {}/{}/{}", position, texcoord, normal),
+            (true, false) => format!(" {}/{}", position, texcoord),
+            (false, true) => format!(" {}//{}", position, normal),
+            (false, false) => format!(" {}", position),
+        };
+        line.push_str(&vertex);
     }
+    line.push('\n');
 
-    Ok(())
+    line
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use obj::load;
-    use std::fs::remove_file;
-    use std::rc::Rc;
+    use obj::{
+        load, load_entities, load_with, CollisionExport, LoadOptions, MaterialEquality,
+        MaterialNaming, MissingMaps, SaveOptions,
+    };
+    use std::fs::{self, read_to_string, File};
+    use tempdir::tempdir_scope;
+    use vfs::MemoryFileSystem;
 
     #[test]
     fn test_material_name_collision_resolution() {
-        let scene = load("tests/cube.obj").unwrap();
+        let scene = load_entities("tests/cube.obj").unwrap();
 
         let cube = &scene[0];
 
@@ -261,10 +1609,10 @@ mod test {
         let cube_roughness = Entity {
             material: Rc::new(
                 MaterialBuilder::from(&*cube.material)
-                // Using the OBJ file as pseudo image file, otherwise saving would fail since it
-                // cannot find the map and thus cannot build a relative path
-                .roughness_map("aitios-test-obj-export.obj")
-                .build(),
+                    // Using the OBJ file as pseudo image file, otherwise saving would fail since it
+                    // cannot find the map and thus cannot build a relative path
+                    .roughness_map("tests/cube.obj")
+                    .build(),
             ),
             ..cube.clone()
         };
@@ -273,22 +1621,25 @@ mod test {
         let cube_normal = Entity {
             material: Rc::new(
                 MaterialBuilder::from(&*cube.material)
-                    .normal_map("aitios-test-obj-export.obj")
+                    .normal_map("tests/cube.mtl")
                     .build(),
             ),
             ..cube.clone()
         };
 
-        let obj_path = "aitios-test-obj-export.obj";
-        let mtl_path = "aitios-test-obj-export.mtl";
+        let loaded = tempdir_scope(|dir| {
+            let obj_path = dir.join("aitios-test-obj-export.obj");
+            let mtl_path = dir.join("aitios-test-obj-export.mtl");
 
-        save(
-            vec![cube, &cube_clone, &cube_roughness, &cube_normal],
-            Some(obj_path),
-            Some(mtl_path),
-        ).unwrap();
+            save(
+                vec![cube, &cube_clone, &cube_roughness, &cube_normal],
+                Some(&obj_path),
+                Some(&mtl_path),
+            )?;
 
-        let loaded = load(obj_path).unwrap();
+            load_entities(&obj_path)
+        })
+        .unwrap();
 
         assert_eq!(
             2,
@@ -325,8 +1676,930 @@ mod test {
                 .count(),
             "Expecting two entities with material Material"
         );
+    }
+
+    #[test]
+    fn test_material_equality() {
+        let cube = load_entities("tests/cube.obj").unwrap().remove(0);
+        let map = |path: &str| {
+            let material = MaterialBuilder::from(&*cube.material)
+                .roughness_map(path)
+                .build();
+            Entity {
+                material: Rc::new(material),
+                ..cube.clone()
+            }
+        };
+        let renamed = |entity: &Entity, name: &str| Entity {
+            material: Rc::new(MaterialBuilder::from(&*entity.material).name(name).build()),
+            ..entity.clone()
+        };
+        let a = map("tests/cube.obj");
+        let b = map("tests/../tests/cube.obj");
+        let c = renamed(&a, "Other");
+        let entities = [&a, &b, &c];
+
+        let count = |equality: MaterialEquality| {
+            let options = SaveOptions::new().material_equality(equality);
+            collect_materials(entities.iter().cloned(), &options)
+                .materials
+                .len()
+        };
+        assert_eq!(3, count(MaterialEquality::Exact));
+        assert_eq!(2, count(MaterialEquality::CanonicalMaps));
+        assert_eq!(1, count(MaterialEquality::SameMaps));
+        let by_name = MaterialEquality::callback(|saved, material| saved.name() == material.name());
+        assert_eq!(2, count(by_name));
+
+        let diffuse = |diffuse| MaterialProperties {
+            diffuse: Some(diffuse),
+            ..MaterialProperties::default()
+        };
+        let properties = [
+            (a.material.name().to_string(), diffuse([0.5; 3])),
+            ("Other".to_string(), diffuse([0.5, 0.5, 0.5001])),
+        ];
+        let count_similar = |tolerance| {
+            let options = SaveOptions::new()
+                .material_equality(MaterialEquality::SimilarProperties(tolerance))
+                .material_properties(properties.iter().cloned().collect());
+            collect_materials(entities.iter().cloned(), &options)
+                .materials
+                .len()
+        };
+        assert_eq!(1, count_similar(0.001));
+        assert_eq!(2, count_similar(0.0));
+    }
+
+    #[test]
+    fn test_mtl_only() {
+        let files = MemoryFileSystem::new();
+        files.insert("scene/cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert("scene/cube.mtl", "newmtl Material\nmap_Kd wood.png\n");
+        files.insert("scene/wood.png", "");
+        files.insert("scene/iron.png", "");
+
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let cube = load_with("scene/cube.obj", &load_options).unwrap().entities[0].clone();
+        let iron = Entity {
+            material: Rc::new(
+                MaterialBuilder::from(&*cube.material)
+                    .name("Iron")
+                    .diffuse_color_map("scene/iron.png")
+                    .build(),
+            ),
+            ..cube.clone()
+        };
+        let entities = [&cube, &iron, &cube];
+
+        let options = SaveOptions::new().file_system(files.clone());
+        save_with(
+            entities.iter().cloned(),
+            None,
+            Some("out/materials.mtl"),
+            &options,
+        )
+        .unwrap();
+        assert!(files.read_to_string("out/materials.obj").is_none());
+        let mtl = files.read_to_string("out/materials.mtl").unwrap();
+        assert!(mtl.contains("newmtl Material\n"));
+        assert!(mtl.contains("map_Kd ../scene/wood.png\n"));
+        assert!(mtl.contains("newmtl Iron\n"));
+        assert!(mtl.contains("map_Kd ../scene/iron.png\n"));
+        assert_eq!(2, mtl.matches("newmtl").count());
+
+        let options = options.mtl_per_entity(true);
+        save_with(
+            entities.iter().cloned(),
+            None,
+            Some("out/materials.mtl"),
+            &options,
+        )
+        .unwrap();
+        let mtl = files.read_to_string("out/materials-Cube-2.mtl").unwrap();
+        assert!(mtl.contains("newmtl Iron\n"));
+        assert!(mtl.contains("map_Kd ../scene/iron.png\n"));
+    }
+
+    #[test]
+    fn test_save_to_writers() {
+        let files = MemoryFileSystem::new();
+        files.insert("scene/cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert("scene/cube.mtl", "newmtl Material\nmap_Kd wood.png\n");
+        files.insert("scene/wood.png", "");
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let entities = load_with("scene/cube.obj", &load_options).unwrap().entities;
+
+        let mut obj = Vec::new();
+        let mut mtl = Vec::new();
+        save_to(entities.iter(), &mut obj, Some(&mut mtl)).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+        assert!(!obj.contains("mtllib"));
+        assert!(obj.contains("usemtl Material\n"));
+        assert!(mtl.contains("/scene/wood.png\n"));
+
+        let options = SaveOptions::new()
+            .file_system(files.clone())
+            .mtl_file_name("materials.mtl")
+            .map_base("scene");
+        let mut obj = Vec::new();
+        let mut mtl = Vec::new();
+        save_to_with(entities.iter(), &mut obj, Some(&mut mtl), &options).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+        assert!(obj.contains("mtllib materials.mtl\n"));
+        assert!(mtl.contains("map_Kd wood.png\n"));
+
+        let mut obj = Vec::new();
+        save_to_with(entities.iter(), &mut obj, None::<Vec<u8>>, &options).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert!(!obj.contains("mtllib") && !obj.contains("usemtl"));
+        assert!(obj.contains("f 1/1/1 2/2/2 3/3/3\n"));
+
+        // Generated textures need a directory
+        let fill = SaveOptions::new()
+            .file_system(files.clone())
+            .require_map("norm")
+            .missing_maps(MissingMaps::Fill);
+        let mut mtl = Vec::new();
+        match save_to_with(entities.iter(), Vec::new(), Some(&mut mtl), &fill) {
+            Err(AssetError::Preflight { .. }) => (),
+            _ => panic!("Expected filling without map base to fail"),
+        }
+        assert!(mtl.is_empty());
+        let fill = fill.map_base("scene");
+        save_to_with(entities.iter(), Vec::new(), Some(&mut mtl), &fill).unwrap();
+        assert!(String::from_utf8(mtl)
+            .unwrap()
+            .contains("norm fill-norm-8080ffff.png\n"));
+        assert!(files.read("scene/fill-norm-8080ffff.png").is_some());
+    }
+
+    #[test]
+    fn test_exporter_policies() {
+        let files = MemoryFileSystem::new();
+        files.insert(
+            "tri.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.3333333 0\nvt 1 0\nvt 0 1\nvn 0 0 1\n\
+             f 1/1/1 2/2/1 3/3/1\n",
+        );
+        let load_options = LoadOptions::new().file_system(files);
+        let entities = load_with("tri.obj", &load_options).unwrap().entities;
+        let options = SaveOptions::new()
+            .precision(2)
+            .line_endings(LineEndings::CrLf)
+            .header_comments(false)
+            .normals(false);
+        let mut obj = Vec::new();
+        let mut mtl = Vec::new();
+        save_to_with(entities.iter(), &mut obj, Some(&mut mtl), &options).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+        assert!(!obj.starts_with('#') && !mtl.starts_with('#'));
+        assert_eq!(obj.matches('\n').count(), obj.matches("\r\n").count());
+        assert!(!obj.contains("vn "));
+        assert!(obj.contains("\r\nf 1/1 2/2 3/3\r\n"));
+        assert!(obj.contains("\r\nvt 0.33 0\r\n"));
+
+        let options = SaveOptions::new().texcoords(false);
+        let mut obj = Vec::new();
+        save_to_with(entities.iter(), &mut obj, None::<Vec<u8>>, &options).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert!(!obj.contains("vt "));
+        assert!(obj.contains("\nf 1//1 2//2 3//3\n"));
+    }
+
+    #[test]
+    fn test_stages_usable_separately() {
+        let scene = load_entities("tests/cube.obj").unwrap();
+        let cube = &scene[0];
+        let cube_roughness = Entity {
+            material: Rc::new(
+                MaterialBuilder::from(&*cube.material)
+                    .roughness_map("tests/cube.obj")
+                    .build(),
+            ),
+            ..cube.clone()
+        };
+        let entities = vec![cube, &cube_roughness, cube];
+        let options = SaveOptions::new().mtl_per_entity(true);
+
+        let collected = collect_materials(entities.iter().cloned(), &options);
+        let names: Vec<_> = collected.materials.iter().map(|m| m.name()).collect();
+        assert_eq!(vec!["Material", "Material-Cube"], names);
+        assert_eq!(vec![0, 1, 0], collected.assignments);
+
+        let plan = plan_outputs(
+            entities.iter().cloned(),
+            Some(PathBuf::from("out/scene.obj")),
+            Some(PathBuf::from("out/scene.mtl")),
+            &options,
+        );
+        assert_eq!(None, plan.mtl);
+        assert_eq!(
+            vec![
+                PathBuf::from("out/scene-Cube.mtl"),
+                PathBuf::from("out/scene-Cube-2.mtl"),
+                PathBuf::from("out/scene-Cube-3.mtl"),
+            ],
+            plan.entity_mtls
+        );
+        assert_eq!(4, plan.paths().count());
+
+        let mut obj = Vec::new();
+        let mut idx_bases = IndexBases::new();
+        write_geometry(&mut obj, cube, None, &mut idx_bases, &options).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let vertex_count = cube.mesh.positions.len() / 3;
+        assert_eq!(
+            vertex_count,
+            obj.lines().filter(|l| l.starts_with("v ")).count()
+        );
+        assert_eq!(1 + vertex_count, idx_bases.position);
+    }
+
+    #[test]
+    fn test_material_naming_strategies() {
+        let scene = load_entities("tests/cube.obj").unwrap();
+        let cube = &scene[0];
+        let variant = |map: &str| Entity {
+            material: Rc::new(
+                MaterialBuilder::from(&*cube.material)
+                    .roughness_map(map)
+                    .build(),
+            ),
+            ..cube.clone()
+        };
+        let entities = [cube.clone(), variant("a.png"), variant("b.png")];
+        let names = |naming| {
+            let options = SaveOptions::new().material_naming(naming);
+            collect_materials(entities.iter(), &options)
+                .materials
+                .iter()
+                .map(|m| m.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec!["Material", "Material-Cube", "Material-Cube-2"],
+            names(MaterialNaming::EntitySuffix)
+        );
+        assert_eq!(
+            vec!["Material", "Material-2", "Material-3"],
+            names(MaterialNaming::NumericSuffix)
+        );
+        let by_asset_id = MaterialNaming::callback(|material, _, attempt| {
+            format!("asset-17-{}-v{}", material.name(), attempt)
+        });
+        assert_eq!(
+            vec!["Material", "asset-17-Material-v1", "asset-17-Material-v2"],
+            names(by_asset_id)
+        );
+    }
+
+    #[test]
+    fn test_sort_by_material_minimizes_usemtl() {
+        let scene = load_entities("tests/cube.obj").unwrap();
+        let cube = &scene[0];
+
+        tempdir_scope(|dir| {
+            let obj_path = dir.join("sorted.obj");
+            // Using the OBJ file as pseudo image file again
+            File::create(&obj_path)?;
+            let cube_roughness = Entity {
+                name: "Rough".to_string(),
+                material: Rc::new(
+                    MaterialBuilder::from(&*cube.material)
+                        .roughness_map(&obj_path)
+                        .build(),
+                ),
+                ..cube.clone()
+            };
+
+            let options = SaveOptions::new().sort_by_material(true);
+            save_with(
+                vec![cube, &cube_roughness, cube],
+                Some(obj_path.clone()),
+                Some(dir.join("sorted.mtl")),
+                &options,
+            )?;
+
+            let obj = read_to_string(&obj_path)?;
+            let loaded = load_entities(obj_path)?;
+            assert_eq!(2, obj.lines().filter(|l| l.starts_with("usemtl")).count());
+            let names: Vec<_> = loaded.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(vec!["Cube", "Cube", "Rough"], names);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_quads_preserved_on_round_trip() {
+        let options = LoadOptions::new().preserve_polygons(true);
+        let scene = load_with("tests/cube.obj", &options).unwrap();
+
+        let loaded = tempdir_scope(|dir| {
+            let obj_path = dir.join("quads.obj");
+            save_scene(&scene, Some(obj_path.clone()), None)?;
+            load_with(obj_path, &options)
+        })
+        .unwrap();
+
+        assert_eq!(Some(vec![4; 6]), loaded.extras[0].face_sizes);
+        assert_eq!(
+            scene.entities[0].mesh.indices.len(),
+            loaded.entities[0].mesh.indices.len()
+        );
+    }
+
+    #[test]
+    fn test_quads_detected_from_triangle_pairs() {
+        let entities = load_entities("tests/cube.obj").unwrap();
+
+        let options = SaveOptions::new().preserve_quads(true);
+        let loaded = tempdir_scope(|dir| {
+            let obj_path = dir.join("quads.obj");
+            save_with(entities.iter(), Some(obj_path.clone()), None, &options)?;
+            load_with(obj_path, &LoadOptions::new().preserve_polygons(true))
+        })
+        .unwrap();
+
+        assert_eq!(Some(vec![4; 6]), loaded.extras[0].face_sizes);
+    }
+
+    #[test]
+    fn test_missing_maps_policy() {
+        let scene = load_entities("tests/cube.obj").unwrap();
+        let required = SaveOptions::new().require_map("norm");
+
+        let (failed, nothing_written, mtl, png) = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_required_maps.obj");
+            let mtl_path = dir.join("cube_required_maps.mtl");
+
+            let failed = save_with(scene.iter(), Some(&obj_path), Some(&mtl_path), &required);
+            let nothing_written = !obj_path.exists();
+
+            save_with(
+                scene.iter(),
+                Some(&obj_path),
+                Some(&mtl_path),
+                &required.missing_maps(MissingMaps::Fill),
+            )?;
+            let mtl = read_to_string(&mtl_path)?;
+            let png = fs::read(dir.join("fill-norm-8080ffff.png"))?;
+            Ok((failed, nothing_written, mtl, png))
+        })
+        .unwrap();
+
+        match failed {
+            Err(AssetError::Preflight { .. }) => (),
+            _ => panic!("Expected missing map to fail saving"),
+        }
+        assert!(nothing_written);
+        assert!(mtl.contains("norm fill-norm-8080ffff.png\n"));
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_fill_property_maps() {
+        let files = MemoryFileSystem::new();
+        files.insert("cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert("cube.mtl", "newmtl Material\nmap_Ns shininess.png\n");
+        files.insert("shininess.png", "");
+        let scene = load_with("cube.obj", &LoadOptions::new().file_system(files.clone())).unwrap();
+        let options = SaveOptions::new()
+            .file_system(files.clone())
+            .require_map("map_Ns")
+            .require_map("map_d")
+            .missing_maps(MissingMaps::Fill);
+
+        save_scene_with(&scene, Some("out/cube.obj"), Some("out/cube.mtl"), &options).unwrap();
+        let unsupported = save_scene_with(
+            &scene,
+            Some("out/cube.obj"),
+            Some("out/cube.mtl"),
+            &options.require_map("map_foo"),
+        );
+
+        let mtl = files.read_to_string("out/cube.mtl").unwrap();
+        assert!(mtl.contains("map_Ns ../shininess.png\n"));
+        assert!(mtl.contains("map_d fill-map_d-ffffffff.png\n"));
+        assert!(files.read("out/fill-map_d-ffffffff.png").is_some());
+        assert!(files.read("out/fill-map_Ns-000000ff.png").is_none());
+        match unsupported {
+            Err(AssetError::Preflight { .. }) => (),
+            _ => panic!("Expected unsupported map to fail saving"),
+        }
+    }
+
+    #[test]
+    fn test_texture_options_round_trip() {
+        let (scene, mtl) = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_map_options.obj");
+            let mtl_path = dir.join("cube_map_options.mtl");
+            let obj = read_to_string("tests/cube.obj")?;
+            fs::write(dir.join("cube.obj"), &obj)?;
+            fs::copy("tests/cube.mtl", dir.join("cube.mtl"))?;
+            fs::write(
+                &obj_path,
+                obj.replace("mtllib cube.mtl", "mtllib cube_map_options.mtl"),
+            )?;
+            fs::write(
+                &mtl_path,
+                "newmtl Material\nmap_bump -bm 0.3 -o 0.5 cube.obj\nmap_Kd cube.obj\n\
+                 map_d -clamp on cube.mtl\nmap_Ns cube.obj\n",
+            )?;
+
+            let scene = load(&obj_path)?;
+            let saved_mtl_path = dir.join("cube_map_options_saved.mtl");
+            save_scene(
+                &scene,
+                Some(dir.join("cube_map_options_saved.obj")),
+                Some(saved_mtl_path.clone()),
+            )?;
+            Ok((scene, read_to_string(saved_mtl_path)?))
+        })
+        .unwrap();
+
+        let dissolve_map = scene.material_properties["Material"]
+            .dissolve_map
+            .clone()
+            .unwrap();
+        assert!(dissolve_map.ends_with("cube.mtl"));
+        assert!(scene.textures.contains(&dissolve_map));
+        let usage = scene.material_usage();
+        assert!(usage[0].maps.iter().any(|key| key == "map_Ns"));
+        assert!(mtl.contains("bump -bm 0.3 -o 0.5 0 0 cube.obj\n"));
+        assert!(mtl.contains("map_Kd cube.obj\n"));
+        assert!(mtl.contains("map_d -clamp on cube.mtl\n"));
+        assert!(mtl.contains("map_Ns cube.obj\n"));
+        assert!(!mtl.contains("norm "));
+    }
+
+    #[test]
+    fn test_memory_file_system() {
+        let files = MemoryFileSystem::new();
+        files.insert("assets/cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert(
+            "assets/cube.mtl",
+            "newmtl Material\nmap_Kd textures/wood.png\n",
+        );
+        files.insert("assets/textures/wood.png", "");
+
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let scene = load_with("assets/cube.obj", &load_options).unwrap();
+        let options = SaveOptions::new()
+            .file_system(files.clone())
+            .require_map("norm")
+            .missing_maps(MissingMaps::Fill);
+        save_scene_with(
+            &scene,
+            Some("export/cube.obj"),
+            Some("export/cube.mtl"),
+            &options,
+        )
+        .unwrap();
+
+        let mtl = files.read_to_string("export/cube.mtl").unwrap();
+        assert!(mtl.contains("map_Kd ../assets/textures/wood.png\n"));
+        assert!(mtl.contains("norm fill-norm-8080ffff.png\n"));
+        let png = files.read("export/fill-norm-8080ffff.png").unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        let reloaded = load_with("export/cube.obj", &load_options).unwrap();
+        assert_eq!(scene.stats.triangle_count, reloaded.stats.triangle_count);
+        assert!(!Path::new("export").exists());
+    }
+
+    #[test]
+    fn test_constant_maps() {
+        let files = MemoryFileSystem::new();
+        files.insert("cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert("cube.mtl", "newmtl Material\nKd 0.64 0.64 0.64\nPr 0.5\n");
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let scene = load_with("cube.obj", &load_options).unwrap();
+
+        let options = SaveOptions::new()
+            .file_system(files.clone())
+            .constant_maps(2);
+        save_scene_with(
+            &scene,
+            Some("export/cube.obj"),
+            Some("export/cube.mtl"),
+            &options,
+        )
+        .unwrap();
+
+        let mtl = files.read_to_string("export/cube.mtl").unwrap();
+        assert!(mtl.contains("map_Kd map_Kd-a3a3a3-2.png\n"));
+        assert!(mtl.contains("map_Pr map_Pr-808080-2.png\n"));
+        let png = files.read("export/map_Kd-a3a3a3-2.png").unwrap();
+        // Width and height in the header
+        assert_eq!(&[0, 0, 0, 2, 0, 0, 0, 2], &png[16..24]);
+        assert!(scene.entities[0].material.maps().is_empty());
+    }
+
+    #[test]
+    fn test_vertex_colors_round_trip() {
+        let files = MemoryFileSystem::new();
+        files.insert(
+            "colors.obj",
+            "v 0 0 0 1 0 0\nv 1 0 0\nv 1 1 0 0 0.5 1\no Colored\nf 1 2 3\n\
+             v 0 0 1\nv 1 0 1\nv 1 1 1\no Plain\nf 4 5 6\n",
+        );
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let scene = load_with("colors.obj", &load_options).unwrap();
+        let colors = vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.5, 1.0];
+        assert_eq!(Some(&colors), scene.extras[0].colors.as_ref());
+        assert_eq!(None, scene.extras[1].colors);
+
+        let options = SaveOptions::new().file_system(files.clone());
+        save_scene_with(&scene, Some("saved.obj"), None, &options).unwrap();
+        let obj = files.read_to_string("saved.obj").unwrap();
+        assert!(obj.contains("v 1 0 0 1 1 1\n"));
+        assert!(obj.contains("v 1 1 0 0 0.5 1\n"));
+        assert!(obj.contains("v 0 0 1\n"));
+
+        let reloaded = load_with("saved.obj", &load_options).unwrap();
+        assert_eq!(Some(&colors), reloaded.extras[0].colors.as_ref());
+        assert_eq!(None, reloaded.extras[1].colors);
+    }
+
+    #[test]
+    fn test_reflection_maps_round_trip() {
+        let files = MemoryFileSystem::new();
+        files.insert("assets/cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert(
+            "assets/cube.mtl",
+            "newmtl Material\nrefl -type cube_top -clamp on sky_top.png\n\
+             refl -type cube_bottom sky_bottom.png\n",
+        );
+        files.insert("assets/sky_top.png", "");
+        files.insert("assets/sky_bottom.png", "");
+
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let scene = load_with("assets/cube.obj", &load_options).unwrap();
+        let reflections = &scene.material_properties["Material"].reflection_maps;
+        assert_eq!(2, reflections.len());
+        assert_eq!(Some("cube_top"), reflections[0].options.map_type.as_deref());
+        assert_eq!(Some(true), reflections[0].options.clamp);
+        assert!(reflections[1].path.ends_with("sky_bottom.png"));
+        assert_eq!(2, scene.textures.len());
+        let usage = scene.material_usage();
+        assert_eq!(vec!["refl", "refl"], usage[0].maps);
+
+        let options = SaveOptions::new().file_system(files.clone());
+        save_scene_with(&scene, Some("cube.obj"), Some("cube.mtl"), &options).unwrap();
+        let mtl = files.read_to_string("cube.mtl").unwrap();
+        assert!(mtl.contains(
+            "refl -clamp on -type cube_top assets/sky_top.png\n\
+             refl -type cube_bottom assets/sky_bottom.png\n"
+        ));
+    }
+
+    #[test]
+    fn test_occlusion_map_spellings() {
+        let files = MemoryFileSystem::new();
+        files.insert("cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert("cube_ao.png", "");
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let save_options = SaveOptions::new().file_system(files.clone());
+
+        for &statement in ["map_AO cube_ao.png", "occlusion -imfchan r cube_ao.png"].iter() {
+            files.insert("cube.mtl", format!("newmtl Material\n{}\n", statement));
+            let scene = load_with("cube.obj", &load_options).unwrap();
+            let occlusion_map = scene.material_properties["Material"]
+                .occlusion_map
+                .clone()
+                .unwrap();
+            assert_eq!(vec![occlusion_map], scene.textures);
+
+            save_scene_with(&scene, Some("out.obj"), Some("out.mtl"), &save_options).unwrap();
+            let mtl = files.read_to_string("out.mtl").unwrap();
+            let saved = statement.replace(statement.split(' ').next().unwrap(), "map_ao");
+            assert!(mtl.contains(&format!("{}\n", saved)));
+        }
+    }
+
+    #[test]
+    fn test_pbr_factors_round_trip() {
+        let files = MemoryFileSystem::new();
+        files.insert("cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert(
+            "cube.mtl",
+            "newmtl Material\nPc 0.8\nPcr 0.05\nmap_pc coat.png\nPcr_map -clamp on coat_r.png\n\
+             aniso 0.6\nanisor 0.25\n",
+        );
+        files.insert("coat.png", "");
+        files.insert("coat_r.png", "");
+
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let scene = load_with("cube.obj", &load_options).unwrap();
+        let properties = &scene.material_properties["Material"];
+        assert_eq!(Some(0.8), properties.clearcoat);
+        assert_eq!(Some(0.05), properties.clearcoat_roughness);
+        assert_eq!(Some(0.6), properties.anisotropy);
+        assert_eq!(Some(0.25), properties.anisotropy_rotation);
+        assert!(properties.clearcoat_map.is_some());
+        assert_eq!(vec!["map_Pc", "map_Pcr"], scene.material_usage()[0].maps);
+
+        let save_options = SaveOptions::new().file_system(files.clone());
+        save_scene_with(&scene, Some("out.obj"), Some("out.mtl"), &save_options).unwrap();
+        let mtl = files.read_to_string("out.mtl").unwrap();
+        assert!(mtl.contains("Pc 0.8\nPcr 0.05\naniso 0.6\nanisor 0.25\n"));
+        assert!(mtl.contains("map_Pc coat.png\nmap_Pcr -clamp on coat_r.png\n"));
+    }
+
+    #[test]
+    fn test_number_notations() {
+        let files = MemoryFileSystem::new();
+        files.insert(
+            "tri.obj",
+            "mtllib tri.mtl\nv 1e-3 -0 2,5\nv 1E+1 0 0\nv 0 1 0\nvt 5e-1 0\nvn 0 -0.0 1\n\
+             usemtl Material\nf 1/1/1 2/1/1 3/1/1\n",
+        );
+        files.insert(
+            "tri.mtl",
+            "newmtl Material\nPc 1e-3\nmap_Kd -bm 2.5E-1 -o 1,5 wood.png\n",
+        );
+        files.insert("wood.png", "");
+
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let scene = load_with("tri.obj", &load_options).unwrap();
+        assert_eq!(Some(0.001), scene.material_properties["Material"].clearcoat);
+
+        let save_options = SaveOptions::new().file_system(files.clone());
+        save_scene_with(&scene, Some("out.obj"), Some("out.mtl"), &save_options).unwrap();
+        let obj = files.read_to_string("out.obj").unwrap();
+        assert!(obj.contains("v 0.001 0 2.5\nv 10 0 0\n"));
+        assert!(obj.contains("vt 0.5 0\n"));
+        assert!(obj.contains("vn 0 0 1\n"));
+        let mtl = files.read_to_string("out.mtl").unwrap();
+        assert!(mtl.contains("Pc 0.001\n"));
+        assert!(mtl.contains("map_Kd -bm 0.25 -o 1.5 0 0 wood.png\n"));
+    }
+
+    #[test]
+    fn test_color_space_round_trip() {
+        let files = MemoryFileSystem::new();
+        files.insert("cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert(
+            "cube.mtl",
+            "newmtl Material\nmap_Kd wood.png\nmap_Ks wood_spec_Linear.png\n\
+             map_Pr -colorspace srgb wood_rough.png\nmap_Ke wood_glow.png\nnorm wood_n.png\n",
+        );
+        for texture in [
+            "wood",
+            "wood_spec_Linear",
+            "wood_rough",
+            "wood_glow",
+            "wood_n",
+        ]
+        .iter()
+        {
+            files.insert(format!("{}.png", texture), "");
+        }
+
+        let load_options = LoadOptions::new()
+            .file_system(files.clone())
+            .map_color_space("map_Ke", ColorSpace::Linear);
+        let scene = load_with("cube.obj", &load_options).unwrap();
+        let properties = &scene.material_properties["Material"];
+        let color_space = |key| properties.color_space(key);
+        assert_eq!(ColorSpace::Srgb, color_space("map_Kd"));
+        assert_eq!(ColorSpace::Linear, color_space("map_Ks"));
+        assert_eq!(ColorSpace::Srgb, color_space("map_Pr"));
+        assert_eq!(ColorSpace::Linear, color_space("map_Ke"));
+        assert_eq!(ColorSpace::Linear, color_space("norm"));
+
+        let save_options = SaveOptions::new().file_system(files.clone());
+        save_scene_with(&scene, Some("out.obj"), Some("out.mtl"), &save_options).unwrap();
+        let mtl = files.read_to_string("out.mtl").unwrap();
+        assert!(mtl.contains("map_Kd wood.png\n"));
+        assert!(mtl.contains("map_Ks -colorspace linear wood_spec_Linear.png\n"));
+        assert!(mtl.contains("map_Pr -colorspace srgb wood_rough.png\n"));
+        assert!(mtl.contains("map_Ke -colorspace linear wood_glow.png\n"));
+        assert!(mtl.contains("norm wood_n.png\n"));
+
+        let reloaded = load_with("out.obj", &load_options).unwrap();
+        assert_eq!(
+            properties.color_spaces,
+            reloaded.material_properties["Material"].color_spaces
+        );
+    }
+
+    #[test]
+    fn test_material_properties_round_trip() {
+        let files = MemoryFileSystem::new();
+        files.insert("cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert("cube.mtl", fs::read("tests/cube.mtl").unwrap());
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let scene = load_with("cube.obj", &load_options).unwrap();
+
+        let save_options = SaveOptions::new().file_system(files.clone());
+        save_scene_with(&scene, Some("out.obj"), Some("out.mtl"), &save_options).unwrap();
+        let mtl = files.read_to_string("out.mtl").unwrap();
+        assert!(mtl.contains("Kd 0.64 0.64 0.64\n"));
+        assert!(mtl.contains("illum 2\n"));
+        assert!(mtl.contains("Ns 96.07843\n"));
+        let reloaded = load_with("out.obj", &load_options).unwrap();
+        assert_eq!(
+            scene.material_properties["Material"],
+            reloaded.material_properties["Material"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_params_round_trip() {
+        let files = MemoryFileSystem::new();
+        files.insert("cube.obj", fs::read("tests/cube.obj").unwrap());
+        files.insert(
+            "cube.mtl",
+            "newmtl Material\nKd 1 1 1\nvray_fresnel 1.6 # comment\nmap_Kd cube.obj\nxTwoSided\n",
+        );
+
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let scene = load_with("cube.obj", &load_options).unwrap();
+        let unknown = &scene.material_properties["Material"].unknown_params;
+        assert_eq!(2, unknown.len());
+        assert_eq!("1.6", unknown["vray_fresnel"]);
+
+        let save_options = SaveOptions::new().file_system(files.clone());
+        save_scene_with(&scene, Some("out.obj"), Some("out.mtl"), &save_options).unwrap();
+        let mtl = files.read_to_string("out.mtl").unwrap();
+        assert!(mtl.contains("vray_fresnel 1.6\nxTwoSided\n"));
+        assert!(mtl.contains("Kd 1 1 1\n"));
+        let reloaded = load_with("out.obj", &load_options).unwrap();
+        assert_eq!(
+            unknown,
+            &reloaded.material_properties["Material"].unknown_params
+        );
+    }
+
+    #[test]
+    fn test_file_names_with_spaces() {
+        let files = MemoryFileSystem::new();
+        let obj = fs::read_to_string("tests/cube.obj")
+            .unwrap()
+            .replace("mtllib cube.mtl", "mtllib extra.mtl my cube.mtl");
+        files.insert("my assets/cube.obj", obj);
+        files.insert("my assets/extra.mtl", "newmtl Extra\n");
+        files.insert(
+            "my assets/my cube.mtl",
+            "newmtl Material\nmap_Kd -s 2 my  wood.png\nmap_Ks \"my wood.png\"\n",
+        );
+        files.insert("my assets/my  wood.png", "");
+        files.insert("my assets/my wood.png", "");
+
+        let load_options = LoadOptions::new().file_system(files.clone());
+        let scene = load_with("my assets/cube.obj", &load_options).unwrap();
+        assert!(scene.material("Extra").is_some());
+        assert_eq!(2, scene.textures.len());
+
+        let save_options = SaveOptions::new().file_system(files.clone());
+        save_scene_with(
+            &scene,
+            Some("my export/cube.obj"),
+            Some("my export/my cube.mtl"),
+            &save_options,
+        )
+        .unwrap();
+        let obj = files.read_to_string("my export/cube.obj").unwrap();
+        assert!(obj.contains("mtllib \"my cube.mtl\"\n"));
+        let mtl = files.read_to_string("my export/my cube.mtl").unwrap();
+        assert!(mtl.contains("map_Kd -s 2 1 1 \"../my assets/my  wood.png\"\n"));
+        assert!(mtl.contains("map_Ks \"../my assets/my wood.png\"\n"));
+
+        let reloaded = load_with("my export/cube.obj", &load_options).unwrap();
+        assert_eq!(scene.textures, reloaded.textures);
+    }
+
+    #[test]
+    fn test_lod_naming() {
+        let cube = load_entities("tests/cube.obj").unwrap().remove(0);
+        let named = |name: &str| Entity {
+            name: name.to_string(),
+            ..cube.clone()
+        };
+        let options = SaveOptions::new().lod_naming(true);
+
+        let entities = vec![named("chair-lod1"), named("chair.LOD0"), named("Cube")];
+        let (obj, reloaded) = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_lods.obj");
+            save_with(entities.iter(), Some(&obj_path), None, &options)?;
+            Ok((read_to_string(&obj_path)?, load(&obj_path)?))
+        })
+        .unwrap();
 
-        remove_file(obj_path).expect("Could not remove obj file created for test");
-        remove_file(mtl_path).expect("Could not remove obj file created for test");
+        assert!(obj.contains("o chair_LOD1\n"));
+        assert!(obj.contains("o chair_LOD0\n"));
+        assert!(obj.contains("o Cube\n"));
+        let groups = reloaded.lod_groups();
+        assert_eq!(vec![1, 0], groups["chair"]);
+
+        let duplicates = vec![named("chair_LOD1"), named("chair-lod1")];
+        let (failed, nothing_written) = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_lods.obj");
+            let failed = save_with(duplicates.iter(), Some(&obj_path), None, &options);
+            Ok((failed, !obj_path.exists()))
+        })
+        .unwrap();
+        match failed {
+            Err(AssetError::Preflight { .. }) => (),
+            _ => panic!("Expected duplicate LOD level to fail saving"),
+        }
+        assert!(nothing_written);
+    }
+
+    #[test]
+    fn test_collision_export() {
+        let cube = load_entities("tests/cube.obj").unwrap().remove(0);
+        let named = |name: &str| Entity {
+            name: name.to_string(),
+            ..cube.clone()
+        };
+        let entities = [
+            named("chair-lod0"),
+            named("UBX_chair-lod0"),
+            named("chair-lod0_collision"),
+        ];
+        let unreal = SaveOptions::new()
+            .lod_naming(true)
+            .collision_export(CollisionExport::Unreal);
+        let obj = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_collision_export.obj");
+            save_with(entities.iter(), Some(&obj_path), None, &unreal)?;
+            Ok(read_to_string(&obj_path)?)
+        })
+        .unwrap();
+        assert!(obj.contains("o chair_LOD0\n"));
+        assert!(obj.contains("o UBX_chair_LOD0_01\n"));
+        assert!(obj.contains("o UCX_chair_LOD0_02\n"));
+
+        let separate = SaveOptions::new().collision_export(CollisionExport::Separate);
+        let (render, collision) = tempdir_scope(|dir| {
+            let obj_path = dir.join("cube_collision_export.obj");
+            save_with(entities.iter(), Some(&obj_path), None, &separate)?;
+            Ok((
+                load(&obj_path)?,
+                load(dir.join("cube_collision_export_collision.obj"))?,
+            ))
+        })
+        .unwrap();
+        assert_eq!(1, render.entities.len());
+        assert_eq!(2, collision.entities.len());
+        for extras in collision.extras.iter() {
+            assert_eq!(Some("chair-lod0".to_string()), extras.collision_for);
+        }
+    }
+
+    #[test]
+    fn test_instance_transform_baked() {
+        let mut scene = load("tests/cube.obj").unwrap();
+        let mut mirrored = scene.extras[0].clone();
+        mirrored.instance_transform = Some([
+            [-1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [10.0, 0.0, 0.0, 1.0],
+        ]);
+        let entity = scene.entities[0].clone();
+        scene.entities.push(entity);
+        scene.extras.push(mirrored);
+
+        let reloaded = tempdir_scope(|dir| {
+            let obj_path = dir.join("instances.obj");
+            save_scene(&scene, Some(obj_path.clone()), None)?;
+            load(obj_path)
+        })
+        .unwrap();
+        let (original, placed) = (&scene.entities[0].mesh, &reloaded.entities[1].mesh);
+        let triangles = |mesh: &DeinterleavedIndexedMeshBuf, mirror: bool| {
+            let mut triangles: Vec<Vec<[i32; 3]>> = mesh
+                .indices
+                .chunks(3)
+                .map(|triangle| {
+                    let mut corners: Vec<[i32; 3]> = triangle
+                        .iter()
+                        .map(|&idx| {
+                            let p = &mesh.positions[idx as usize * 3..idx as usize * 3 + 3];
+                            let x = if mirror { 10.0 - p[0] } else { p[0] };
+                            [x as i32, p[1] as i32, p[2] as i32]
+                        })
+                        .collect();
+                    if mirror {
+                        corners.swap(1, 2);
+                    }
+                    // Same triangle regardless of the first corner
+                    let first = (0..3).min_by_key(|&i| corners[i]).unwrap();
+                    corners.rotate_left(first);
+                    corners
+                })
+                .collect();
+            triangles.sort();
+            triangles
+        };
+        assert_eq!(triangles(original, true), triangles(placed, false));
     }
 }
@@ -0,0 +1,131 @@
+//! Checks performed before an export writes anything, so that exports fail
+//! early instead of leaving some of the files written and others not.
+
+use err::{AssetError, Result};
+use fs2::available_space;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the probe files created by this process, to keep their names
+/// unique when checking the same directory concurrently.
+static PROBES: AtomicUsize = AtomicUsize::new(0);
+
+/// Checks that each of the given files can be created or overwritten, and
+/// that each directory has enough space left for the estimated sizes of the
+/// files in it.
+pub fn check_outputs<'a, I>(outputs: I) -> Result<()>
+where
+    I: IntoIterator<Item = (&'a Path, u64)>,
+{
+    let mut dir_sizes = BTreeMap::new();
+
+    for (path, estimated_size) in outputs {
+        if let Ok(metadata) = fs::metadata(path) {
+            if metadata.is_dir() {
+                return Err(preflight_error(path, "Output path is a directory"));
+            }
+            if metadata.permissions().readonly() {
+                return Err(preflight_error(path, "Output file is write-protected"));
+            }
+        }
+
+        *dir_sizes.entry(parent_dir(path)).or_insert(0) += estimated_size;
+    }
+
+    for (dir, required) in dir_sizes {
+        check_writable_dir(&dir)?;
+
+        // Not all platforms and file systems can report free space, only
+        // fail if they can and it is insufficient
+        if let Ok(available) = available_space(&dir) {
+            if available < required {
+                return Err(preflight_error(
+                    &dir,
+                    format!(
+                        "Export needs about {} bytes, but only {} bytes are available",
+                        required, available
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Permissions do not tell the full story, e.g. with ACLs or read-only mounts,
+/// so actually try to create a file in the directory.
+fn check_writable_dir(dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(preflight_error(dir, "Output directory does not exist"));
+    }
+
+    loop {
+        let number = PROBES.fetch_add(1, Ordering::SeqCst);
+        let probe = dir.join(format!(".aitios-preflight-{}-{}", process::id(), number));
+        match OpenOptions::new().write(true).create_new(true).open(&probe) {
+            Ok(_) => {
+                fs::remove_file(&probe).ok();
+                return Ok(());
+            }
+            // Left over from an earlier process with the same ID
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(preflight_error(
+                    dir,
+                    format!("Output directory is not writable: {}", err),
+                ))
+            }
+        }
+    }
+}
+
+fn preflight_error<S: Into<String>>(path: &Path, reason: S) -> AssetError {
+    AssetError::Preflight {
+        path: path.to_path_buf(),
+        reason: reason.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::tempdir_scope;
+
+    #[test]
+    fn test_missing_directory_fails() {
+        tempdir_scope(|dir| {
+            let path = dir.join("does-not-exist/out.obj");
+            match check_outputs(vec![(path.as_path(), 0)]) {
+                Err(AssetError::Preflight { path, .. }) => {
+                    assert_eq!(dir.join("does-not-exist"), path)
+                }
+                _ => panic!("Expected preflight error"),
+            }
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_insufficient_space_fails() {
+        tempdir_scope(|dir| {
+            let path = dir.join("out.obj");
+            assert!(check_outputs(vec![(path.as_path(), 0)]).is_ok());
+            assert!(check_outputs(vec![(path.as_path(), u64::max_value())]).is_err());
+            Ok(())
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+//! Per-entity statistics of loaded scenes for quality assurance.
+//!
+//! ```no_run
+//! # extern crate aitios_asset;
+//! use aitios_asset::obj;
+//! use aitios_asset::report::{self, ReportFormat};
+//! use std::fs::File;
+//!
+//! # fn main() {
+//! let scene = obj::load("chairs.obj").unwrap();
+//! let csv = File::create("chairs.csv").unwrap();
+//! report::write_stats(&scene, ReportFormat::Csv, csv).unwrap();
+//! # }
+//! ```
+
+use err::{AssetError, Result};
+use loaded::LoadedScene;
+use scene::{DeinterleavedIndexedMeshBuf, Entity};
+use serde_json;
+use std::io::Write;
+use validate::validate_mesh;
+use vector::{face_normal, length};
+
+/// The file format written by `write_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Comma-separated values with a header row and one row per entity.
+    Csv,
+    /// An array with an object per entity.
+    Json,
+}
+
+/// Statistics of a single entity.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntityReport {
+    pub entity: String,
+    pub material: String,
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    /// Sum of the areas of all triangles.
+    pub surface_area: f32,
+    /// Sum of the areas of all triangles in texture space.
+    pub uv_area: f32,
+    /// Texture coordinate units per unit of length on the surface, or zero
+    /// if the entity has no area.
+    pub uv_density: f32,
+    /// Problems found when validating the geometry, with the number of
+    /// occurrences, e.g. `triangle without area (3)`.
+    pub problems: Vec<String>,
+}
+
+/// Computes statistics for each entity of the scene, in the same order as
+/// the entities.
+pub fn entity_reports(scene: &LoadedScene) -> Vec<EntityReport> {
+    scene.entities.iter().map(entity_report).collect()
+}
+
+/// Writes statistics of each entity of the scene in the given format.
+pub fn write_stats<W: Write>(
+    scene: &LoadedScene,
+    format: ReportFormat,
+    mut writer: W,
+) -> Result<()> {
+    let reports = entity_reports(scene);
+    match format {
+        ReportFormat::Csv => write_csv(&reports, &mut writer),
+        ReportFormat::Json => serde_json::to_writer_pretty(&mut writer, &reports)
+            .map_err(|err| AssetError::Save(err.into())),
+    }
+}
+
+fn entity_report(entity: &Entity) -> EntityReport {
+    let mesh = &*entity.mesh;
+    let (surface_area, uv_area) = areas(mesh);
+    let uv_density = if surface_area > 0.0 {
+        (uv_area / surface_area).sqrt()
+    } else {
+        0.0
+    };
+
+    EntityReport {
+        entity: entity.name.clone(),
+        material: entity.material.name().to_string(),
+        vertex_count: mesh.positions.len() / 3,
+        triangle_count: mesh.indices.len() / 3,
+        surface_area,
+        uv_area,
+        uv_density,
+        problems: validate_mesh(mesh)
+            .iter()
+            .map(|finding| format!("{} ({})", finding.problem, finding.count))
+            .collect(),
+    }
+}
+
+/// Sums the areas of the triangles in space and in texture space, skipping
+/// triangles with indices out of range.
+fn areas(mesh: &DeinterleavedIndexedMeshBuf) -> (f32, f32) {
+    let vertex_count = mesh.positions.len() / 3;
+    let has_texcoords = mesh.texcoords.len() == vertex_count * 2;
+    let mut surface_area = 0.0;
+    let mut uv_area = 0.0;
+
+    for triangle in mesh.indices.chunks(3) {
+        if triangle.len() < 3 || triangle.iter().any(|&i| i as usize >= vertex_count) {
+            continue;
+        }
+        let position = |i: usize| {
+            let idx = triangle[i] as usize * 3;
+            [
+                mesh.positions[idx],
+                mesh.positions[idx + 1],
+                mesh.positions[idx + 2],
+            ]
+        };
+        surface_area += 0.5 * length(face_normal(position(0), position(1), position(2)));
+
+        if has_texcoords {
+            let texcoords = |i: usize| {
+                let idx = triangle[i] as usize * 2;
+                [mesh.texcoords[idx], mesh.texcoords[idx + 1]]
+            };
+            let (a, b, c) = (texcoords(0), texcoords(1), texcoords(2));
+            uv_area += 0.5 * ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])).abs();
+        }
+    }
+
+    (surface_area, uv_area)
+}
+
+fn write_csv<W: Write>(reports: &[EntityReport], writer: &mut W) -> Result<()> {
+    writeln!(
+        writer,
+        "entity,material,vertex_count,triangle_count,surface_area,uv_area,uv_density,problems"
+    )?;
+    for report in reports {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{}",
+            csv_field(&report.entity),
+            csv_field(&report.material),
+            report.vertex_count,
+            report.triangle_count,
+            report.surface_area,
+            report.uv_area,
+            report.uv_density,
+            csv_field(&report.problems.join("; "))
+        )?;
+    }
+    Ok(())
+}
+
+/// Quotes the field if it contains separators, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use obj;
+
+    #[test]
+    fn test_write_stats() {
+        let mut scene = obj::load("tests/cube.obj").unwrap();
+        scene.entities[0].name = "Cube, \"large\"".to_string();
+
+        let mut csv = Vec::new();
+        write_stats(&scene, ReportFormat::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(2, rows.len());
+        assert!(rows[1].starts_with("\"Cube, \"\"large\"\"\",Material,"));
+        assert!(rows[1].contains(",Material,24,12,"));
+
+        let mut json = Vec::new();
+        write_stats(&scene, ReportFormat::Json, &mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(12, json[0]["triangle_count"]);
+        let area = json[0]["surface_area"].as_f64().unwrap();
+        assert!((area - 24.0).abs() < 1e-3);
+        assert_eq!(0.0, json[0]["uv_density"]);
+    }
+}
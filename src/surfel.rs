@@ -0,0 +1,267 @@
+//! Blue-noise samples on the surface of meshes.
+
+use scene::DeinterleavedIndexedMeshBuf;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use vector::{face_normal, length};
+
+/// A point on the surface of a mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surfel {
+    pub position: [f32; 3],
+    /// Normal interpolated from the vertex normals, or the normal of the
+    /// triangle if the mesh has no normals.
+    pub normal: [f32; 3],
+    /// Texture coordinates interpolated from the vertex texture coordinates,
+    /// or zero if the mesh has none.
+    pub texcoords: [f32; 2],
+    /// Index of the triangle the surfel lies on.
+    pub face: u32,
+}
+
+/// Number of random candidates per expected surfel, of which only those far
+/// enough from earlier surfels are kept.
+const CANDIDATES_PER_SURFEL: f32 = 8.0;
+
+/// Upper bound for the number of candidates, so that huge meshes or
+/// densities do not sample forever.
+const MAX_CANDIDATES: f32 = 16_777_216.0;
+
+/// Samples the surface of the given mesh with roughly `density` surfels per
+/// unit of area, with no two surfels closer than a minimum distance derived
+/// from the density.
+///
+/// Sampling is deterministic, the same mesh and density always yield the
+/// same surfels.
+pub fn sample(mesh: &DeinterleavedIndexedMeshBuf, density: f32) -> Vec<Surfel> {
+    let positions = &mesh.positions;
+    let triangles: Vec<&[u32]> = mesh.indices.chunks(3).collect();
+    let corner = |triangle: &[u32], i: usize| {
+        let idx = triangle[i] as usize * 3;
+        [positions[idx], positions[idx + 1], positions[idx + 2]]
+    };
+
+    // Cumulative areas, to pick triangles proportional to their area
+    let mut total_area = 0.0;
+    let cumulative_areas: Vec<f32> = triangles
+        .iter()
+        .map(|triangle| {
+            let normal = face_normal(
+                corner(triangle, 0),
+                corner(triangle, 1),
+                corner(triangle, 2),
+            );
+            total_area += 0.5 * length(normal);
+            total_area
+        })
+        .collect();
+
+    // Also false for NaN, areas overflow for coordinates near the limits of f32
+    let has_surface = density > 0.0 && total_area > 0.0 && total_area.is_finite();
+    if !has_surface {
+        return Vec::new();
+    }
+
+    let min_distance = min_distance(density);
+    let candidate_count = (total_area * density * CANDIDATES_PER_SURFEL)
+        .ceil()
+        .min(MAX_CANDIDATES) as usize;
+    let mut grid = Grid::new(min_distance);
+    let mut rng = XorShift::new(mesh.indices.len() as u64 ^ density.to_bits() as u64);
+    let mut surfels = Vec::new();
+
+    for _ in 0..candidate_count {
+        let picked = rng.next_f32() * total_area;
+        let face = match cumulative_areas
+            .binary_search_by(|area| area.partial_cmp(&picked).unwrap_or(Ordering::Less))
+        {
+            Ok(face) | Err(face) => face.min(triangles.len() - 1),
+        };
+        let triangle = triangles[face];
+
+        // Uniformly distributed barycentric coordinates
+        let (mut u, mut v) = (rng.next_f32(), rng.next_f32());
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+        let weights = [1.0 - u - v, u, v];
+
+        let position = interpolate3(positions, triangle, weights);
+        if !grid.is_free(position) {
+            continue;
+        }
+        grid.insert(position);
+
+        let normal = if mesh.normals.len() == positions.len() {
+            normalize(interpolate3(&mesh.normals, triangle, weights))
+        } else {
+            normalize(face_normal(
+                corner(triangle, 0),
+                corner(triangle, 1),
+                corner(triangle, 2),
+            ))
+        };
+        let texcoords = if mesh.texcoords.len() / 2 == positions.len() / 3 {
+            let mut texcoords = [0.0; 2];
+            for (&vertex, weight) in triangle.iter().zip(weights.iter()) {
+                texcoords[0] += mesh.texcoords[vertex as usize * 2] * weight;
+                texcoords[1] += mesh.texcoords[vertex as usize * 2 + 1] * weight;
+            }
+            texcoords
+        } else {
+            [0.0; 2]
+        };
+
+        surfels.push(Surfel {
+            position,
+            normal,
+            texcoords,
+            face: face as u32,
+        });
+    }
+
+    surfels
+}
+
+/// Distance between surfels so that randomly packing them yields about the
+/// given number of surfels per unit of area.
+fn min_distance(density: f32) -> f32 {
+    0.75 / density.sqrt()
+}
+
+fn interpolate3(attribute: &[f32], triangle: &[u32], weights: [f32; 3]) -> [f32; 3] {
+    let mut value = [0.0; 3];
+    for (&vertex, weight) in triangle.iter().zip(weights.iter()) {
+        let idx = vertex as usize * 3;
+        value[0] += attribute[idx] * weight;
+        value[1] += attribute[idx + 1] * weight;
+        value[2] += attribute[idx + 2] * weight;
+    }
+    value
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = length(v);
+    if len > 0.0 {
+        [v[0] / len, v[1] / len, v[2] / len]
+    } else {
+        v
+    }
+}
+
+/// Uniform grid of accepted surfels with cells as large as the minimum
+/// distance, so only neighboring cells need to be checked.
+struct Grid {
+    cell_size: f32,
+    cells: HashMap<[i32; 3], Vec<[f32; 3]>>,
+}
+
+impl Grid {
+    fn new(cell_size: f32) -> Grid {
+        Grid {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, position: [f32; 3]) -> [i32; 3] {
+        [
+            (position[0] / self.cell_size).floor() as i32,
+            (position[1] / self.cell_size).floor() as i32,
+            (position[2] / self.cell_size).floor() as i32,
+        ]
+    }
+
+    fn is_free(&self, position: [f32; 3]) -> bool {
+        let cell = self.cell(position);
+        let min_distance_sq = self.cell_size * self.cell_size;
+        for x in -1..2 {
+            for y in -1..2 {
+                for z in -1..2 {
+                    let neighbor = [cell[0] + x, cell[1] + y, cell[2] + z];
+                    let points = match self.cells.get(&neighbor) {
+                        Some(points) => points,
+                        None => continue,
+                    };
+                    let occupied = points.iter().any(|p| {
+                        let d = [p[0] - position[0], p[1] - position[1], p[2] - position[2]];
+                        d[0] * d[0] + d[1] * d[1] + d[2] * d[2] < min_distance_sq
+                    });
+                    if occupied {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn insert(&mut self, position: [f32; 3]) {
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push(position);
+    }
+}
+
+/// Small pseudo-random number generator, so that sampling is reproducible.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> XorShift {
+        // The state must never be zero
+        XorShift(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    /// Uniformly distributed number in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_density_and_spacing() {
+        // Unit square in the xy plane, made of two triangles
+        let mesh = DeinterleavedIndexedMeshBuf {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+            normals: Vec::new(),
+            texcoords: Vec::new(),
+            indices: vec![0, 1, 2, 0, 2, 3],
+        };
+        let density = 400.0;
+        let surfels = sample(&mesh, density);
+
+        assert_eq!(surfels, sample(&mesh, density));
+        assert!(surfels.len() > 300 && surfels.len() < 500);
+        let min_distance = min_distance(density);
+        for (i, a) in surfels.iter().enumerate() {
+            assert_eq!([0.0, 0.0, 1.0], a.normal);
+            for b in surfels[i + 1..].iter() {
+                let d = length([
+                    a.position[0] - b.position[0],
+                    a.position[1] - b.position[1],
+                    a.position[2] - b.position[2],
+                ]);
+                assert!(d >= min_distance);
+            }
+        }
+        assert!(sample(&mesh, 0.0).is_empty());
+    }
+
+    #[test]
+    fn test_overflowing_area() {
+        let mesh = DeinterleavedIndexedMeshBuf {
+            positions: vec![-1e38, -1e38, 0.0, 1e38, -1e38, 0.0, 1e38, 1e38, 0.0],
+            normals: Vec::new(),
+            texcoords: Vec::new(),
+            indices: vec![0, 1, 2],
+        };
+        assert!(sample(&mesh, 1.0).is_empty());
+    }
+}
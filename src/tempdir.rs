@@ -0,0 +1,127 @@
+//! Temporary directories for tests and intermediate exports, removed with
+//! everything in them when no longer needed.
+
+use err::Result;
+use std::env;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the directories created by this process, to keep their names
+/// unique when created concurrently.
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// A uniquely named directory in the temporary directory of the system,
+/// removed along with its contents when dropped. On Unix, only the current
+/// user may access it.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a new empty directory with a name starting with
+    /// `aitios-asset-`, followed by the process ID and a number.
+    pub fn new() -> Result<TempDir> {
+        let parent = env::temp_dir();
+        let mut builder = fs::DirBuilder::new();
+        // The temporary directory is usually shared with other users
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        loop {
+            let number = CREATED.fetch_add(1, Ordering::SeqCst);
+            let path = parent.join(format!("aitios-asset-{}-{}", process::id(), number));
+            match builder.create(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                // Left over from an earlier process with the same ID
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keeps the directory instead of removing it when dropped, e.g. for
+    /// extracted files that are still needed afterwards, and returns its path.
+    pub fn into_path(mut self) -> PathBuf {
+        let path = mem::replace(&mut self.path, PathBuf::new());
+        mem::forget(self);
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.path) {
+            warn!(
+                "Temporary directory {:?} could not be removed: {}",
+                self.path, err
+            );
+        }
+    }
+}
+
+/// Creates a temporary directory, calls the given function with its path
+/// and removes the directory again, also if the function fails or panics.
+///
+/// ```
+/// # extern crate aitios_asset;
+/// use aitios_asset::{obj, tempdir_scope};
+///
+/// # fn main() {
+/// let scene = obj::load("tests/cube.obj").unwrap();
+/// tempdir_scope(|dir| {
+///     let obj_path = dir.join("cube.obj");
+///     obj::save_scene(&scene, Some(obj_path.clone()), Some(dir.join("cube.mtl")))?;
+///     obj::load(obj_path)
+/// })
+/// .unwrap();
+/// # }
+/// ```
+pub fn tempdir_scope<F, T>(scope: F) -> Result<T>
+where
+    F: FnOnce(&Path) -> Result<T>,
+{
+    let dir = TempDir::new()?;
+    scope(dir.path())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tempdir_scope() {
+        let (first, second) = tempdir_scope(|first| {
+            fs::write(first.join("file"), "content")?;
+            let second = tempdir_scope(|second| Ok(second.to_path_buf()))?;
+            assert!(first.join("file").exists());
+            Ok((first.to_path_buf(), second))
+        })
+        .unwrap();
+
+        assert_ne!(first, second);
+        assert!(!first.exists());
+        assert!(!second.exists());
+    }
+
+    #[test]
+    fn test_into_path_keeps_directory() {
+        let dir = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        assert_ne!(dir.path(), other.path());
+
+        let kept = dir.into_path();
+        assert!(kept.is_dir());
+        fs::remove_dir(&kept).unwrap();
+    }
+}
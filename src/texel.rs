@@ -0,0 +1,55 @@
+//! Textures of a single color, used in place of missing maps.
+
+use err::{AssetError, Result};
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::io::Write;
+use std::path::Path;
+use vfs::FileSystem;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Writes a square PNG with the given size in texels, all of the given RGBA
+/// color, to the given file system.
+pub fn write_png(
+    file_system: &dyn FileSystem,
+    path: &Path,
+    rgba: [u8; 4],
+    size: u32,
+) -> Result<()> {
+    // Eight bits per channel, RGBA, no interlacing
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&size.to_be_bytes());
+    header.extend_from_slice(&size.to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Each row starts with the filter type, zero for none
+    let mut row = vec![0];
+    for _ in 0..size {
+        row.extend_from_slice(&rgba);
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for _ in 0..size {
+        encoder.write_all(&row).map_err(AssetError::Save)?;
+    }
+    let data = encoder.finish().map_err(AssetError::Save)?;
+
+    let mut png = file_system.create(path).map_err(AssetError::Save)?;
+    png.write_all(&PNG_SIGNATURE)?;
+    write_chunk(&mut png, b"IHDR", &header)?;
+    write_chunk(&mut png, b"IDAT", &data)?;
+    write_chunk(&mut png, b"IEND", &[])?;
+    Ok(())
+}
+
+fn write_chunk<W: Write>(png: &mut W, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+
+    png.write_all(&(data.len() as u32).to_be_bytes())?;
+    png.write_all(kind)?;
+    png.write_all(data)?;
+    png.write_all(&crc.sum().to_be_bytes())?;
+    Ok(())
+}
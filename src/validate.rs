@@ -0,0 +1,162 @@
+//! Checks for geometry that would cause trouble in later processing.
+
+use scene::DeinterleavedIndexedMeshBuf;
+use std::fmt;
+use vector::face_normal;
+
+/// Normals whose length differs from one by more than this are reported.
+const NORMAL_LENGTH_TOLERANCE: f32 = 1e-3;
+
+/// A kind of problem with the geometry of a mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GeometryProblem {
+    /// A vertex position component is NaN or infinite.
+    NonFinitePosition,
+    /// A normal component is NaN or infinite.
+    NonFiniteNormal,
+    /// A texture coordinate is NaN or infinite.
+    NonFiniteTexcoords,
+    /// A normal does not have a length of one.
+    UnnormalizedNormal,
+    /// An index refers to a vertex that does not exist, or the number of
+    /// indices is not a multiple of three.
+    IndexOutOfRange,
+    /// A triangle has no area.
+    ZeroAreaTriangle,
+}
+
+impl fmt::Display for GeometryProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match *self {
+            GeometryProblem::NonFinitePosition => "position that is NaN or infinite",
+            GeometryProblem::NonFiniteNormal => "normal that is NaN or infinite",
+            GeometryProblem::NonFiniteTexcoords => "texture coordinates that are NaN or infinite",
+            GeometryProblem::UnnormalizedNormal => "normal without unit length",
+            GeometryProblem::IndexOutOfRange => "index out of range",
+            GeometryProblem::ZeroAreaTriangle => "triangle without area",
+        };
+        f.write_str(description)
+    }
+}
+
+/// All occurrences of one kind of problem in a mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Finding {
+    pub problem: GeometryProblem,
+    /// Number of vertices, indices or triangles with the problem.
+    pub count: usize,
+    /// Index of the first vertex, index or triangle with the problem.
+    pub first: usize,
+}
+
+/// Checks the given mesh for non-finite attributes, out-of-range indices,
+/// triangles without area and normals without unit length.
+///
+/// Returns one finding for each kind of problem that occurs, in the order of
+/// `GeometryProblem`.
+pub fn validate_mesh(mesh: &DeinterleavedIndexedMeshBuf) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+    let mut report = |problem, at| match findings.iter_mut().find(|f| f.problem == problem) {
+        Some(finding) => finding.count += 1,
+        None => findings.push(Finding {
+            problem,
+            count: 1,
+            first: at,
+        }),
+    };
+
+    let all_finite = |values: &[f32]| values.iter().all(|v| v.is_finite());
+    for (vertex, position) in mesh.positions.chunks(3).enumerate() {
+        if !all_finite(position) {
+            report(GeometryProblem::NonFinitePosition, vertex);
+        }
+    }
+    for (vertex, normal) in mesh.normals.chunks(3).enumerate() {
+        if !all_finite(normal) {
+            report(GeometryProblem::NonFiniteNormal, vertex);
+        } else {
+            let length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+            if (length - 1.0).abs() > NORMAL_LENGTH_TOLERANCE {
+                report(GeometryProblem::UnnormalizedNormal, vertex);
+            }
+        }
+    }
+    for (vertex, texcoords) in mesh.texcoords.chunks(2).enumerate() {
+        if !all_finite(texcoords) {
+            report(GeometryProblem::NonFiniteTexcoords, vertex);
+        }
+    }
+
+    let vertex_count = mesh.positions.len() / 3;
+    for (idx, &index) in mesh.indices.iter().enumerate() {
+        if index as usize >= vertex_count {
+            report(GeometryProblem::IndexOutOfRange, idx);
+        }
+    }
+    if mesh.indices.chunks(3).any(|triangle| triangle.len() < 3) {
+        report(GeometryProblem::IndexOutOfRange, mesh.indices.len());
+    }
+
+    for (face, triangle) in mesh.indices.chunks(3).enumerate() {
+        if triangle.len() < 3 || triangle.iter().any(|&i| i as usize >= vertex_count) {
+            continue;
+        }
+        let corner = |i: usize| {
+            let idx = triangle[i] as usize * 3;
+            [
+                mesh.positions[idx],
+                mesh.positions[idx + 1],
+                mesh.positions[idx + 2],
+            ]
+        };
+        if face_normal(corner(0), corner(1), corner(2)) == [0.0; 3] {
+            report(GeometryProblem::ZeroAreaTriangle, face);
+        }
+    }
+
+    findings.sort_by_key(|f| f.problem);
+    findings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32;
+
+    #[test]
+    fn test_findings() {
+        let nan = f32::NAN;
+        let mesh = DeinterleavedIndexedMeshBuf {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, nan, 0.0, 0.0],
+            normals: vec![0.0, 0.0, 1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0],
+            texcoords: Vec::new(),
+            indices: vec![0, 1, 2, 0, 1, 1, 0, 2, 4],
+        };
+
+        assert_eq!(
+            vec![
+                Finding {
+                    problem: GeometryProblem::NonFinitePosition,
+                    count: 1,
+                    first: 3,
+                },
+                Finding {
+                    problem: GeometryProblem::UnnormalizedNormal,
+                    count: 1,
+                    first: 1,
+                },
+                Finding {
+                    problem: GeometryProblem::IndexOutOfRange,
+                    count: 1,
+                    first: 8,
+                },
+                Finding {
+                    problem: GeometryProblem::ZeroAreaTriangle,
+                    count: 1,
+                    first: 1,
+                },
+            ],
+            validate_mesh(&mesh)
+        );
+    }
+}
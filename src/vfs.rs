@@ -0,0 +1,315 @@
+//! File systems that OBJ and MTL files are loaded from and saved to.
+//!
+//! By default, files are read from and written to disk. Setting a
+//! `MemoryFileSystem` with `LoadOptions::file_system` or
+//! `SaveOptions::file_system` instead keeps all files in memory, so that
+//! complete load and save flows can be tested without touching the disk.
+//!
+//! ```
+//! # extern crate aitios_asset;
+//! use aitios_asset::obj::{self, LoadOptions, SaveOptions};
+//! use aitios_asset::vfs::MemoryFileSystem;
+//!
+//! # fn main() {
+//! let files = MemoryFileSystem::new();
+//! files.insert("scene/tri.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n");
+//!
+//! let load_options = LoadOptions::new().file_system(files.clone());
+//! let scene = obj::load_with("scene/tri.obj", &load_options).unwrap();
+//!
+//! let save_options = SaveOptions::new().file_system(files.clone());
+//! obj::save_scene_with(&scene, Some("out/tri.obj"), None, &save_options).unwrap();
+//! let saved = obj::load_with("out/tri.obj", &load_options).unwrap();
+//! assert_eq!(1, saved.stats.triangle_count);
+//! # }
+//! ```
+
+use err::Result;
+use preflight;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+/// Access to the files that are loaded and saved.
+pub trait FileSystem: fmt::Debug {
+    /// Opens the file at the given path for reading.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read>>;
+
+    /// Creates the file at the given path for writing, replacing it if it
+    /// already exists.
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write>>;
+
+    /// Creates the directory at the given path along with its parents.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Checks if there is a file or directory at the given path.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Checks if there is a file at the given path.
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// Gets the absolute path of the existing file or directory at the given
+    /// path, with all `.` and `..` components resolved.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Lists the paths of the files and directories in the directory at the
+    /// given path.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Checks before an export writes anything that the given files can be
+    /// written, along with their estimated sizes in bytes. By default,
+    /// nothing is checked.
+    fn check_outputs(&self, outputs: &[(&Path, u64)]) -> Result<()> {
+        let _ = outputs;
+        Ok(())
+    }
+}
+
+/// The file system of the operating system, which is used unless another
+/// file system is set in the options.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiskFileSystem;
+
+impl FileSystem for DiskFileSystem {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+        Ok(Box::new(File::create(path)?))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        // Broken symbolic links count as existing
+        fs::symlink_metadata(path).is_ok()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    /// Checks that the files can be created and that there is enough space
+    /// left for them.
+    fn check_outputs(&self, outputs: &[(&Path, u64)]) -> Result<()> {
+        preflight::check_outputs(outputs.iter().cloned())
+    }
+}
+
+/// A file system that only exists in memory, starting out empty.
+///
+/// Clones share the same files, so a clone can be set in the options while
+/// the original is used to prepare inputs and inspect outputs. Relative paths
+/// are relative to the root directory and directories exist as long as there
+/// are files in them.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFileSystem {
+    files: Rc<RefCell<BTreeMap<PathBuf, Vec<u8>>>>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> MemoryFileSystem {
+        MemoryFileSystem::default()
+    }
+
+    /// Adds a file with the given contents, replacing the file if it already
+    /// exists.
+    pub fn insert<P, C>(&self, path: P, contents: C)
+    where
+        P: AsRef<Path>,
+        C: Into<Vec<u8>>,
+    {
+        self.files
+            .borrow_mut()
+            .insert(absolute(path.as_ref()), contents.into());
+    }
+
+    /// Gets the contents of the file at the given path, if it exists.
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Option<Vec<u8>> {
+        self.files.borrow().get(&absolute(path.as_ref())).cloned()
+    }
+
+    /// Gets the contents of the file at the given path, if it exists and is
+    /// valid UTF-8.
+    pub fn read_to_string<P: AsRef<Path>>(&self, path: P) -> Option<String> {
+        self.read(path)
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+    }
+
+    /// Gets the absolute paths of all files, in lexicographic order.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.borrow().keys().cloned().collect()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        let path = absolute(path);
+        self.files
+            .borrow()
+            .keys()
+            .any(|file| file != &path && file.starts_with(&path))
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        match self.read(path) {
+            Some(contents) => Ok(Box::new(Cursor::new(contents))),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+        let path = absolute(path);
+        if self.is_dir(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is a directory", path),
+            ));
+        }
+        self.files.borrow_mut().insert(path.clone(), Vec::new());
+        Ok(Box::new(MemoryFile {
+            files: self.files.clone(),
+            path,
+        }))
+    }
+
+    fn create_dir_all(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.is_file(path) || self.is_dir(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.borrow().contains_key(&absolute(path))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        if self.exists(path) {
+            Ok(absolute(path))
+        } else {
+            Err(not_found(path))
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let dir = absolute(path);
+        if !self.is_dir(&dir) {
+            return Err(not_found(path));
+        }
+
+        let mut entries: Vec<PathBuf> = self
+            .files
+            .borrow()
+            .keys()
+            .filter_map(|file| file.strip_prefix(&dir).ok())
+            .filter_map(|rest| rest.components().next())
+            .map(|entry| dir.join(entry))
+            .collect();
+        entries.dedup();
+        Ok(entries)
+    }
+}
+
+/// A file being written to a `MemoryFileSystem`.
+struct MemoryFile {
+    files: Rc<RefCell<BTreeMap<PathBuf, Vec<u8>>>>,
+    path: PathBuf,
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.files
+            .borrow_mut()
+            .entry(self.path.clone())
+            .or_default()
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Resolves the given path against the root directory, removing `.` and
+/// `..` components.
+fn absolute(path: &Path) -> PathBuf {
+    let mut absolute = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => absolute = PathBuf::from("/"),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            Component::Normal(name) => absolute.push(name),
+        }
+    }
+    absolute
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{:?} not found", path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_memory_file_system() {
+        let files = MemoryFileSystem::new();
+        files.insert("scene/cube.obj", "v 0 0 0\n");
+        {
+            let mut mtl = files.create(Path::new("/scene/./cube.mtl")).unwrap();
+            mtl.write_all(b"newmtl ").unwrap();
+            mtl.write_all(b"Material\n").unwrap();
+        }
+
+        assert_eq!(
+            Some("newmtl Material\n".to_string()),
+            files.read_to_string("scene/cube.mtl")
+        );
+        assert!(files.is_file(Path::new("scene/../scene/cube.obj")));
+        assert!(files.exists(Path::new("scene")));
+        assert!(!files.is_file(Path::new("scene")));
+        assert_eq!(
+            PathBuf::from("/scene/cube.obj"),
+            files.canonicalize(Path::new("scene/cube.obj")).unwrap()
+        );
+        assert!(files.canonicalize(Path::new("scene/cube.png")).is_err());
+        assert_eq!(
+            vec![PathBuf::from("/scene")],
+            files.read_dir(Path::new(".")).unwrap()
+        );
+        assert_eq!(2, files.read_dir(Path::new("scene")).unwrap().len());
+        assert!(files.create(Path::new("scene")).is_err());
+
+        let mut contents = String::new();
+        files
+            .open(Path::new("scene/cube.obj"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!("v 0 0 0\n", contents);
+    }
+}
@@ -0,0 +1,167 @@
+//! Merging of vertices with the same attributes.
+
+use loaded::EntityExtras;
+use scene::DeinterleavedIndexedMeshBuf;
+use std::collections::HashMap;
+
+/// Merges vertices of the mesh whose positions, normals, texture coordinates
+/// and per-vertex extras all differ by at most `epsilon` in each component,
+/// keeping the first of them, and returns the number of removed vertices.
+///
+/// Indices of triangles, lines and points are rewritten to the kept vertices.
+pub fn weld(
+    mesh: &mut DeinterleavedIndexedMeshBuf,
+    extras: &mut EntityExtras,
+    epsilon: f32,
+) -> usize {
+    let vertex_count = mesh.positions.len() / 3;
+    let epsilon = if epsilon > 0.0 { epsilon } else { 0.0 };
+
+    let mut attributes: Vec<(&[f32], usize)> = vec![(&mesh.positions, 3)];
+    if mesh.normals.len() == vertex_count * 3 {
+        attributes.push((&mesh.normals, 3));
+    }
+    if mesh.texcoords.len() == vertex_count * 2 {
+        attributes.push((&mesh.texcoords, 2));
+    }
+    if let Some(ref colors) = extras.colors {
+        attributes.push((colors, 3));
+    }
+    if let Some(ref ws) = extras.texcoord_ws {
+        attributes.push((ws, 1));
+    }
+    let same = |a: usize, b: usize| {
+        attributes.iter().all(|&(values, size)| {
+            let (a, b) = (
+                &values[a * size..(a + 1) * size],
+                &values[b * size..(b + 1) * size],
+            );
+            a.iter().zip(b).all(|(a, b)| (a - b).abs() <= epsilon)
+        })
+    };
+
+    // Kept vertices by grid cell of their position, cells are as large as
+    // epsilon so only neighbouring cells need to be searched
+    let cell_of = |vertex: usize| {
+        let position = &mesh.positions[vertex * 3..vertex * 3 + 3];
+        let mut cell = [0i64; 3];
+        for (cell, &coordinate) in cell.iter_mut().zip(position) {
+            *cell = if epsilon > 0.0 {
+                (coordinate / epsilon).floor() as i64
+            } else {
+                // Adding zero turns negative zero into positive zero
+                i64::from((coordinate + 0.0).to_bits())
+            };
+        }
+        cell
+    };
+    let reach = if epsilon > 0.0 { 1 } else { 0 };
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    let mut kept = Vec::new();
+    let mut remap = Vec::with_capacity(vertex_count);
+
+    for vertex in 0..vertex_count {
+        let cell = cell_of(vertex);
+        let mut existing = None;
+        'search: for x in -reach..reach + 1 {
+            for y in -reach..reach + 1 {
+                for z in -reach..reach + 1 {
+                    let neighbor = [cell[0] + x, cell[1] + y, cell[2] + z];
+                    let candidates = cells.get(&neighbor).into_iter().flatten();
+                    for &candidate in candidates {
+                        if same(kept[candidate], vertex) {
+                            existing = Some(candidate);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+
+        let new_index = match existing {
+            Some(new_index) => new_index,
+            None => {
+                kept.push(vertex);
+                cells.entry(cell).or_default().push(kept.len() - 1);
+                kept.len() - 1
+            }
+        };
+        remap.push(new_index as u32);
+    }
+
+    let removed = vertex_count - kept.len();
+    if removed == 0 {
+        return 0;
+    }
+
+    compact(&mut mesh.positions, 3, &kept);
+    if mesh.normals.len() == vertex_count * 3 {
+        compact(&mut mesh.normals, 3, &kept);
+    }
+    if mesh.texcoords.len() == vertex_count * 2 {
+        compact(&mut mesh.texcoords, 2, &kept);
+    }
+    if let Some(ref mut colors) = extras.colors {
+        compact(colors, 3, &kept);
+    }
+    if let Some(ref mut ws) = extras.texcoord_ws {
+        compact(ws, 1, &kept);
+    }
+    if let Some(ref mut precise) = extras.precise_positions {
+        compact(precise, 3, &kept);
+    }
+
+    let indices = mesh
+        .indices
+        .iter_mut()
+        .chain(extras.lines.iter_mut().flat_map(|line| line.iter_mut()))
+        .chain(extras.points.iter_mut());
+    for index in indices {
+        *index = remap[*index as usize];
+    }
+
+    removed
+}
+
+/// Keeps only the values of the given vertices, in the given order.
+fn compact<T: Clone>(values: &mut Vec<T>, size: usize, kept: &[usize]) {
+    let compacted = kept
+        .iter()
+        .flat_map(|&vertex| values[vertex * size..(vertex + 1) * size].iter().cloned())
+        .collect();
+    *values = compacted;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_weld() {
+        // Two triangles sharing an edge, with the shared vertices repeated
+        // and one of them slightly off
+        let mut mesh = DeinterleavedIndexedMeshBuf {
+            positions: vec![
+                0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0,
+                1.0001, 0.0,
+            ],
+            normals: [0.0, 0.0, 1.0].repeat(6),
+            texcoords: vec![0.0; 12],
+            indices: vec![0, 1, 2, 3, 4, 5],
+        };
+        let mut extras = EntityExtras {
+            points: vec![5],
+            ..EntityExtras::default()
+        };
+
+        let mut exact = mesh.clone();
+        assert_eq!(1, weld(&mut exact, &mut extras.clone(), 0.0));
+        assert_eq!(vec![0, 1, 2, 1, 3, 4], exact.indices);
+
+        assert_eq!(2, weld(&mut mesh, &mut extras, 1e-3));
+        assert_eq!(vec![0, 1, 2, 1, 3, 2], mesh.indices);
+        assert_eq!(12, mesh.positions.len());
+        assert_eq!(8, mesh.texcoords.len());
+        assert_eq!(vec![2], extras.points);
+    }
+}
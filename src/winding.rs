@@ -0,0 +1,289 @@
+//! Detection and correction of the winding order of faces.
+
+use adjacency::position_ids;
+use coords;
+use scene::DeinterleavedIndexedMeshBuf;
+use std::collections::HashMap;
+use vector::{self, cross};
+
+/// Reverses all faces of the mesh and negates its normals, turning the mesh
+/// inside out.
+pub fn flip(mesh: &mut DeinterleavedIndexedMeshBuf, face_sizes: Option<&Vec<u32>>) {
+    coords::reverse_winding(&mut mesh.indices, face_sizes);
+    for normal in mesh.normals.iter_mut() {
+        *normal = -*normal;
+    }
+}
+
+/// Counts the triangles that `orient` would reverse.
+pub fn misoriented_triangles(
+    mesh: &DeinterleavedIndexedMeshBuf,
+    face_sizes: Option<&Vec<u32>>,
+) -> usize {
+    let faces = faces(mesh, face_sizes);
+    orientation_fixes(mesh, &faces)
+        .iter()
+        .zip(faces.iter())
+        .filter(|&(&reverse, _)| reverse)
+        .map(|(_, &(_, count))| count)
+        .sum()
+}
+
+/// Reverses faces so each connected part of the mesh is wound consistently,
+/// facing outwards if the part is closed or else in the direction of the
+/// vertex normals, and returns the number of reversed triangles.
+///
+/// Normals pointing away from the faces around their vertex afterwards are
+/// negated. Polygons with preserved face sizes are reversed as a whole.
+pub fn orient(mesh: &mut DeinterleavedIndexedMeshBuf, face_sizes: Option<&Vec<u32>>) -> usize {
+    let faces = faces(mesh, face_sizes);
+    let fixes = orientation_fixes(mesh, &faces);
+
+    let mut reversed = 0;
+    for (&(start, count), _) in faces.iter().zip(fixes.iter()).filter(|&(_, &fix)| fix) {
+        let polygon = vec![count as u32 + 2];
+        coords::reverse_winding(
+            &mut mesh.indices[start * 3..(start + count) * 3],
+            Some(&polygon),
+        );
+        reversed += count;
+    }
+
+    fix_normals(mesh);
+    reversed
+}
+
+/// First triangle and number of triangles of each face, either polygons as
+/// preserved on load, or single triangles.
+fn faces(mesh: &DeinterleavedIndexedMeshBuf, face_sizes: Option<&Vec<u32>>) -> Vec<(usize, usize)> {
+    let triangle_count = mesh.indices.len() / 3;
+    let face_sizes = face_sizes.filter(|sizes| {
+        sizes.iter().all(|&s| s >= 3)
+            && sizes.iter().map(|&s| s as usize - 2).sum::<usize>() == triangle_count
+    });
+    match face_sizes {
+        Some(face_sizes) => {
+            let mut start = 0;
+            face_sizes
+                .iter()
+                .map(|&size| {
+                    let face = (start, size as usize - 2);
+                    start += face.1;
+                    face
+                })
+                .collect()
+        }
+        None => (0..triangle_count).map(|triangle| (triangle, 1)).collect(),
+    }
+}
+
+/// Decides for each face whether it has to be reversed.
+fn orientation_fixes(mesh: &DeinterleavedIndexedMeshBuf, faces: &[(usize, usize)]) -> Vec<bool> {
+    let vertex_count = mesh.positions.len() / 3;
+    if mesh.indices.iter().any(|&i| i as usize >= vertex_count) {
+        return vec![false; faces.len()];
+    }
+
+    // Vertices are identified by position, like in face adjacency
+    let vertex_ids = position_ids(&mesh.positions);
+
+    // Faces using each edge, and whether they use it from lower to higher id
+    let mut edges: HashMap<(u32, u32), Vec<(usize, bool)>> = HashMap::new();
+    for (face, &(start, count)) in faces.iter().enumerate() {
+        for triangle in mesh.indices[start * 3..(start + count) * 3].chunks(3) {
+            for corner in 0..3 {
+                let from = vertex_ids[triangle[corner] as usize];
+                let to = vertex_ids[triangle[(corner + 1) % 3] as usize];
+                if from != to {
+                    let key = (from.min(to), from.max(to));
+                    edges.entry(key).or_default().push((face, from < to));
+                }
+            }
+        }
+    }
+    let mut neighbors: Vec<Vec<(usize, bool)>> = vec![Vec::new(); faces.len()];
+    let mut open = vec![false; faces.len()];
+    for sharing in edges.values() {
+        match *sharing.as_slice() {
+            [(a, _)] => open[a] = true,
+            // Edges between triangles of the same polygon are skipped
+            [(a, a_forward), (b, b_forward)] if a != b => {
+                // Consistent faces use the shared edge in opposite directions
+                let same_direction = a_forward == b_forward;
+                neighbors[a].push((b, same_direction));
+                neighbors[b].push((a, same_direction));
+            }
+            _ => (),
+        }
+    }
+
+    let mut fixes = vec![false; faces.len()];
+    let mut visited = vec![false; faces.len()];
+    for seed in 0..faces.len() {
+        if visited[seed] {
+            continue;
+        }
+
+        // Wind the connected part like the seed
+        let mut part = vec![seed];
+        visited[seed] = true;
+        let mut next = 0;
+        while next < part.len() {
+            let face = part[next];
+            next += 1;
+            for &(neighbor, same_direction) in neighbors[face].iter() {
+                if !visited[neighbor] {
+                    visited[neighbor] = true;
+                    fixes[neighbor] = fixes[face] != same_direction;
+                    part.push(neighbor);
+                }
+            }
+        }
+
+        if part_faces_inwards(mesh, faces, &part, &fixes, &open) {
+            for &face in part.iter() {
+                fixes[face] = !fixes[face];
+            }
+        }
+    }
+    fixes
+}
+
+/// Checks if a consistently wound part of the mesh would face inwards after
+/// applying the fixes, judged by its volume if closed, else by the vertex
+/// normals, or else by whether most faces would be reversed.
+fn part_faces_inwards(
+    mesh: &DeinterleavedIndexedMeshBuf,
+    faces: &[(usize, usize)],
+    part: &[usize],
+    fixes: &[bool],
+    open: &[bool],
+) -> bool {
+    let closed = part.iter().all(|&face| !open[face]);
+    let has_normals = mesh.normals.len() == mesh.positions.len();
+    let mut volume = 0.0;
+    let mut normal_agreement = 0.0;
+
+    for &face in part.iter() {
+        let (start, count) = faces[face];
+        let sign = if fixes[face] { -1.0 } else { 1.0 };
+        for triangle in mesh.indices[start * 3..(start + count) * 3].chunks(3) {
+            let position = |corner: usize| {
+                let idx = triangle[corner] as usize * 3;
+                [
+                    mesh.positions[idx],
+                    mesh.positions[idx + 1],
+                    mesh.positions[idx + 2],
+                ]
+            };
+            let (a, b, c) = (position(0), position(1), position(2));
+            volume += sign * dot(a, cross(b, c));
+
+            if has_normals {
+                let face_normal = vector::face_normal(a, b, c);
+                for &vertex in triangle {
+                    let idx = vertex as usize * 3;
+                    let normal = [
+                        mesh.normals[idx],
+                        mesh.normals[idx + 1],
+                        mesh.normals[idx + 2],
+                    ];
+                    normal_agreement += sign * dot(face_normal, normal);
+                }
+            }
+        }
+    }
+
+    if closed && volume != 0.0 {
+        volume < 0.0
+    } else if normal_agreement != 0.0 {
+        normal_agreement < 0.0
+    } else {
+        let reversed = part.iter().filter(|&&face| fixes[face]).count();
+        reversed * 2 > part.len()
+    }
+}
+
+/// Negates vertex normals that point away from the faces using the vertex.
+fn fix_normals(mesh: &mut DeinterleavedIndexedMeshBuf) {
+    let vertex_count = mesh.positions.len() / 3;
+    if mesh.normals.len() != vertex_count * 3 {
+        return;
+    }
+
+    let mut face_normals = vec![[0.0f32; 3]; vertex_count];
+    for triangle in mesh.indices.chunks(3) {
+        if triangle.len() < 3 || triangle.iter().any(|&i| i as usize >= vertex_count) {
+            continue;
+        }
+        let position = |corner: usize| {
+            let idx = triangle[corner] as usize * 3;
+            [
+                mesh.positions[idx],
+                mesh.positions[idx + 1],
+                mesh.positions[idx + 2],
+            ]
+        };
+        let (a, b, c) = (position(0), position(1), position(2));
+        let face_normal = vector::face_normal(a, b, c);
+        for &vertex in triangle {
+            let sum = &mut face_normals[vertex as usize];
+            for (sum, component) in sum.iter_mut().zip(face_normal.iter()) {
+                *sum += component;
+            }
+        }
+    }
+
+    for (normal, face_normal) in mesh.normals.chunks_mut(3).zip(face_normals) {
+        if dot([normal[0], normal[1], normal[2]], face_normal) < 0.0 {
+            for component in normal.iter_mut() {
+                *component = -*component;
+            }
+        }
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use obj;
+
+    #[test]
+    fn test_orient() {
+        let cube = obj::load("tests/cube.obj").unwrap().entities.remove(0);
+        let original = (*cube.mesh).clone();
+        assert_eq!(0, misoriented_triangles(&original, None));
+
+        // Reverse one triangle and turn the whole cube inside out
+        let mut mixed = original.clone();
+        mixed.indices.swap(1, 2);
+        flip(&mut mixed, None);
+        assert_eq!(11, misoriented_triangles(&mixed, None));
+
+        assert_eq!(11, orient(&mut mixed, None));
+        assert_eq!(0, misoriented_triangles(&mixed, None));
+        let mut triangles: Vec<[u32; 3]> = mixed
+            .indices
+            .chunks(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+        let mut expected: Vec<[u32; 3]> = original
+            .indices
+            .chunks(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+        // Reversed triangles start at another corner
+        for triangle in triangles.iter_mut().chain(expected.iter_mut()) {
+            let first = (0..3).min_by_key(|&i| triangle[i]).unwrap();
+            triangle.rotate_left(first);
+        }
+        triangles.sort();
+        expected.sort();
+        assert_eq!(expected, triangles);
+        assert_eq!(original.normals, mixed.normals);
+    }
+}
@@ -4,7 +4,8 @@ use aitios_asset::obj;
 
 #[test]
 fn inout_test() {
-    let mut entities = obj::load("tests/cube.obj").unwrap();
+    let scene = obj::load("tests/cube.obj").unwrap();
+    let mut entities = scene.entities.clone();
 
     obj::save(
         entities.iter(),
@@ -12,6 +13,16 @@ fn inout_test() {
         Some("tests/cube_with_mtl.mtl"),
     ).unwrap();
 
+    // Colors and factors of materials are only known with the scene
+    obj::save_scene(
+        &scene,
+        Some("tests/cube_with_mtl.obj"),
+        Some("tests/cube_with_mtl.mtl"),
+    ).unwrap();
+    let mtl = std::fs::read_to_string("tests/cube_with_mtl.mtl").unwrap();
+    assert!(mtl.contains("Kd 0.64 0.64 0.64\n"));
+    assert!(mtl.contains("illum 2\n"));
+
     obj::save(entities.iter(), Some("tests/cube_without_mtl.obj"), None).unwrap();
 
     obj::save(